@@ -4,7 +4,7 @@ use std::io::Write;
 use tg_api::bot;
 
 #[tokio::main]
-pub async fn main() -> Result<(), bot::TgError> {
+pub async fn main() -> anyhow::Result<()> {
     Builder::new()
         .format(|buf, record| {
             writeln!(
@@ -21,7 +21,10 @@ pub async fn main() -> Result<(), bot::TgError> {
     log::info!("Starting buttons bot...");
 
     let bot = bot::TgBot::new();
-    let _ = bot.init().await;
+    if let Err(err) = bot.init().await {
+        log::error!("Unable to start the bot: {}", err);
+        std::process::exit(1);
+    }
 
     Ok(())
 }
@@ -0,0 +1,293 @@
+use crate::chains::{ChainConfig, GLOBAL_CHAIN_REGISTRY};
+use crate::database::{Database, GLOBAL_DATABASE};
+use crate::requests::gas;
+use crate::requests::on_chain::format_amount;
+use crate::requests::swap::{erc20_token, swap_client, Erc20, SwapToken};
+use crate::settings::GLOBAL_SETTINGS_STORE;
+use crate::wallets::GLOBAL_WALLET_STORE;
+use ethers::types::{Address, TxHash, U256};
+use lazy_static::lazy_static;
+use rusqlite::{params, OptionalExtension};
+use std::str::FromStr;
+use std::sync::Arc;
+use teloxide::types::UserId;
+
+lazy_static! {
+    /// Used to list the allowances of the users' wallets and revoke them
+    pub(crate) static ref GLOBAL_APPROVAL_BOOK: ApprovalBook = ApprovalBook::new(&GLOBAL_DATABASE);
+}
+
+/// Token and spender pair of a wallet whose allowance /approvals checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TrackedApproval {
+    pub(crate) id: i64,
+    pub(crate) chain_id: u64,
+    pub(crate) wallet_slot: u8,
+    pub(crate) token: Address,
+    pub(crate) spender: Address,
+}
+
+/// Allowance currently given to a spender, read on chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Allowance {
+    pub(crate) id: i64,
+    pub(crate) token: SwapToken,
+    pub(crate) spender: Address,
+    pub(crate) amount: U256,
+}
+
+impl Allowance {
+    /// Approvals of the max amount, some tokens take what a swap spends off them
+    /// so they are shown as unlimited from half of it up
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.amount >= U256::MAX >> 1
+    }
+
+    /// Shows the allowance and its spender, e.g. "USDC: unlimited for the router"
+    pub(crate) fn summary(&self, chain: &ChainConfig) -> String {
+        let amount = match self.is_unlimited() {
+            true => "unlimited".to_string(),
+            false => format_amount(self.amount, self.token.decimals),
+        };
+        format!(
+            "{}: {} for {}",
+            self.token.symbol,
+            amount,
+            spender_label(chain, self.spender)
+        )
+    }
+}
+
+/// Names the chain's router and Permit2, shows the address of any other spender
+pub(crate) fn spender_label(chain: &ChainConfig, spender: Address) -> String {
+    match chain.router() {
+        Ok(router) if router == spender => "the router".to_string(),
+        _ if spender == chain.permit2() => "Permit2".to_string(),
+        _ => format!("{:?}", spender),
+    }
+}
+
+/// Remembers which tokens each wallet may have approved, allowances can't be listed from the chain
+/// without scanning its logs so /approvals reads the ones of these pairs
+pub(crate) struct ApprovalBook {
+    database: &'static Database,
+}
+
+impl ApprovalBook {
+    pub(crate) fn new(database: &'static Database) -> Self {
+        Self { database }
+    }
+
+    /// Remembers the token and spender pair of the wallet, does nothing if it is known already
+    pub(crate) fn track(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+        wallet_slot: u8,
+        token: Address,
+        spender: Address,
+    ) -> anyhow::Result<()> {
+        self.database.connection().execute(
+            "INSERT OR IGNORE INTO approvals (user_id, chain_id, wallet_slot, token, spender) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                user_id.0,
+                chain_id,
+                wallet_slot,
+                format!("{:?}", token),
+                format!("{:?}", spender)
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn approval(
+        &self,
+        user_id: UserId,
+        id: i64,
+    ) -> anyhow::Result<Option<TrackedApproval>> {
+        let approval: Option<(u64, u8, String, String)> = self
+            .database
+            .connection()
+            .query_row(
+                "SELECT chain_id, wallet_slot, token, spender FROM approvals \
+                 WHERE user_id = ?1 AND id = ?2",
+                params![user_id.0, id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        match approval {
+            Some((chain_id, wallet_slot, token, spender)) => Ok(Some(TrackedApproval {
+                id,
+                chain_id,
+                wallet_slot,
+                token: Address::from_str(&token)?,
+                spender: Address::from_str(&spender)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn approvals(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+        wallet_slot: u8,
+    ) -> anyhow::Result<Vec<TrackedApproval>> {
+        let connection = self.database.connection();
+        let mut statement = connection.prepare(
+            "SELECT id, token, spender FROM approvals \
+             WHERE user_id = ?1 AND chain_id = ?2 AND wallet_slot = ?3 ORDER BY id",
+        )?;
+        let rows = statement
+            .query_map(params![user_id.0, chain_id, wallet_slot], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(id, token, spender)| {
+                Ok(TrackedApproval {
+                    id,
+                    chain_id,
+                    wallet_slot,
+                    token: Address::from_str(&token)?,
+                    spender: Address::from_str(&spender)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Non-zero allowances of the wallet on the trading chain, for the pairs it approved through the
+    /// bot and for every token the user traded with the contract the chain's swaps are approved to
+    pub(crate) async fn allowances(
+        &self,
+        user_id: UserId,
+        wallet_slot: u8,
+    ) -> anyhow::Result<Vec<Allowance>> {
+        let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+        let spender = chain.token_spender()?;
+        for token in GLOBAL_WALLET_STORE.traded_tokens(user_id, chain.chain_id)? {
+            self.track(user_id, chain.chain_id, wallet_slot, token, spender)?;
+        }
+        let owner = GLOBAL_WALLET_STORE
+            .wallets(user_id)?
+            .into_iter()
+            .find(|wallet| wallet.slot == wallet_slot)
+            .map(|wallet| wallet.address)
+            .ok_or_else(|| anyhow::anyhow!("Wallet not found"))?;
+
+        let client = Arc::new(chain.provider().await?);
+        let mut allowances = Vec::new();
+        for approval in self.approvals(user_id, chain.chain_id, wallet_slot)? {
+            let amount = Erc20::new(approval.token, client.clone())
+                .allowance(owner, approval.spender)
+                .call()
+                .await?;
+            if amount.is_zero() {
+                continue;
+            }
+            allowances.push(Allowance {
+                id: approval.id,
+                token: erc20_token(chain, approval.token).await?,
+                spender: approval.spender,
+                amount,
+            });
+        }
+        Ok(allowances)
+    }
+
+    /// Sets the allowance of the pair back to 0 and waits for it to be mined
+    pub(crate) async fn revoke(
+        &self,
+        user_id: UserId,
+        approval: &TrackedApproval,
+    ) -> anyhow::Result<TxHash> {
+        let chain = GLOBAL_CHAIN_REGISTRY
+            .get(approval.chain_id)
+            .ok_or_else(|| anyhow::anyhow!("Chain {} is not supported", approval.chain_id))?;
+        let wallet = GLOBAL_WALLET_STORE.signer(user_id, approval.wallet_slot)?;
+        let client = swap_client(chain, wallet).await?;
+        let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+        let fees =
+            gas::gas_fees(client.as_ref(), settings.gas_preset, settings.max_fee_gwei).await?;
+
+        log::info!("revoking {:?} of {:?}", approval.spender, approval.token);
+        let call = fees.apply(
+            Erc20::new(approval.token, client.clone()).approve(approval.spender, U256::zero()),
+        );
+        let pending = call.send().await?;
+        let tx_hash = pending.tx_hash();
+        match pending.await? {
+            Some(receipt) if receipt.status == Some(1u64.into()) => Ok(tx_hash),
+            _ => Err(anyhow::anyhow!("Revoke failed\n{}", chain.tx_url(tx_hash))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn book() -> ApprovalBook {
+        let database = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        ApprovalBook::new(Box::leak(Box::new(database)))
+    }
+
+    #[test]
+    fn tracks_each_pair_once() {
+        let book = book();
+        let (user, token, spender) = (
+            UserId(1),
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+        );
+        book.track(user, 1, 0, token, spender).unwrap();
+        book.track(user, 1, 0, token, spender).unwrap();
+        book.track(user, 1, 0, Address::from_low_u64_be(3), spender)
+            .unwrap();
+
+        let approvals = book.approvals(user, 1, 0).unwrap();
+        assert_eq!(approvals.len(), 2);
+        assert_eq!(
+            approvals[0],
+            TrackedApproval {
+                id: approvals[0].id,
+                chain_id: 1,
+                wallet_slot: 0,
+                token,
+                spender,
+            }
+        );
+        assert_eq!(approvals[1].token, Address::from_low_u64_be(3));
+        assert_eq!(
+            book.approval(user, approvals[0].id).unwrap(),
+            Some(approvals[0].clone())
+        );
+    }
+
+    #[test]
+    fn lists_the_pairs_of_one_wallet_of_the_user() {
+        let book = book();
+        let (token, spender) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        book.track(UserId(1), 1, 0, token, spender).unwrap();
+        book.track(UserId(1), 1, 1, token, spender).unwrap();
+        book.track(UserId(1), 137, 0, token, spender).unwrap();
+        book.track(UserId(2), 1, 0, token, spender).unwrap();
+
+        let approvals = book.approvals(UserId(1), 1, 0).unwrap();
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(book.approvals(UserId(1), 1, 1).unwrap().len(), 1);
+        assert_eq!(book.approvals(UserId(1), 137, 0).unwrap().len(), 1);
+        assert!(book.approvals(UserId(1), 1, 2).unwrap().is_empty());
+
+        // another user can't read the approval by its id
+        let id = approvals[0].id;
+        assert!(book.approval(UserId(2), id).unwrap().is_none());
+        assert!(book.approval(UserId(1), id).unwrap().is_some());
+    }
+}
@@ -1,26 +1,67 @@
-use crate::consts::{BUY, CLOSE, MAIN_MENU};
+use crate::chains::{ChainRegistry, GLOBAL_CHAIN_REGISTRY};
+use crate::database::{Database, GLOBAL_DATABASE};
+use crate::dca::run_dca_scheduler;
+use crate::exit_orders::ExitKind;
 use crate::handlers::callback_handlers::{
-    handle_buy_amount_callback, handle_buy_callback, handle_buy_token_callback,
-    handle_close_callback, handle_menu_callback, handle_private_tx_callback,
-    handle_rebate_callback, handle_receive_token_callback, handle_send_tx_callback,
-    handle_wallet_callback,
+    handle_approvals_wallet_callback, handle_approve_callback, handle_buy_amount_callback,
+    handle_buy_callback, handle_buy_quote_callback, handle_buy_risk_callback,
+    handle_buy_token_callback, handle_close_callback, handle_confirm_risk_callback,
+    handle_custom_slippage_callback, handle_dca_amount_callback, handle_dca_approval_callback,
+    handle_dca_buy_token_callback, handle_dca_cancel_callback, handle_dca_create_callback,
+    handle_dca_interval_callback, handle_dca_new_callback, handle_dca_pause_callback,
+    handle_dca_receive_token_callback, handle_dca_resume_callback, handle_dca_runs_callback,
+    handle_dca_wallet_callback, handle_exit_cancel_callback, handle_exit_preset_callback,
+    handle_history_page_callback, handle_history_trade_callback, handle_limit_amount_callback,
+    handle_limit_callback, handle_limit_cancel_callback, handle_limit_create_callback,
+    handle_limit_expiry_callback, handle_limit_orders_callback, handle_limit_price_callback,
+    handle_limit_token_callback, handle_limit_wallet_callback, handle_menu_callback,
+    handle_order_deadline_callback, handle_order_gas_callback, handle_order_slippage_callback,
+    handle_pick_sell_token_callback, handle_pick_token_callback, handle_private_tx_callback,
+    handle_rebate_callback, handle_rebate_claim_callback, handle_receive_token_callback,
+    handle_revoke_callback, handle_sell_amount_callback, handle_sell_callback,
+    handle_sell_percent_callback, handle_sell_token_callback, handle_send_tx_callback,
+    handle_settings_deadline_callback, handle_settings_gas_callback,
+    handle_settings_max_fee_callback, handle_settings_slippage_callback, handle_wallet_callback,
+    handle_wallet_copy_callback, handle_wallet_default_callback, handle_wallet_rename_callback,
 };
 use crate::handlers::dialogue_handlers::{
     buy_address_dialogue_handler, buy_address_or_token_handler, buy_amount_dialogue_handler,
-    PromptDialogueState,
+    dca_amount_dialogue_handler, dca_token_dialogue_handler, limit_token_dialogue_handler,
+    limit_value_dialogue_handler, sell_address_handler, sell_amount_dialogue_handler,
+    slippage_dialogue_handler, wallet_name_dialogue_handler, PromptDialogueState,
 };
-use crate::handlers::{delete_previous_messages, matching_sub_menu, SubMenuType};
+use crate::handlers::{
+    delete_previous_messages, send_approvals_menu, send_history_menu, send_orders_menu,
+    send_rebates_menu, send_schedules_menu, send_settings_menu, send_wallets_menu,
+};
+use crate::keyboards::approval_buttons::ApprovalButtons;
 use crate::keyboards::buy_buttons::BuyButtons;
-use crate::keyboards::menu_keyboard;
+use crate::keyboards::callback_data::{CallbackData, CallbackDataError};
+use crate::keyboards::dca_buttons::DcaButtons;
+use crate::keyboards::exit_buttons::ExitButtons;
+use crate::keyboards::history_buttons::HistoryButtons;
+use crate::keyboards::limit_buttons::LimitButtons;
+use crate::keyboards::rebate_buttons::RebateButtons;
+use crate::keyboards::sell_buttons::SellButtons;
+use crate::keyboards::settings_buttons::SettingsButtons;
+use crate::keyboards::wallet_buttons::WalletButtons;
+use crate::keyboards::{menu_keyboard, MenuButtons};
+use crate::limit_orders::{run_limit_order_engine, LimitSide};
+use crate::rebates::{RebateBook, GLOBAL_REBATE_BOOK};
 use crate::requests::on_chain;
-use crate::storages::{TgMessage, TgMessageStorage, GLOBAL_MAIN_MENU_STORAGE};
+use crate::requests::permit::{permit_sponsor_from_env, GLOBAL_PERMIT_SPONSOR};
+use crate::requests::simulation::SimulationError;
+use crate::storages::{
+    dialogue_storage, StorageBackend, TgMessage, GLOBAL_MAIN_MENU_STORAGE, GLOBAL_STORAGE_BACKEND,
+};
+use crate::wallets::{WalletStore, GLOBAL_WALLET_STORE};
 use std::sync::Arc;
 use teloxide::dispatching::HandlerExt;
 use teloxide::{
-    dispatching::{dialogue::InMemStorage, UpdateFilterExt},
+    dispatching::{dialogue::ErasedStorage, UpdateFilterExt},
     dptree,
     error_handlers::LoggingErrorHandler,
-    payloads::SendMessageSetters,
+    payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
     prelude::{Dispatcher, Requester},
     types::{CallbackQuery, Message, ParseMode, Update},
     utils::command::BotCommands,
@@ -29,7 +70,6 @@ use teloxide::{
 use tokio::time::{sleep, Duration};
 
 use std::fmt;
-use teloxide::dispatching::dialogue::InMemStorageError;
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -37,11 +77,14 @@ pub enum TgError {
     AnyhowError(anyhow::Error),
     Parse(String),
     TeloxideRequest(teloxide::RequestError),
-    TeloxideInMemStorageError(InMemStorageError),
+    DialogueStorage(Box<dyn std::error::Error + Send + Sync>),
     UnmatchedQuery(teloxide::types::CallbackQuery),
     NoQueryData(teloxide::types::CallbackQuery),
     NoQueryMessage(teloxide::types::CallbackQuery),
     UserNotFound(teloxide::types::Message),
+    CallbackData(CallbackDataError),
+    /// The swap would revert, it was reported to the user instead of being sent
+    Simulation(SimulationError),
 }
 
 #[derive(BotCommands, Clone)]
@@ -57,6 +100,16 @@ enum Command {
     Start,
     #[command(description = "Display Trade History")]
     History,
+    #[command(description = "Default slippage, deadline and gas")]
+    Settings,
+    #[command(description = "Rebate tier, balances and claims")]
+    Rebates,
+    #[command(description = "Open limit orders")]
+    Orders,
+    #[command(description = "DCA schedules")]
+    Dca,
+    #[command(description = "Token allowances of your wallets")]
+    Approvals,
 }
 
 #[derive(Clone, Debug)]
@@ -64,6 +117,12 @@ pub struct TgBot {
     bot: Bot,
 }
 
+impl Default for TgBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TgBot {
     pub fn new() -> Self {
         let bot = Bot::from_env();
@@ -71,12 +130,14 @@ impl TgBot {
     }
 
     pub async fn init(self) -> Result<(), TgError> {
+        load_globals()?;
+
         let handler = dptree::entry()
             .branch(Update::filter_message().filter_command::<Command>().endpoint(command_callback))
             .branch(Update::filter_callback_query().endpoint(button_callback))
             .branch(
                  Update::filter_message()
-                     .enter_dialogue::<Message,InMemStorage<PromptDialogueState>,PromptDialogueState>()
+                     .enter_dialogue::<Message,ErasedStorage<PromptDialogueState>,PromptDialogueState>()
                          .branch(dptree::case![PromptDialogueState::BuyStartAddressPrompt]
                              .endpoint(buy_address_dialogue_handler))
                          .branch(dptree::case![PromptDialogueState::BuyAddressReceived]
@@ -85,15 +146,42 @@ impl TgBot {
                              .endpoint(buy_address_dialogue_handler))
                          .branch(dptree::case![PromptDialogueState::ReceiveAddressReceived]
                              .endpoint(buy_address_or_token_handler))
+                         .branch(dptree::case![PromptDialogueState::BuyTokenNameReceived]
+                             .endpoint(buy_address_or_token_handler))
+                         .branch(dptree::case![PromptDialogueState::ReceiveTokenNameReceived]
+                             .endpoint(buy_address_or_token_handler))
                          .branch(dptree::case![PromptDialogueState::BuyAmountReceived]
                              .endpoint(buy_amount_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::SellAddressReceived]
+                             .endpoint(sell_address_handler))
+                         .branch(dptree::case![PromptDialogueState::SellAmountReceived]
+                             .endpoint(sell_amount_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::WalletNameReceived { slot }]
+                             .endpoint(wallet_name_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::SlippageReceived]
+                             .endpoint(slippage_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::LimitTokenReceived]
+                             .endpoint(limit_token_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::LimitAmountReceived]
+                             .endpoint(limit_value_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::LimitPriceReceived]
+                             .endpoint(limit_value_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::DcaBuyTokenReceived]
+                             .endpoint(dca_token_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::DcaReceiveTokenReceived]
+                             .endpoint(dca_token_dialogue_handler))
+                         .branch(dptree::case![PromptDialogueState::DcaAmountReceived]
+                             .endpoint(dca_amount_dialogue_handler))
             );
 
+        tokio::spawn(run_limit_order_engine(self.bot.clone()));
+        tokio::spawn(run_dca_scheduler(self.bot.clone()));
+
         Dispatcher::builder(self.bot, handler)
             .error_handler(LoggingErrorHandler::with_custom_text(
                 "An error has occurred in the dispatcher",
             ))
-            .dependencies(dptree::deps![InMemStorage::<PromptDialogueState>::new()])
+            .dependencies(dptree::deps![dialogue_storage::<PromptDialogueState>()])
             .enable_ctrlc_handler()
             .build()
             .dispatch()
@@ -102,6 +190,17 @@ impl TgBot {
     }
 }
 
+/// Loads the config every handler reads, the database first as the others keep their state in it
+fn load_globals() -> anyhow::Result<()> {
+    GLOBAL_DATABASE.load(Database::from_env)?;
+    GLOBAL_WALLET_STORE.load(WalletStore::from_env)?;
+    GLOBAL_STORAGE_BACKEND.load(StorageBackend::from_env)?;
+    GLOBAL_CHAIN_REGISTRY.load(ChainRegistry::from_env)?;
+    GLOBAL_REBATE_BOOK.load(RebateBook::from_env)?;
+    GLOBAL_PERMIT_SPONSOR.load(permit_sponsor_from_env)?;
+    Ok(())
+}
+
 async fn command_callback(bot: Bot, cmd: Command, msg: Message) -> Result<(), TgError> {
     match cmd {
         Command::Help => {
@@ -119,32 +218,27 @@ async fn command_callback(bot: Bot, cmd: Command, msg: Message) -> Result<(), Tg
                 .parse_mode(ParseMode::MarkdownV2)
                 .reply_markup(keyboard)
                 .await?;
-            let message_sent = Arc::new(message_sent);
 
             // Updates the GLOBAL_MAIN_MENU_STORAGE
-            let _user_name = message_sent
-                .clone()
-                .from()
-                .and_then(|user| user.username.as_ref())
-                .and_then(|user_name| {
-                    let message = TgMessage {
-                        chat_id: message_sent.chat.id,
-                        message_id: message_sent.id,
-                        message: message_sent.clone(),
-                    };
-                    GLOBAL_MAIN_MENU_STORAGE.insert(user_name.to_string(), message);
-                    Some(user_name)
-                });
+            if let Some(user) = msg.from() {
+                let message = TgMessage {
+                    chat_id: message_sent.chat.id,
+                    message_id: message_sent.id,
+                };
+                GLOBAL_MAIN_MENU_STORAGE.insert((msg.chat.id, user.id), message);
+            }
 
             // delete previous messages
             let last_message_id = message_sent.id;
-            let _ =
-                delete_previous_messages(&bot, msg.chat.id.0, last_message_id.0 - 1, 20).await?;
+            delete_previous_messages(&bot, msg.chat.id.0, last_message_id.0 - 1, 20).await?;
         }
         Command::Start => {
             sleep(Duration::from_secs(3)).await;
             let keyboard = menu_keyboard();
-            let menu_msg = on_chain::get_on_chain_info_start().await?;
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            let menu_msg = on_chain::get_on_chain_info_start(user.id).await?;
 
             // send the new message
             let _message_sent = bot
@@ -154,10 +248,46 @@ async fn command_callback(bot: Bot, cmd: Command, msg: Message) -> Result<(), Tg
                 .await?;
         }
         Command::Wallets => {
-            todo!()
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_wallets_menu(&bot, msg.chat.id, user.id).await?;
         }
         Command::History => {
-            todo!()
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_history_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Command::Settings => {
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_settings_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Command::Rebates => {
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_rebates_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Command::Orders => {
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_orders_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Command::Dca => {
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_schedules_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Command::Approvals => {
+            let user = msg
+                .from()
+                .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+            send_approvals_menu(&bot, msg.chat.id, user.id).await?;
         }
     }
     Ok(())
@@ -166,61 +296,248 @@ async fn command_callback(bot: Bot, cmd: Command, msg: Message) -> Result<(), Tg
 async fn button_callback(
     bot: Bot,
     q: CallbackQuery,
-    storage: Arc<InMemStorage<PromptDialogueState>>,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
 ) -> Result<(), TgError> {
-    if let Some(action) = &q.data {
-        match action.as_str() {
-            // main-menu
-            BUY => handle_buy_callback(&bot, &q).await?,
-            MAIN_MENU => handle_menu_callback(&bot, &q).await?,
-            CLOSE => handle_close_callback(&bot, &q).await?,
-
-            // sub-menus
-            _ => match matching_sub_menu(&bot, &q) {
-                Some(SubMenuType::SendBuyTx) => match BuyButtons::new(action) {
-                    BuyButtons::SendBuyTx => handle_send_tx_callback(&bot, &q).await?,
-                    BuyButtons::PrivateTx(_) => handle_private_tx_callback(&bot, &q).await?,
-                    BuyButtons::Rebate(_) => handle_rebate_callback(&bot, &q).await?,
-                    BuyButtons::Wallet1(_) | BuyButtons::Wallet2(_) | BuyButtons::Wallet3(_) => {
-                        handle_wallet_callback(&bot, &q).await?
-                    }
-                    BuyButtons::BuyToken => {
-                        handle_buy_token_callback(
-                            &bot,
-                            PromptDialogueState::BuyStartAddressPrompt,
-                            &q,
-                            storage,
-                        )
-                        .await?
-                    }
-                    BuyButtons::ReceiveToken => {
-                        handle_receive_token_callback(
-                            &bot,
-                            PromptDialogueState::ReceiveStartAddressPrompt,
-                            &q,
-                            storage,
-                        )
-                        .await?
-                    }
-                    BuyButtons::BuyAmount => {
-                        handle_buy_amount_callback(
-                            &bot,
-                            PromptDialogueState::StartBuyAmountPrompt,
-                            &q,
-                            storage,
-                        )
-                        .await?
-                    }
-                    _ => {}
-                },
-                Some(SubMenuType::SendSellTx) => {
-                    todo!()
-                }
-                _ => {}
-            },
+    let data = q
+        .data
+        .as_deref()
+        .ok_or_else(|| TgError::NoQueryData(q.clone()))?;
+    let action = match CallbackData::decode(data) {
+        Ok(action) => action,
+        Err(err) => {
+            // most likely a keyboard sent by an older version of the bot
+            bot.answer_callback_query(&q.id)
+                .text("This menu is outdated, use /menu to open a new one")
+                .await?;
+            return Err(err.into());
+        }
+    };
+
+    match action {
+        // main-menu
+        CallbackData::Menu(MenuButtons::Buy) => handle_buy_callback(&bot, &q).await?,
+        CallbackData::Menu(MenuButtons::Sell) => handle_sell_callback(&bot, &q).await?,
+        CallbackData::Menu(MenuButtons::LimitBuy) => {
+            handle_limit_callback(&bot, &q, LimitSide::Buy).await?
+        }
+        CallbackData::Menu(MenuButtons::LimitSell) => {
+            handle_limit_callback(&bot, &q, LimitSide::Sell).await?
+        }
+        CallbackData::Menu(MenuButtons::MainMenu) => handle_menu_callback(&bot, &q).await?,
+        CallbackData::Menu(MenuButtons::Close) => handle_close_callback(&bot, &q).await?,
+        CallbackData::Menu(_) => {
+            bot.answer_callback_query(&q.id).await?;
+        }
+
+        // wallets menu
+        CallbackData::Wallets(WalletButtons::Copy(slot)) => {
+            handle_wallet_copy_callback(&bot, &q, slot).await?
+        }
+        CallbackData::Wallets(WalletButtons::Rename(slot)) => {
+            handle_wallet_rename_callback(&bot, &q, slot, storage).await?
+        }
+        CallbackData::Wallets(WalletButtons::SetDefault(slot)) => {
+            handle_wallet_default_callback(&bot, &q, slot).await?
+        }
+
+        // history menu
+        CallbackData::History(HistoryButtons::Page(page)) => {
+            handle_history_page_callback(&bot, &q, page).await?
+        }
+        CallbackData::History(HistoryButtons::Trade { id, page }) => {
+            handle_history_trade_callback(&bot, &q, id, page).await?
         }
-        log::info!("You chose: {}", action);
+
+        // settings menu
+        CallbackData::Settings(SettingsButtons::Slippage(slippage_bps)) => {
+            handle_settings_slippage_callback(&bot, &q, slippage_bps).await?
+        }
+        CallbackData::Settings(SettingsButtons::CustomSlippage) => {
+            handle_custom_slippage_callback(&bot, &q, storage).await?
+        }
+        CallbackData::Settings(SettingsButtons::Deadline(deadline_secs)) => {
+            handle_settings_deadline_callback(&bot, &q, deadline_secs).await?
+        }
+        CallbackData::Settings(SettingsButtons::Gas(gas_preset)) => {
+            handle_settings_gas_callback(&bot, &q, gas_preset).await?
+        }
+        CallbackData::Settings(SettingsButtons::MaxFee(max_fee_gwei)) => {
+            handle_settings_max_fee_callback(&bot, &q, max_fee_gwei).await?
+        }
+
+        // rebates menu
+        CallbackData::Rebates(RebateButtons::Claim(chain_id)) => {
+            handle_rebate_claim_callback(&bot, &q, chain_id).await?
+        }
+
+        // limit order menus
+        CallbackData::Limit(button) => match button {
+            LimitButtons::Wallet(slot) => handle_limit_wallet_callback(&bot, &q, slot).await?,
+            LimitButtons::Token => handle_limit_token_callback(&bot, &q, storage).await?,
+            LimitButtons::Amount => handle_limit_amount_callback(&bot, &q, storage).await?,
+            LimitButtons::TriggerPrice => handle_limit_price_callback(&bot, &q, storage).await?,
+            LimitButtons::Expiry(expiry_secs) => {
+                handle_limit_expiry_callback(&bot, &q, expiry_secs).await?
+            }
+            LimitButtons::Create => handle_limit_create_callback(&bot, &q).await?,
+            LimitButtons::Orders => handle_limit_orders_callback(&bot, &q).await?,
+            LimitButtons::Cancel(id) => handle_limit_cancel_callback(&bot, &q, id).await?,
+        },
+
+        // exit orders of a filled buy
+        CallbackData::Exit(button) => match button {
+            ExitButtons::StopLoss { position, percent } => {
+                handle_exit_preset_callback(&bot, &q, position, ExitKind::StopLoss, percent, 100)
+                    .await?
+            }
+            ExitButtons::TakeProfit {
+                position,
+                percent,
+                sell_percent,
+            } => {
+                handle_exit_preset_callback(
+                    &bot,
+                    &q,
+                    position,
+                    ExitKind::TakeProfit,
+                    percent,
+                    sell_percent,
+                )
+                .await?
+            }
+            ExitButtons::TrailingStop { position, percent } => {
+                handle_exit_preset_callback(
+                    &bot,
+                    &q,
+                    position,
+                    ExitKind::TrailingStop,
+                    percent,
+                    100,
+                )
+                .await?
+            }
+            ExitButtons::Cancel(id) => handle_exit_cancel_callback(&bot, &q, id).await?,
+        },
+
+        // DCA menu and schedules
+        CallbackData::Dca(button) => match button {
+            DcaButtons::New => handle_dca_new_callback(&bot, &q).await?,
+            DcaButtons::Wallet(slot) => handle_dca_wallet_callback(&bot, &q, slot).await?,
+            DcaButtons::BuyToken => handle_dca_buy_token_callback(&bot, &q, storage).await?,
+            DcaButtons::ReceiveToken => {
+                handle_dca_receive_token_callback(&bot, &q, storage).await?
+            }
+            DcaButtons::Amount => handle_dca_amount_callback(&bot, &q, storage).await?,
+            DcaButtons::Interval(interval_secs) => {
+                handle_dca_interval_callback(&bot, &q, interval_secs).await?
+            }
+            DcaButtons::Runs(runs) => handle_dca_runs_callback(&bot, &q, runs).await?,
+            DcaButtons::Approval(approval) => {
+                handle_dca_approval_callback(&bot, &q, approval).await?
+            }
+            DcaButtons::Create => handle_dca_create_callback(&bot, &q).await?,
+            DcaButtons::Pause(id) => handle_dca_pause_callback(&bot, &q, id).await?,
+            DcaButtons::Resume(id) => handle_dca_resume_callback(&bot, &q, id).await?,
+            DcaButtons::Cancel(id) => handle_dca_cancel_callback(&bot, &q, id).await?,
+        },
+
+        // approvals
+        CallbackData::Approvals(button) => match button {
+            ApprovalButtons::Wallet(slot) => {
+                handle_approvals_wallet_callback(&bot, &q, slot).await?
+            }
+            ApprovalButtons::Revoke(id) => handle_revoke_callback(&bot, &q, id).await?,
+            ApprovalButtons::Approve { sell, mode } => {
+                handle_approve_callback(&bot, &q, sell, mode).await?
+            }
+        },
+
+        // buy menu
+        CallbackData::Buy(button) => match button {
+            BuyButtons::SendBuyTx => handle_send_tx_callback(&bot, &q).await?,
+            BuyButtons::PrivateTx => handle_private_tx_callback(&bot, &q).await?,
+            BuyButtons::Rebate => handle_rebate_callback(&bot, &q).await?,
+            BuyButtons::Wallet(slot) => handle_wallet_callback(&bot, &q, slot).await?,
+            BuyButtons::BuyToken => {
+                handle_buy_token_callback(
+                    &bot,
+                    PromptDialogueState::BuyStartAddressPrompt,
+                    &q,
+                    storage,
+                )
+                .await?
+            }
+            BuyButtons::ReceiveToken => {
+                handle_receive_token_callback(
+                    &bot,
+                    PromptDialogueState::ReceiveStartAddressPrompt,
+                    &q,
+                    storage,
+                )
+                .await?
+            }
+            BuyButtons::BuyAmount => {
+                handle_buy_amount_callback(
+                    &bot,
+                    PromptDialogueState::StartBuyAmountPrompt,
+                    &q,
+                    storage,
+                )
+                .await?
+            }
+            BuyButtons::PickBuyToken(token) => {
+                handle_pick_token_callback(&bot, &q, token, true, storage).await?
+            }
+            BuyButtons::PickReceiveToken(token) => {
+                handle_pick_token_callback(&bot, &q, token, false, storage).await?
+            }
+            BuyButtons::EstimatedReceivedAmount => handle_buy_quote_callback(&bot, &q).await?,
+            BuyButtons::Risk => handle_buy_risk_callback(&bot, &q).await?,
+            BuyButtons::ConfirmRisk(token) => handle_confirm_risk_callback(&bot, &q, token).await?,
+            BuyButtons::Slippage => handle_order_slippage_callback(&bot, &q).await?,
+            BuyButtons::Deadline => handle_order_deadline_callback(&bot, &q).await?,
+            BuyButtons::Gas => handle_order_gas_callback(&bot, &q).await?,
+        },
+
+        // sell menu
+        CallbackData::Sell(button) => match button {
+            SellButtons::SendSellTx => handle_send_tx_callback(&bot, &q).await?,
+            SellButtons::PrivateTx => handle_private_tx_callback(&bot, &q).await?,
+            SellButtons::Rebate => handle_rebate_callback(&bot, &q).await?,
+            SellButtons::Wallet(slot) => handle_wallet_callback(&bot, &q, slot).await?,
+            SellButtons::Percent(percent) => {
+                handle_sell_percent_callback(&bot, &q, percent).await?
+            }
+            SellButtons::SellToken => {
+                handle_sell_token_callback(
+                    &bot,
+                    PromptDialogueState::SellStartAddressPrompt,
+                    &q,
+                    storage,
+                )
+                .await?
+            }
+            SellButtons::SellAmount => {
+                handle_sell_amount_callback(
+                    &bot,
+                    PromptDialogueState::StartSellAmountPrompt,
+                    &q,
+                    storage,
+                )
+                .await?
+            }
+            SellButtons::EstimatedReceivedAmount => {
+                bot.answer_callback_query(&q.id).await?;
+            }
+            SellButtons::Slippage => handle_order_slippage_callback(&bot, &q).await?,
+            SellButtons::Deadline => handle_order_deadline_callback(&bot, &q).await?,
+            SellButtons::Gas => handle_order_gas_callback(&bot, &q).await?,
+            SellButtons::PickToken(token) => {
+                handle_pick_sell_token_callback(&bot, &q, token, storage).await?
+            }
+        },
     }
+    log::info!("You chose: {}", data);
     Ok(())
 }
 
@@ -231,8 +548,8 @@ impl fmt::Display for TgError {
             Self::TeloxideRequest(ref err) => {
                 write!(f, "Telegram request error: {}", err)
             }
-            Self::TeloxideInMemStorageError(ref err) => {
-                write!(f, "InMemStorage error: {}", err)
+            Self::DialogueStorage(ref err) => {
+                write!(f, "Dialogue storage error: {}", err)
             }
             Self::UnmatchedQuery(ref cb_query) => {
                 write!(f, "Could not match callback query: {:?}", cb_query)
@@ -246,6 +563,8 @@ impl fmt::Display for TgError {
             Self::UserNotFound(ref msg) => {
                 write!(f, "Could not find user for message: {:?}", msg)
             }
+            Self::CallbackData(ref err) => write!(f, "Callback data error: {}", err),
+            Self::Simulation(ref err) => write!(f, "{}", err),
             Self::AnyhowError(ref err) => write!(f, "Anyhow error: {}", err),
         }
     }
@@ -257,9 +576,21 @@ impl From<teloxide::RequestError> for TgError {
     }
 }
 
-impl From<InMemStorageError> for TgError {
-    fn from(err: InMemStorageError) -> Self {
-        Self::TeloxideInMemStorageError(err)
+impl From<Box<dyn std::error::Error + Send + Sync>> for TgError {
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Self::DialogueStorage(err)
+    }
+}
+
+impl From<CallbackDataError> for TgError {
+    fn from(err: CallbackDataError) -> Self {
+        Self::CallbackData(err)
+    }
+}
+
+impl From<SimulationError> for TgError {
+    fn from(err: SimulationError) -> Self {
+        Self::Simulation(err)
     }
 }
 
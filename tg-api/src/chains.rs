@@ -0,0 +1,183 @@
+use crate::global::Global;
+use crate::requests::relay::RelayConfig;
+use dotenv::dotenv;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Address, TxHash, H160},
+};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// Chains the bot reads from and trades on, see [ChainRegistry::from_env]
+pub(crate) static GLOBAL_CHAIN_REGISTRY: Global<ChainRegistry> = Global::new("chain registry");
+
+/// Permit2 is deployed at the same address on every chain
+const PERMIT2: Address = H160([
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x22, 0xd4, 0x73, 0x03, 0x0f, 0x11, 0x6d, 0xde, 0xe9, 0xf6, 0xb4,
+    0x3a, 0xc7, 0x8b, 0xa3,
+]);
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+fn default_private_tx_blocks() -> u64 {
+    25
+}
+
+/// One entry of the chain registry
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ChainConfig {
+    pub(crate) chain_id: u64,
+    pub(crate) name: String,
+    /// Tried in order until one of them answers for the expected chain
+    pub(crate) rpc_urls: Vec<String>,
+    pub(crate) native_symbol: String,
+    /// Uniswap V2-style routers, swaps are sent to the first one
+    pub(crate) routers: Vec<Address>,
+    /// Multicall3 contract, defaults to its canonical deployment
+    #[serde(default)]
+    pub(crate) multicall: Option<Address>,
+    /// Universal Router swaps from ERC-20s are sent to instead, pulling the tokens with Permit2 so
+    /// each swap signs its own allowance. Its V2 factory must be the one of the first router
+    #[serde(default)]
+    pub(crate) universal_router: Option<Address>,
+    /// Permit2 contract, defaults to its canonical deployment
+    #[serde(default)]
+    pub(crate) permit2: Option<Address>,
+    /// Base URL of the block explorer, without a trailing slash
+    pub(crate) explorer_url: String,
+    /// Uniswap-format token lists, tokens listed there can be picked by symbol or name
+    #[serde(default)]
+    pub(crate) token_lists: Vec<String>,
+    /// Relays Private Tx trades are sent to instead of the public mempool
+    #[serde(default)]
+    pub(crate) private_relays: Vec<RelayConfig>,
+    /// Blocks a private transaction is tracked and kept valid for
+    #[serde(default = "default_private_tx_blocks")]
+    pub(crate) private_tx_blocks: u64,
+    /// Traces reverted swap simulations with `debug_traceCall` to find the contract that reverted,
+    /// needs an RPC serving the debug namespace
+    #[serde(default)]
+    pub(crate) trace_simulation: bool,
+    #[serde(default = "enabled_by_default")]
+    pub(crate) enabled: bool,
+    /// Provider of the RPC found to serve this chain, shared by the clones of this entry
+    #[serde(skip)]
+    provider: Arc<Mutex<Option<Provider<Http>>>>,
+}
+
+impl ChainConfig {
+    /// Connects to the first RPC that is reachable and serves this chain, its chain id is only
+    /// checked on the first call and the provider is reused afterwards
+    pub(crate) async fn provider(&self) -> anyhow::Result<Provider<Http>> {
+        if let Some(provider) = self.provider.lock().clone() {
+            return Ok(provider);
+        }
+        for rpc_url in &self.rpc_urls {
+            let provider = match Provider::<Http>::try_from(rpc_url.as_str()) {
+                Ok(provider) => provider,
+                Err(err) => {
+                    log::warn!("invalid {} rpc url: {}", self.name, err);
+                    continue;
+                }
+            };
+            match provider.get_chainid().await {
+                Ok(chain_id) if chain_id.as_u64() == self.chain_id => {
+                    *self.provider.lock() = Some(provider.clone());
+                    return Ok(provider);
+                }
+                Ok(chain_id) => {
+                    log::warn!("{} rpc serves chain {} instead", self.name, chain_id)
+                }
+                Err(err) => log::warn!("{} rpc unavailable: {}", self.name, err),
+            }
+        }
+        Err(anyhow::anyhow!("No RPC available for {}", self.name))
+    }
+
+    /// Router the swaps on this chain are sent to
+    pub(crate) fn router(&self) -> anyhow::Result<Address> {
+        self.routers
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("No router configured for {}", self.name))
+    }
+
+    pub(crate) fn permit2(&self) -> Address {
+        self.permit2.unwrap_or(PERMIT2)
+    }
+
+    /// Contract the ERC-20s swapped on this chain are approved to
+    pub(crate) fn token_spender(&self) -> anyhow::Result<Address> {
+        match self.universal_router {
+            Some(_) => Ok(self.permit2()),
+            None => self.router(),
+        }
+    }
+
+    /// Whether trades on this chain can be sent privately, see [private_relays](Self::private_relays)
+    pub(crate) fn has_private_relays(&self) -> bool {
+        !self.private_relays.is_empty()
+    }
+
+    pub(crate) fn tx_url(&self, tx_hash: TxHash) -> String {
+        format!("{}/tx/{:?}", self.explorer_url, tx_hash)
+    }
+}
+
+/// Chains known to the bot, loaded from the JSON file at `CHAINS_CONFIG` (defaults to `chains.json`)
+///
+/// The file holds a list of [ChainConfig], the first enabled chain is the one the buy and sell menus trade on
+#[derive(Debug, Clone)]
+pub(crate) struct ChainRegistry {
+    chains: Vec<ChainConfig>,
+}
+
+impl ChainRegistry {
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        dotenv().ok();
+        let path = std::env::var("CHAINS_CONFIG").unwrap_or_else(|_| "chains.json".to_string());
+        let config = std::fs::read_to_string(&path)
+            .map_err(|err| anyhow::anyhow!("Unable to read {}: {}", path, err))?;
+        let registry = Self::new(serde_json::from_str(&config)?)?;
+        log::info!("Loaded {} chains from {}", registry.chains.len(), path);
+        Ok(registry)
+    }
+
+    pub(crate) fn new(chains: Vec<ChainConfig>) -> anyhow::Result<Self> {
+        for (index, chain) in chains.iter().enumerate() {
+            if chain.rpc_urls.is_empty() {
+                return Err(anyhow::anyhow!("{} has no RPC url", chain.name));
+            }
+            if chains[..index]
+                .iter()
+                .any(|other| other.chain_id == chain.chain_id)
+            {
+                return Err(anyhow::anyhow!("Chain {} is listed twice", chain.chain_id));
+            }
+        }
+        if !chains.iter().any(|chain| chain.enabled) {
+            return Err(anyhow::anyhow!("No chain is enabled"));
+        }
+        Ok(Self { chains })
+    }
+
+    /// Enabled chains, in the order of the config file
+    pub(crate) fn enabled(&self) -> impl Iterator<Item = &ChainConfig> {
+        self.chains.iter().filter(|chain| chain.enabled)
+    }
+
+    pub(crate) fn get(&self, chain_id: u64) -> Option<&ChainConfig> {
+        self.chains.iter().find(|chain| chain.chain_id == chain_id)
+    }
+
+    /// Chain the buy and sell menus trade on
+    pub(crate) fn trading_chain(&self) -> &ChainConfig {
+        self.enabled()
+            .next()
+            .expect("the registry has at least one enabled chain")
+    }
+}
@@ -14,4 +14,20 @@ pub const BUY_AMOUNT: &str = "Buy Amount";
 pub const ESTIMATED_RECEIVED_AMOUNT: &str = "Estimated Received Amount";
 pub const BUY_TOKEN: &str = "Buy Token";
 pub const RECEIVE_TOKEN: &str = "Receive Token";
-pub const BOT_NAME: &str = "NishikigoiBot";
+pub const SELL: &str = "Sell";
+pub const SELL_TOKEN: &str = "Sell Token";
+pub const SELL_AMOUNT: &str = "Sell Amount";
+pub const SELL_25_PERCENT: &str = "25%";
+pub const SELL_50_PERCENT: &str = "50%";
+pub const SELL_100_PERCENT: &str = "100%";
+pub const SLIPPAGE: &str = "Slippage";
+pub const DEADLINE: &str = "Deadline";
+pub const GAS: &str = "Gas";
+pub const LIMIT_BUY: &str = "Limit Buy";
+pub const LIMIT_SELL: &str = "Limit Sell";
+pub const LIMIT_TOKEN: &str = "Token";
+pub const LIMIT_AMOUNT: &str = "Amount";
+pub const TRIGGER_PRICE: &str = "Trigger Price";
+pub const OPEN_ORDERS: &str = "Open Orders";
+pub const DCA_AMOUNT: &str = "Amount Per Leg";
+pub const NEW_DCA: &str = "New DCA";
@@ -0,0 +1,286 @@
+use crate::global::Global;
+use dotenv::dotenv;
+use parking_lot::{Mutex, MutexGuard};
+use rusqlite::Connection;
+
+/// Connection to the bot's SQLite database, see [Database::from_env]
+pub(crate) static GLOBAL_DATABASE: Global<Database> = Global::new("database");
+
+/// Schema migrations, applied in order and tracked with `PRAGMA user_version`
+/// Note: only ever append to this list, released migrations must not change
+const MIGRATIONS: &[&str] = &[
+    // 1: per-user wallets, the private key is encrypted with the master key
+    "CREATE TABLE wallets (
+        user_id INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        address TEXT NOT NULL,
+        encrypted_key BLOB NOT NULL,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+        PRIMARY KEY (user_id, slot)
+    );",
+    // 2: wallet names, per-user settings and the tokens each user has traded
+    "ALTER TABLE wallets ADD COLUMN name TEXT;
+    CREATE TABLE user_settings (
+        user_id INTEGER PRIMARY KEY,
+        default_wallet INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE traded_tokens (
+        user_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        token TEXT NOT NULL,
+        PRIMARY KEY (user_id, chain_id, token)
+    );",
+    // 3: ledger of every trade submitted by the bot, token columns are NULL for the native coin
+    "CREATE TABLE trades (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        wallet TEXT NOT NULL,
+        chain_id INTEGER NOT NULL,
+        token_in TEXT,
+        symbol_in TEXT NOT NULL,
+        decimals_in INTEGER NOT NULL,
+        token_out TEXT,
+        symbol_out TEXT NOT NULL,
+        decimals_out INTEGER NOT NULL,
+        amount_in TEXT NOT NULL,
+        amount_out TEXT,
+        tx_hash TEXT NOT NULL UNIQUE,
+        status TEXT NOT NULL,
+        gas_used TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX trades_by_user ON trades (user_id, id);",
+    // 4: dialogue states and per-session values of the SQLite storage backend, stored as JSON
+    "CREATE TABLE dialogues (
+        chat_id INTEGER PRIMARY KEY,
+        state TEXT NOT NULL
+    );
+    CREATE TABLE sessions (
+        storage TEXT NOT NULL,
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (storage, chat_id, user_id)
+    );",
+    // 5: default slippage and deadline of each user's orders, NULL until the user changes them
+    "ALTER TABLE user_settings ADD COLUMN slippage_bps INTEGER;
+    ALTER TABLE user_settings ADD COLUMN deadline_secs INTEGER;",
+    // 6: gas preset and max fee cap of each user's trades, a NULL cap lets any fee through
+    "ALTER TABLE user_settings ADD COLUMN gas_preset TEXT;
+    ALTER TABLE user_settings ADD COLUMN max_fee_gwei INTEGER;",
+    // 7: rebate accounting, amounts are in wei of the chain's native coin
+    "CREATE TABLE rebate_accruals (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        tx_hash TEXT NOT NULL UNIQUE,
+        fee TEXT NOT NULL,
+        share_bps INTEGER NOT NULL,
+        rebate TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE rebate_balances (
+        user_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        balance TEXT NOT NULL,
+        PRIMARY KEY (user_id, chain_id)
+    );
+    CREATE TABLE rebate_claims (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        recipient TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        tx_hash TEXT,
+        status TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );",
+    // 8: limit orders, prices are in native coin per token
+    "CREATE TABLE limit_orders (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        chat_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        side TEXT NOT NULL,
+        token TEXT NOT NULL,
+        amount REAL NOT NULL,
+        trigger_price REAL NOT NULL,
+        expires_at INTEGER NOT NULL,
+        wallet_slot INTEGER NOT NULL,
+        slippage_bps INTEGER NOT NULL,
+        gas_preset TEXT NOT NULL,
+        status TEXT NOT NULL,
+        tx_hash TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX limit_orders_status ON limit_orders (chain_id, status);",
+    // 9: positions opened by filled buys and their stop loss, take profit and trailing stop orders,
+    // a position's amount is the tokens it still holds, in base units, its exit orders sell a share of it
+    "CREATE TABLE positions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        chat_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        token TEXT NOT NULL,
+        wallet_slot INTEGER NOT NULL,
+        entry_price REAL NOT NULL,
+        amount TEXT NOT NULL,
+        tx_hash TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE exit_orders (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        position_id INTEGER NOT NULL REFERENCES positions (id),
+        kind TEXT NOT NULL,
+        percent INTEGER NOT NULL,
+        sell_percent INTEGER NOT NULL,
+        peak_price REAL NOT NULL,
+        slippage_bps INTEGER NOT NULL,
+        gas_preset TEXT NOT NULL,
+        status TEXT NOT NULL,
+        tx_hash TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX exit_orders_status ON exit_orders (status);",
+    // 10: DCA schedules, a NULL buy token pays with the native coin, the approval is how the legs
+    // approve the router
+    "CREATE TABLE dca_schedules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        chat_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        buy_token TEXT,
+        receive_token TEXT NOT NULL,
+        amount REAL NOT NULL,
+        interval_secs INTEGER NOT NULL,
+        runs_total INTEGER NOT NULL,
+        runs_done INTEGER NOT NULL,
+        wallet_slot INTEGER NOT NULL,
+        slippage_bps INTEGER NOT NULL,
+        gas_preset TEXT NOT NULL,
+        status TEXT NOT NULL,
+        next_run_at INTEGER NOT NULL,
+        approval TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX dca_schedules_due ON dca_schedules (chain_id, status, next_run_at);",
+    // 11: token and spender pairs whose allowance /approvals checks, per wallet
+    "CREATE TABLE approvals (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        chain_id INTEGER NOT NULL,
+        wallet_slot INTEGER NOT NULL,
+        token TEXT NOT NULL,
+        spender TEXT NOT NULL,
+        UNIQUE (user_id, chain_id, wallet_slot, token, spender)
+    );",
+    // 12: EIP-2612 permits the sponsor wallet sent for the users' wallets
+    "CREATE TABLE sponsored_permits (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chain_id INTEGER NOT NULL,
+        owner TEXT NOT NULL,
+        token TEXT NOT NULL,
+        spender TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        UNIQUE (chain_id, owner, token, spender)
+    );
+    CREATE INDEX sponsored_permits_owner ON sponsored_permits (owner, created_at);",
+];
+
+/// SQLite database holding everything that has to survive a restart
+#[derive(Debug)]
+pub(crate) struct Database {
+    connection: Mutex<Connection>,
+}
+
+impl Database {
+    /// Opens the database at `DATABASE_PATH`, defaults to `koi-bot.sqlite` in the working directory
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        dotenv().ok();
+        let path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| "koi-bot.sqlite".to_string());
+        let connection = Connection::open(&path)?;
+        log::info!("Opened database at {}", path);
+        Self::new(connection)
+    }
+
+    /// Wraps the connection and brings its schema up to date
+    pub(crate) fn new(connection: Connection) -> anyhow::Result<Self> {
+        connection.pragma_update(None, "foreign_keys", "ON")?;
+        let database = Self {
+            connection: Mutex::new(connection),
+        };
+        database.migrate()?;
+        Ok(database)
+    }
+
+    /// Locks the connection, keep the guard short lived as every handler shares it
+    pub(crate) fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock()
+    }
+
+    fn migrate(&self) -> anyhow::Result<()> {
+        let mut connection = self.connection();
+        let version: usize =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = connection.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+            log::info!("Applied database migration {}", index + 1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(database: &Database) -> usize {
+        database
+            .connection()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrates_an_empty_database_to_the_latest_version() {
+        let database = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        assert_eq!(user_version(&database), MIGRATIONS.len());
+
+        let tables: Vec<String> = database
+            .connection()
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        for table in [
+            "approvals",
+            "dca_schedules",
+            "sessions",
+            "trades",
+            "wallets",
+        ] {
+            assert!(tables.iter().any(|name| name == table), "{} missing", table);
+        }
+
+        // an up to date database is left alone
+        database.migrate().unwrap();
+        assert_eq!(user_version(&database), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn applies_only_the_missing_migrations() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+
+        // migration 1 would fail if it ran again, its table already exists
+        let database = Database::new(connection).unwrap();
+        assert_eq!(user_version(&database), MIGRATIONS.len());
+    }
+}
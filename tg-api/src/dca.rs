@@ -0,0 +1,425 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::database::GLOBAL_DATABASE;
+use crate::drafts::DcaDraft;
+use crate::handlers::submit_buy;
+use crate::keyboards::token_label;
+use crate::ledger::format_timestamp;
+use crate::requests::server::SendBuyTxRequest;
+use crate::requests::swap::{ApprovalMode, SentSwap};
+use crate::settings::{GasPreset, GLOBAL_SETTINGS_STORE};
+use crate::tracker;
+use ethers::types::Address;
+use lazy_static::lazy_static;
+use rusqlite::{params, Row};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::prelude::Requester;
+use teloxide::types::{ChatId, MessageId, UserId};
+use teloxide::Bot;
+use tokio::time::{sleep, Duration};
+
+lazy_static! {
+    /// Used to create, list, pause and run the DCA schedules of every user
+    pub(crate) static ref GLOBAL_DCA_BOOK: DcaBook = DcaBook;
+}
+
+/// How often the scheduler looks for due legs
+const SCHEDULER_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Intervals between two legs offered on the DCA menu, in seconds
+pub(crate) const INTERVAL_PRESETS_SECS: [u64; 4] =
+    [60 * 60, 4 * 60 * 60, 24 * 60 * 60, 7 * 24 * 60 * 60];
+
+pub(crate) const DEFAULT_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Numbers of legs offered on the DCA menu
+pub(crate) const RUNS_PRESETS: [u32; 4] = [5, 10, 20, 50];
+
+pub(crate) const DEFAULT_RUNS: u32 = 10;
+
+/// Formats an interval in hours or days, e.g. "4h"
+pub(crate) fn format_interval(interval_secs: u64) -> String {
+    match interval_secs % (24 * 60 * 60) {
+        0 => format!("{}d", interval_secs / (24 * 60 * 60)),
+        _ => format!("{}h", interval_secs / (60 * 60)),
+    }
+}
+
+fn now() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Lifecycle of a DCA schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DcaStatus {
+    Active,
+    Paused,
+    /// Every leg has run
+    Completed,
+    Cancelled,
+}
+
+impl DcaStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Paused => "paused",
+            Self::Completed => "completed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for DcaStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(status: &str) -> anyhow::Result<Self> {
+        match status {
+            "active" => Ok(Self::Active),
+            "paused" => Ok(Self::Paused),
+            "completed" => Ok(Self::Completed),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(anyhow::anyhow!("Unknown DCA status: {}", status)),
+        }
+    }
+}
+
+/// A DCA schedule as stored in the book, each leg buys `receive` with `amount` of `buy`
+#[derive(Debug, Clone)]
+pub(crate) struct DcaSchedule {
+    pub(crate) id: i64,
+    pub(crate) user_id: UserId,
+    /// Chat the schedule was created in, where its legs are reported
+    pub(crate) chat_id: ChatId,
+    pub(crate) chain_id: u64,
+    /// Token paid with, `None` pays with the native coin
+    pub(crate) buy: Option<Address>,
+    pub(crate) receive: Address,
+    /// Amount of the buy token paid on each leg
+    pub(crate) amount: f64,
+    pub(crate) interval_secs: u64,
+    pub(crate) runs_total: u32,
+    pub(crate) runs_done: u32,
+    pub(crate) wallet_slot: u8,
+    pub(crate) slippage_bps: u32,
+    pub(crate) gas_preset: GasPreset,
+    pub(crate) status: DcaStatus,
+    pub(crate) next_run_at: u64,
+    /// How each leg approves the router when paying with a token
+    pub(crate) approval: ApprovalMode,
+}
+
+/// Columns selected for a [DcaSchedule], in the order [DcaSchedule::from_row] reads them
+const DCA_SCHEDULE_COLUMNS: &str = "id, user_id, chat_id, chain_id, buy_token, receive_token, \
+    amount, interval_secs, runs_total, runs_done, wallet_slot, slippage_bps, gas_preset, status, \
+    next_run_at, approval";
+
+impl DcaSchedule {
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user_id: UserId(row.get(1)?),
+            chat_id: ChatId(row.get(2)?),
+            chain_id: row.get(3)?,
+            buy: row
+                .get::<_, Option<String>>(4)?
+                .map(|buy| Address::from_str(&buy))
+                .transpose()?,
+            receive: Address::from_str(&row.get::<_, String>(5)?)?,
+            amount: row.get(6)?,
+            interval_secs: row.get(7)?,
+            runs_total: row.get(8)?,
+            runs_done: row.get(9)?,
+            wallet_slot: row.get(10)?,
+            slippage_bps: row.get(11)?,
+            gas_preset: GasPreset::from_key(&row.get::<_, String>(12)?).unwrap_or_default(),
+            status: row.get::<_, String>(13)?.parse()?,
+            next_run_at: row.get(14)?,
+            approval: ApprovalMode::from_key(&row.get::<_, String>(15)?)
+                .ok_or_else(|| anyhow::anyhow!("Unknown approval mode"))?,
+        })
+    }
+
+    /// Symbol of the token paid with
+    fn buy_label(&self) -> String {
+        match self.buy {
+            Some(buy) => token_label(buy),
+            None => GLOBAL_CHAIN_REGISTRY
+                .get(self.chain_id)
+                .map_or_else(String::new, |chain| chain.native_symbol.clone()),
+        }
+    }
+
+    /// One entry of the /dca menu
+    pub(crate) fn summary(&self) -> String {
+        let progress = match self.status {
+            DcaStatus::Active => format!("next {}", format_timestamp(self.next_run_at)),
+            DcaStatus::Paused => "paused".to_string(),
+            DcaStatus::Completed => "completed".to_string(),
+            DcaStatus::Cancelled => "cancelled".to_string(),
+        };
+        format!(
+            "DCA #{} {} {} → {} every {}\n{}/{} runs, {}",
+            self.id,
+            self.amount,
+            self.buy_label(),
+            token_label(self.receive),
+            format_interval(self.interval_secs),
+            self.runs_done,
+            self.runs_total,
+            progress
+        )
+    }
+}
+
+/// Persistent book of the DCA schedules, their legs are run by [run_dca_scheduler]
+#[derive(Debug)]
+pub(crate) struct DcaBook;
+
+impl DcaBook {
+    /// Creates an active schedule from a complete draft, its first leg runs right away. Returns its id
+    pub(crate) fn create(
+        &self,
+        user_id: UserId,
+        chat_id: ChatId,
+        draft: &DcaDraft,
+    ) -> anyhow::Result<i64> {
+        let receive = draft
+            .receive_token
+            .ok_or_else(|| anyhow::anyhow!("No receive token address found"))?;
+        let amount = draft
+            .amount
+            .ok_or_else(|| anyhow::anyhow!("No amount found"))?;
+        if draft.buy_token == Some(receive) {
+            return Err(anyhow::anyhow!("Buy and receive tokens must differ"));
+        }
+        let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+        let now = now()?;
+
+        let connection = GLOBAL_DATABASE.connection();
+        connection.execute(
+            "INSERT INTO dca_schedules (user_id, chat_id, chain_id, buy_token, receive_token, \
+             amount, interval_secs, runs_total, runs_done, wallet_slot, slippage_bps, gas_preset, \
+             status, next_run_at, created_at, approval) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, ?9, ?10, ?11, ?12, ?13, ?13, ?14)",
+            params![
+                user_id.0,
+                chat_id.0,
+                GLOBAL_CHAIN_REGISTRY.trading_chain().chain_id,
+                draft.buy_token.map(|buy| format!("{:?}", buy)),
+                format!("{:?}", receive),
+                amount,
+                draft.interval_secs,
+                draft.runs,
+                draft.wallet_slot,
+                settings.slippage_bps,
+                settings.gas_preset.key(),
+                DcaStatus::Active.as_str(),
+                now,
+                draft.approval.key(),
+            ],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    fn query(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params,
+    ) -> anyhow::Result<Vec<DcaSchedule>> {
+        let connection = GLOBAL_DATABASE.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM dca_schedules WHERE {} ORDER BY id",
+            DCA_SCHEDULE_COLUMNS, filter
+        ))?;
+        let mut rows = statement.query(params)?;
+        let mut schedules = Vec::new();
+        while let Some(row) = rows.next()? {
+            schedules.push(DcaSchedule::from_row(row)?);
+        }
+        Ok(schedules)
+    }
+
+    /// Active and paused schedules of the user, oldest first
+    pub(crate) fn schedules(&self, user_id: UserId) -> anyhow::Result<Vec<DcaSchedule>> {
+        self.query(
+            "user_id = ?1 AND status IN (?2, ?3)",
+            params![
+                user_id.0,
+                DcaStatus::Active.as_str(),
+                DcaStatus::Paused.as_str()
+            ],
+        )
+    }
+
+    /// Active schedules of every user on the chain whose next leg is due
+    fn due(&self, chain_id: u64, now: u64) -> anyhow::Result<Vec<DcaSchedule>> {
+        self.query(
+            "chain_id = ?1 AND status = ?2 AND next_run_at <= ?3",
+            params![chain_id, DcaStatus::Active.as_str(), now],
+        )
+    }
+
+    /// Counts the next leg of the schedule as run before it is sent, so that a restart never sends
+    /// it twice, and plans the one after. `false` if the schedule was paused, cancelled or
+    /// claimed since it was read
+    fn claim_leg(&self, schedule: &DcaSchedule, now: u64) -> anyhow::Result<bool> {
+        let status = match schedule.runs_done + 1 >= schedule.runs_total {
+            true => DcaStatus::Completed,
+            false => DcaStatus::Active,
+        };
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE dca_schedules SET runs_done = runs_done + 1, next_run_at = ?1, status = ?2 \
+             WHERE id = ?3 AND status = ?4 AND next_run_at = ?5",
+            params![
+                now + schedule.interval_secs,
+                status.as_str(),
+                schedule.id,
+                DcaStatus::Active.as_str(),
+                schedule.next_run_at
+            ],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Pauses an active schedule, no leg runs until it is resumed
+    pub(crate) fn pause(&self, user_id: UserId, id: i64) -> anyhow::Result<()> {
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE dca_schedules SET status = ?1 WHERE id = ?2 AND user_id = ?3 AND status = ?4",
+            params![
+                DcaStatus::Paused.as_str(),
+                id,
+                user_id.0,
+                DcaStatus::Active.as_str()
+            ],
+        )?;
+        match updated {
+            1 => Ok(()),
+            _ => Err(anyhow::anyhow!("DCA #{} is no longer active", id)),
+        }
+    }
+
+    /// Resumes a paused schedule, a leg missed while it was paused runs right away
+    pub(crate) fn resume(&self, user_id: UserId, id: i64) -> anyhow::Result<()> {
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE dca_schedules SET status = ?1, next_run_at = MAX(next_run_at, ?2) \
+             WHERE id = ?3 AND user_id = ?4 AND status = ?5",
+            params![
+                DcaStatus::Active.as_str(),
+                now()?,
+                id,
+                user_id.0,
+                DcaStatus::Paused.as_str()
+            ],
+        )?;
+        match updated {
+            1 => Ok(()),
+            _ => Err(anyhow::anyhow!("DCA #{} is no longer paused", id)),
+        }
+    }
+
+    /// Cancels one of the user's active or paused schedules
+    pub(crate) fn cancel(&self, user_id: UserId, id: i64) -> anyhow::Result<()> {
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE dca_schedules SET status = ?1 WHERE id = ?2 AND user_id = ?3 AND status IN (?4, ?5)",
+            params![
+                DcaStatus::Cancelled.as_str(),
+                id,
+                user_id.0,
+                DcaStatus::Active.as_str(),
+                DcaStatus::Paused.as_str()
+            ],
+        )?;
+        match updated {
+            1 => Ok(()),
+            _ => Err(anyhow::anyhow!("DCA #{} has already ended", id)),
+        }
+    }
+}
+
+/// Sends one leg of the schedule through the same pipeline as the buys of the buy menu
+async fn execute(schedule: &DcaSchedule) -> anyhow::Result<SentSwap> {
+    let settings = GLOBAL_SETTINGS_STORE.get(schedule.user_id)?;
+    let request = SendBuyTxRequest {
+        wallet_slot: schedule.wallet_slot,
+        private_tx: false,
+        rebate: false,
+        buy: schedule.buy,
+        receive: schedule.receive,
+        buy_amount: schedule.amount,
+        slippage_bps: schedule.slippage_bps,
+        deadline_secs: settings.deadline_secs,
+        gas_preset: schedule.gas_preset,
+        max_fee_gwei: settings.max_fee_gwei,
+        approval: Some(schedule.approval),
+    };
+    submit_buy(schedule.user_id, &request, false).await
+}
+
+async fn notify(bot: &Bot, schedule: &DcaSchedule, text: String) -> Option<MessageId> {
+    match bot.send_message(schedule.chat_id, text).await {
+        Ok(message) => Some(message.id),
+        Err(err) => {
+            log::warn!("DCA #{} update not sent: {}", schedule.id, err);
+            None
+        }
+    }
+}
+
+/// Sends one leg of the schedule and follows it until it is mined
+async fn run_leg(bot: Bot, schedule: DcaSchedule) {
+    let leg = schedule.runs_done + 1;
+    match execute(&schedule).await {
+        Ok(sent) => {
+            let text = format!(
+                "DCA #{} leg {}/{}\n⏳ Tx Sent\n{}",
+                schedule.id,
+                leg,
+                schedule.runs_total,
+                GLOBAL_CHAIN_REGISTRY.trading_chain().tx_url(sent.tx_hash)
+            );
+            let message = notify(&bot, &schedule, text).await;
+            // the outcome is shown to the user, the next leg runs either way
+            let _ = tracker::track_trade(&bot, schedule.chat_id, &sent, message).await;
+        }
+        Err(err) => {
+            log::warn!("DCA #{} leg {} failed: {}", schedule.id, leg, err);
+            let text = format!(
+                "DCA #{} leg {}/{} failed, the schedule moves on to the next leg\n{}",
+                schedule.id, leg, schedule.runs_total, err
+            );
+            notify(&bot, &schedule, text).await;
+        }
+    }
+    if leg >= schedule.runs_total {
+        notify(&bot, &schedule, format!("DCA #{} completed", schedule.id)).await;
+    }
+}
+
+/// Runs the legs that are due on the trading chain, each on its own task so that a slow leg
+/// doesn't hold back the others
+async fn run_due_legs(bot: &Bot) -> anyhow::Result<()> {
+    let now = now()?;
+    let schedules = GLOBAL_DCA_BOOK.due(GLOBAL_CHAIN_REGISTRY.trading_chain().chain_id, now)?;
+    for schedule in schedules {
+        match GLOBAL_DCA_BOOK.claim_leg(&schedule, now) {
+            Ok(true) => {
+                tokio::spawn(run_leg(bot.clone(), schedule));
+            }
+            Ok(false) => {}
+            Err(err) => log::error!("DCA #{} leg not claimed: {}", schedule.id, err),
+        }
+    }
+    Ok(())
+}
+
+/// Background task running the legs of the DCA schedules as they fall due, schedules are read
+/// from the database on every pass so they carry on after a restart
+pub(crate) async fn run_dca_scheduler(bot: Bot) {
+    loop {
+        if let Err(err) = run_due_legs(&bot).await {
+            log::warn!("DCA legs not run: {}", err);
+        }
+        sleep(SCHEDULER_POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,183 @@
+use crate::chains::ChainConfig;
+use crate::dca::{DEFAULT_INTERVAL_SECS, DEFAULT_RUNS};
+use crate::limit_orders::{LimitSide, DEFAULT_EXPIRY_SECS};
+use crate::requests::quote::BuyQuote;
+use crate::requests::swap::ApprovalMode;
+use crate::requests::token_safety::TokenRisk;
+use crate::settings::{GasPreset, UserSettings, DEFAULT_DEADLINE_SECS, DEFAULT_SLIPPAGE_BPS};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+fn default_slippage_bps() -> u32 {
+    DEFAULT_SLIPPAGE_BPS
+}
+
+fn default_deadline_secs() -> u64 {
+    DEFAULT_DEADLINE_SECS
+}
+
+/// Amount to sell, either a share of the wallet balance or an exact amount
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum SellAmount {
+    Percent(u8),
+    Exact(f64),
+    /// Exact amount in the token's base units, capped to the wallet balance
+    BaseUnits(U256),
+}
+
+/// The buy order a user is putting together on the buy menu, the menu is rendered from it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BuyOrderDraft {
+    pub(crate) wallet_slot: u8,
+    pub(crate) private_tx: bool,
+    pub(crate) rebate: bool,
+    /// Starts at the user's default, see [UserSettings]
+    #[serde(default = "default_slippage_bps")]
+    pub(crate) slippage_bps: u32,
+    #[serde(default = "default_deadline_secs")]
+    pub(crate) deadline_secs: u64,
+    #[serde(default)]
+    pub(crate) gas_preset: GasPreset,
+    /// Token paid with, `None` pays with the native coin
+    pub(crate) buy_token: Option<Address>,
+    pub(crate) receive_token: Option<Address>,
+    /// Amount of the buy token to pay
+    pub(crate) buy_amount: Option<f64>,
+    /// Last quote of the order, stale once the tokens or amount change
+    #[serde(default)]
+    pub(crate) quote: Option<BuyQuote>,
+    /// Outcome of the honeypot and tax check of the receive token
+    #[serde(default)]
+    pub(crate) risk: Option<TokenRisk>,
+}
+
+impl BuyOrderDraft {
+    /// Trades privately by default on chains with private relays
+    pub(crate) fn new(wallet_slot: u8, settings: &UserSettings, chain: &ChainConfig) -> Self {
+        Self {
+            wallet_slot,
+            private_tx: chain.has_private_relays(),
+            rebate: false,
+            slippage_bps: settings.slippage_bps,
+            deadline_secs: settings.deadline_secs,
+            gas_preset: settings.gas_preset,
+            buy_token: None,
+            receive_token: None,
+            buy_amount: None,
+            quote: None,
+            risk: None,
+        }
+    }
+
+    /// Tokens and amount to quote, `None` until the receive token and amount are entered
+    pub(crate) fn quote_inputs(&self) -> Option<(Option<Address>, Address, f64)> {
+        Some((self.buy_token, self.receive_token?, self.buy_amount?))
+    }
+
+    /// The quote, if it is still for the tokens and amount of the order
+    pub(crate) fn current_quote(&self) -> Option<&BuyQuote> {
+        self.quote
+            .as_ref()
+            .filter(|quote| quote.is_for(self.buy_token, self.receive_token, self.buy_amount))
+    }
+
+    /// The risk check, if it is still for the receive token of the order
+    pub(crate) fn current_risk(&self) -> Option<&TokenRisk> {
+        self.risk
+            .as_ref()
+            .filter(|risk| Some(risk.token) == self.receive_token)
+    }
+}
+
+/// The sell order a user is putting together on the sell menu, the menu is rendered from it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SellOrderDraft {
+    pub(crate) wallet_slot: u8,
+    pub(crate) private_tx: bool,
+    pub(crate) rebate: bool,
+    /// Starts at the user's default, see [UserSettings]
+    #[serde(default = "default_slippage_bps")]
+    pub(crate) slippage_bps: u32,
+    #[serde(default = "default_deadline_secs")]
+    pub(crate) deadline_secs: u64,
+    #[serde(default)]
+    pub(crate) gas_preset: GasPreset,
+    pub(crate) sell_token: Option<Address>,
+    pub(crate) amount: SellAmount,
+}
+
+impl SellOrderDraft {
+    /// Trades privately by default on chains with private relays
+    pub(crate) fn new(wallet_slot: u8, settings: &UserSettings, chain: &ChainConfig) -> Self {
+        Self {
+            wallet_slot,
+            private_tx: chain.has_private_relays(),
+            rebate: false,
+            slippage_bps: settings.slippage_bps,
+            deadline_secs: settings.deadline_secs,
+            gas_preset: settings.gas_preset,
+            sell_token: None,
+            amount: SellAmount::Percent(100),
+        }
+    }
+}
+
+/// The limit order a user is putting together on the Limit Buy or Limit Sell menu
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LimitOrderDraft {
+    pub(crate) side: LimitSide,
+    pub(crate) wallet_slot: u8,
+    pub(crate) token: Option<Address>,
+    /// Native coin to spend on a buy, tokens to sell on a sell
+    pub(crate) amount: Option<f64>,
+    /// Price in native coin per token the order triggers at
+    pub(crate) trigger_price: Option<f64>,
+    pub(crate) expiry_secs: u64,
+}
+
+impl LimitOrderDraft {
+    pub(crate) fn new(side: LimitSide, wallet_slot: u8) -> Self {
+        Self {
+            side,
+            wallet_slot,
+            token: None,
+            amount: None,
+            trigger_price: None,
+            expiry_secs: DEFAULT_EXPIRY_SECS,
+        }
+    }
+}
+
+/// The DCA schedule a user is putting together on the DCA menu
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DcaDraft {
+    pub(crate) wallet_slot: u8,
+    /// Token paid with, `None` pays with the native coin
+    pub(crate) buy_token: Option<Address>,
+    pub(crate) receive_token: Option<Address>,
+    /// Amount of the buy token paid on each leg
+    pub(crate) amount: Option<f64>,
+    pub(crate) interval_secs: u64,
+    pub(crate) runs: u32,
+    /// How each leg approves the router when paying with a token, picked when the schedule is created
+    #[serde(default = "default_dca_approval")]
+    pub(crate) approval: ApprovalMode,
+}
+
+fn default_dca_approval() -> ApprovalMode {
+    ApprovalMode::Exact
+}
+
+impl DcaDraft {
+    pub(crate) fn new(wallet_slot: u8) -> Self {
+        Self {
+            wallet_slot,
+            buy_token: None,
+            receive_token: None,
+            amount: None,
+            interval_secs: DEFAULT_INTERVAL_SECS,
+            runs: DEFAULT_RUNS,
+            approval: ApprovalMode::Exact,
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use crate::chains::ChainConfig;
+use crate::database::GLOBAL_DATABASE;
+use crate::drafts::SellAmount;
+use crate::handlers::{resume_order_swap, submit_sell};
+use crate::keyboards::token_label;
+use crate::limit_orders::{format_price, PriceCache};
+use crate::requests::quote::{self, to_f64};
+use crate::requests::server::SendSellTxRequest;
+use crate::requests::swap::{self, ApprovalMode, SentSwap};
+use crate::settings::{GasPreset, GLOBAL_SETTINGS_STORE};
+use crate::tracker;
+use crate::wallets::GLOBAL_WALLET_STORE;
+use ethers::types::{Address, TransactionReceipt, TxHash, U256};
+use lazy_static::lazy_static;
+use rusqlite::{params, OptionalExtension, Row};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::prelude::Requester;
+use teloxide::types::{ChatId, MessageId, UserId};
+use teloxide::Bot;
+
+lazy_static! {
+    /// Used to protect filled buys with stop loss, take profit and trailing stop orders
+    pub(crate) static ref GLOBAL_EXIT_ORDER_BOOK: ExitOrderBook = ExitOrderBook;
+}
+
+/// Stop losses offered after a buy, in percent below the entry price
+pub(crate) const STOP_LOSS_PRESETS: [u32; 3] = [10, 25, 50];
+
+/// Take profits offered after a buy, as the gain above the entry price and the share of the balance sold, in percent
+pub(crate) const TAKE_PROFIT_PRESETS: [(u32, u8); 4] =
+    [(50, 100), (100, 50), (100, 100), (200, 100)];
+
+/// Trailing stops offered after a buy, in percent below the highest price since the buy
+pub(crate) const TRAILING_STOP_PRESETS: [u32; 3] = [10, 20, 30];
+
+fn now() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// How an exit order triggers, `percent` is relative to the entry price or, for a trailing stop, to the peak price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExitKind {
+    StopLoss,
+    TakeProfit,
+    TrailingStop,
+}
+
+impl ExitKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::StopLoss => "stop_loss",
+            Self::TakeProfit => "take_profit",
+            Self::TrailingStop => "trailing_stop",
+        }
+    }
+}
+
+impl FromStr for ExitKind {
+    type Err = anyhow::Error;
+
+    fn from_str(kind: &str) -> anyhow::Result<Self> {
+        match kind {
+            "stop_loss" => Ok(Self::StopLoss),
+            "take_profit" => Ok(Self::TakeProfit),
+            "trailing_stop" => Ok(Self::TrailingStop),
+            _ => Err(anyhow::anyhow!("Unknown exit order kind: {}", kind)),
+        }
+    }
+}
+
+impl fmt::Display for ExitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StopLoss => write!(f, "Stop Loss"),
+            Self::TakeProfit => write!(f, "Take Profit"),
+            Self::TrailingStop => write!(f, "Trailing Stop"),
+        }
+    }
+}
+
+/// Lifecycle of an exit order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitOrderStatus {
+    Open,
+    /// Triggered, its sell is being sent or waits to be mined
+    Executing,
+    /// Its sell was mined and succeeded
+    Filled,
+    /// Its sell could not be sent or its outcome is unknown
+    Failed,
+    Cancelled,
+}
+
+impl ExitOrderStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Executing => "executing",
+            Self::Filled => "filled",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// A filled buy whose tokens exit orders can sell
+#[derive(Debug, Clone)]
+pub(crate) struct Position {
+    pub(crate) id: i64,
+    /// Price paid per token, in native coin
+    pub(crate) entry_price: f64,
+}
+
+/// A stop loss, take profit or trailing stop of a position
+#[derive(Debug, Clone)]
+pub(crate) struct ExitOrder {
+    pub(crate) id: i64,
+    pub(crate) position_id: i64,
+    user_id: UserId,
+    chat_id: ChatId,
+    pub(crate) token: Address,
+    wallet_slot: u8,
+    pub(crate) kind: ExitKind,
+    pub(crate) percent: u32,
+    /// Share of the position sold when the order triggers
+    pub(crate) sell_percent: u8,
+    /// Tokens the position still holds
+    position_amount: U256,
+    pub(crate) entry_price: f64,
+    /// Highest price seen since the order was placed, what a trailing stop trails
+    peak_price: f64,
+    slippage_bps: u32,
+    gas_preset: GasPreset,
+}
+
+/// Columns selected for an [ExitOrder], in the order [ExitOrder::from_row] reads them
+const EXIT_ORDER_COLUMNS: &str = "e.id, e.position_id, p.user_id, p.chat_id, p.token, \
+    p.wallet_slot, e.kind, e.percent, e.sell_percent, p.entry_price, e.peak_price, \
+    e.slippage_bps, e.gas_preset, p.amount";
+
+impl ExitOrder {
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            position_id: row.get(1)?,
+            user_id: UserId(row.get(2)?),
+            chat_id: ChatId(row.get(3)?),
+            token: Address::from_str(&row.get::<_, String>(4)?)?,
+            wallet_slot: row.get(5)?,
+            kind: row.get::<_, String>(6)?.parse()?,
+            percent: row.get(7)?,
+            sell_percent: row.get(8)?,
+            entry_price: row.get(9)?,
+            peak_price: row.get(10)?,
+            slippage_bps: row.get(11)?,
+            gas_preset: GasPreset::from_key(&row.get::<_, String>(12)?).unwrap_or_default(),
+            position_amount: U256::from_dec_str(&row.get::<_, String>(13)?)?,
+        })
+    }
+
+    /// What the order sells, its share of the position
+    fn sell_amount(&self) -> SellAmount {
+        SellAmount::BaseUnits(
+            self.position_amount * U256::from(self.sell_percent) / U256::from(100u64),
+        )
+    }
+
+    /// Price at or beyond which the order sells
+    pub(crate) fn trigger_price(&self) -> f64 {
+        let percent = self.percent as f64 / 100.0;
+        match self.kind {
+            ExitKind::StopLoss => self.entry_price * (1.0 - percent),
+            ExitKind::TakeProfit => self.entry_price * (1.0 + percent),
+            ExitKind::TrailingStop => self.peak_price * (1.0 - percent),
+        }
+    }
+
+    fn is_triggered(&self, price: f64) -> bool {
+        match self.kind {
+            ExitKind::StopLoss | ExitKind::TrailingStop => price <= self.trigger_price(),
+            ExitKind::TakeProfit => price >= self.trigger_price(),
+        }
+    }
+
+    /// One line of the orders menu
+    pub(crate) fn summary(&self) -> String {
+        let condition = match self.kind {
+            ExitKind::StopLoss => format!("-{}%", self.percent),
+            ExitKind::TakeProfit => format!("+{}%", self.percent),
+            ExitKind::TrailingStop => format!("{}% below the peak", self.percent),
+        };
+        format!(
+            "{} #{} {}: sell {}% at {}, trigger {}",
+            self.kind,
+            self.id,
+            token_label(self.token),
+            self.sell_percent,
+            condition,
+            format_price(self.trigger_price())
+        )
+    }
+}
+
+/// Persistent book of the positions opened by filled buys and of their exit orders
+#[derive(Debug)]
+pub(crate) struct ExitOrderBook;
+
+impl ExitOrderBook {
+    /// Records the position of a mined buy and the `amount` of tokens it received, its exit orders
+    /// sell from the wallet that bought
+    pub(crate) fn open_position(
+        &self,
+        user_id: UserId,
+        chat_id: ChatId,
+        sent: &SentSwap,
+        entry_price: f64,
+        amount: U256,
+    ) -> anyhow::Result<i64> {
+        let token = sent
+            .token_out
+            .address
+            .ok_or_else(|| anyhow::anyhow!("Only a buy opens a position"))?;
+        let wallet_slot = GLOBAL_WALLET_STORE
+            .wallets(user_id)?
+            .iter()
+            .find(|wallet| wallet.address == sent.wallet)
+            .map(|wallet| wallet.slot)
+            .ok_or_else(|| anyhow::anyhow!("Wallet not found"))?;
+
+        let connection = GLOBAL_DATABASE.connection();
+        connection.execute(
+            "INSERT INTO positions (user_id, chat_id, chain_id, token, wallet_slot, entry_price, \
+             amount, tx_hash, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                user_id.0,
+                chat_id.0,
+                sent.chain_id,
+                format!("{:?}", token),
+                wallet_slot,
+                entry_price,
+                amount.to_string(),
+                format!("{:?}", sent.tx_hash),
+                now()?,
+            ],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Gets one of the user's positions
+    pub(crate) fn position(&self, user_id: UserId, id: i64) -> anyhow::Result<Option<Position>> {
+        Ok(GLOBAL_DATABASE
+            .connection()
+            .query_row(
+                "SELECT id, entry_price FROM positions WHERE id = ?1 AND user_id = ?2",
+                params![id, user_id.0],
+                |row| {
+                    Ok(Position {
+                        id: row.get(0)?,
+                        entry_price: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Places an exit order on one of the user's positions, returns its id
+    pub(crate) fn place(
+        &self,
+        user_id: UserId,
+        position: &Position,
+        kind: ExitKind,
+        percent: u32,
+        sell_percent: u8,
+    ) -> anyhow::Result<i64> {
+        let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+        let connection = GLOBAL_DATABASE.connection();
+        connection.execute(
+            "INSERT INTO exit_orders (position_id, kind, percent, sell_percent, peak_price, \
+             slippage_bps, gas_preset, status, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                position.id,
+                kind.as_str(),
+                percent,
+                sell_percent,
+                position.entry_price,
+                settings.slippage_bps,
+                settings.gas_preset.key(),
+                ExitOrderStatus::Open.as_str(),
+                now()?,
+            ],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    fn query(&self, filter: &str, params: impl rusqlite::Params) -> anyhow::Result<Vec<ExitOrder>> {
+        let connection = GLOBAL_DATABASE.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM exit_orders e JOIN positions p ON p.id = e.position_id \
+             WHERE {} ORDER BY e.id",
+            EXIT_ORDER_COLUMNS, filter
+        ))?;
+        let mut rows = statement.query(params)?;
+        let mut orders = Vec::new();
+        while let Some(row) = rows.next()? {
+            orders.push(ExitOrder::from_row(row)?);
+        }
+        Ok(orders)
+    }
+
+    /// Open exit orders of the user, oldest first
+    pub(crate) fn open_orders(&self, user_id: UserId) -> anyhow::Result<Vec<ExitOrder>> {
+        self.query(
+            "p.user_id = ?1 AND e.status = ?2",
+            params![user_id.0, ExitOrderStatus::Open.as_str()],
+        )
+    }
+
+    /// Open exit orders of one of the user's positions
+    pub(crate) fn position_orders(
+        &self,
+        user_id: UserId,
+        position_id: i64,
+    ) -> anyhow::Result<Vec<ExitOrder>> {
+        self.query(
+            "p.user_id = ?1 AND e.position_id = ?2 AND e.status = ?3",
+            params![user_id.0, position_id, ExitOrderStatus::Open.as_str()],
+        )
+    }
+
+    /// Open exit orders of every user on the chain
+    fn open_orders_on(&self, chain_id: u64) -> anyhow::Result<Vec<ExitOrder>> {
+        self.query(
+            "p.chain_id = ?1 AND e.status = ?2",
+            params![chain_id, ExitOrderStatus::Open.as_str()],
+        )
+    }
+
+    /// Moves the order from one status to another, `false` if it wasn't in the `from` status anymore
+    fn transition(
+        &self,
+        id: i64,
+        from: ExitOrderStatus,
+        to: ExitOrderStatus,
+        tx_hash: Option<TxHash>,
+    ) -> anyhow::Result<bool> {
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE exit_orders SET status = ?1, tx_hash = ?2 WHERE id = ?3 AND status = ?4",
+            params![
+                to.as_str(),
+                tx_hash.map(|tx_hash| format!("{:?}", tx_hash)),
+                id,
+                from.as_str()
+            ],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Cancels one of the user's open exit orders
+    pub(crate) fn cancel(&self, user_id: UserId, id: i64) -> anyhow::Result<()> {
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE exit_orders SET status = ?1 WHERE id = ?2 AND status = ?3 \
+             AND position_id IN (SELECT id FROM positions WHERE user_id = ?4)",
+            params![
+                ExitOrderStatus::Cancelled.as_str(),
+                id,
+                ExitOrderStatus::Open.as_str(),
+                user_id.0
+            ],
+        )?;
+        match updated {
+            1 => Ok(()),
+            _ => Err(anyhow::anyhow!("Order #{} is no longer open", id)),
+        }
+    }
+
+    /// Takes the tokens a mined sell sold off the position, returns what the position still holds
+    fn reduce_position(&self, position_id: i64, sold: U256) -> anyhow::Result<U256> {
+        let mut connection = GLOBAL_DATABASE.connection();
+        let tx = connection.transaction()?;
+        let amount: String = tx.query_row(
+            "SELECT amount FROM positions WHERE id = ?1",
+            params![position_id],
+            |row| row.get(0),
+        )?;
+        let remaining = U256::from_dec_str(&amount)?.saturating_sub(sold);
+        tx.execute(
+            "UPDATE positions SET amount = ?1 WHERE id = ?2",
+            params![remaining.to_string(), position_id],
+        )?;
+        tx.commit()?;
+        Ok(remaining)
+    }
+
+    /// Cancels the open exit orders left on a position once it has been sold in full
+    fn close_position(&self, position_id: i64) -> anyhow::Result<usize> {
+        Ok(GLOBAL_DATABASE.connection().execute(
+            "UPDATE exit_orders SET status = ?1 WHERE position_id = ?2 AND status = ?3",
+            params![
+                ExitOrderStatus::Cancelled.as_str(),
+                position_id,
+                ExitOrderStatus::Open.as_str()
+            ],
+        )?)
+    }
+
+    /// Records a new highest price for a trailing stop
+    fn raise_peak(&self, id: i64, peak_price: f64) -> anyhow::Result<()> {
+        GLOBAL_DATABASE.connection().execute(
+            "UPDATE exit_orders SET peak_price = ?1 WHERE id = ?2 AND peak_price < ?1",
+            params![peak_price, id],
+        )?;
+        Ok(())
+    }
+
+    /// Orders whose sell was being sent or followed when the bot stopped, with the hash of the sell
+    /// once it was sent
+    fn interrupted(&self) -> anyhow::Result<Vec<(ExitOrder, Option<TxHash>)>> {
+        let connection = GLOBAL_DATABASE.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {}, e.tx_hash FROM exit_orders e JOIN positions p ON p.id = e.position_id \
+             WHERE e.status = ?1 ORDER BY e.id",
+            EXIT_ORDER_COLUMNS
+        ))?;
+        let mut rows = statement.query(params![ExitOrderStatus::Executing.as_str()])?;
+        let mut orders = Vec::new();
+        while let Some(row) = rows.next()? {
+            let tx_hash = row
+                .get::<_, Option<String>>(14)?
+                .map(|tx_hash| TxHash::from_str(&tx_hash))
+                .transpose()?;
+            orders.push((ExitOrder::from_row(row)?, tx_hash));
+        }
+        Ok(orders)
+    }
+}
+
+/// Price paid per token by a mined buy, in native coin
+///
+/// A buy paid with the native coin is priced from the tokens it received, one paid with another token at the pool price
+pub(crate) async fn entry_price(
+    chain: &ChainConfig,
+    sent: &SentSwap,
+    receipt: &TransactionReceipt,
+) -> anyhow::Result<f64> {
+    let token = sent
+        .token_out
+        .address
+        .ok_or_else(|| anyhow::anyhow!("Only a buy has an entry price"))?;
+    if sent.token_in.address.is_some() {
+        return quote::token_price(chain, token).await;
+    }
+
+    let received = swap::amount_received(sent, receipt);
+    if received.is_zero() {
+        return quote::token_price(chain, token).await;
+    }
+    Ok(to_f64(sent.amount_in)
+        / 1e18
+        / (to_f64(received) / 10f64.powi(sent.token_out.decimals as i32)))
+}
+
+/// Sends the sell of a triggered exit order with the position's wallet
+async fn execute(order: &ExitOrder) -> anyhow::Result<SentSwap> {
+    let settings = GLOBAL_SETTINGS_STORE.get(order.user_id)?;
+    let request = SendSellTxRequest {
+        wallet_slot: order.wallet_slot,
+        private_tx: false,
+        rebate: false,
+        sell: order.token,
+        amount: order.sell_amount(),
+        slippage_bps: order.slippage_bps,
+        deadline_secs: settings.deadline_secs,
+        gas_preset: order.gas_preset,
+        max_fee_gwei: settings.max_fee_gwei,
+        approval: Some(ApprovalMode::Exact),
+    };
+    submit_sell(order.user_id, &request).await
+}
+
+async fn notify(bot: &Bot, order: &ExitOrder, text: String) -> Option<MessageId> {
+    match bot.send_message(order.chat_id, text).await {
+        Ok(message) => Some(message.id),
+        Err(err) => {
+            log::warn!("exit order #{} update not sent: {}", order.id, err);
+            None
+        }
+    }
+}
+
+/// Fills the order once its sell is mined and takes what it sold off the position, the position
+/// is closed once sold in full. A sell that reverts or is dropped puts the order back on the book,
+/// one whose outcome is unknown fails the order so it is not sold twice
+async fn settle(bot: Bot, order: ExitOrder, sent: SentSwap, message: Option<MessageId>) {
+    let book = &GLOBAL_EXIT_ORDER_BOOK;
+    let (status, text) = match tracker::track_trade(&bot, order.chat_id, &sent, message).await {
+        Ok(Some(receipt)) if receipt.status == Some(1u64.into()) => (ExitOrderStatus::Filled, None),
+        Ok(_) => (
+            ExitOrderStatus::Open,
+            Some(format!(
+                "{} #{} did not sell, it is active again",
+                order.kind, order.id
+            )),
+        ),
+        Err(_) => (
+            ExitOrderStatus::Failed,
+            Some(format!(
+                "{} #{} is closed, its sell could not be followed. Check the tx before placing it again",
+                order.kind, order.id
+            )),
+        ),
+    };
+    let settled = async {
+        book.transition(
+            order.id,
+            ExitOrderStatus::Executing,
+            status,
+            Some(sent.tx_hash),
+        )?;
+        if status != ExitOrderStatus::Filled {
+            return anyhow::Ok(text);
+        }
+        let remaining = book.reduce_position(order.position_id, sent.amount_in)?;
+        let sold_out = order.sell_percent == 100 || remaining.is_zero();
+        if sold_out && book.close_position(order.position_id)? > 0 {
+            return Ok(Some(format!(
+                "{} #{} sold the position, its other exit orders are cancelled",
+                order.kind, order.id
+            )));
+        }
+        Ok(None)
+    };
+    match settled.await {
+        Ok(Some(text)) => {
+            notify(&bot, &order, text).await;
+        }
+        Ok(None) => {}
+        Err(err) => log::error!("exit order #{} not settled: {}", order.id, err),
+    }
+}
+
+/// Settles the orders interrupted by a restart. One whose sell was sent is followed again until it is
+/// mined or dropped, like [settle] does. Whether the sell of the others went out is unknown,
+/// they fail so the position is not sold twice
+pub(crate) async fn resume_interrupted(bot: &Bot) -> anyhow::Result<()> {
+    let book = &GLOBAL_EXIT_ORDER_BOOK;
+    for (order, tx_hash) in book.interrupted()? {
+        let resumed = match tx_hash {
+            Some(tx_hash) => resume_order_swap(tx_hash).await,
+            None => Err(anyhow::anyhow!("its sell was not sent")),
+        };
+        match resumed {
+            Ok(sent) => {
+                log::info!("following exit order #{} again", order.id);
+                tokio::spawn(settle(bot.clone(), order, sent, None));
+            }
+            Err(err) => {
+                log::warn!("interrupted exit order #{} failed: {}", order.id, err);
+                book.transition(
+                    order.id,
+                    ExitOrderStatus::Executing,
+                    ExitOrderStatus::Failed,
+                    tx_hash,
+                )?;
+                let text = format!(
+                    "{} #{} was interrupted by a restart, it is closed. Check your wallet before placing it again",
+                    order.kind, order.id
+                );
+                notify(bot, &order, text).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the open exit orders of the chain against the pool prices, trailing stops follow the price up
+pub(crate) async fn check_exit_orders(
+    bot: &Bot,
+    chain: &ChainConfig,
+    prices: &mut PriceCache<'_>,
+) -> anyhow::Result<()> {
+    let book = &GLOBAL_EXIT_ORDER_BOOK;
+    for mut order in book.open_orders_on(chain.chain_id)? {
+        let Some(price) = prices.get(order.token).await else {
+            continue;
+        };
+        if order.kind == ExitKind::TrailingStop && price > order.peak_price {
+            if let Err(err) = book.raise_peak(order.id, price) {
+                log::error!("peak of exit order #{} not raised: {}", order.id, err);
+                continue;
+            }
+            order.peak_price = price;
+        }
+        if !order.is_triggered(price) {
+            continue;
+        }
+        // the order may have been cancelled since it was read
+        match book.transition(
+            order.id,
+            ExitOrderStatus::Open,
+            ExitOrderStatus::Executing,
+            None,
+        ) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                log::error!("exit order #{} not triggered: {}", order.id, err);
+                continue;
+            }
+        }
+
+        log::info!("{} #{} triggered at {}", order.kind, order.id, price);
+        match execute(&order).await {
+            Ok(sent) => {
+                if let Err(err) = book.transition(
+                    order.id,
+                    ExitOrderStatus::Executing,
+                    ExitOrderStatus::Executing,
+                    Some(sent.tx_hash),
+                ) {
+                    log::error!("tx of exit order #{} not recorded: {}", order.id, err);
+                }
+                let text = format!(
+                    "{} #{} triggered at {} {}, selling {}% of your {} position\n⏳ Tx Sent\n{}",
+                    order.kind,
+                    order.id,
+                    format_price(price),
+                    chain.native_symbol,
+                    order.sell_percent,
+                    token_label(order.token),
+                    chain.tx_url(sent.tx_hash)
+                );
+                let message = notify(bot, &order, text).await;
+                tokio::spawn(settle(bot.clone(), order, sent, message));
+            }
+            Err(err) => {
+                log::warn!("exit order #{} failed: {}", order.id, err);
+                if let Err(err) = book.transition(
+                    order.id,
+                    ExitOrderStatus::Executing,
+                    ExitOrderStatus::Failed,
+                    None,
+                ) {
+                    log::error!("exit order #{} not marked as failed: {}", order.id, err);
+                }
+                let text = format!("{} #{} triggered but failed\n{}", order.kind, order.id, err);
+                notify(bot, &order, text).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(kind: ExitKind, percent: u32, sell_percent: u8) -> ExitOrder {
+        ExitOrder {
+            id: 1,
+            position_id: 1,
+            user_id: UserId(1),
+            chat_id: ChatId(1),
+            token: Address::zero(),
+            wallet_slot: 0,
+            kind,
+            percent,
+            sell_percent,
+            position_amount: U256::from(1_000u64),
+            entry_price: 2.0,
+            peak_price: 4.0,
+            slippage_bps: 100,
+            gas_preset: GasPreset::Standard,
+        }
+    }
+
+    #[test]
+    fn triggers_relative_to_the_entry_or_peak_price() {
+        let stop_loss = order(ExitKind::StopLoss, 25, 100);
+        assert!(!stop_loss.is_triggered(1.6));
+        assert!(stop_loss.is_triggered(1.5));
+
+        let take_profit = order(ExitKind::TakeProfit, 100, 50);
+        assert!(!take_profit.is_triggered(3.9));
+        assert!(take_profit.is_triggered(4.0));
+
+        let trailing_stop = order(ExitKind::TrailingStop, 10, 100);
+        assert!(!trailing_stop.is_triggered(3.7));
+        assert!(trailing_stop.is_triggered(3.6));
+    }
+
+    #[test]
+    fn sells_a_share_of_the_position() {
+        let take_profit = order(ExitKind::TakeProfit, 100, 50);
+        assert_eq!(
+            take_profit.sell_amount(),
+            SellAmount::BaseUnits(U256::from(500u64))
+        );
+        let stop_loss = order(ExitKind::StopLoss, 25, 100);
+        assert_eq!(
+            stop_loss.sell_amount(),
+            SellAmount::BaseUnits(U256::from(1_000u64))
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use std::ops::Deref;
+use std::sync::OnceLock;
+
+/// Global loaded from the config once, by [TgBot::init](crate::bot::TgBot::init), so a bad config
+/// fails the boot with an error instead of panicking the first handler that reads it
+pub(crate) struct Global<T> {
+    name: &'static str,
+    value: OnceLock<T>,
+}
+
+impl<T> Global<T> {
+    pub(crate) const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Loads the value unless it is loaded already
+    pub(crate) fn load(&self, load: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<()> {
+        if self.value.get().is_none() {
+            let value = load()
+                .map_err(|err| anyhow::anyhow!("Unable to load the {}: {}", self.name, err))?;
+            let _ = self.value.set(value);
+        }
+        Ok(())
+    }
+}
+
+impl<T> Deref for Global<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+            .get()
+            .unwrap_or_else(|| panic!("The {} is read before it is loaded", self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_once_and_reports_the_error() {
+        let global: Global<u32> = Global::new("answer");
+        let err = global.load(|| Err(anyhow::anyhow!("missing"))).unwrap_err();
+        assert_eq!(err.to_string(), "Unable to load the answer: missing");
+
+        global.load(|| Ok(42)).unwrap();
+        global.load(|| Ok(7)).unwrap();
+        assert_eq!(*global, 42);
+    }
+}
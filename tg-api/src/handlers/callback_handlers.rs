@@ -1,24 +1,55 @@
+use crate::approvals::GLOBAL_APPROVAL_BOOK;
 use crate::bot::TgError;
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::dca::{format_interval, GLOBAL_DCA_BOOK};
+use crate::drafts::{BuyOrderDraft, DcaDraft, LimitOrderDraft, SellAmount, SellOrderDraft};
+use crate::exit_orders::{ExitKind, GLOBAL_EXIT_ORDER_BOOK};
 use crate::handlers::dialogue_handlers::PromptDialogueState;
 use crate::handlers::{
-    delete_previous_messages, find_keyboard_from_callback, find_sub_menu_type_from_callback,
+    approvals_menu, dca_menu, delete_previous_messages, delete_up_to_messages,
+    find_sub_menu_type_from_callback, history_menu, limit_menu, orders_menu, rebates_menu,
+    requote_buy_draft, schedules_menu, settings_menu, submit_buy, submit_sell, track_sent_swap,
     SubMenuType,
 };
-use crate::keyboards::buy_buttons::{buy_keyboard, BuyButtons};
+use crate::keyboards::approval_buttons::approve_keyboard;
+use crate::keyboards::buy_buttons::{buy_keyboard, confirm_risk_keyboard};
+use crate::keyboards::exit_buttons::position_keyboard;
+use crate::keyboards::history_buttons::trade_keyboard;
 use crate::keyboards::menu_keyboard;
-use crate::requests::on_chain;
-use crate::storages::{TgMessage, TgMessageStorage};
-use crate::storages::{GLOBAL_BUY_MENU_STORAGE, GLOBAL_MAIN_MENU_STORAGE};
+use crate::keyboards::sell_buttons::{held_tokens_keyboard, sell_keyboard};
+use crate::keyboards::wallet_buttons::wallets_keyboard;
+use crate::ledger::GLOBAL_TRADE_LEDGER;
+use crate::limit_orders::{format_expiry, format_price, LimitSide, GLOBAL_LIMIT_ORDER_BOOK};
+use crate::rebates::GLOBAL_REBATE_BOOK;
+use crate::requests::server::{SendBuyTxRequest, SendSellTxRequest};
+use crate::requests::simulation::SimulationError;
+use crate::requests::swap::{AllowanceError, ApprovalMode, SentSwap};
+use crate::requests::token_safety::RiskLevel;
+use crate::requests::{on_chain, quote};
+use crate::settings::{
+    format_slippage, next_preset, GasPreset, DEADLINE_PRESETS_SECS, GLOBAL_SETTINGS_STORE,
+    MAX_SLIPPAGE_BPS, SLIPPAGE_PRESETS_BPS,
+};
+use crate::storages::{SessionKey, TgMessage};
+use crate::storages::{
+    GLOBAL_BUY_MENU_STORAGE, GLOBAL_BUY_ORDER_DRAFTS, GLOBAL_DCA_DRAFTS, GLOBAL_DCA_MENU_STORAGE,
+    GLOBAL_LIMIT_MENU_STORAGE, GLOBAL_LIMIT_ORDER_DRAFTS, GLOBAL_MAIN_MENU_STORAGE,
+    GLOBAL_SELL_MENU_STORAGE, GLOBAL_SELL_ORDER_DRAFTS,
+};
+use crate::wallets::{GLOBAL_WALLET_STORE, MAX_WALLET_NAME_LEN};
+use ethers::types::Address;
 use std::sync::Arc;
-use teloxide::dispatching::dialogue::InMemStorage;
+use teloxide::dispatching::dialogue::ErasedStorage;
 use teloxide::{
-    dispatching::dialogue::Storage,
-    payloads::{EditMessageTextSetters, SendMessageSetters},
+    payloads::{
+        AnswerCallbackQuerySetters, EditMessageReplyMarkupSetters, EditMessageTextSetters,
+        SendMessageSetters,
+    },
     prelude::Requester,
-    types::{CallbackQuery, InlineKeyboardButtonKind, Message, ParseMode},
-    Bot,
+    types::{CallbackQuery, ChatId, Message, MessageId, ParseMode, UserId},
+    utils::markdown::code_inline,
+    ApiError, Bot, RequestError,
 };
-use tokio::time::{sleep, Duration};
 
 /// Upon a user clicks the "Main Menu", it'll clear the text and show the menu again
 pub(crate) async fn handle_menu_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
@@ -32,37 +63,53 @@ pub(crate) async fn handle_menu_callback(bot: &Bot, q: &CallbackQuery) -> Result
             .parse_mode(ParseMode::MarkdownV2)
             .reply_markup(keyboard)
             .await?;
-        let message_sent = Arc::new(message_sent);
-
-        // Updates the GLOBAL_STORAGE
-        let _user_name = message_sent
-            .clone()
-            .from()
-            .and_then(|user| user.username.as_ref())
-            .and_then(|user_name| {
-                let message = TgMessage {
-                    chat_id: message_sent.chat.id,
-                    message_id: message_sent.id,
-                    message: message_sent.clone(),
-                };
-                GLOBAL_MAIN_MENU_STORAGE.insert(user_name.to_string(), message);
-                Some(user_name)
-            });
+
+        // Updates the GLOBAL_MAIN_MENU_STORAGE
+        let message = TgMessage {
+            chat_id: message_sent.chat.id,
+            message_id: message_sent.id,
+        };
+        GLOBAL_MAIN_MENU_STORAGE.insert((chat.id, q.from.id), message);
 
         let last_message_id = message_sent.id;
-        let _ = delete_previous_messages(bot, chat.id.0, last_message_id.0 - 1, 20).await?;
+        delete_previous_messages(bot, chat.id.0, last_message_id.0 - 1, 20).await?;
     };
     Ok(())
 }
 
 pub(crate) async fn handle_buy_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
-    let keyboard = buy_keyboard(true, false, true, false, false)?;
     bot.answer_callback_query(&q.id).await?;
-    if let Some(Message { id: _id, chat, .. }) = &q.message {
+    if let Some(Message { chat, .. }) = &q.message {
+        let draft = BuyOrderDraft::new(
+            GLOBAL_WALLET_STORE.default_slot(q.from.id)?,
+            &GLOBAL_SETTINGS_STORE.get(q.from.id)?,
+            GLOBAL_CHAIN_REGISTRY.trading_chain(),
+        );
+        let keyboard = buy_keyboard(&draft);
+        GLOBAL_BUY_ORDER_DRAFTS.insert((chat.id, q.from.id), draft);
+
         let menu_msg = on_chain::get_on_chain_info().await?;
-        // todo: add custom info for buy
-        let _ = bot
-            .send_message(chat.id, menu_msg)
+        bot.send_message(chat.id, menu_msg)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_sell_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        let draft = SellOrderDraft::new(
+            GLOBAL_WALLET_STORE.default_slot(q.from.id)?,
+            &GLOBAL_SETTINGS_STORE.get(q.from.id)?,
+            GLOBAL_CHAIN_REGISTRY.trading_chain(),
+        );
+        let keyboard = sell_keyboard(&draft);
+        GLOBAL_SELL_ORDER_DRAFTS.insert((chat.id, q.from.id), draft);
+
+        let menu_msg = on_chain::get_on_chain_info().await?;
+        bot.send_message(chat.id, menu_msg)
             .parse_mode(ParseMode::MarkdownV2)
             .reply_markup(keyboard)
             .await?;
@@ -78,188 +125,388 @@ pub(crate) async fn handle_close_callback(bot: &Bot, q: &CallbackQuery) -> Resul
     Ok(())
 }
 
-pub(crate) async fn handle_wallet_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
-    bot.answer_callback_query(&q.id).await?;
+/// Applies the change to the user's buy order draft and renders the buy menu again
+async fn update_buy_menu(
+    bot: &Bot,
+    q: &CallbackQuery,
+    change: impl FnOnce(&mut BuyOrderDraft),
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let draft = match GLOBAL_BUY_ORDER_DRAFTS.update((chat.id, q.from.id), Box::new(change)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        bot.answer_callback_query(&q.id).await?;
 
-    if let (Some(button), Some(Message { id, chat, .. })) = (&q.data, &q.message) {
         let menu_msg = on_chain::get_on_chain_info().await?;
-        // let sub_menu_type = find_sub_menu_type_from_callback(q)?;
-        let buy_button = BuyButtons::new(button);
-        let new_button_text = buy_button.toggle();
-
-        //if let SubMenuType::SendBuyTx = sub_menu_type {
-        let mut keyboard = find_keyboard_from_callback(q)?.clone();
-
-        // Determine the index based on the button type
-        let index = match buy_button {
-            BuyButtons::Wallet1(_) => 0,
-            BuyButtons::Wallet2(_) => 1,
-            BuyButtons::Wallet3(_) => 2,
-            _ => return Ok(()), // Return early if no match
-        };
+        bot.edit_message_text(chat.id, *id, menu_msg)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(buy_keyboard(&draft))
+            .await?;
+    }
+    Ok(())
+}
 
-        // Update the button text and kind
-        if let Some(button) = keyboard
-            .inline_keyboard
-            .get_mut(3)
-            .and_then(|row| row.get_mut(index))
-        {
-            button.text = new_button_text.to_string();
-            button.kind = InlineKeyboardButtonKind::CallbackData(new_button_text.to_string());
-        }
+/// Applies the change to the user's sell order draft and renders the sell menu again
+async fn update_sell_menu(
+    bot: &Bot,
+    q: &CallbackQuery,
+    change: impl FnOnce(&mut SellOrderDraft),
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let draft = match GLOBAL_SELL_ORDER_DRAFTS.update((chat.id, q.from.id), Box::new(change)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        bot.answer_callback_query(&q.id).await?;
 
-        // Edit the message with the updated keyboard
+        let menu_msg = on_chain::get_on_chain_info().await?;
         bot.edit_message_text(chat.id, *id, menu_msg)
             .parse_mode(ParseMode::MarkdownV2)
-            .reply_markup(keyboard)
+            .reply_markup(sell_keyboard(&draft))
             .await?;
-        //} else if let SubMenuType::SendSellTx = sub_menu_type {
-        //    todo!(); // Handle the SendSellTx case here
-        //}
     }
+    Ok(())
+}
+
+/// Quotes the buy order again when the estimated received amount is tapped
+pub(crate) async fn handle_buy_quote_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let session = (chat.id, q.from.id);
+        let draft = match GLOBAL_BUY_ORDER_DRAFTS.get(session) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        if draft.quote_inputs().is_none() {
+            bot.answer_callback_query(&q.id)
+                .text("Enter the receive token and amount first")
+                .await?;
+            return Ok(());
+        }
+
+        let draft = requote_buy_draft(session, draft, true).await;
+        match draft.current_quote() {
+            Some(_) => bot.answer_callback_query(&q.id).await?,
+            None => {
+                bot.answer_callback_query(&q.id)
+                    .text("No quote available for this pair")
+                    .await?
+            }
+        };
+
+        let menu_msg = on_chain::get_on_chain_info().await?;
+        let edited = bot
+            .edit_message_text(chat.id, *id, menu_msg)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(buy_keyboard(&draft))
+            .await;
+        match edited {
+            // the quote and chain info haven't moved since the last refresh
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
 
+/// The draft behind the menu is gone, e.g. after a restart
+async fn answer_expired_menu(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id)
+        .text("This menu has expired, use /menu to open a new one")
+        .await?;
     Ok(())
 }
 
-// Note: any value changed to the keyboard layout will affect this function
+/// Selects the wallet in the given slot of the buy or sell menu
+pub(crate) async fn handle_wallet_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slot: u8,
+) -> Result<(), TgError> {
+    match find_sub_menu_type_from_callback(q)? {
+        SubMenuType::SendBuyTx => update_buy_menu(bot, q, |draft| draft.wallet_slot = slot).await,
+        SubMenuType::SendSellTx => update_sell_menu(bot, q, |draft| draft.wallet_slot = slot).await,
+    }
+}
+
 pub(crate) async fn handle_private_tx_callback(
     bot: &Bot,
     q: &CallbackQuery,
 ) -> Result<(), TgError> {
-    bot.answer_callback_query(&q.id).await?;
+    // menus sent before the chain lost its relays still show the toggle
+    let relays = GLOBAL_CHAIN_REGISTRY.trading_chain().has_private_relays();
     match find_sub_menu_type_from_callback(q)? {
         SubMenuType::SendBuyTx => {
-            if let Some(button) = &q.data {
-                if let Some(Message { id, chat, .. }) = &q.message {
-                    let menu_msg = on_chain::get_on_chain_info().await?;
-
-                    // Gets current keyboard layout
-                    let keyboard = find_keyboard_from_callback(q)?.clone();
-                    let mut new_keyboard = keyboard.clone();
-                    let button = BuyButtons::new(button);
-                    let new_button_text = button.toggle();
-
-                    // Change the text to toggled value
-                    if let Some(button) = new_keyboard
-                        .inline_keyboard
-                        .get_mut(1)
-                        .and_then(|row| row.get_mut(0))
-                    {
-                        button.text = new_button_text.to_string();
-                        button.kind =
-                            InlineKeyboardButtonKind::CallbackData(new_button_text.to_string());
-                    }
-
-                    // Edit the message with the new keyboard
-                    bot.edit_message_text(chat.id, *id, menu_msg)
-                        .parse_mode(ParseMode::MarkdownV2)
-                        .reply_markup(new_keyboard)
-                        .await?;
-                }
-            }
+            update_buy_menu(bot, q, |draft| {
+                draft.private_tx = !draft.private_tx && relays
+            })
+            .await
         }
         SubMenuType::SendSellTx => {
-            todo!()
+            update_sell_menu(bot, q, |draft| {
+                draft.private_tx = !draft.private_tx && relays
+            })
+            .await
         }
     }
-    Ok(())
 }
 
 pub(crate) async fn handle_rebate_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
-    bot.answer_callback_query(&q.id).await?;
     match find_sub_menu_type_from_callback(q)? {
         SubMenuType::SendBuyTx => {
-            if let Some(button) = &q.data {
-                if let Some(Message { id, chat, .. }) = &q.message {
-                    let menu_msg = on_chain::get_on_chain_info().await?;
-
-                    // Gets current keyboard layout
-                    let keyboard = find_keyboard_from_callback(q)?.clone();
-                    let mut new_keyboard = keyboard.clone();
-                    let button = BuyButtons::new(button);
-                    let new_button_text = button.toggle();
-
-                    // Change the text to toggled value
-                    if let Some(button) = new_keyboard
-                        .inline_keyboard
-                        .get_mut(1)
-                        .and_then(|row| row.get_mut(1))
-                    {
-                        button.text = new_button_text.to_string();
-                        button.kind =
-                            InlineKeyboardButtonKind::CallbackData(new_button_text.to_string());
-                    }
-
-                    // Edit the message with the new keyboard
-                    bot.edit_message_text(chat.id, *id, menu_msg)
-                        .parse_mode(ParseMode::MarkdownV2)
-                        .reply_markup(new_keyboard)
-                        .await?;
-                }
-            }
+            update_buy_menu(bot, q, |draft| draft.rebate = !draft.rebate).await
         }
         SubMenuType::SendSellTx => {
-            todo!()
+            update_sell_menu(bot, q, |draft| draft.rebate = !draft.rebate).await
         }
     }
-    Ok(())
 }
 
-pub(crate) async fn handle_send_tx_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
-    bot.answer_callback_query(&q.id).await?;
+/// Moves the order's slippage to the next preset
+pub(crate) async fn handle_order_slippage_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+) -> Result<(), TgError> {
     match find_sub_menu_type_from_callback(q)? {
         SubMenuType::SendBuyTx => {
-            //let _req = SendBuyTxRequest::new(keyboard);
-            //log::info!("req: {:?}", _req);
-            sleep(Duration::from_secs(3)).await;
-            bot.answer_callback_query(&q.id).await?;
-            if let Some(Message { id: _id, chat, .. }) = &q.message {
-                // todo: add custom info for buy
-                let _ = bot
-                    .send_message(chat.id, "Tx Sent\nTx Hash: 0x39ee32a72c11b78e4d190158682e9e3f3ec7cf1aeb849644355475bc778eacba")
-                    .parse_mode(ParseMode::MarkdownV2)
-                    .await?;
-            }
+            update_buy_menu(bot, q, |draft| {
+                draft.slippage_bps = next_preset(&SLIPPAGE_PRESETS_BPS, draft.slippage_bps)
+            })
+            .await
+        }
+        SubMenuType::SendSellTx => {
+            update_sell_menu(bot, q, |draft| {
+                draft.slippage_bps = next_preset(&SLIPPAGE_PRESETS_BPS, draft.slippage_bps)
+            })
+            .await
+        }
+    }
+}
+
+/// Moves the order's deadline to the next preset
+pub(crate) async fn handle_order_deadline_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+) -> Result<(), TgError> {
+    match find_sub_menu_type_from_callback(q)? {
+        SubMenuType::SendBuyTx => {
+            update_buy_menu(bot, q, |draft| {
+                draft.deadline_secs = next_preset(&DEADLINE_PRESETS_SECS, draft.deadline_secs)
+            })
+            .await
+        }
+        SubMenuType::SendSellTx => {
+            update_sell_menu(bot, q, |draft| {
+                draft.deadline_secs = next_preset(&DEADLINE_PRESETS_SECS, draft.deadline_secs)
+            })
+            .await
+        }
+    }
+}
+
+/// Moves the order's gas preset to the next one
+pub(crate) async fn handle_order_gas_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    match find_sub_menu_type_from_callback(q)? {
+        SubMenuType::SendBuyTx => {
+            update_buy_menu(bot, q, |draft| draft.gas_preset = draft.gas_preset.next()).await
         }
         SubMenuType::SendSellTx => {
-            todo!()
+            update_sell_menu(bot, q, |draft| draft.gas_preset = draft.gas_preset.next()).await
+        }
+    }
+}
+
+pub(crate) async fn handle_send_tx_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        let menu_type = find_sub_menu_type_from_callback(q)?;
+        if let SubMenuType::SendBuyTx = menu_type {
+            let risk = GLOBAL_BUY_ORDER_DRAFTS
+                .get((chat.id, q.from.id))
+                .and_then(|draft| draft.current_risk().cloned());
+            // high risk tokens are only bought once the user confirms it
+            if let Some(risk) = risk.filter(|risk| risk.level() == RiskLevel::High) {
+                bot.send_message(
+                    chat.id,
+                    format!("{}\n\nBuy this token anyway?", risk.details()),
+                )
+                .reply_markup(confirm_risk_keyboard(risk.token))
+                .await?;
+                return Ok(());
+            }
+        }
+        send_tx(bot, chat.id, q.from.id, menu_type, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends the buy of a high risk token once the user confirmed it
+pub(crate) async fn handle_confirm_risk_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    token: Address,
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let confirmed = GLOBAL_BUY_ORDER_DRAFTS
+            .get((chat.id, q.from.id))
+            .is_some_and(|draft| draft.receive_token == Some(token));
+        if !confirmed {
+            bot.answer_callback_query(&q.id)
+                .text("The order changed, send it from the buy menu again")
+                .await?;
+            return Ok(());
+        }
+        bot.answer_callback_query(&q.id).await?;
+        bot.delete_message(chat.id, *id).await?;
+        send_tx(bot, chat.id, q.from.id, SubMenuType::SendBuyTx, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends the swap of the user's buy or sell order draft and replies with its outcome,
+/// asks how to approve the router first when its allowance is too low and `approval` isn't set
+async fn send_tx(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+    menu_type: SubMenuType,
+    approval: Option<ApprovalMode>,
+) -> Result<(), TgError> {
+    let session = (chat_id, user_id);
+    let sent = match menu_type {
+        SubMenuType::SendBuyTx => send_buy_tx(session, approval).await.map_err(|err| {
+            log::warn!("send buy tx failed: {}", err);
+            err
+        }),
+        SubMenuType::SendSellTx => send_sell_tx(session, approval).await.map_err(|err| {
+            log::warn!("send sell tx failed: {}", err);
+            err
+        }),
+    };
+    if let Err(err) = &sent {
+        if let Some(allowance) = err.downcast_ref::<AllowanceError>() {
+            let sell = matches!(menu_type, SubMenuType::SendSellTx);
+            bot.send_message(
+                chat_id,
+                format!("{}\nApprove it to send the swap", allowance),
+            )
+            .reply_markup(approve_keyboard(sell))
+            .await?;
+            return Ok(());
+        }
+    }
+    let reply = match &sent {
+        Ok(sent) => match sent.private_max_block {
+            Some(max_block) => format!(
+                "⏳ Private Tx Sent\nTx Hash: {:?}\nTracking it until block {}",
+                sent.tx_hash, max_block
+            ),
+            None => format!("⏳ Tx Sent\nTx Hash: {:?}", sent.tx_hash),
+        },
+        Err(err) => match err.downcast_ref::<SimulationError>() {
+            Some(simulation) => format!("Tx Not Sent\n{}", simulation),
+            None => format!("Tx Failed\n{}", err),
+        },
+    };
+    let message = bot.send_message(chat_id, reply).await?;
+
+    match sent {
+        Ok(sent) => {
+            tokio::spawn(track_sent_swap(
+                bot.clone(),
+                chat_id,
+                user_id,
+                sent,
+                Some(message.id),
+            ));
+        }
+        Err(err) => {
+            if let Ok(simulation) = err.downcast::<SimulationError>() {
+                return Err(simulation.into());
+            }
         }
     }
 
     Ok(())
 }
 
+/// Shows every finding of the receive token's risk check
+pub(crate) async fn handle_buy_risk_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    let risk = q.message.as_ref().and_then(|msg| {
+        GLOBAL_BUY_ORDER_DRAFTS
+            .get((msg.chat.id, q.from.id))
+            .and_then(|draft| draft.current_risk().cloned())
+    });
+    match risk {
+        Some(risk) => {
+            bot.answer_callback_query(&q.id)
+                .text(risk.details())
+                .show_alert(true)
+                .await?
+        }
+        None => return answer_expired_menu(bot, q).await,
+    };
+    Ok(())
+}
+
+/// Broadcasts the swap of the user's buy order draft with the selected wallet
+async fn send_buy_tx(
+    session: SessionKey,
+    approval: Option<ApprovalMode>,
+) -> anyhow::Result<SentSwap> {
+    let (_, user_id) = session;
+    let draft = GLOBAL_BUY_ORDER_DRAFTS
+        .get(session)
+        .ok_or_else(|| anyhow::anyhow!("This menu has expired, use /menu to open a new one"))?;
+    let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+    let mut request = SendBuyTxRequest::new(&draft, settings.max_fee_gwei)?;
+    request.approval = approval;
+    log::info!("req: {:?}", request);
+    submit_buy(user_id, &request, true).await
+}
+
+/// Broadcasts the swap of the user's sell order draft with the selected wallet
+async fn send_sell_tx(
+    session: SessionKey,
+    approval: Option<ApprovalMode>,
+) -> anyhow::Result<SentSwap> {
+    let (_, user_id) = session;
+    let draft = GLOBAL_SELL_ORDER_DRAFTS
+        .get(session)
+        .ok_or_else(|| anyhow::anyhow!("This menu has expired, use /menu to open a new one"))?;
+    let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+    let mut request = SendSellTxRequest::new(&draft, settings.max_fee_gwei)?;
+    request.approval = approval;
+    log::info!("req: {:?}", request);
+    submit_sell(user_id, &request).await
+}
+
 pub(crate) async fn handle_buy_token_callback(
     bot: &Bot,
     state: PromptDialogueState,
     q: &CallbackQuery,
-    storage: Arc<InMemStorage<PromptDialogueState>>,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
 ) -> Result<(), TgError> {
     bot.answer_callback_query(&q.id).await?;
 
     // Updates the GLOBAL_BUY_MENU_STORAGE
     if let Some(msg) = &q.message {
-        let msg = Arc::new(msg);
-        let _user_name = msg
-            .clone()
-            .from()
-            .and_then(|user| user.username.as_ref())
-            .and_then(|user_name| {
-                let message = TgMessage {
-                    chat_id: msg.chat.id,
-                    message_id: msg.id,
-                    message: (*msg).clone().into(),
-                };
-                GLOBAL_BUY_MENU_STORAGE.insert(user_name.to_string(), message);
-                Some(user_name)
-            });
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_BUY_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
     }
 
     if let Some(Message { chat, .. }) = &q.message {
         storage.clone().update_dialogue(chat.id, state).await?;
         bot.send_message(
             chat.id,
-            "Enter the address or name of the token you want to buy",
+            "Enter the address or symbol of the token you want to pay with",
         )
         .await?;
         storage
@@ -273,33 +520,24 @@ pub(crate) async fn handle_receive_token_callback(
     bot: &Bot,
     state: PromptDialogueState,
     q: &CallbackQuery,
-    storage: Arc<InMemStorage<PromptDialogueState>>,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
 ) -> Result<(), TgError> {
     bot.answer_callback_query(&q.id).await?;
 
     // Updates the GLOBAL_BUY_MENU_STORAGE
     if let Some(msg) = &q.message {
-        let msg = Arc::new(msg);
-        let _user_name = msg
-            .clone()
-            .from()
-            .and_then(|user| user.username.as_ref())
-            .and_then(|user_name| {
-                let message = TgMessage {
-                    chat_id: msg.chat.id,
-                    message_id: msg.id,
-                    message: (*msg).clone().into(),
-                };
-                GLOBAL_BUY_MENU_STORAGE.insert(user_name.to_string(), message);
-                Some(user_name)
-            });
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_BUY_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
     }
 
     if let Some(Message { chat, .. }) = &q.message {
         storage.clone().update_dialogue(chat.id, state).await?;
         bot.send_message(
             chat.id,
-            "Enter the address or name of the token you want to sell",
+            "Enter the address or symbol of the token you want to receive",
         )
         .await?;
         storage
@@ -309,30 +547,65 @@ pub(crate) async fn handle_receive_token_callback(
     Ok(())
 }
 
+/// Sets the token picked on the token chooser and renders the buy menu the prompt came from again
+pub(crate) async fn handle_pick_token_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    token: Address,
+    is_buy_token: bool,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let change = |draft: &mut BuyOrderDraft| match is_buy_token {
+            true => draft.buy_token = Some(token),
+            false => draft.receive_token = Some(token),
+        };
+        let draft = match GLOBAL_BUY_ORDER_DRAFTS.update((chat.id, q.from.id), Box::new(change)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        bot.answer_callback_query(&q.id).await?;
+        let draft = requote_buy_draft((chat.id, q.from.id), draft, false).await;
+
+        match GLOBAL_BUY_MENU_STORAGE.get((chat.id, q.from.id)) {
+            Some(menu) => {
+                let menu_msg = on_chain::get_on_chain_info().await?;
+                bot.edit_message_text(chat.id, menu.message_id, menu_msg)
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .reply_markup(buy_keyboard(&draft))
+                    .await?;
+                delete_up_to_messages(bot, chat.id.0, id.0, menu.message_id.0).await?;
+            }
+            None => log::warn!("message not found"),
+        }
+
+        // the pick ends the prompt, unless the user has moved on to another one
+        if let Some(
+            PromptDialogueState::BuyTokenNameReceived
+            | PromptDialogueState::ReceiveTokenNameReceived,
+        ) = storage.clone().get_dialogue(chat.id).await?
+        {
+            storage.remove_dialogue(chat.id).await?;
+        }
+    }
+    Ok(())
+}
+
 pub(crate) async fn handle_buy_amount_callback(
     bot: &Bot,
     state: PromptDialogueState,
     q: &CallbackQuery,
-    storage: Arc<InMemStorage<PromptDialogueState>>,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
 ) -> Result<(), TgError> {
     bot.answer_callback_query(&q.id).await?;
 
     // Updates the GLOBAL_BUY_MENU_STORAGE
     if let Some(msg) = &q.message {
-        let msg = Arc::new(msg);
-        let _user_name = msg
-            .clone()
-            .from()
-            .and_then(|user| user.username.as_ref())
-            .and_then(|user_name| {
-                let message = TgMessage {
-                    chat_id: msg.chat.id,
-                    message_id: msg.id,
-                    message: (*msg).clone().into(),
-                };
-                GLOBAL_BUY_MENU_STORAGE.insert(user_name.to_string(), message);
-                Some(user_name)
-            });
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_BUY_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
     }
 
     if let Some(Message { chat, .. }) = &q.message {
@@ -345,3 +618,1023 @@ pub(crate) async fn handle_buy_amount_callback(
     }
     Ok(())
 }
+
+/// Selects one of the percentage buttons of the sell menu and clears any exact amount
+pub(crate) async fn handle_sell_percent_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    percent: u8,
+) -> Result<(), TgError> {
+    update_sell_menu(bot, q, |draft| draft.amount = SellAmount::Percent(percent)).await
+}
+
+pub(crate) async fn handle_sell_token_callback(
+    bot: &Bot,
+    state: PromptDialogueState,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+
+    // Updates the GLOBAL_SELL_MENU_STORAGE
+    if let Some(msg) = &q.message {
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_SELL_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
+    }
+
+    if let Some(Message { chat, .. }) = &q.message {
+        storage.clone().update_dialogue(chat.id, state).await?;
+        let held = match held_sell_tokens(chat.id, q.from.id).await {
+            Ok(held) => held,
+            Err(err) => {
+                log::warn!("held tokens of user {} unavailable: {}", q.from.id, err);
+                Vec::new()
+            }
+        };
+        match held.is_empty() {
+            true => {
+                bot.send_message(chat.id, "Enter the address of the token you want to sell")
+                    .await?
+            }
+            false => {
+                bot.send_message(
+                    chat.id,
+                    "Pick the token you want to sell, or enter its address",
+                )
+                .reply_markup(held_tokens_keyboard(&held))
+                .await?
+            }
+        };
+        storage
+            .update_dialogue(chat.id, PromptDialogueState::SellAddressReceived)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Tokens the wallet selected on the sell menu holds, among the ones the user has traded
+async fn held_sell_tokens(
+    chat_id: ChatId,
+    user_id: UserId,
+) -> anyhow::Result<Vec<on_chain::TokenBalance>> {
+    let slot = match GLOBAL_SELL_ORDER_DRAFTS.get((chat_id, user_id)) {
+        Some(draft) => draft.wallet_slot,
+        None => GLOBAL_WALLET_STORE.default_slot(user_id)?,
+    };
+    let wallet = GLOBAL_WALLET_STORE
+        .wallets(user_id)?
+        .into_iter()
+        .find(|wallet| wallet.slot == slot)
+        .ok_or_else(|| anyhow::anyhow!("Wallet not found"))?;
+    let query = on_chain::OnChainInfoQuery::new(GLOBAL_CHAIN_REGISTRY.trading_chain()).await?;
+    query.held_tokens(user_id, wallet.address).await
+}
+
+/// Sets the sell token picked out of the held tokens and ends the address prompt
+pub(crate) async fn handle_pick_sell_token_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    token: Address,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let change = |draft: &mut SellOrderDraft| draft.sell_token = Some(token);
+        let draft = match GLOBAL_SELL_ORDER_DRAFTS.update((chat.id, q.from.id), Box::new(change)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        bot.answer_callback_query(&q.id).await?;
+
+        match GLOBAL_SELL_MENU_STORAGE.get((chat.id, q.from.id)) {
+            Some(menu) => {
+                let menu_msg = on_chain::get_on_chain_info().await?;
+                bot.edit_message_text(chat.id, menu.message_id, menu_msg)
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .reply_markup(sell_keyboard(&draft))
+                    .await?;
+                delete_up_to_messages(bot, chat.id.0, id.0, menu.message_id.0).await?;
+            }
+            None => log::warn!("message not found"),
+        }
+
+        // the pick ends the prompt, unless the user has moved on to another one
+        if let Some(PromptDialogueState::SellAddressReceived) =
+            storage.clone().get_dialogue(chat.id).await?
+        {
+            storage.remove_dialogue(chat.id).await?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_sell_amount_callback(
+    bot: &Bot,
+    state: PromptDialogueState,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+
+    // Updates the GLOBAL_SELL_MENU_STORAGE
+    if let Some(msg) = &q.message {
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_SELL_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
+    }
+
+    if let Some(Message { chat, .. }) = &q.message {
+        storage.clone().update_dialogue(chat.id, state).await?;
+        bot.send_message(chat.id, "Enter the amount of tokens you want to sell")
+            .await?;
+        storage
+            .update_dialogue(chat.id, PromptDialogueState::SellAmountReceived)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Sends the wallet address on its own so it can be copied with a tap
+pub(crate) async fn handle_wallet_copy_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slot: u8,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        let wallets = GLOBAL_WALLET_STORE.wallets(q.from.id)?;
+        if let Some(wallet) = wallets.iter().find(|wallet| wallet.slot == slot) {
+            bot.send_message(chat.id, code_inline(&format!("{:?}", wallet.address)))
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_wallet_rename_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slot: u8,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        bot.send_message(
+            chat.id,
+            format!(
+                "Enter the new name of the wallet (up to {} characters)",
+                MAX_WALLET_NAME_LEN
+            ),
+        )
+        .await?;
+        storage
+            .update_dialogue(chat.id, PromptDialogueState::WalletNameReceived { slot })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Makes the wallet the one preselected on the buy and sell menus and refreshes /wallets
+pub(crate) async fn handle_wallet_default_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slot: u8,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        GLOBAL_WALLET_STORE.set_default(q.from.id, slot)?;
+        let wallets = GLOBAL_WALLET_STORE.wallets(q.from.id)?;
+        let wallets_msg = on_chain::get_wallets_info(q.from.id).await?;
+        bot.edit_message_text(chat.id, *id, wallets_msg)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(wallets_keyboard(&wallets))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Shows another page of /history
+pub(crate) async fn handle_history_page_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    page: usize,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let (history_msg, keyboard) = history_menu(q.from.id, page)?;
+        let edited = bot
+            .edit_message_text(chat.id, *id, history_msg)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the page counter was tapped, it shows the page already open
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shows the details of one trade of /history
+pub(crate) async fn handle_history_trade_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    trade_id: i64,
+    page: usize,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let trade_msg = match GLOBAL_TRADE_LEDGER.get(q.from.id, trade_id)? {
+            Some(trade) => trade.details(),
+            None => "Trade not found".to_string(),
+        };
+        bot.edit_message_text(chat.id, *id, trade_msg)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(trade_keyboard(page))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Renders /settings again after one of the defaults changed
+async fn update_settings_menu(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let (settings_msg, keyboard) = settings_menu(q.from.id)?;
+        let edited = bot
+            .edit_message_text(chat.id, *id, settings_msg)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the selected preset was tapped again
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets the user's default slippage to one of the presets
+pub(crate) async fn handle_settings_slippage_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slippage_bps: u32,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    GLOBAL_SETTINGS_STORE.set_slippage(q.from.id, slippage_bps)?;
+    update_settings_menu(bot, q).await
+}
+
+/// Sets the user's default deadline to one of the presets
+pub(crate) async fn handle_settings_deadline_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    deadline_secs: u64,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    GLOBAL_SETTINGS_STORE.set_deadline(q.from.id, deadline_secs)?;
+    update_settings_menu(bot, q).await
+}
+
+/// Sets the user's default gas preset
+pub(crate) async fn handle_settings_gas_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    gas_preset: GasPreset,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    GLOBAL_SETTINGS_STORE.set_gas_preset(q.from.id, gas_preset)?;
+    update_settings_menu(bot, q).await
+}
+
+/// Sets or removes the user's max fee cap
+pub(crate) async fn handle_settings_max_fee_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    max_fee_gwei: Option<u64>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    GLOBAL_SETTINGS_STORE.set_max_fee(q.from.id, max_fee_gwei)?;
+    update_settings_menu(bot, q).await
+}
+
+pub(crate) async fn handle_custom_slippage_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        bot.send_message(
+            chat.id,
+            format!(
+                "Enter the slippage tolerance in percent, up to {}",
+                format_slippage(MAX_SLIPPAGE_BPS)
+            ),
+        )
+        .await?;
+        storage
+            .update_dialogue(chat.id, PromptDialogueState::SlippageReceived)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Pays the rebate accrued on the chain out to the user's default wallet and refreshes /rebates
+pub(crate) async fn handle_rebate_claim_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    chain_id: u64,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let claimed = async {
+            let chain = GLOBAL_CHAIN_REGISTRY
+                .get(chain_id)
+                .ok_or_else(|| anyhow::anyhow!("Chain {} is not supported", chain_id))?;
+            let wallets = GLOBAL_WALLET_STORE.wallets(q.from.id)?;
+            let recipient = wallets
+                .iter()
+                .find(|wallet| wallet.is_default)
+                .map(|wallet| wallet.address)
+                .ok_or_else(|| anyhow::anyhow!("Wallet not found"))?;
+            let (amount, tx_hash) = GLOBAL_REBATE_BOOK
+                .claim(q.from.id, chain, recipient)
+                .await?;
+            anyhow::Ok(format!(
+                "Rebate of {} {} paid to {:?}\n{}",
+                on_chain::format_amount(amount, 18),
+                chain.native_symbol,
+                recipient,
+                chain.tx_url(tx_hash)
+            ))
+        };
+        let reply = claimed.await.unwrap_or_else(|err| {
+            log::warn!("rebate claim failed: {}", err);
+            err.to_string()
+        });
+        bot.send_message(chat.id, reply).await?;
+
+        let (rebates_msg, keyboard) = rebates_menu(q.from.id)?;
+        let edited = bot
+            .edit_message_text(chat.id, *id, rebates_msg)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the claim failed and the balance is unchanged
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Opens the Limit Buy or Limit Sell menu with a new draft
+pub(crate) async fn handle_limit_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    side: LimitSide,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        let draft = LimitOrderDraft::new(side, GLOBAL_WALLET_STORE.default_slot(q.from.id)?);
+        let (menu_msg, keyboard) = limit_menu(&draft).await;
+        GLOBAL_LIMIT_ORDER_DRAFTS.insert((chat.id, q.from.id), draft);
+
+        bot.send_message(chat.id, menu_msg)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Applies the change to the user's limit order draft and renders the limit menu again
+async fn update_limit_menu(
+    bot: &Bot,
+    q: &CallbackQuery,
+    change: impl FnOnce(&mut LimitOrderDraft),
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let draft = match GLOBAL_LIMIT_ORDER_DRAFTS.update((chat.id, q.from.id), Box::new(change)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        bot.answer_callback_query(&q.id).await?;
+
+        let (menu_msg, keyboard) = limit_menu(&draft).await;
+        let edited = bot
+            .edit_message_text(chat.id, *id, menu_msg)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the selected wallet or expiry was tapped again
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Selects the wallet in the given slot of the limit menu
+pub(crate) async fn handle_limit_wallet_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slot: u8,
+) -> Result<(), TgError> {
+    update_limit_menu(bot, q, |draft| draft.wallet_slot = slot).await
+}
+
+/// Sets the limit order's expiry to one of the presets
+pub(crate) async fn handle_limit_expiry_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    expiry_secs: u64,
+) -> Result<(), TgError> {
+    update_limit_menu(bot, q, |draft| draft.expiry_secs = expiry_secs).await
+}
+
+/// Remembers the limit menu the prompt comes from, sends the prompt and waits for the answer
+async fn prompt_limit_field(
+    bot: &Bot,
+    q: &CallbackQuery,
+    prompt: String,
+    state: PromptDialogueState,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(msg) = &q.message {
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_LIMIT_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
+
+        bot.send_message(msg.chat.id, prompt).await?;
+        storage.update_dialogue(msg.chat.id, state).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_limit_token_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    let prompt = "Enter the address of the token".to_string();
+    prompt_limit_field(
+        bot,
+        q,
+        prompt,
+        PromptDialogueState::LimitTokenReceived,
+        storage,
+    )
+    .await
+}
+
+pub(crate) async fn handle_limit_amount_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    let side = q
+        .message
+        .as_ref()
+        .and_then(|msg| GLOBAL_LIMIT_ORDER_DRAFTS.get((msg.chat.id, q.from.id)))
+        .map(|draft| draft.side);
+    let prompt = match side {
+        Some(LimitSide::Buy) => format!(
+            "Enter the amount of {} you want to spend",
+            GLOBAL_CHAIN_REGISTRY.trading_chain().native_symbol
+        ),
+        _ => "Enter the amount of tokens you want to sell".to_string(),
+    };
+    prompt_limit_field(
+        bot,
+        q,
+        prompt,
+        PromptDialogueState::LimitAmountReceived,
+        storage,
+    )
+    .await
+}
+
+/// Asks for the trigger price, showing the token's current price when it is known
+pub(crate) async fn handle_limit_price_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let mut prompt = format!(
+        "Enter the trigger price, in {} per token",
+        chain.native_symbol
+    );
+    let token = q
+        .message
+        .as_ref()
+        .and_then(|msg| GLOBAL_LIMIT_ORDER_DRAFTS.get((msg.chat.id, q.from.id)))
+        .and_then(|draft| draft.token);
+    if let Some(token) = token {
+        if let Ok(price) = quote::token_price(chain, token).await {
+            prompt.push_str(&format!(
+                "\nCurrent price: {} {}",
+                format_price(price),
+                chain.native_symbol
+            ));
+        }
+    }
+    prompt_limit_field(
+        bot,
+        q,
+        prompt,
+        PromptDialogueState::LimitPriceReceived,
+        storage,
+    )
+    .await
+}
+
+/// Opens the limit order of the user's draft
+pub(crate) async fn handle_limit_create_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+) -> Result<(), TgError> {
+    if let Some(Message { chat, .. }) = &q.message {
+        let draft = match GLOBAL_LIMIT_ORDER_DRAFTS.get((chat.id, q.from.id)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        let id = match GLOBAL_LIMIT_ORDER_BOOK.create(q.from.id, chat.id, &draft) {
+            Ok(id) => id,
+            Err(err) => {
+                bot.answer_callback_query(&q.id)
+                    .text(err.to_string())
+                    .await?;
+                return Ok(());
+            }
+        };
+        bot.answer_callback_query(&q.id).await?;
+        bot.send_message(
+            chat.id,
+            format!(
+                "{} #{} created, it expires in {}\nSee your open orders with /orders",
+                draft.side,
+                id,
+                format_expiry(draft.expiry_secs)
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Sends the list of the user's open limit orders
+pub(crate) async fn handle_limit_orders_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        let (orders_msg, keyboard) = orders_menu(q.from.id)?;
+        bot.send_message(chat.id, orders_msg)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Renders the open orders list again after an order was cancelled
+async fn update_orders_menu(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let (orders_msg, keyboard) = orders_menu(q.from.id)?;
+        let edited = bot
+            .edit_message_text(chat.id, *id, orders_msg)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the order was already gone from the list
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cancels one of the user's open limit orders and refreshes the list
+pub(crate) async fn handle_limit_cancel_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    id: i64,
+) -> Result<(), TgError> {
+    match GLOBAL_LIMIT_ORDER_BOOK.cancel(q.from.id, id) {
+        Ok(()) => bot
+            .answer_callback_query(&q.id)
+            .text(format!("Order #{} cancelled", id)),
+        Err(err) => bot.answer_callback_query(&q.id).text(err.to_string()),
+    }
+    .await?;
+    update_orders_menu(bot, q).await
+}
+
+/// Cancels one of the user's open exit orders and refreshes the list
+pub(crate) async fn handle_exit_cancel_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    id: i64,
+) -> Result<(), TgError> {
+    match GLOBAL_EXIT_ORDER_BOOK.cancel(q.from.id, id) {
+        Ok(()) => bot
+            .answer_callback_query(&q.id)
+            .text(format!("Order #{} cancelled", id)),
+        Err(err) => bot.answer_callback_query(&q.id).text(err.to_string()),
+    }
+    .await?;
+    update_orders_menu(bot, q).await
+}
+
+/// Places the exit order of the tapped preset on the position, or cancels it when it is already open
+pub(crate) async fn handle_exit_preset_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    position_id: i64,
+    kind: ExitKind,
+    percent: u32,
+    sell_percent: u8,
+) -> Result<(), TgError> {
+    let position = match GLOBAL_EXIT_ORDER_BOOK.position(q.from.id, position_id)? {
+        Some(position) => position,
+        None => {
+            bot.answer_callback_query(&q.id)
+                .text("Position not found")
+                .await?;
+            return Ok(());
+        }
+    };
+    let orders = GLOBAL_EXIT_ORDER_BOOK.position_orders(q.from.id, position_id)?;
+    let open = orders.iter().find(|order| {
+        order.kind == kind && order.percent == percent && order.sell_percent == sell_percent
+    });
+    let answer = match open {
+        Some(order) => {
+            GLOBAL_EXIT_ORDER_BOOK.cancel(q.from.id, order.id)?;
+            format!("{} #{} cancelled", kind, order.id)
+        }
+        None => {
+            let id =
+                GLOBAL_EXIT_ORDER_BOOK.place(q.from.id, &position, kind, percent, sell_percent)?;
+            format!("{} #{} placed", kind, id)
+        }
+    };
+    bot.answer_callback_query(&q.id).text(answer).await?;
+
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let orders = GLOBAL_EXIT_ORDER_BOOK.position_orders(q.from.id, position_id)?;
+        bot.edit_message_reply_markup(chat.id, *id)
+            .reply_markup(position_keyboard(position_id, &orders))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Opens the DCA menu of a new schedule
+pub(crate) async fn handle_dca_new_callback(bot: &Bot, q: &CallbackQuery) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { chat, .. }) = &q.message {
+        let draft = DcaDraft::new(GLOBAL_WALLET_STORE.default_slot(q.from.id)?);
+        let (menu_msg, keyboard) = dca_menu(&draft);
+        GLOBAL_DCA_DRAFTS.insert((chat.id, q.from.id), draft);
+
+        bot.send_message(chat.id, menu_msg)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Applies the change to the user's DCA draft and renders the DCA menu again
+async fn update_dca_menu(
+    bot: &Bot,
+    q: &CallbackQuery,
+    change: impl FnOnce(&mut DcaDraft),
+) -> Result<(), TgError> {
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let draft = match GLOBAL_DCA_DRAFTS.update((chat.id, q.from.id), Box::new(change)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        bot.answer_callback_query(&q.id).await?;
+
+        let (menu_msg, keyboard) = dca_menu(&draft);
+        let edited = bot
+            .edit_message_text(chat.id, *id, menu_msg)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the selected wallet, interval or runs was tapped again
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Selects the wallet in the given slot of the DCA menu
+pub(crate) async fn handle_dca_wallet_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    slot: u8,
+) -> Result<(), TgError> {
+    update_dca_menu(bot, q, |draft| draft.wallet_slot = slot).await
+}
+
+/// Sets the time between two legs to one of the presets
+pub(crate) async fn handle_dca_interval_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    interval_secs: u64,
+) -> Result<(), TgError> {
+    update_dca_menu(bot, q, |draft| draft.interval_secs = interval_secs).await
+}
+
+/// Sets the number of legs to one of the presets
+pub(crate) async fn handle_dca_runs_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    runs: u32,
+) -> Result<(), TgError> {
+    update_dca_menu(bot, q, |draft| draft.runs = runs).await
+}
+
+/// Sets how the legs of the schedule approve the router
+pub(crate) async fn handle_dca_approval_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    approval: ApprovalMode,
+) -> Result<(), TgError> {
+    update_dca_menu(bot, q, |draft| draft.approval = approval).await
+}
+
+/// Remembers the DCA menu the prompt comes from, sends the prompt and waits for the answer
+async fn prompt_dca_field(
+    bot: &Bot,
+    q: &CallbackQuery,
+    prompt: String,
+    state: PromptDialogueState,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(msg) = &q.message {
+        let message = TgMessage {
+            chat_id: msg.chat.id,
+            message_id: msg.id,
+        };
+        GLOBAL_DCA_MENU_STORAGE.insert((msg.chat.id, q.from.id), message);
+
+        bot.send_message(msg.chat.id, prompt).await?;
+        storage.update_dialogue(msg.chat.id, state).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_dca_buy_token_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    let prompt = format!(
+        "Enter the address of the token to pay with, or {} to pay with the native coin",
+        GLOBAL_CHAIN_REGISTRY.trading_chain().native_symbol
+    );
+    prompt_dca_field(
+        bot,
+        q,
+        prompt,
+        PromptDialogueState::DcaBuyTokenReceived,
+        storage,
+    )
+    .await
+}
+
+pub(crate) async fn handle_dca_receive_token_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    let prompt = "Enter the address of the token to receive".to_string();
+    prompt_dca_field(
+        bot,
+        q,
+        prompt,
+        PromptDialogueState::DcaReceiveTokenReceived,
+        storage,
+    )
+    .await
+}
+
+pub(crate) async fn handle_dca_amount_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    storage: Arc<ErasedStorage<PromptDialogueState>>,
+) -> Result<(), TgError> {
+    let prompt = "Enter the amount to pay on each leg".to_string();
+    prompt_dca_field(
+        bot,
+        q,
+        prompt,
+        PromptDialogueState::DcaAmountReceived,
+        storage,
+    )
+    .await
+}
+
+/// Creates the DCA schedule of the user's draft
+pub(crate) async fn handle_dca_create_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+) -> Result<(), TgError> {
+    if let Some(Message { chat, .. }) = &q.message {
+        let draft = match GLOBAL_DCA_DRAFTS.get((chat.id, q.from.id)) {
+            Some(draft) => draft,
+            None => return answer_expired_menu(bot, q).await,
+        };
+        let id = match GLOBAL_DCA_BOOK.create(q.from.id, chat.id, &draft) {
+            Ok(id) => id,
+            Err(err) => {
+                bot.answer_callback_query(&q.id)
+                    .text(err.to_string())
+                    .await?;
+                return Ok(());
+            }
+        };
+        bot.answer_callback_query(&q.id).await?;
+        bot.send_message(
+            chat.id,
+            format!(
+                "DCA #{} created, it buys every {} for {} runs\nPause, resume or cancel it with /dca",
+                id,
+                format_interval(draft.interval_secs),
+                draft.runs
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Answers with the outcome of a pause, resume or cancel and renders the schedules list again
+async fn update_schedules_menu(
+    bot: &Bot,
+    q: &CallbackQuery,
+    result: anyhow::Result<()>,
+    done: String,
+) -> Result<(), TgError> {
+    match result {
+        Ok(()) => bot.answer_callback_query(&q.id).text(done),
+        Err(err) => bot.answer_callback_query(&q.id).text(err.to_string()),
+    }
+    .await?;
+
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let (schedules_msg, keyboard) = schedules_menu(q.from.id)?;
+        let edited = bot
+            .edit_message_text(chat.id, *id, schedules_msg)
+            .reply_markup(keyboard)
+            .await;
+        match edited {
+            // the schedule was already in that state
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            edited => {
+                edited?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_dca_pause_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    id: i64,
+) -> Result<(), TgError> {
+    let result = GLOBAL_DCA_BOOK.pause(q.from.id, id);
+    update_schedules_menu(bot, q, result, format!("DCA #{} paused", id)).await
+}
+
+pub(crate) async fn handle_dca_resume_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    id: i64,
+) -> Result<(), TgError> {
+    let result = GLOBAL_DCA_BOOK.resume(q.from.id, id);
+    update_schedules_menu(bot, q, result, format!("DCA #{} resumed", id)).await
+}
+
+pub(crate) async fn handle_dca_cancel_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    id: i64,
+) -> Result<(), TgError> {
+    let result = GLOBAL_DCA_BOOK.cancel(q.from.id, id);
+    update_schedules_menu(bot, q, result, format!("DCA #{} cancelled", id)).await
+}
+
+/// Approves the router the way the user picked, then sends the swap the approval was asked for
+pub(crate) async fn handle_approve_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    sell: bool,
+    mode: ApprovalMode,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id)
+        .text("Approving, the swap is sent once the approval is mined")
+        .await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        bot.delete_message(chat.id, *id).await?;
+        let menu_type = match sell {
+            true => SubMenuType::SendSellTx,
+            false => SubMenuType::SendBuyTx,
+        };
+        send_tx(bot, chat.id, q.from.id, menu_type, Some(mode)).await?;
+    }
+    Ok(())
+}
+
+/// Shows /approvals for the wallet in the given slot
+pub(crate) async fn handle_approvals_wallet_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    wallet_slot: u8,
+) -> Result<(), TgError> {
+    bot.answer_callback_query(&q.id).await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        update_approvals_menu(bot, chat.id, *id, q.from.id, wallet_slot).await?;
+    }
+    Ok(())
+}
+
+/// Sets the allowance back to 0, then lists the wallet's allowances again
+pub(crate) async fn handle_revoke_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    id: i64,
+) -> Result<(), TgError> {
+    let Some(approval) = GLOBAL_APPROVAL_BOOK.approval(q.from.id, id)? else {
+        return answer_expired_menu(bot, q).await;
+    };
+    bot.answer_callback_query(&q.id)
+        .text("Revoking, waiting for it to be mined")
+        .await?;
+    if let Some(Message { id, chat, .. }) = &q.message {
+        let reply = match GLOBAL_APPROVAL_BOOK.revoke(q.from.id, &approval).await {
+            Ok(tx_hash) => match GLOBAL_CHAIN_REGISTRY.get(approval.chain_id) {
+                Some(chain) => format!("Allowance revoked\n{}", chain.tx_url(tx_hash)),
+                None => format!("Allowance revoked\nTx Hash: {:?}", tx_hash),
+            },
+            Err(err) => {
+                log::warn!("revoke failed: {}", err);
+                err.to_string()
+            }
+        };
+        bot.send_message(chat.id, reply).await?;
+        update_approvals_menu(bot, chat.id, *id, q.from.id, approval.wallet_slot).await?;
+    }
+    Ok(())
+}
+
+async fn update_approvals_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    user_id: UserId,
+    wallet_slot: u8,
+) -> Result<(), TgError> {
+    let (approvals_msg, keyboard) = approvals_menu(user_id, wallet_slot).await?;
+    let edited = bot
+        .edit_message_text(chat_id, message_id, approvals_msg)
+        .reply_markup(keyboard)
+        .await;
+    match edited {
+        // the wallet shown was tapped again
+        Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+        edited => {
+            edited?;
+        }
+    }
+    Ok(())
+}
@@ -1,43 +1,218 @@
 use crate::bot::TgError;
-use crate::consts::{BOT_NAME, BUY_TOKEN, RECEIVE_TOKEN};
-use crate::handlers::delete_up_to_messages;
-use crate::handlers::find_keyboard_from_message;
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::drafts::{BuyOrderDraft, DcaDraft, LimitOrderDraft, SellAmount, SellOrderDraft};
+use crate::handlers::{dca_menu, delete_up_to_messages, limit_menu, requote_buy_draft};
+use crate::handlers::{send_settings_menu, send_wallets_menu};
+use crate::keyboards::buy_buttons::{buy_keyboard, token_chooser_keyboard};
+use crate::keyboards::sell_buttons::sell_keyboard;
 use crate::requests::on_chain;
-use crate::storages::{TgMessageStorage, GLOBAL_BUY_MENU_STORAGE};
+use crate::settings::{parse_slippage, GLOBAL_SETTINGS_STORE};
+use crate::storages::{
+    GLOBAL_BUY_MENU_STORAGE, GLOBAL_BUY_ORDER_DRAFTS, GLOBAL_DCA_DRAFTS, GLOBAL_DCA_MENU_STORAGE,
+    GLOBAL_LIMIT_MENU_STORAGE, GLOBAL_LIMIT_ORDER_DRAFTS, GLOBAL_SELL_MENU_STORAGE,
+    GLOBAL_SELL_ORDER_DRAFTS,
+};
+use crate::tokens::GLOBAL_TOKEN_REGISTRY;
+use crate::wallets::GLOBAL_WALLET_STORE;
 use ethers::types::Address;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use teloxide::{
-    dispatching::dialogue::{Dialogue, InMemStorage},
-    payloads::EditMessageTextSetters,
+    dispatching::dialogue::{Dialogue, ErasedStorage},
+    payloads::{EditMessageTextSetters, SendMessageSetters},
     requests::Requester,
-    types::{InlineKeyboardButtonKind, Message, ParseMode},
+    types::{Message, ParseMode},
     Bot,
 };
 
 pub(crate) type BuyAddressPromptDialogue =
-    Dialogue<PromptDialogueState, InMemStorage<PromptDialogueState>>;
+    Dialogue<PromptDialogueState, ErasedStorage<PromptDialogueState>>;
 
-/// Dialogue state
+/// Dialogue state, stored as JSON by the SQLite storage backend
+/// Note: renaming a variant or field loses the dialogues stored under the old name
 #[allow(dead_code)]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub(crate) enum PromptDialogueState {
     #[default]
     /// Represents state when the buy menu buy token button clicked
     BuyStartAddressPrompt,
     /// Represents state when the buy menu buy token address is received
     BuyAddressReceived,
-    /// Represents state when the buy menu buy token name is received
+    /// Represents state when several tokens match the buy token name received, waiting for the pick
     BuyTokenNameReceived,
     /// Represents state when the buy menu receive token button clicked
     ReceiveStartAddressPrompt,
     /// Represents state when the buy menu receive token address is received
     ReceiveAddressReceived,
-    /// Represents state when the buy menu receive token name is received
+    /// Represents state when several tokens match the receive token name received, waiting for the pick
     ReceiveTokenNameReceived,
     /// Represents state when the buy amount button is clicked
     StartBuyAmountPrompt,
     /// Represents state when the buy amount is received
     BuyAmountReceived,
+    /// Represents state when the sell menu sell token button clicked
+    SellStartAddressPrompt,
+    /// Represents state when the sell menu sell token address is received
+    SellAddressReceived,
+    /// Represents state when the sell amount button is clicked
+    StartSellAmountPrompt,
+    /// Represents state when the sell amount is received
+    SellAmountReceived,
+    /// Represents state when the /wallets rename button is clicked
+    WalletNameReceived { slot: u8 },
+    /// Represents state when the /settings custom slippage button is clicked
+    SlippageReceived,
+    /// Represents state when the limit menu token button is clicked
+    LimitTokenReceived,
+    /// Represents state when the limit menu amount button is clicked
+    LimitAmountReceived,
+    /// Represents state when the limit menu trigger price button is clicked
+    LimitPriceReceived,
+    /// Represents state when the DCA menu buy token button is clicked
+    DcaBuyTokenReceived,
+    /// Represents state when the DCA menu receive token button is clicked
+    DcaReceiveTokenReceived,
+    /// Represents state when the DCA menu amount button is clicked
+    DcaAmountReceived,
+}
+
+/// Applies the change to the user's buy order draft and renders the buy menu the prompt came from again
+async fn refresh_buy_menu(
+    bot: &Bot,
+    msg: &Message,
+    change: impl FnOnce(&mut BuyOrderDraft),
+) -> Result<(), TgError> {
+    let user = msg
+        .from()
+        .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+    let draft = match GLOBAL_BUY_ORDER_DRAFTS.update((msg.chat.id, user.id), Box::new(change)) {
+        Some(draft) => draft,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "This menu has expired, use /menu to open a new one",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let draft = requote_buy_draft((msg.chat.id, user.id), draft, false).await;
+
+    match GLOBAL_BUY_MENU_STORAGE.get((msg.chat.id, user.id)) {
+        Some(menu) => {
+            let menu_msg = on_chain::get_on_chain_info().await?;
+            bot.edit_message_text(msg.chat.id, menu.message_id, menu_msg)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(buy_keyboard(&draft))
+                .await?;
+            delete_up_to_messages(bot, msg.chat.id.0, msg.id.0, menu.message_id.0).await?;
+        }
+        None => log::warn!("message not found"),
+    }
+    Ok(())
+}
+
+/// Applies the change to the user's sell order draft and renders the sell menu the prompt came from again
+async fn refresh_sell_menu(
+    bot: &Bot,
+    msg: &Message,
+    change: impl FnOnce(&mut SellOrderDraft),
+) -> Result<(), TgError> {
+    let user = msg
+        .from()
+        .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+    let draft = match GLOBAL_SELL_ORDER_DRAFTS.update((msg.chat.id, user.id), Box::new(change)) {
+        Some(draft) => draft,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "This menu has expired, use /menu to open a new one",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    match GLOBAL_SELL_MENU_STORAGE.get((msg.chat.id, user.id)) {
+        Some(menu) => {
+            let menu_msg = on_chain::get_on_chain_info().await?;
+            bot.edit_message_text(msg.chat.id, menu.message_id, menu_msg)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(sell_keyboard(&draft))
+                .await?;
+            delete_up_to_messages(bot, msg.chat.id.0, msg.id.0, menu.message_id.0).await?;
+        }
+        None => log::warn!("message not found"),
+    }
+    Ok(())
+}
+
+/// Applies the change to the user's limit order draft and renders the limit menu the prompt came from again
+async fn refresh_limit_menu(
+    bot: &Bot,
+    msg: &Message,
+    change: impl FnOnce(&mut LimitOrderDraft),
+) -> Result<(), TgError> {
+    let user = msg
+        .from()
+        .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+    let draft = match GLOBAL_LIMIT_ORDER_DRAFTS.update((msg.chat.id, user.id), Box::new(change)) {
+        Some(draft) => draft,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "This menu has expired, use /menu to open a new one",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    match GLOBAL_LIMIT_MENU_STORAGE.get((msg.chat.id, user.id)) {
+        Some(menu) => {
+            let (menu_msg, keyboard) = limit_menu(&draft).await;
+            bot.edit_message_text(msg.chat.id, menu.message_id, menu_msg)
+                .reply_markup(keyboard)
+                .await?;
+            delete_up_to_messages(bot, msg.chat.id.0, msg.id.0, menu.message_id.0).await?;
+        }
+        None => log::warn!("message not found"),
+    }
+    Ok(())
+}
+
+/// Applies the change to the user's DCA draft and renders the DCA menu the prompt came from again
+async fn refresh_dca_menu(
+    bot: &Bot,
+    msg: &Message,
+    change: impl FnOnce(&mut DcaDraft),
+) -> Result<(), TgError> {
+    let user = msg
+        .from()
+        .ok_or_else(|| TgError::UserNotFound(msg.clone()))?;
+    let draft = match GLOBAL_DCA_DRAFTS.update((msg.chat.id, user.id), Box::new(change)) {
+        Some(draft) => draft,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "This menu has expired, use /dca to open a new one",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    match GLOBAL_DCA_MENU_STORAGE.get((msg.chat.id, user.id)) {
+        Some(menu) => {
+            let (menu_msg, keyboard) = dca_menu(&draft);
+            bot.edit_message_text(msg.chat.id, menu.message_id, menu_msg)
+                .reply_markup(keyboard)
+                .await?;
+            delete_up_to_messages(bot, msg.chat.id.0, msg.id.0, menu.message_id.0).await?;
+        }
+        None => log::warn!("message not found"),
+    }
+    Ok(())
 }
 
 pub(crate) async fn buy_address_dialogue_handler(
@@ -47,7 +222,7 @@ pub(crate) async fn buy_address_dialogue_handler(
 ) -> Result<(), TgError> {
     bot.send_message(
         msg.chat.id,
-        "Enter the address of the token you want to trade",
+        "Enter the address or symbol of the token you want to trade",
     )
     .await?;
 
@@ -71,66 +246,229 @@ pub(crate) async fn buy_address_or_token_handler(
         }
     };
 
-    // Checks if it's valid address
-    if text.starts_with("0x") && Address::from_str(text).is_ok() {
-        let menu_msg = on_chain::get_on_chain_info().await?;
-
-        if let Some(menu) = GLOBAL_BUY_MENU_STORAGE.get(BOT_NAME.to_string()) {
-            let buy_sell_msg = menu.message;
-            let buy_sell_msg_id = menu.message_id;
-            let keyboard = find_keyboard_from_message(&buy_sell_msg)?;
-            let mut new_keyboard = keyboard.clone();
+    // Gets the dialogue state
+    let is_buy_token = match dialogue.get().await? {
+        Some(
+            PromptDialogueState::BuyAddressReceived | PromptDialogueState::BuyTokenNameReceived,
+        ) => true,
+        Some(
+            PromptDialogueState::ReceiveAddressReceived
+            | PromptDialogueState::ReceiveTokenNameReceived,
+        ) => false,
+        _ => {
+            log::warn!("No dialogue found");
+            return Ok(());
+        }
+    };
 
-            // Gets the dialogue state
-            match dialogue.get().await? {
-                Some(PromptDialogueState::BuyAddressReceived) => {
-                    let new_button_text = format!("{}", text);
-                    if let Some(button) = new_keyboard
-                        .inline_keyboard
-                        .get_mut(4)
-                        .and_then(|row| row.get_mut(0))
-                    {
-                        button.text = new_button_text.to_string();
-                        button.kind = InlineKeyboardButtonKind::CallbackData(BUY_TOKEN.to_string());
-                    };
-                }
-                Some(PromptDialogueState::ReceiveAddressReceived) => {
-                    let new_button_text = format!("{}", text);
-                    if let Some(button) = new_keyboard
-                        .inline_keyboard
-                        .get_mut(4)
-                        .and_then(|row| row.get_mut(1))
-                    {
-                        button.text = new_button_text.to_string();
-                        button.kind =
-                            InlineKeyboardButtonKind::CallbackData(RECEIVE_TOKEN.to_string());
-                    };
+    // Takes a valid ERC-20 address, otherwise looks the symbol or name up in the token lists
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let token = match Address::from_str(text) {
+        Ok(token) if text.starts_with("0x") => match on_chain::token_metadata(chain, token).await {
+            Ok(metadata) => metadata.address,
+            Err(err) => {
+                bot.send_message(msg.chat.id, err.to_string()).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            let tokens = GLOBAL_TOKEN_REGISTRY.search(chain.chain_id, text);
+            match tokens.as_slice() {
+                [] => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "No token named {} on {}, enter its address",
+                            text, chain.name
+                        ),
+                    )
+                    .await?;
+                    return Ok(());
                 }
+                [token] => token.address,
                 _ => {
-                    log::warn!("No dialogue found")
+                    bot.send_message(
+                        msg.chat.id,
+                        format!("Several tokens are named {}, pick one", text),
+                    )
+                    .reply_markup(token_chooser_keyboard(&tokens, is_buy_token))
+                    .await?;
+                    dialogue
+                        .update(match is_buy_token {
+                            true => PromptDialogueState::BuyTokenNameReceived,
+                            false => PromptDialogueState::ReceiveTokenNameReceived,
+                        })
+                        .await?;
+                    return Ok(());
                 }
             }
+        }
+    };
 
-            // Edit the message with the new keyboard
-            bot.edit_message_text(msg.chat.id, buy_sell_msg_id, menu_msg)
-                .parse_mode(ParseMode::MarkdownV2)
-                .reply_markup(new_keyboard)
+    refresh_buy_menu(&bot, &msg, |draft| match is_buy_token {
+        true => draft.buy_token = Some(token),
+        false => draft.receive_token = Some(token),
+    })
+    .await?;
+    dialogue.exit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn buy_amount_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let text = match msg.text() {
+        Some(t) => t,
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+    // Checks if it's a positive numeric value
+    match text.parse::<f64>() {
+        Ok(amount) if amount > 0.0 => {
+            refresh_buy_menu(&bot, &msg, |draft| draft.buy_amount = Some(amount)).await?;
+            dialogue.exit().await?;
+        }
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter a positive numeric value")
                 .await?;
+        }
+    };
+
+    Ok(())
+}
+
+pub(crate) async fn sell_address_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let text = match msg.text() {
+        Some(t) => t,
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+
+    // Checks if it's the address of an ERC-20 token
+    let token = match Address::from_str(text) {
+        Ok(token) if text.starts_with("0x") => token,
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter valid address")
+                .await?;
+            return Ok(());
+        }
+    };
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    match on_chain::token_metadata(chain, token).await {
+        Ok(_) => {
+            refresh_sell_menu(&bot, &msg, |draft| draft.sell_token = Some(token)).await?;
             dialogue.exit().await?;
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_string()).await?;
+        }
+    };
 
-            let _ = delete_up_to_messages(&bot, msg.chat.id.0, msg.id.0, buy_sell_msg_id.0).await?;
-        } else {
-            log::warn!("message not found");
+    Ok(())
+}
+
+pub(crate) async fn sell_amount_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let text = match msg.text() {
+        Some(t) => t,
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+
+    // Checks if it's a positive numeric value
+    match text.parse::<f64>() {
+        Ok(amount) if amount > 0.0 => {
+            // An exact amount replaces the selected percentage
+            refresh_sell_menu(&bot, &msg, |draft| draft.amount = SellAmount::Exact(amount)).await?;
+            dialogue.exit().await?;
+        }
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter a positive numeric value")
+                .await?;
         }
-    } else {
-        bot.send_message(msg.chat.id, "Please enter valid address")
-            .await?;
     };
 
     Ok(())
 }
 
-pub(crate) async fn buy_amount_dialogue_handler(
+pub(crate) async fn wallet_name_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+    slot: u8,
+) -> Result<(), TgError> {
+    let (text, user) = match (msg.text(), msg.from()) {
+        (Some(text), Some(user)) => (text, user),
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+
+    match GLOBAL_WALLET_STORE.rename(user.id, slot, text) {
+        Ok(()) => {
+            dialogue.exit().await?;
+            send_wallets_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_string()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn slippage_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let (text, user) = match (msg.text(), msg.from()) {
+        (Some(text), Some(user)) => (text, user),
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+
+    let slippage_bps = match parse_slippage(text) {
+        Ok(slippage_bps) => slippage_bps,
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    match GLOBAL_SETTINGS_STORE.set_slippage(user.id, slippage_bps) {
+        Ok(()) => {
+            dialogue.exit().await?;
+            send_settings_menu(&bot, msg.chat.id, user.id).await?;
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_string()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn limit_token_dialogue_handler(
     bot: Bot,
     dialogue: BuyAddressPromptDialogue,
     msg: Message,
@@ -142,44 +480,143 @@ pub(crate) async fn buy_amount_dialogue_handler(
             return Ok(());
         }
     };
-    let is_numeric =
-        |value: &str| -> bool { value.parse::<f64>().is_ok() || value.parse::<i64>().is_ok() };
-
-    // Checks if it's is numeric value
-    if is_numeric(text) {
-        let menu_msg = on_chain::get_on_chain_info().await?;
-
-        if let Some(menu) = GLOBAL_BUY_MENU_STORAGE.get(BOT_NAME.to_string()) {
-            let buy_sell_msg = menu.message;
-            let buy_sell_msg_id = menu.message_id;
-            let keyboard = find_keyboard_from_message(&buy_sell_msg)?;
-            let mut new_keyboard = keyboard.clone();
-
-            // Gets the dialogue state
-            let new_button_text = format!("{}", text);
-            if let Some(button) = new_keyboard
-                .inline_keyboard
-                .get_mut(5)
-                .and_then(|row| row.get_mut(0))
-            {
-                button.text = new_button_text.to_string();
-                button.kind = InlineKeyboardButtonKind::CallbackData(BUY_TOKEN.to_string());
-            };
-            // Edit the message with the new keyboard
-            bot.edit_message_text(msg.chat.id, buy_sell_msg_id, menu_msg)
-                .parse_mode(ParseMode::MarkdownV2)
-                .reply_markup(new_keyboard)
+
+    // Checks if it's the address of an ERC-20 token
+    let token = match Address::from_str(text) {
+        Ok(token) if text.starts_with("0x") => token,
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter valid address")
                 .await?;
+            return Ok(());
+        }
+    };
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    match on_chain::token_metadata(chain, token).await {
+        Ok(_) => {
+            refresh_limit_menu(&bot, &msg, |draft| draft.token = Some(token)).await?;
             dialogue.exit().await?;
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_string()).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Takes the amount or the trigger price of the limit order, depending on the prompt
+pub(crate) async fn limit_value_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let text = match msg.text() {
+        Some(t) => t,
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
 
-            let _ = delete_up_to_messages(&bot, msg.chat.id.0, msg.id.0, buy_sell_msg_id.0).await?;
-        } else {
-            log::warn!("message not found");
+    // Checks if it's a positive numeric value
+    let value = match text.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => value,
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter a positive numeric value")
+                .await?;
+            return Ok(());
+        }
+    };
+    match dialogue.get().await? {
+        Some(PromptDialogueState::LimitAmountReceived) => {
+            refresh_limit_menu(&bot, &msg, |draft| draft.amount = Some(value)).await?
+        }
+        Some(PromptDialogueState::LimitPriceReceived) => {
+            refresh_limit_menu(&bot, &msg, |draft| draft.trigger_price = Some(value)).await?
+        }
+        _ => log::warn!("No dialogue found"),
+    }
+    dialogue.exit().await?;
+
+    Ok(())
+}
+
+/// Takes the buy or receive token of the DCA schedule, depending on the prompt.
+/// The native coin's symbol pays with the native coin
+pub(crate) async fn dca_token_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let text = match msg.text() {
+        Some(t) => t.trim(),
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let state = dialogue.get().await?;
+
+    if matches!(state, Some(PromptDialogueState::DcaBuyTokenReceived))
+        && text.eq_ignore_ascii_case(&chain.native_symbol)
+    {
+        refresh_dca_menu(&bot, &msg, |draft| draft.buy_token = None).await?;
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    // Checks if it's the address of an ERC-20 token
+    let token = match Address::from_str(text) {
+        Ok(token) if text.starts_with("0x") => token,
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter valid address")
+                .await?;
+            return Ok(());
+        }
+    };
+    if let Err(err) = on_chain::token_metadata(chain, token).await {
+        bot.send_message(msg.chat.id, err.to_string()).await?;
+        return Ok(());
+    }
+    match state {
+        Some(PromptDialogueState::DcaBuyTokenReceived) => {
+            refresh_dca_menu(&bot, &msg, |draft| draft.buy_token = Some(token)).await?
+        }
+        Some(PromptDialogueState::DcaReceiveTokenReceived) => {
+            refresh_dca_menu(&bot, &msg, |draft| draft.receive_token = Some(token)).await?
+        }
+        _ => log::warn!("No dialogue found"),
+    }
+    dialogue.exit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn dca_amount_dialogue_handler(
+    bot: Bot,
+    dialogue: BuyAddressPromptDialogue,
+    msg: Message,
+) -> Result<(), TgError> {
+    let text = match msg.text() {
+        Some(t) => t,
+        _ => {
+            bot.send_message(msg.chat.id, "Send me plain text.").await?;
+            return Ok(());
+        }
+    };
+
+    // Checks if it's a positive numeric value
+    let amount = match text.trim().parse::<f64>() {
+        Ok(amount) if amount > 0.0 && amount.is_finite() => amount,
+        _ => {
+            bot.send_message(msg.chat.id, "Please enter a positive numeric value")
+                .await?;
+            return Ok(());
         }
-    } else {
-        bot.send_message(msg.chat.id, "Please enter numeric value")
-            .await?;
     };
+    refresh_dca_menu(&bot, &msg, |draft| draft.amount = Some(amount)).await?;
+    dialogue.exit().await?;
 
     Ok(())
 }
@@ -1,65 +1,338 @@
 pub(crate) mod callback_handlers;
 pub(crate) mod dialogue_handlers;
 
+use crate::approvals::GLOBAL_APPROVAL_BOOK;
 use crate::bot::TgError;
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::dca::{format_interval, GLOBAL_DCA_BOOK};
+use crate::drafts::{BuyOrderDraft, DcaDraft, LimitOrderDraft};
+use crate::exit_orders::{self, GLOBAL_EXIT_ORDER_BOOK};
+use crate::keyboards::approval_buttons::approvals_keyboard;
+use crate::keyboards::callback_data::CallbackData;
+use crate::keyboards::dca_buttons::{dca_keyboard, schedules_keyboard};
+use crate::keyboards::exit_buttons::position_keyboard;
+use crate::keyboards::history_buttons::history_keyboard;
+use crate::keyboards::limit_buttons::{limit_order_keyboard, orders_keyboard};
+use crate::keyboards::rebate_buttons::rebates_keyboard;
+use crate::keyboards::settings_buttons::settings_keyboard;
+use crate::keyboards::token_label;
+use crate::keyboards::wallet_buttons::wallets_keyboard;
+use crate::ledger::{self, GLOBAL_TRADE_LEDGER};
+use crate::limit_orders::{format_price, LimitSide, GLOBAL_LIMIT_ORDER_BOOK};
+use crate::rebates::GLOBAL_REBATE_BOOK;
+use crate::requests::on_chain::format_amount;
+use crate::requests::server::{SendBuyTxRequest, SendSellTxRequest};
+use crate::requests::swap::{self, ApprovalMode, SentSwap};
+use crate::requests::token_safety::{self, RiskLevel};
+use crate::requests::{on_chain, quote};
+use crate::settings::{format_deadline, format_max_fee, format_slippage, GLOBAL_SETTINGS_STORE};
+use crate::storages::{SessionKey, GLOBAL_BUY_ORDER_DRAFTS};
+use crate::tracker;
+use crate::wallets::GLOBAL_WALLET_STORE;
+use ethers::types::{TransactionReceipt, TxHash};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::sync::Arc;
 use teloxide::{
+    payloads::SendMessageSetters,
     prelude::Requester,
-    types::{CallbackQuery, ChatId, InlineKeyboardMarkup, Message, MessageId},
+    types::{
+        CallbackQuery, ChatId, InlineKeyboardButtonKind, InlineKeyboardMarkup, Message, MessageId,
+        ParseMode, UserId,
+    },
     Bot,
 };
 use tokio::time::{sleep, Duration};
 
+/// Number of trades on one page of /history
+pub(crate) const HISTORY_PAGE_SIZE: usize = 5;
+
+/// Held while a swap of the wallet is being sent
+type WalletLock = Arc<tokio::sync::Mutex<()>>;
+
+lazy_static! {
+    /// Lock of each wallet, see [wallet_lock]
+    static ref GLOBAL_WALLET_LOCKS: Mutex<HashMap<(UserId, u8), WalletLock>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Lock of one of the user's wallets, its swaps are sent one at a time so the menus, orders and
+/// DCA legs never pick the same nonce
+fn wallet_lock(user_id: UserId, slot: u8) -> WalletLock {
+    GLOBAL_WALLET_LOCKS
+        .lock()
+        .entry((user_id, slot))
+        .or_default()
+        .clone()
+}
+
 #[derive(Debug)]
 pub(crate) enum SubMenuType {
     SendBuyTx,
     SendSellTx,
 }
 
-/// Gets the last vec in the larger vec in the InlineKeyboardMarkup. See https://docs.rs/teloxide/latest/teloxide/types/struct.InlineKeyboardMarkup.html
-/// Gets the last button in the last vec, which should either be "Send Buy Tx" or "Send Sell Tx"
+/// Gets the menu a sub-menu callback belongs to from its callback data
+fn sub_menu_type(data: &str) -> Option<SubMenuType> {
+    match CallbackData::decode(data) {
+        Ok(CallbackData::Buy(_)) => Some(SubMenuType::SendBuyTx),
+        Ok(CallbackData::Sell(_)) => Some(SubMenuType::SendSellTx),
+        _ => None,
+    }
+}
+
+/// Gets the menu the clicked button belongs to
 pub(crate) fn find_sub_menu_type_from_callback(q: &CallbackQuery) -> anyhow::Result<SubMenuType> {
-    q.message
-        .as_ref()
-        .and_then(|msg| msg.reply_markup())
-        .and_then(|keyboard| keyboard.inline_keyboard.last())
-        .and_then(|last_vec| last_vec.last())
-        .and_then(|last_button| match last_button.text.as_str() {
-            "Send Buy Tx" => Some(SubMenuType::SendBuyTx),
-            "Send Sell Tx" => Some(SubMenuType::SendSellTx),
-            _ => None,
-        })
+    q.data
+        .as_deref()
+        .and_then(sub_menu_type)
         .ok_or_else(|| anyhow::anyhow!("find_sub_menu_type_from_callback: No valid sub menu found"))
 }
 
+/// Gets the last vec in the larger vec in the InlineKeyboardMarkup. See https://docs.rs/teloxide/latest/teloxide/types/struct.InlineKeyboardMarkup.html
+/// Gets the menu from the callback data of the last button in the last vec, the Send Buy Tx or Send Sell Tx button
 pub(crate) fn find_sub_menu_type_from_message(msg: &Message) -> anyhow::Result<SubMenuType> {
     msg.reply_markup()
         .and_then(|keyboard| keyboard.inline_keyboard.last())
         .and_then(|last_vec| last_vec.last())
-        .and_then(|last_button| match last_button.text.as_str() {
-            "Send Buy Tx" => Some(SubMenuType::SendBuyTx),
-            "Send Sell Tx" => Some(SubMenuType::SendSellTx),
+        .and_then(|last_button| match &last_button.kind {
+            InlineKeyboardButtonKind::CallbackData(data) => sub_menu_type(data),
             _ => None,
         })
         .ok_or_else(|| anyhow::anyhow!("find_sub_menu_type_from_message: No valid sub menu found"))
 }
 
-pub(crate) fn find_keyboard_from_callback(
-    q: &CallbackQuery,
-) -> anyhow::Result<&InlineKeyboardMarkup> {
-    q.message
-        .as_ref()
-        .and_then(|msg| msg.reply_markup())
-        .and_then(|keyboard| Some(keyboard))
-        .ok_or_else(|| anyhow::anyhow!("find_sub_menu_type_from_callback: No valid sub menu found"))
+/// Checks the receive token of the buy order for a honeypot, taxes and limits once it changes
+async fn check_receive_token(session: SessionKey, draft: BuyOrderDraft) -> BuyOrderDraft {
+    let Some(receive) = draft.receive_token else {
+        return draft;
+    };
+    if draft.current_risk().is_some() {
+        return draft;
+    }
+
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let risk = match token_safety::check_token(chain, receive).await {
+        Ok(risk) => Some(risk),
+        Err(err) => {
+            log::warn!(
+                "token check of {:?} on {} failed: {}",
+                receive,
+                chain.name,
+                err
+            );
+            None
+        }
+    };
+    GLOBAL_BUY_ORDER_DRAFTS
+        .update(session, Box::new(move |draft| draft.risk = risk))
+        .unwrap_or(draft)
 }
 
-pub(crate) fn find_keyboard_from_message(msg: &Message) -> anyhow::Result<&InlineKeyboardMarkup> {
-    msg.reply_markup()
-        .ok_or_else(|| anyhow::anyhow!("find_keyboard_from_message: No valid sub menu found"))
+/// Quotes the buy order again when its tokens or amount have changed since the last quote,
+/// `force` quotes it even when the last quote is still for them
+pub(crate) async fn requote_buy_draft(
+    session: SessionKey,
+    draft: BuyOrderDraft,
+    force: bool,
+) -> BuyOrderDraft {
+    let draft = check_receive_token(session, draft).await;
+    let Some((buy, receive, amount)) = draft.quote_inputs() else {
+        return draft;
+    };
+    if !force && draft.current_quote().is_some() {
+        return draft;
+    }
+
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let quote = match quote::quote_buy(chain, buy, receive, amount).await {
+        Ok(quote) => Some(quote),
+        Err(err) => {
+            log::warn!("buy quote on {} failed: {}", chain.name, err);
+            None
+        }
+    };
+    GLOBAL_BUY_ORDER_DRAFTS
+        .update(session, Box::new(move |draft| draft.quote = quote))
+        .unwrap_or(draft)
+}
+
+/// Broadcasts a buy with the user's wallet of the request and records it in the ledger,
+/// every buy goes through here whether it comes from the buy menu or an order.
+/// `risk_shown` is set by the buy menu, it already showed the token check and asked the user
+/// before buying a high risk token. Orders buy unattended, they run the check here and don't
+/// buy a high risk token, a check that can't run doesn't hold them up, like on the buy menu
+pub(crate) async fn submit_buy(
+    user_id: UserId,
+    request: &SendBuyTxRequest,
+    risk_shown: bool,
+) -> anyhow::Result<SentSwap> {
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    if !risk_shown {
+        match token_safety::check_token(chain, request.receive).await {
+            Ok(risk) if risk.level() == RiskLevel::High => {
+                return Err(anyhow::anyhow!(
+                    "Not bought, the token check flags it\n{}",
+                    risk.details()
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!(
+                "token check of {:?} on {} failed: {}",
+                request.receive,
+                chain.name,
+                err
+            ),
+        }
+    }
+    let wallet = GLOBAL_WALLET_STORE.signer(user_id, request.wallet_slot)?;
+    if let Some(buy) = request.buy {
+        GLOBAL_APPROVAL_BOOK.track(
+            user_id,
+            chain.chain_id,
+            request.wallet_slot,
+            buy,
+            chain.token_spender()?,
+        )?;
+    }
+    let lock = wallet_lock(user_id, request.wallet_slot);
+    let sent = {
+        let _sending = lock.lock().await;
+        swap::send_buy_tx(chain, request, wallet).await?
+    };
+    GLOBAL_TRADE_LEDGER.record(user_id, &sent)?;
+    GLOBAL_WALLET_STORE.record_traded_tokens(user_id, sent.chain_id, &sent.erc20_tokens())?;
+    Ok(sent)
+}
+
+/// Broadcasts a sell with the user's wallet of the request and records it in the ledger,
+/// every sell goes through here whether it comes from the sell menu or an order
+pub(crate) async fn submit_sell(
+    user_id: UserId,
+    request: &SendSellTxRequest,
+) -> anyhow::Result<SentSwap> {
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let wallet = GLOBAL_WALLET_STORE.signer(user_id, request.wallet_slot)?;
+    // tracked before sending, the approval stays even when the swap fails
+    GLOBAL_APPROVAL_BOOK.track(
+        user_id,
+        chain.chain_id,
+        request.wallet_slot,
+        request.sell,
+        chain.token_spender()?,
+    )?;
+    let lock = wallet_lock(user_id, request.wallet_slot);
+    let sent = {
+        let _sending = lock.lock().await;
+        swap::send_sell_tx(chain, request, wallet).await?
+    };
+    GLOBAL_TRADE_LEDGER.record(user_id, &sent)?;
+    GLOBAL_WALLET_STORE.record_traded_tokens(user_id, sent.chain_id, &sent.erc20_tokens())?;
+    Ok(sent)
+}
+
+/// Rebuilds the swap an order sent before the bot restarted from its trade in the ledger, so it can
+/// be followed again. Orders send their swaps to the public mempool with the Rebate toggle off
+pub(crate) async fn resume_order_swap(tx_hash: TxHash) -> anyhow::Result<SentSwap> {
+    let trade = GLOBAL_TRADE_LEDGER
+        .find(tx_hash)?
+        .ok_or_else(|| anyhow::anyhow!("Trade {:?} was not recorded", tx_hash))?;
+    let chain = GLOBAL_CHAIN_REGISTRY
+        .get(trade.chain_id)
+        .ok_or_else(|| anyhow::anyhow!("Chain {} is not supported", trade.chain_id))?;
+    let native_payout = match trade.token_out.address {
+        Some(_) => None,
+        None => Some(swap::native_payout(chain).await?),
+    };
+    Ok(SentSwap {
+        chain_id: trade.chain_id,
+        wallet: trade.wallet,
+        tx_hash,
+        token_in: trade.token_in,
+        token_out: trade.token_out,
+        amount_in: trade.amount_in,
+        private_max_block: None,
+        rebate: false,
+        native_payout,
+    })
+}
+
+/// Opens the position of a mined buy and offers its stop loss, take profit and trailing stop presets
+async fn offer_exit_orders(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+    sent: &SentSwap,
+    receipt: &TransactionReceipt,
+) -> anyhow::Result<()> {
+    let chain = GLOBAL_CHAIN_REGISTRY
+        .get(sent.chain_id)
+        .ok_or_else(|| anyhow::anyhow!("Chain {} is not supported", sent.chain_id))?;
+    let entry_price = exit_orders::entry_price(chain, sent, receipt).await?;
+    let amount = swap::amount_received(sent, receipt);
+    let position =
+        GLOBAL_EXIT_ORDER_BOOK.open_position(user_id, chat_id, sent, entry_price, amount)?;
+    bot.send_message(
+        chat_id,
+        format!(
+            "Bought {} at {} {} each\nProtect the position with a stop loss, take profit or trailing stop, they sell the {} this buy received",
+            sent.token_out.symbol,
+            format_price(entry_price),
+            chain.native_symbol,
+            sent.token_out.symbol
+        ),
+    )
+    .reply_markup(position_keyboard(position, &[]))
+    .await?;
+    Ok(())
 }
 
-pub(crate) fn matching_sub_menu(_bot: &Bot, q: &CallbackQuery) -> Option<SubMenuType> {
-    find_sub_menu_type_from_callback(q).ok()
+/// Follows a sent swap until it is mined or dropped and shows its final status in place of `message`,
+/// then credits the rebate of a swap sent with Rebate on and offers exit orders on a buy.
+/// Returns the receipt of a swap that was mined and succeeded
+pub(crate) async fn track_sent_swap(
+    bot: Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+    sent: SentSwap,
+    message: Option<MessageId>,
+) -> Option<TransactionReceipt> {
+    let is_buy = sent.token_out.address.is_some();
+    let receipt = tracker::track_trade(&bot, chat_id, &sent, message)
+        .await
+        .ok()
+        .flatten()
+        .filter(|receipt| receipt.status == Some(1u64.into()))?;
+
+    if sent.rebate {
+        let fee =
+            receipt.gas_used.unwrap_or_default() * receipt.effective_gas_price.unwrap_or_default();
+        match GLOBAL_REBATE_BOOK.accrue(user_id, sent.chain_id, sent.tx_hash, fee) {
+            Ok(rebate) if !rebate.is_zero() => {
+                let native_symbol = GLOBAL_CHAIN_REGISTRY
+                    .get(sent.chain_id)
+                    .map_or("", |chain| chain.native_symbol.as_str());
+                let text = format!(
+                    "Rebate earned: {} {}, claim it with /rebates",
+                    format_amount(rebate, 18),
+                    native_symbol
+                );
+                if let Err(err) = bot.send_message(chat_id, text).await {
+                    log::warn!("rebate update not sent: {}", err);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => log::error!("rebate of {:?} not recorded: {}", sent.tx_hash, err),
+        }
+    }
+
+    if is_buy {
+        if let Err(err) = offer_exit_orders(&bot, chat_id, user_id, &sent, &receipt).await {
+            log::warn!("exit orders of {:?} not offered: {}", sent.tx_hash, err);
+        }
+    }
+    Some(receipt)
 }
 
 /// Helper function to delete number_of_deletes previous messages
@@ -94,3 +367,276 @@ pub(crate) async fn delete_up_to_messages(
     }
     Ok(())
 }
+
+/// Helper function to send the /wallets menu with the user's balances
+pub(crate) async fn send_wallets_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let wallets = GLOBAL_WALLET_STORE.wallets(user_id)?;
+    let wallets_msg = on_chain::get_wallets_info(user_id).await?;
+    bot.send_message(chat_id, wallets_msg)
+        .parse_mode(ParseMode::MarkdownV2)
+        .reply_markup(wallets_keyboard(&wallets))
+        .await?;
+    Ok(())
+}
+
+/// Renders one page of the user's trade history with its keyboard
+pub(crate) fn history_menu(
+    user_id: UserId,
+    page: usize,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let (trades, total) = GLOBAL_TRADE_LEDGER.page(user_id, page, HISTORY_PAGE_SIZE)?;
+    let total_pages = ledger::page_count(total, HISTORY_PAGE_SIZE);
+
+    let history_msg = match trades.is_empty() {
+        true => "No trades yet".to_string(),
+        false => trades
+            .iter()
+            .map(|trade| trade.summary())
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+    };
+    Ok((history_msg, history_keyboard(&trades, page, total_pages)))
+}
+
+/// Helper function to send the first page of /history
+pub(crate) async fn send_history_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let (history_msg, keyboard) = history_menu(user_id, 0)?;
+    bot.send_message(chat_id, history_msg)
+        .parse_mode(ParseMode::MarkdownV2)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Renders the user's /settings with its keyboard
+pub(crate) fn settings_menu(user_id: UserId) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+    let settings_msg = format!(
+        "Slippage: {}\nDeadline: {}\nGas: {}\nMax Fee: {}\n\nNew buy and sell orders start with these, each order can still change them on its menu. Trades are blocked while gas is above the max fee, below it the max fee caps what a trade may pay, so a trade can wait longer to be mined if gas rises",
+        format_slippage(settings.slippage_bps),
+        format_deadline(settings.deadline_secs),
+        settings.gas_preset.label(),
+        format_max_fee(settings.max_fee_gwei)
+    );
+    Ok((settings_msg, settings_keyboard(&settings)))
+}
+
+/// Helper function to send /settings
+pub(crate) async fn send_settings_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let (settings_msg, keyboard) = settings_menu(user_id)?;
+    bot.send_message(chat_id, settings_msg)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Renders the user's rebate tier and accrued balances with their claim buttons
+pub(crate) fn rebates_menu(user_id: UserId) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let trades = GLOBAL_REBATE_BOOK.trade_count(user_id)?;
+    let tier = GLOBAL_REBATE_BOOK.tier(trades);
+    let mut lines = vec![format!(
+        "Rebate: {} of the gas fee of each trade sent with Rebate on ({} rebated trades)",
+        tier.share(),
+        trades
+    )];
+    if let Some(next) = GLOBAL_REBATE_BOOK.next_tier(trades) {
+        lines.push(format!("{} from {} trades", next.share(), next.min_trades));
+    }
+
+    let balances = GLOBAL_REBATE_BOOK.balances(user_id)?;
+    lines.push(String::new());
+    match balances.is_empty() {
+        true => lines.push("No rebate to claim yet".to_string()),
+        false => lines.extend(balances.iter().filter_map(|balance| {
+            let chain = GLOBAL_CHAIN_REGISTRY.get(balance.chain_id)?;
+            Some(format!(
+                "{}: {} {}",
+                chain.name,
+                format_amount(balance.balance, 18),
+                chain.native_symbol
+            ))
+        })),
+    }
+    Ok((lines.join("\n"), rebates_keyboard(&balances)))
+}
+
+/// Helper function to send /rebates
+pub(crate) async fn send_rebates_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let (rebates_msg, keyboard) = rebates_menu(user_id)?;
+    bot.send_message(chat_id, rebates_msg)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Renders the Limit Buy or Limit Sell menu from the user's draft, with the token's current price once it is entered
+pub(crate) async fn limit_menu(draft: &LimitOrderDraft) -> (String, InlineKeyboardMarkup) {
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let mut lines = vec![match draft.side {
+        LimitSide::Buy => format!(
+            "Limit Buy\nBuys the token with {} once its price falls to the trigger price",
+            chain.native_symbol
+        ),
+        LimitSide::Sell => format!(
+            "Limit Sell\nSells the token for {} once its price rises to the trigger price",
+            chain.native_symbol
+        ),
+    }];
+    if let Some(token) = draft.token {
+        lines.push(match quote::token_price(chain, token).await {
+            Ok(price) => format!(
+                "\nPrice of {}: {} {}",
+                token_label(token),
+                format_price(price),
+                chain.native_symbol
+            ),
+            Err(err) => {
+                log::warn!("price of {:?} unavailable: {}", token, err);
+                format!("\nPrice of {}: unavailable", token_label(token))
+            }
+        });
+    }
+    (lines.join("\n"), limit_order_keyboard(draft))
+}
+
+/// Renders the user's open limit and exit orders with their cancel buttons
+pub(crate) fn orders_menu(user_id: UserId) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let orders = GLOBAL_LIMIT_ORDER_BOOK.open_orders(user_id)?;
+    let exit_orders = GLOBAL_EXIT_ORDER_BOOK.open_orders(user_id)?;
+    let summaries = orders
+        .iter()
+        .map(|order| order.summary())
+        .chain(exit_orders.iter().map(|order| order.summary()))
+        .collect::<Vec<String>>();
+    let orders_msg = match summaries.is_empty() {
+        true => "No open orders".to_string(),
+        false => summaries.join("\n\n"),
+    };
+    Ok((orders_msg, orders_keyboard(&orders, &exit_orders)))
+}
+
+/// Helper function to send /orders
+pub(crate) async fn send_orders_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let (orders_msg, keyboard) = orders_menu(user_id)?;
+    bot.send_message(chat_id, orders_msg)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Renders the DCA menu from the user's draft
+pub(crate) fn dca_menu(draft: &DcaDraft) -> (String, InlineKeyboardMarkup) {
+    let native_symbol = &GLOBAL_CHAIN_REGISTRY.trading_chain().native_symbol;
+    let mut lines = vec![format!(
+        "DCA\nBuys the receive token with the same amount every {}, {} times. The first leg runs as soon as the schedule is created",
+        format_interval(draft.interval_secs),
+        draft.runs
+    )];
+    if let Some(amount) = draft.amount {
+        let buy_label = draft
+            .buy_token
+            .map_or_else(|| native_symbol.clone(), token_label);
+        lines.push(format!(
+            "\nTotal: {} {}",
+            amount * draft.runs as f64,
+            buy_label
+        ));
+    }
+    if draft.buy_token.is_some() {
+        lines.push(match draft.approval {
+            ApprovalMode::Exact => "Each leg approves the router for its own amount".to_string(),
+            ApprovalMode::Unlimited => {
+                "The first leg approves the router for an unlimited amount, revoke it with /approvals"
+                    .to_string()
+            }
+        });
+    }
+    (lines.join("\n"), dca_keyboard(draft))
+}
+
+/// Renders the user's active and paused DCA schedules with their pause, resume and cancel buttons
+pub(crate) fn schedules_menu(user_id: UserId) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let schedules = GLOBAL_DCA_BOOK.schedules(user_id)?;
+    let schedules_msg = match schedules.is_empty() {
+        true => "No DCA schedules".to_string(),
+        false => schedules
+            .iter()
+            .map(|schedule| schedule.summary())
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+    };
+    Ok((schedules_msg, schedules_keyboard(&schedules)))
+}
+
+/// Helper function to send /dca
+pub(crate) async fn send_schedules_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let (schedules_msg, keyboard) = schedules_menu(user_id)?;
+    bot.send_message(chat_id, schedules_msg)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Renders /approvals for the wallet in the given slot, its allowances on the trading chain
+pub(crate) async fn approvals_menu(
+    user_id: UserId,
+    wallet_slot: u8,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    let allowances = GLOBAL_APPROVAL_BOOK
+        .allowances(user_id, wallet_slot)
+        .await?;
+    let wallet = GLOBAL_WALLET_STORE
+        .wallets(user_id)?
+        .into_iter()
+        .find(|wallet| wallet.slot == wallet_slot)
+        .ok_or_else(|| anyhow::anyhow!("Wallet not found"))?;
+    let mut lines = vec![format!("Allowances of {} on {}", wallet.name, chain.name)];
+    lines.push(String::new());
+    match allowances.is_empty() {
+        true => lines.push("No active allowances".to_string()),
+        false => lines.extend(allowances.iter().map(|allowance| allowance.summary(chain))),
+    }
+    Ok((
+        lines.join("\n"),
+        approvals_keyboard(wallet_slot, &allowances),
+    ))
+}
+
+/// Helper function to send /approvals, opened on the default wallet
+pub(crate) async fn send_approvals_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), TgError> {
+    let wallet_slot = GLOBAL_WALLET_STORE.default_slot(user_id)?;
+    let (approvals_msg, keyboard) = approvals_menu(user_id, wallet_slot).await?;
+    bot.send_message(chat_id, approvals_msg)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
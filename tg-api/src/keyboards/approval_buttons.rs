@@ -0,0 +1,92 @@
+use crate::approvals::Allowance;
+use crate::consts::CLOSE;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, I64_LEN};
+use crate::keyboards::{add_emoji, wallet_row, MenuButtons};
+use crate::requests::swap::ApprovalMode;
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the /approvals menu and of the prompt to approve the router before a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ApprovalButtons {
+    /// Lists the allowances of the wallet in the given slot
+    Wallet(u8),
+    /// Sets the allowance with the given id back to 0
+    Revoke(i64),
+    /// Approves the router, then sends the swap of the sell menu when `sell` is set, of the buy menu otherwise
+    Approve { sell: bool, mode: ApprovalMode },
+}
+
+impl CallbackAction for ApprovalButtons {
+    const NAMESPACE: &'static str = "a";
+    const MAX_ACTION_LEN: usize = "revoke:".len() + I64_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Wallet(slot) => format!("wallet:{}", slot),
+            Self::Revoke(id) => format!("revoke:{}", id),
+            Self::Approve { sell, mode } => {
+                let menu = match sell {
+                    true => "sell",
+                    false => "buy",
+                };
+                format!("approve:{}:{}", menu, mode.key())
+            }
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("wallet", [slot]) => Some(Self::Wallet(slot.parse().ok()?)),
+            ("revoke", [id]) => Some(Self::Revoke(id.parse().ok()?)),
+            ("approve", [menu, mode]) => Some(Self::Approve {
+                sell: match *menu {
+                    "sell" => true,
+                    "buy" => false,
+                    _ => return None,
+                },
+                mode: ApprovalMode::from_key(mode)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Create the /approvals keyboard, the wallet picker and a revoke button per allowance
+pub(crate) fn approvals_keyboard(
+    wallet_slot: u8,
+    allowances: &[Allowance],
+) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    keyboard = keyboard.append_row(wallet_row(wallet_slot, ApprovalButtons::Wallet));
+    for allowance in allowances {
+        keyboard = keyboard.append_row(vec![callback_button(
+            format!("🗑 Revoke {}", allowance.token.symbol),
+            ApprovalButtons::Revoke(allowance.id),
+        )]);
+    }
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
+
+/// Create the keyboard asking how to approve the router before sending the swap
+pub(crate) fn approve_keyboard(sell: bool) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default()
+        .append_row(vec![
+            callback_button(
+                "Approve Exact",
+                ApprovalButtons::Approve {
+                    sell,
+                    mode: ApprovalMode::Exact,
+                },
+            ),
+            callback_button(
+                "Approve Unlimited",
+                ApprovalButtons::Approve {
+                    sell,
+                    mode: ApprovalMode::Unlimited,
+                },
+            ),
+        ])
+        .append_row(vec![callback_button(CLOSE, MenuButtons::Close)])
+}
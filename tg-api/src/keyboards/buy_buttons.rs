@@ -1,161 +1,200 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
 use crate::consts::{
-    BUY_AMOUNT, BUY_TOKEN, CLOSE, ESTIMATED_RECEIVED_AMOUNT, MAIN_MENU, PRIVATE_TX, REBATE,
-    RECEIVE_TOKEN, SEND_BUY_TX, SEND_SELL_TX, WALLET_1, WALLET_2, WALLET_3,
+    BUY_AMOUNT, BUY_TOKEN, CLOSE, ESTIMATED_RECEIVED_AMOUNT, PRIVATE_TX, REBATE, RECEIVE_TOKEN,
+    SEND_BUY_TX,
 };
-use crate::keyboards::add_emoji;
-use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use crate::drafts::BuyOrderDraft;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, ADDRESS_LEN};
+use crate::keyboards::{
+    labeled_value, navigation_row, order_settings_row, selectable_button, token_label, wallet_row,
+    MenuButtons,
+};
+use crate::tokens::TokenInfo;
+use ethers::types::Address;
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Most tokens offered when several of them match the name the user typed
+pub(crate) const MAX_TOKEN_CHOICES: usize = 8;
 
-#[derive(Debug, Clone)]
-pub(crate) enum BuyButtons<'a> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BuyButtons {
     SendBuyTx,
-    SendSellTx,
-    MainMenu,
-    Close,
-    PrivateTx(&'a str),
-    Rebate(&'a str),
-    Wallet1(&'a str),
-    Wallet2(&'a str),
-    Wallet3(&'a str),
+    PrivateTx,
+    Rebate,
+    /// Selects the wallet in the given slot
+    Wallet(u8),
     BuyToken,
     ReceiveToken,
     BuyAmount,
     EstimatedReceivedAmount,
+    /// Moves the order's slippage to the next preset
+    Slippage,
+    /// Moves the order's deadline to the next preset
+    Deadline,
+    /// Moves the order's gas preset to the next one
+    Gas,
+    /// Picks the buy token out of the tokens matching the name the user typed
+    PickBuyToken(Address),
+    /// Picks the receive token out of the tokens matching the name the user typed
+    PickReceiveToken(Address),
+    /// Shows the findings of the receive token's risk check
+    Risk,
+    /// Sends the buy of a high risk receive token, the token is checked against the order's
+    ConfirmRisk(Address),
 }
 
-impl<'a> BuyButtons<'a> {
-    pub(crate) fn new(text: &'a str) -> Self {
-        match text {
-            t if t == SEND_BUY_TX || t == add_emoji(SEND_BUY_TX).as_str() => Self::SendBuyTx,
-            t if t == SEND_SELL_TX || t == add_emoji(SEND_SELL_TX).as_str() => Self::SendSellTx,
-            t if t == MAIN_MENU || t == add_emoji(MAIN_MENU).as_str() => Self::MainMenu,
-            t if t == CLOSE || t == add_emoji(CLOSE).as_str() => Self::Close,
-            t if t == PRIVATE_TX || t == add_emoji(PRIVATE_TX).as_str() => Self::PrivateTx(text),
-            t if t == REBATE || t == add_emoji(REBATE).as_str() => Self::Rebate(text),
-            t if t == WALLET_1 || t == add_emoji(WALLET_1).as_str() => Self::Wallet1(text),
-            t if t == WALLET_2 || t == add_emoji(WALLET_2).as_str() => Self::Wallet2(text),
-            t if t == WALLET_3 || t == add_emoji(WALLET_3).as_str() => Self::Wallet3(text),
-            BUY_TOKEN => Self::BuyToken,
-            RECEIVE_TOKEN => Self::ReceiveToken,
-            BUY_AMOUNT => Self::BuyAmount,
-            ESTIMATED_RECEIVED_AMOUNT => Self::EstimatedReceivedAmount,
-            _ => Self::SendSellTx,
-        }
-    }
+impl CallbackAction for BuyButtons {
+    const NAMESPACE: &'static str = "b";
+    const MAX_ACTION_LEN: usize = "pick_receive:".len() + ADDRESS_LEN;
 
-    pub(crate) fn toggle(&self) -> String {
+    fn encode_action(&self) -> String {
         match self {
-            Self::PrivateTx(text) => self.toggle_text(text, PRIVATE_TX),
-            Self::Rebate(text) => self.toggle_text(text, REBATE),
-            Self::Wallet1(text) => self.toggle_text(text, WALLET_1),
-            Self::Wallet2(text) => self.toggle_text(text, WALLET_2),
-            Self::Wallet3(text) => self.toggle_text(text, WALLET_3),
-            _ => format!("{:?}", self),
+            Self::SendBuyTx => "send".to_string(),
+            Self::PrivateTx => "toggle_private".to_string(),
+            Self::Rebate => "toggle_rebate".to_string(),
+            Self::Wallet(slot) => format!("wallet:{}", slot),
+            Self::BuyToken => "buy_token".to_string(),
+            Self::ReceiveToken => "receive_token".to_string(),
+            Self::BuyAmount => "amount".to_string(),
+            Self::EstimatedReceivedAmount => "estimate".to_string(),
+            Self::Slippage => "slippage".to_string(),
+            Self::Deadline => "deadline".to_string(),
+            Self::Gas => "gas".to_string(),
+            Self::PickBuyToken(token) => format!("pick_buy:{:?}", token),
+            Self::PickReceiveToken(token) => format!("pick_receive:{:?}", token),
+            Self::Risk => "risk".to_string(),
+            Self::ConfirmRisk(token) => format!("confirm:{:?}", token),
         }
     }
 
-    fn toggle_text(&self, current: &str, default: &str) -> String {
-        if current == default {
-            add_emoji(default)
-        } else {
-            default.to_string()
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("send", []) => Some(Self::SendBuyTx),
+            ("toggle_private", []) => Some(Self::PrivateTx),
+            ("toggle_rebate", []) => Some(Self::Rebate),
+            ("wallet", [slot]) => Some(Self::Wallet(slot.parse().ok()?)),
+            ("buy_token", []) => Some(Self::BuyToken),
+            ("receive_token", []) => Some(Self::ReceiveToken),
+            ("amount", []) => Some(Self::BuyAmount),
+            ("estimate", []) => Some(Self::EstimatedReceivedAmount),
+            ("slippage", []) => Some(Self::Slippage),
+            ("deadline", []) => Some(Self::Deadline),
+            ("gas", []) => Some(Self::Gas),
+            ("pick_buy", [token]) => Some(Self::PickBuyToken(token.parse().ok()?)),
+            ("pick_receive", [token]) => Some(Self::PickReceiveToken(token.parse().ok()?)),
+            ("risk", []) => Some(Self::Risk),
+            ("confirm", [token]) => Some(Self::ConfirmRisk(token.parse().ok()?)),
+            _ => None,
         }
     }
 }
 
-/// Create the Buy keyboard layout
-/// Note: any change to this function will affect the handle_send_tx function() and handle_private_tx_callback()
-fn create_buy_keyboard(
-    private_tx: bool,
-    rebate: bool,
-    wallet1: bool,
-    wallet2: bool,
-    wallet3: bool,
-) -> anyhow::Result<InlineKeyboardMarkup> {
-    if [wallet1, wallet2, wallet3].iter().filter(|&&x| x).count() != 1 {
-        return Err(anyhow::anyhow!("Only one wallet can be selected"));
-    };
-
+/// Create the Buy keyboard layout from the user's draft
+pub(crate) fn buy_keyboard(draft: &BuyOrderDraft) -> InlineKeyboardMarkup {
     let mut keyboard = InlineKeyboardMarkup::default();
 
     // 1st row
-    keyboard = keyboard.append_row(vec![
-        // no need to add emoji in the callback value
-        InlineKeyboardButton::callback(add_emoji(MAIN_MENU), MAIN_MENU.to_owned()),
-        InlineKeyboardButton::callback(add_emoji(CLOSE), CLOSE.to_owned()),
-    ]);
-
-    // 2nd row
-    keyboard = keyboard.append_row(vec![
-        match private_tx {
-            true => InlineKeyboardButton::callback(add_emoji(PRIVATE_TX), add_emoji(PRIVATE_TX)),
-            false => InlineKeyboardButton::callback(PRIVATE_TX.to_owned(), PRIVATE_TX.to_owned()),
-        },
-        match rebate {
-            true => InlineKeyboardButton::callback(add_emoji(REBATE), add_emoji(REBATE)),
-            false => InlineKeyboardButton::callback(REBATE.to_owned(), REBATE.to_owned()),
-        },
-    ]);
+    keyboard = keyboard.append_row(navigation_row());
+
+    // 2nd row, Private Tx is only offered on chains with private relays
+    let mut toggles = vec![selectable_button(REBATE, draft.rebate, BuyButtons::Rebate)];
+    if GLOBAL_CHAIN_REGISTRY.trading_chain().has_private_relays() {
+        toggles.insert(
+            0,
+            selectable_button(PRIVATE_TX, draft.private_tx, BuyButtons::PrivateTx),
+        );
+    }
+    keyboard = keyboard.append_row(toggles);
 
     // 3rd row
-    keyboard = keyboard.append_row(vec![InlineKeyboardButton::callback(
-        "=Select Wallet=".to_owned(),
-        "=Select Wallet=".to_owned(),
-    )]);
+    keyboard = keyboard.append_row(vec![callback_button("=Select Wallet=", MenuButtons::Noop)]);
 
     // 4th row
-    // Default selection to wallet 1
-    keyboard = keyboard.append_row(vec![
-        match wallet1 {
-            true => InlineKeyboardButton::callback(add_emoji(WALLET_1), add_emoji(WALLET_1)),
-            false => InlineKeyboardButton::callback(WALLET_1.to_owned(), WALLET_1.to_owned()),
-        },
-        match wallet2 {
-            true => InlineKeyboardButton::callback(add_emoji(WALLET_2), add_emoji(WALLET_2)),
-            false => InlineKeyboardButton::callback(WALLET_2.to_owned(), WALLET_2.to_owned()),
-        },
-        match wallet3 {
-            true => InlineKeyboardButton::callback(add_emoji(WALLET_3), add_emoji(WALLET_3)),
-            false => InlineKeyboardButton::callback(WALLET_3.to_owned(), WALLET_3.to_owned()),
-        },
-    ]);
+    keyboard = keyboard.append_row(wallet_row(draft.wallet_slot, BuyButtons::Wallet));
 
     // 5th row
     keyboard = keyboard.append_row(vec![
-        InlineKeyboardButton::callback(BUY_TOKEN.to_owned(), BUY_TOKEN.to_owned()),
-        InlineKeyboardButton::callback(RECEIVE_TOKEN.to_owned(), RECEIVE_TOKEN.to_owned()),
+        callback_button(
+            labeled_value(BUY_TOKEN, draft.buy_token.map(token_label)),
+            BuyButtons::BuyToken,
+        ),
+        callback_button(
+            labeled_value(RECEIVE_TOKEN, draft.receive_token.map(token_label)),
+            BuyButtons::ReceiveToken,
+        ),
     ]);
 
     // 6th row
-    keyboard = keyboard.append_row(vec![InlineKeyboardButton::callback(
-        BUY_AMOUNT.to_owned(),
-        BUY_AMOUNT.to_owned(),
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(BUY_AMOUNT, draft.buy_amount),
+        BuyButtons::BuyAmount,
     )]);
 
     // 7th row
-    keyboard = keyboard.append_row(vec![InlineKeyboardButton::callback(
-        ESTIMATED_RECEIVED_AMOUNT.to_owned(),
-        ESTIMATED_RECEIVED_AMOUNT.to_owned(),
+    keyboard = keyboard.append_row(vec![callback_button(
+        match draft.current_quote() {
+            Some(quote) => quote.summary(draft.slippage_bps),
+            None => ESTIMATED_RECEIVED_AMOUNT.to_string(),
+        },
+        BuyButtons::EstimatedReceivedAmount,
     )]);
 
+    // risk badge of the receive token, once it is checked
+    if let Some(risk) = draft.current_risk() {
+        keyboard = keyboard.append_row(vec![callback_button(risk.badge(), BuyButtons::Risk)]);
+    }
+
     // 8th row
-    // Last one will always be Send Buy Tx
-    keyboard = keyboard.append_row(vec![InlineKeyboardButton::callback(
-        SEND_BUY_TX.to_owned(),
-        SEND_BUY_TX.to_owned(),
-    )]);
+    keyboard = keyboard.append_row(order_settings_row(
+        draft.slippage_bps,
+        draft.deadline_secs,
+        draft.gas_preset,
+        [
+            BuyButtons::Slippage.into(),
+            BuyButtons::Deadline.into(),
+            BuyButtons::Gas.into(),
+        ],
+    ));
+
+    // 9th row
+    keyboard = keyboard.append_row(vec![callback_button(SEND_BUY_TX, BuyButtons::SendBuyTx)]);
+
+    keyboard
+}
 
-    Ok(keyboard)
+/// Create the keyboard asking to confirm the buy of a high risk token
+pub(crate) fn confirm_risk_keyboard(token: Address) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default()
+        .append_row(vec![callback_button(CLOSE, MenuButtons::Close)])
+        .append_row(vec![callback_button(
+            "⚠️ Buy Anyway",
+            BuyButtons::ConfirmRisk(token),
+        )])
 }
 
-pub(crate) fn buy_keyboard(
-    private_tx: bool,
-    rebate: bool,
-    wallet1: bool,
-    wallet2: bool,
-    wallet3: bool,
-) -> anyhow::Result<InlineKeyboardMarkup> {
-    match create_buy_keyboard(private_tx, rebate, wallet1, wallet2, wallet3) {
-        Ok(keyboard) => Ok(keyboard),
-        _ => Err(anyhow::anyhow!("Error creating keyboard")),
+/// Create the keyboard to pick the buy or receive token out of the tokens sharing a name
+pub(crate) fn token_chooser_keyboard(
+    tokens: &[&TokenInfo],
+    is_buy_token: bool,
+) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for token in tokens.iter().take(MAX_TOKEN_CHOICES) {
+        let address = format!("{:?}", token.address);
+        let text = format!(
+            "{} · {} · {}…{}",
+            token.symbol,
+            token.name,
+            &address[..6],
+            &address[address.len() - 4..]
+        );
+        let action = match is_buy_token {
+            true => BuyButtons::PickBuyToken(token.address),
+            false => BuyButtons::PickReceiveToken(token.address),
+        };
+        keyboard = keyboard.append_row(vec![callback_button(text, action)]);
     }
+    keyboard = keyboard.append_row(vec![callback_button(CLOSE, MenuButtons::Close)]);
+
+    keyboard
 }
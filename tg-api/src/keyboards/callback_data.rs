@@ -0,0 +1,450 @@
+use crate::keyboards::approval_buttons::ApprovalButtons;
+use crate::keyboards::buy_buttons::BuyButtons;
+use crate::keyboards::dca_buttons::DcaButtons;
+use crate::keyboards::exit_buttons::ExitButtons;
+use crate::keyboards::history_buttons::HistoryButtons;
+use crate::keyboards::limit_buttons::LimitButtons;
+use crate::keyboards::rebate_buttons::RebateButtons;
+use crate::keyboards::sell_buttons::SellButtons;
+use crate::keyboards::settings_buttons::SettingsButtons;
+use crate::keyboards::wallet_buttons::WalletButtons;
+use crate::keyboards::MenuButtons;
+use std::fmt;
+use teloxide::types::InlineKeyboardButton;
+
+/// Version of the callback data layout, bump it whenever an encoding changes so that
+/// keyboards sent by an older build are rejected instead of being routed to the wrong action
+pub(crate) const CALLBACK_DATA_VERSION: &str = "v1";
+
+/// Telegram rejects buttons carrying more than 64 bytes of callback data
+pub(crate) const MAX_CALLBACK_DATA_LEN: usize = 64;
+
+/// Widest decimal forms of the action arguments, the sign included
+pub(crate) const U8_LEN: usize = 3;
+pub(crate) const U32_LEN: usize = 10;
+pub(crate) const U64_LEN: usize = 20;
+pub(crate) const I64_LEN: usize = 20;
+pub(crate) const USIZE_LEN: usize = 20;
+
+/// An address formatted with `{:?}`, "0x" and 40 hex digits
+pub(crate) const ADDRESS_LEN: usize = 42;
+
+/// Actions of one menu, sent as "namespace:version:action[:args]" in the callback data
+pub(crate) trait CallbackAction: Sized {
+    /// Short prefix telling the menus apart
+    const NAMESPACE: &'static str;
+
+    /// Longest output of [encode_action](Self::encode_action), with every argument at its widest
+    const MAX_ACTION_LEN: usize;
+
+    /// Fails the build when the longest action of the menu doesn't fit in Telegram's limit
+    const FITS_CALLBACK_DATA: () = assert!(
+        Self::NAMESPACE.len() + CALLBACK_DATA_VERSION.len() + 2 + Self::MAX_ACTION_LEN
+            <= MAX_CALLBACK_DATA_LEN,
+        "the callback data of this menu can be longer than Telegram allows"
+    );
+
+    /// Encodes the action and its arguments, separated by ':'
+    fn encode_action(&self) -> String;
+
+    /// Decodes the action from the parts following the version
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self>;
+}
+
+/// Reasons callback data can't be decoded, reported as [TgError::CallbackData](crate::bot::TgError)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackDataError {
+    /// Not something the bot encodes, or longer than Telegram allows
+    Invalid(String),
+    /// Encoded by another version of the bot
+    UnsupportedVersion(String),
+}
+
+impl fmt::Display for CallbackDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(data) => write!(f, "invalid callback data: {}", data),
+            Self::UnsupportedVersion(data) => {
+                write!(f, "unsupported callback data version: {}", data)
+            }
+        }
+    }
+}
+
+/// Decoded callback data of any button sent by the bot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CallbackData {
+    Menu(MenuButtons),
+    Buy(BuyButtons),
+    Sell(SellButtons),
+    Wallets(WalletButtons),
+    History(HistoryButtons),
+    Settings(SettingsButtons),
+    Rebates(RebateButtons),
+    Limit(LimitButtons),
+    Exit(ExitButtons),
+    Dca(DcaButtons),
+    Approvals(ApprovalButtons),
+}
+
+fn encode<A: CallbackAction>(action: &A) -> String {
+    // checked when the menu's encoder is compiled, not when a button is sent
+    let () = A::FITS_CALLBACK_DATA;
+    let encoded = action.encode_action();
+    debug_assert!(
+        encoded.len() <= A::MAX_ACTION_LEN,
+        "{} is longer than the declared maximum",
+        encoded
+    );
+    format!("{}:{}:{}", A::NAMESPACE, CALLBACK_DATA_VERSION, encoded)
+}
+
+impl CallbackData {
+    pub(crate) fn encode(&self) -> String {
+        match self {
+            Self::Menu(action) => encode(action),
+            Self::Buy(action) => encode(action),
+            Self::Sell(action) => encode(action),
+            Self::Wallets(action) => encode(action),
+            Self::History(action) => encode(action),
+            Self::Settings(action) => encode(action),
+            Self::Rebates(action) => encode(action),
+            Self::Limit(action) => encode(action),
+            Self::Exit(action) => encode(action),
+            Self::Dca(action) => encode(action),
+            Self::Approvals(action) => encode(action),
+        }
+    }
+
+    pub(crate) fn decode(data: &str) -> Result<Self, CallbackDataError> {
+        if data.len() > MAX_CALLBACK_DATA_LEN {
+            return Err(CallbackDataError::Invalid(data.to_string()));
+        }
+        let parts: Vec<&str> = data.split(':').collect();
+        let (namespace, version, action, args) = match parts.as_slice() {
+            [namespace, version, action, args @ ..] => (*namespace, *version, *action, args),
+            _ => return Err(CallbackDataError::Invalid(data.to_string())),
+        };
+        if version != CALLBACK_DATA_VERSION {
+            return Err(CallbackDataError::UnsupportedVersion(data.to_string()));
+        }
+
+        let decoded = match namespace {
+            MenuButtons::NAMESPACE => MenuButtons::decode_action(action, args).map(Self::Menu),
+            BuyButtons::NAMESPACE => BuyButtons::decode_action(action, args).map(Self::Buy),
+            SellButtons::NAMESPACE => SellButtons::decode_action(action, args).map(Self::Sell),
+            WalletButtons::NAMESPACE => {
+                WalletButtons::decode_action(action, args).map(Self::Wallets)
+            }
+            HistoryButtons::NAMESPACE => {
+                HistoryButtons::decode_action(action, args).map(Self::History)
+            }
+            SettingsButtons::NAMESPACE => {
+                SettingsButtons::decode_action(action, args).map(Self::Settings)
+            }
+            RebateButtons::NAMESPACE => {
+                RebateButtons::decode_action(action, args).map(Self::Rebates)
+            }
+            LimitButtons::NAMESPACE => LimitButtons::decode_action(action, args).map(Self::Limit),
+            ExitButtons::NAMESPACE => ExitButtons::decode_action(action, args).map(Self::Exit),
+            DcaButtons::NAMESPACE => DcaButtons::decode_action(action, args).map(Self::Dca),
+            ApprovalButtons::NAMESPACE => {
+                ApprovalButtons::decode_action(action, args).map(Self::Approvals)
+            }
+            _ => None,
+        };
+        decoded.ok_or_else(|| CallbackDataError::Invalid(data.to_string()))
+    }
+}
+
+impl From<MenuButtons> for CallbackData {
+    fn from(action: MenuButtons) -> Self {
+        Self::Menu(action)
+    }
+}
+
+impl From<BuyButtons> for CallbackData {
+    fn from(action: BuyButtons) -> Self {
+        Self::Buy(action)
+    }
+}
+
+impl From<SellButtons> for CallbackData {
+    fn from(action: SellButtons) -> Self {
+        Self::Sell(action)
+    }
+}
+
+impl From<WalletButtons> for CallbackData {
+    fn from(action: WalletButtons) -> Self {
+        Self::Wallets(action)
+    }
+}
+
+impl From<HistoryButtons> for CallbackData {
+    fn from(action: HistoryButtons) -> Self {
+        Self::History(action)
+    }
+}
+
+impl From<SettingsButtons> for CallbackData {
+    fn from(action: SettingsButtons) -> Self {
+        Self::Settings(action)
+    }
+}
+
+impl From<RebateButtons> for CallbackData {
+    fn from(action: RebateButtons) -> Self {
+        Self::Rebates(action)
+    }
+}
+
+impl From<LimitButtons> for CallbackData {
+    fn from(action: LimitButtons) -> Self {
+        Self::Limit(action)
+    }
+}
+
+impl From<ExitButtons> for CallbackData {
+    fn from(action: ExitButtons) -> Self {
+        Self::Exit(action)
+    }
+}
+
+impl From<DcaButtons> for CallbackData {
+    fn from(action: DcaButtons) -> Self {
+        Self::Dca(action)
+    }
+}
+
+impl From<ApprovalButtons> for CallbackData {
+    fn from(action: ApprovalButtons) -> Self {
+        Self::Approvals(action)
+    }
+}
+
+/// Creates a button showing `text` whose callback data is the encoded action
+pub(crate) fn callback_button(
+    text: impl Into<String>,
+    action: impl Into<CallbackData>,
+) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, action.into().encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests::swap::ApprovalMode;
+    use crate::settings::GasPreset;
+    use ethers::types::Address;
+
+    /// Every action with its arguments at their widest, at least once per variant
+    fn actions() -> Vec<CallbackData> {
+        let token = Address::repeat_byte(0xab);
+        vec![
+            MenuButtons::Buy.into(),
+            MenuButtons::Sell.into(),
+            MenuButtons::LimitBuy.into(),
+            MenuButtons::LimitSell.into(),
+            MenuButtons::MainMenu.into(),
+            MenuButtons::Close.into(),
+            MenuButtons::Noop.into(),
+            BuyButtons::SendBuyTx.into(),
+            BuyButtons::PrivateTx.into(),
+            BuyButtons::Rebate.into(),
+            BuyButtons::Wallet(u8::MAX).into(),
+            BuyButtons::BuyToken.into(),
+            BuyButtons::ReceiveToken.into(),
+            BuyButtons::BuyAmount.into(),
+            BuyButtons::EstimatedReceivedAmount.into(),
+            BuyButtons::Slippage.into(),
+            BuyButtons::Deadline.into(),
+            BuyButtons::Gas.into(),
+            BuyButtons::PickBuyToken(token).into(),
+            BuyButtons::PickReceiveToken(token).into(),
+            BuyButtons::Risk.into(),
+            BuyButtons::ConfirmRisk(token).into(),
+            SellButtons::SendSellTx.into(),
+            SellButtons::PrivateTx.into(),
+            SellButtons::Rebate.into(),
+            SellButtons::Wallet(u8::MAX).into(),
+            SellButtons::SellToken.into(),
+            SellButtons::Percent(100).into(),
+            SellButtons::SellAmount.into(),
+            SellButtons::EstimatedReceivedAmount.into(),
+            SellButtons::Slippage.into(),
+            SellButtons::Deadline.into(),
+            SellButtons::Gas.into(),
+            SellButtons::PickToken(token).into(),
+            WalletButtons::Copy(u8::MAX).into(),
+            WalletButtons::Rename(u8::MAX).into(),
+            WalletButtons::SetDefault(u8::MAX).into(),
+            HistoryButtons::Page(usize::MAX).into(),
+            HistoryButtons::Trade {
+                id: i64::MIN,
+                page: usize::MAX,
+            }
+            .into(),
+            SettingsButtons::Slippage(u32::MAX).into(),
+            SettingsButtons::CustomSlippage.into(),
+            SettingsButtons::Deadline(u64::MAX).into(),
+            SettingsButtons::Gas(GasPreset::Turbo).into(),
+            SettingsButtons::MaxFee(Some(u64::MAX)).into(),
+            SettingsButtons::MaxFee(None).into(),
+            RebateButtons::Claim(u64::MAX).into(),
+            LimitButtons::Wallet(u8::MAX).into(),
+            LimitButtons::Token.into(),
+            LimitButtons::Amount.into(),
+            LimitButtons::TriggerPrice.into(),
+            LimitButtons::Expiry(u64::MAX).into(),
+            LimitButtons::Create.into(),
+            LimitButtons::Orders.into(),
+            LimitButtons::Cancel(i64::MIN).into(),
+            ExitButtons::StopLoss {
+                position: i64::MIN,
+                percent: 99,
+            }
+            .into(),
+            ExitButtons::TakeProfit {
+                position: i64::MIN,
+                percent: u32::MAX,
+                sell_percent: 100,
+            }
+            .into(),
+            ExitButtons::TrailingStop {
+                position: i64::MIN,
+                percent: 99,
+            }
+            .into(),
+            ExitButtons::Cancel(i64::MIN).into(),
+            DcaButtons::New.into(),
+            DcaButtons::Wallet(u8::MAX).into(),
+            DcaButtons::BuyToken.into(),
+            DcaButtons::ReceiveToken.into(),
+            DcaButtons::Amount.into(),
+            DcaButtons::Interval(u64::MAX).into(),
+            DcaButtons::Runs(u32::MAX).into(),
+            DcaButtons::Approval(ApprovalMode::Exact).into(),
+            DcaButtons::Approval(ApprovalMode::Unlimited).into(),
+            DcaButtons::Create.into(),
+            DcaButtons::Pause(i64::MIN).into(),
+            DcaButtons::Resume(i64::MIN).into(),
+            DcaButtons::Cancel(i64::MIN).into(),
+            ApprovalButtons::Wallet(u8::MAX).into(),
+            ApprovalButtons::Revoke(i64::MIN).into(),
+            ApprovalButtons::Approve {
+                sell: true,
+                mode: ApprovalMode::Exact,
+            }
+            .into(),
+            ApprovalButtons::Approve {
+                sell: false,
+                mode: ApprovalMode::Unlimited,
+            }
+            .into(),
+        ]
+    }
+
+    /// Declared maximum action length of the action's menu
+    fn max_action_len(action: &CallbackData) -> usize {
+        match action {
+            CallbackData::Menu(_) => MenuButtons::MAX_ACTION_LEN,
+            CallbackData::Buy(_) => BuyButtons::MAX_ACTION_LEN,
+            CallbackData::Sell(_) => SellButtons::MAX_ACTION_LEN,
+            CallbackData::Wallets(_) => WalletButtons::MAX_ACTION_LEN,
+            CallbackData::History(_) => HistoryButtons::MAX_ACTION_LEN,
+            CallbackData::Settings(_) => SettingsButtons::MAX_ACTION_LEN,
+            CallbackData::Rebates(_) => RebateButtons::MAX_ACTION_LEN,
+            CallbackData::Limit(_) => LimitButtons::MAX_ACTION_LEN,
+            CallbackData::Exit(_) => ExitButtons::MAX_ACTION_LEN,
+            CallbackData::Dca(_) => DcaButtons::MAX_ACTION_LEN,
+            CallbackData::Approvals(_) => ApprovalButtons::MAX_ACTION_LEN,
+        }
+    }
+
+    #[test]
+    fn every_action_round_trips() {
+        for action in actions() {
+            let data = action.encode();
+            assert!(data.len() <= MAX_CALLBACK_DATA_LEN, "{} is too long", data);
+            assert_eq!(CallbackData::decode(&data), Ok(action), "{}", data);
+        }
+    }
+
+    /// The action and its arguments, without the namespace and version
+    fn encoded_action(action: &CallbackData) -> String {
+        action.encode().splitn(3, ':').nth(2).unwrap().to_string()
+    }
+
+    #[test]
+    fn declared_maximums_are_reached_by_the_widest_actions() {
+        let actions = actions();
+        for action in &actions {
+            assert!(encoded_action(action).len() <= max_action_len(action));
+        }
+        // a loose maximum would hide a menu getting close to the limit
+        let namespace = |action: &CallbackData| action.encode()[..1].to_string();
+        for action in &actions {
+            let max = max_action_len(action);
+            assert!(
+                actions
+                    .iter()
+                    .any(|other| namespace(other) == namespace(action)
+                        && encoded_action(other).len() == max),
+                "no action of {} reaches {}",
+                action.encode(),
+                max
+            );
+        }
+    }
+
+    #[test]
+    fn longest_action_fits_in_telegram_limit() {
+        let data =
+            CallbackData::from(BuyButtons::PickReceiveToken(Address::repeat_byte(0xab))).encode();
+        assert_eq!(
+            data,
+            "b:v1:pick_receive:0xabababababababababababababababababababab"
+        );
+        assert_eq!(data.len(), 60);
+        let longest = actions().iter().map(|action| action.encode().len()).max();
+        assert_eq!(longest, Some(60));
+    }
+
+    #[test]
+    fn rejects_foreign_and_malformed_data() {
+        assert_eq!(
+            CallbackData::decode("b:v0:send"),
+            Err(CallbackDataError::UnsupportedVersion(
+                "b:v0:send".to_string()
+            ))
+        );
+        for data in [
+            "",
+            "send",
+            "b:v1",
+            "x:v1:send",
+            "b:v1:unknown",
+            "b:v1:send:extra",
+            "b:v1:wallet:256",
+            "b:v1:confirm:0x12",
+            "s:v1:percent:0",
+            "s:v1:percent:101",
+            "p:v1:sl:1:100",
+            "c:v1:gas:ludicrous",
+            "a:v1:approve:swap:exact",
+        ] {
+            assert_eq!(
+                CallbackData::decode(data),
+                Err(CallbackDataError::Invalid(data.to_string())),
+                "{}",
+                data
+            );
+        }
+        let too_long = format!("b:v1:pick_receive:0x{}", "ab".repeat(30));
+        assert!(matches!(
+            CallbackData::decode(&too_long),
+            Err(CallbackDataError::Invalid(_))
+        ));
+    }
+}
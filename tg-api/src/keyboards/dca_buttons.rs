@@ -0,0 +1,188 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::consts::{BUY_TOKEN, CLOSE, DCA_AMOUNT, NEW_DCA, RECEIVE_TOKEN};
+use crate::dca::{format_interval, DcaSchedule, DcaStatus, INTERVAL_PRESETS_SECS, RUNS_PRESETS};
+use crate::drafts::DcaDraft;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, U64_LEN};
+use crate::keyboards::{
+    add_emoji, labeled_value, navigation_row, selectable_button, token_label, wallet_row,
+    MenuButtons,
+};
+use crate::requests::swap::ApprovalMode;
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the DCA menu and of the /dca schedules list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DcaButtons {
+    /// Opens the menu of a new schedule
+    New,
+    /// Selects the wallet in the given slot
+    Wallet(u8),
+    BuyToken,
+    ReceiveToken,
+    Amount,
+    /// Sets the time between two legs to the given preset, in seconds
+    Interval(u64),
+    /// Sets the number of legs to the given preset
+    Runs(u32),
+    /// Sets how the legs approve the router
+    Approval(ApprovalMode),
+    /// Creates the schedule
+    Create,
+    /// Pauses the schedule with the given id
+    Pause(i64),
+    /// Resumes the schedule with the given id
+    Resume(i64),
+    /// Cancels the schedule with the given id
+    Cancel(i64),
+}
+
+impl CallbackAction for DcaButtons {
+    const NAMESPACE: &'static str = "d";
+    const MAX_ACTION_LEN: usize = "interval:".len() + U64_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::New => "new".to_string(),
+            Self::Wallet(slot) => format!("wallet:{}", slot),
+            Self::BuyToken => "buy_token".to_string(),
+            Self::ReceiveToken => "receive_token".to_string(),
+            Self::Amount => "amount".to_string(),
+            Self::Interval(interval_secs) => format!("interval:{}", interval_secs),
+            Self::Runs(runs) => format!("runs:{}", runs),
+            Self::Approval(mode) => format!("approval:{}", mode.key()),
+            Self::Create => "create".to_string(),
+            Self::Pause(id) => format!("pause:{}", id),
+            Self::Resume(id) => format!("resume:{}", id),
+            Self::Cancel(id) => format!("cancel:{}", id),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("new", []) => Some(Self::New),
+            ("wallet", [slot]) => Some(Self::Wallet(slot.parse().ok()?)),
+            ("buy_token", []) => Some(Self::BuyToken),
+            ("receive_token", []) => Some(Self::ReceiveToken),
+            ("amount", []) => Some(Self::Amount),
+            ("interval", [interval_secs]) => Some(Self::Interval(interval_secs.parse().ok()?)),
+            ("runs", [runs]) => Some(Self::Runs(runs.parse().ok()?)),
+            ("approval", [mode]) => Some(Self::Approval(ApprovalMode::from_key(mode)?)),
+            ("create", []) => Some(Self::Create),
+            ("pause", [id]) => Some(Self::Pause(id.parse().ok()?)),
+            ("resume", [id]) => Some(Self::Resume(id.parse().ok()?)),
+            ("cancel", [id]) => Some(Self::Cancel(id.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Create the DCA menu keyboard layout from the user's draft
+pub(crate) fn dca_keyboard(draft: &DcaDraft) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+    let native_symbol = &GLOBAL_CHAIN_REGISTRY.trading_chain().native_symbol;
+    let buy_label = draft
+        .buy_token
+        .map_or_else(|| native_symbol.clone(), token_label);
+
+    keyboard = keyboard.append_row(navigation_row());
+
+    keyboard = keyboard.append_row(vec![callback_button("=Select Wallet=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(wallet_row(draft.wallet_slot, DcaButtons::Wallet));
+
+    keyboard = keyboard.append_row(vec![
+        callback_button(
+            labeled_value(BUY_TOKEN, Some(&buy_label)),
+            DcaButtons::BuyToken,
+        ),
+        callback_button(
+            labeled_value(RECEIVE_TOKEN, draft.receive_token.map(token_label)),
+            DcaButtons::ReceiveToken,
+        ),
+    ]);
+
+    let amount = draft
+        .amount
+        .map(|amount| format!("{} {}", amount, buy_label));
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(DCA_AMOUNT, amount),
+        DcaButtons::Amount,
+    )]);
+
+    keyboard = keyboard.append_row(vec![callback_button("=Every=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        INTERVAL_PRESETS_SECS
+            .iter()
+            .map(|&preset| {
+                selectable_button(
+                    &format_interval(preset),
+                    preset == draft.interval_secs,
+                    DcaButtons::Interval(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard = keyboard.append_row(vec![callback_button("=Runs=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        RUNS_PRESETS
+            .iter()
+            .map(|&preset| {
+                selectable_button(
+                    &preset.to_string(),
+                    preset == draft.runs,
+                    DcaButtons::Runs(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    // a leg paid with the native coin needs no approval
+    if draft.buy_token.is_some() {
+        keyboard = keyboard.append_row(vec![callback_button("=Approve=", MenuButtons::Noop)]);
+        keyboard = keyboard.append_row(vec![
+            selectable_button(
+                "Exact Per Leg",
+                draft.approval == ApprovalMode::Exact,
+                DcaButtons::Approval(ApprovalMode::Exact),
+            ),
+            selectable_button(
+                "Unlimited",
+                draft.approval == ApprovalMode::Unlimited,
+                DcaButtons::Approval(ApprovalMode::Unlimited),
+            ),
+        ]);
+    }
+
+    keyboard.append_row(vec![callback_button("Create DCA", DcaButtons::Create)])
+}
+
+/// Create the /dca keyboard, a pause or resume and a cancel button per schedule
+pub(crate) fn schedules_keyboard(schedules: &[DcaSchedule]) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for schedule in schedules {
+        let toggle = match schedule.status {
+            DcaStatus::Paused => callback_button(
+                format!("▶️ Resume #{}", schedule.id),
+                DcaButtons::Resume(schedule.id),
+            ),
+            _ => callback_button(
+                format!("⏸ Pause #{}", schedule.id),
+                DcaButtons::Pause(schedule.id),
+            ),
+        };
+        keyboard = keyboard.append_row(vec![
+            toggle,
+            callback_button(
+                format!("🗑 Cancel #{}", schedule.id),
+                DcaButtons::Cancel(schedule.id),
+            ),
+        ]);
+    }
+
+    keyboard = keyboard.append_row(vec![callback_button(
+        format!("➕ {}", NEW_DCA),
+        DcaButtons::New,
+    )]);
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
@@ -0,0 +1,136 @@
+use crate::consts::CLOSE;
+use crate::exit_orders::{
+    ExitKind, ExitOrder, STOP_LOSS_PRESETS, TAKE_PROFIT_PRESETS, TRAILING_STOP_PRESETS,
+};
+use crate::keyboards::callback_data::{callback_button, CallbackAction, I64_LEN, U32_LEN, U8_LEN};
+use crate::keyboards::{add_emoji, selectable_button, MenuButtons};
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons offered on a filled buy to protect the position, and the cancel buttons of the orders list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExitButtons {
+    /// Toggles a stop loss the given percent below the entry price
+    StopLoss { position: i64, percent: u32 },
+    /// Toggles a take profit selling `sell_percent` of the balance the given percent above the entry price
+    TakeProfit {
+        position: i64,
+        percent: u32,
+        sell_percent: u8,
+    },
+    /// Toggles a trailing stop the given percent below the highest price since the buy
+    TrailingStop { position: i64, percent: u32 },
+    /// Cancels the open exit order with the given id
+    Cancel(i64),
+}
+
+impl CallbackAction for ExitButtons {
+    const NAMESPACE: &'static str = "p";
+    const MAX_ACTION_LEN: usize = "tp:".len() + I64_LEN + 1 + U32_LEN + 1 + U8_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::StopLoss { position, percent } => format!("sl:{}:{}", position, percent),
+            Self::TakeProfit {
+                position,
+                percent,
+                sell_percent,
+            } => format!("tp:{}:{}:{}", position, percent, sell_percent),
+            Self::TrailingStop { position, percent } => format!("ts:{}:{}", position, percent),
+            Self::Cancel(id) => format!("cancel:{}", id),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("sl", [position, percent]) => Some(Self::StopLoss {
+                position: position.parse().ok()?,
+                percent: match percent.parse().ok()? {
+                    percent @ 1..=99 => percent,
+                    _ => return None,
+                },
+            }),
+            ("tp", [position, percent, sell_percent]) => Some(Self::TakeProfit {
+                position: position.parse().ok()?,
+                percent: match percent.parse().ok()? {
+                    0 => return None,
+                    percent => percent,
+                },
+                sell_percent: match sell_percent.parse().ok()? {
+                    sell_percent @ 1..=100 => sell_percent,
+                    _ => return None,
+                },
+            }),
+            ("ts", [position, percent]) => Some(Self::TrailingStop {
+                position: position.parse().ok()?,
+                percent: match percent.parse().ok()? {
+                    percent @ 1..=99 => percent,
+                    _ => return None,
+                },
+            }),
+            ("cancel", [id]) => Some(Self::Cancel(id.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Create the keyboard of a filled buy, the presets with an open order are selected
+pub(crate) fn position_keyboard(position: i64, orders: &[ExitOrder]) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+    let is_open = |kind: ExitKind, percent: u32, sell_percent: u8| {
+        orders.iter().any(|order| {
+            order.kind == kind && order.percent == percent && order.sell_percent == sell_percent
+        })
+    };
+
+    keyboard = keyboard.append_row(vec![callback_button("=Stop Loss=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        STOP_LOSS_PRESETS
+            .iter()
+            .map(|&percent| {
+                selectable_button(
+                    &format!("-{}%", percent),
+                    is_open(ExitKind::StopLoss, percent, 100),
+                    ExitButtons::StopLoss { position, percent },
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard = keyboard.append_row(vec![callback_button("=Take Profit=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        TAKE_PROFIT_PRESETS
+            .iter()
+            .map(|&(percent, sell_percent)| {
+                let label = match sell_percent {
+                    100 => format!("+{}%", percent),
+                    _ => format!("{}% at +{}%", sell_percent, percent),
+                };
+                selectable_button(
+                    &label,
+                    is_open(ExitKind::TakeProfit, percent, sell_percent),
+                    ExitButtons::TakeProfit {
+                        position,
+                        percent,
+                        sell_percent,
+                    },
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard = keyboard.append_row(vec![callback_button("=Trailing Stop=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        TRAILING_STOP_PRESETS
+            .iter()
+            .map(|&percent| {
+                selectable_button(
+                    &format!("{}%", percent),
+                    is_open(ExitKind::TrailingStop, percent, 100),
+                    ExitButtons::TrailingStop { position, percent },
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
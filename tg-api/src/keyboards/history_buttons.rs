@@ -0,0 +1,79 @@
+use crate::consts::CLOSE;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, I64_LEN, USIZE_LEN};
+use crate::keyboards::{add_emoji, MenuButtons};
+use crate::ledger::Trade;
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the /history menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HistoryButtons {
+    /// Shows a page of the trade list
+    Page(usize),
+    /// Shows the details of a trade, remembering the page to go back to
+    Trade { id: i64, page: usize },
+}
+
+impl CallbackAction for HistoryButtons {
+    const NAMESPACE: &'static str = "h";
+    const MAX_ACTION_LEN: usize = "trade:".len() + I64_LEN + 1 + USIZE_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Page(page) => format!("page:{}", page),
+            Self::Trade { id, page } => format!("trade:{}:{}", id, page),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("page", [page]) => Some(Self::Page(page.parse().ok()?)),
+            ("trade", [id, page]) => Some(Self::Trade {
+                id: id.parse().ok()?,
+                page: page.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Create the /history list keyboard, one button per trade and a prev/next row
+pub(crate) fn history_keyboard(
+    trades: &[Trade],
+    page: usize,
+    total_pages: usize,
+) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for trade in trades {
+        keyboard = keyboard.append_row(vec![callback_button(
+            format!(
+                "🔎 #{} {} → {}",
+                trade.id, trade.token_in.symbol, trade.token_out.symbol
+            ),
+            HistoryButtons::Trade { id: trade.id, page },
+        )]);
+    }
+
+    let mut navigation = vec![];
+    if page > 0 {
+        navigation.push(callback_button("⬅️ Prev", HistoryButtons::Page(page - 1)));
+    }
+    navigation.push(callback_button(
+        format!("{}/{}", page + 1, total_pages.max(1)),
+        HistoryButtons::Page(page),
+    ));
+    if page + 1 < total_pages {
+        navigation.push(callback_button("Next ➡️", HistoryButtons::Page(page + 1)));
+    }
+    keyboard = keyboard.append_row(navigation);
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
+
+/// Create the keyboard of the trade detail view
+pub(crate) fn trade_keyboard(page: usize) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row(vec![
+        callback_button("⬅️ Back", HistoryButtons::Page(page)),
+        callback_button(add_emoji(CLOSE), MenuButtons::Close),
+    ])
+}
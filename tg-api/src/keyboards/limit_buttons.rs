@@ -0,0 +1,141 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::consts::{CLOSE, LIMIT_AMOUNT, LIMIT_TOKEN, OPEN_ORDERS, TRIGGER_PRICE};
+use crate::drafts::LimitOrderDraft;
+use crate::exit_orders::ExitOrder;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, U64_LEN};
+use crate::keyboards::exit_buttons::ExitButtons;
+use crate::keyboards::{
+    add_emoji, labeled_value, navigation_row, selectable_button, token_label, wallet_row,
+    MenuButtons,
+};
+use crate::limit_orders::{
+    format_expiry, format_price, LimitOrder, LimitSide, EXPIRY_PRESETS_SECS,
+};
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the Limit Buy and Limit Sell menus and of the open orders list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LimitButtons {
+    /// Selects the wallet in the given slot
+    Wallet(u8),
+    Token,
+    Amount,
+    TriggerPrice,
+    /// Sets the order's expiry to the given preset, in seconds
+    Expiry(u64),
+    /// Opens the order
+    Create,
+    /// Lists the user's open orders
+    Orders,
+    /// Cancels the open order with the given id
+    Cancel(i64),
+}
+
+impl CallbackAction for LimitButtons {
+    const NAMESPACE: &'static str = "l";
+    const MAX_ACTION_LEN: usize = "expiry:".len() + U64_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Wallet(slot) => format!("wallet:{}", slot),
+            Self::Token => "token".to_string(),
+            Self::Amount => "amount".to_string(),
+            Self::TriggerPrice => "price".to_string(),
+            Self::Expiry(expiry_secs) => format!("expiry:{}", expiry_secs),
+            Self::Create => "create".to_string(),
+            Self::Orders => "orders".to_string(),
+            Self::Cancel(id) => format!("cancel:{}", id),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("wallet", [slot]) => Some(Self::Wallet(slot.parse().ok()?)),
+            ("token", []) => Some(Self::Token),
+            ("amount", []) => Some(Self::Amount),
+            ("price", []) => Some(Self::TriggerPrice),
+            ("expiry", [expiry_secs]) => Some(Self::Expiry(expiry_secs.parse().ok()?)),
+            ("create", []) => Some(Self::Create),
+            ("orders", []) => Some(Self::Orders),
+            ("cancel", [id]) => Some(Self::Cancel(id.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Create the Limit Buy or Limit Sell keyboard layout from the user's draft
+pub(crate) fn limit_order_keyboard(draft: &LimitOrderDraft) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+    let native_symbol = &GLOBAL_CHAIN_REGISTRY.trading_chain().native_symbol;
+
+    keyboard = keyboard.append_row(navigation_row());
+
+    keyboard = keyboard.append_row(vec![callback_button("=Select Wallet=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(wallet_row(draft.wallet_slot, LimitButtons::Wallet));
+
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(LIMIT_TOKEN, draft.token.map(token_label)),
+        LimitButtons::Token,
+    )]);
+
+    let amount = draft.amount.map(|amount| match draft.side {
+        LimitSide::Buy => format!("{} {}", amount, native_symbol),
+        LimitSide::Sell => amount.to_string(),
+    });
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(LIMIT_AMOUNT, amount),
+        LimitButtons::Amount,
+    )]);
+
+    let trigger_price = draft
+        .trigger_price
+        .map(|price| format!("{} {}", format_price(price), native_symbol));
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(TRIGGER_PRICE, trigger_price),
+        LimitButtons::TriggerPrice,
+    )]);
+
+    keyboard = keyboard.append_row(vec![callback_button("=Expires In=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        EXPIRY_PRESETS_SECS
+            .iter()
+            .map(|&preset| {
+                selectable_button(
+                    &format_expiry(preset),
+                    preset == draft.expiry_secs,
+                    LimitButtons::Expiry(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard = keyboard.append_row(vec![callback_button(OPEN_ORDERS, LimitButtons::Orders)]);
+
+    keyboard.append_row(vec![callback_button(
+        format!("Create {}", draft.side),
+        LimitButtons::Create,
+    )])
+}
+
+/// Create the open orders keyboard, one cancel button per limit and exit order
+pub(crate) fn orders_keyboard(
+    orders: &[LimitOrder],
+    exit_orders: &[ExitOrder],
+) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for order in orders {
+        keyboard = keyboard.append_row(vec![callback_button(
+            format!("🗑 Cancel {} #{}", order.side, order.id),
+            LimitButtons::Cancel(order.id),
+        )]);
+    }
+    for order in exit_orders {
+        keyboard = keyboard.append_row(vec![callback_button(
+            format!("🗑 Cancel {} #{}", order.kind, order.id),
+            ExitButtons::Cancel(order.id),
+        )]);
+    }
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
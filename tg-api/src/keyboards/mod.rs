@@ -1,15 +1,80 @@
+pub(crate) mod approval_buttons;
 pub(crate) mod buy_buttons;
+pub(crate) mod callback_data;
+pub(crate) mod dca_buttons;
+pub(crate) mod exit_buttons;
+pub(crate) mod history_buttons;
+pub(crate) mod limit_buttons;
+pub(crate) mod rebate_buttons;
+pub(crate) mod sell_buttons;
+pub(crate) mod settings_buttons;
+pub(crate) mod wallet_buttons;
 
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::consts::{BUY, CLOSE, DEADLINE, GAS, LIMIT_BUY, LIMIT_SELL, MAIN_MENU, SELL, SLIPPAGE};
+use crate::keyboards::callback_data::{callback_button, CallbackAction, CallbackData};
+use crate::settings::{format_deadline, format_slippage, GasPreset};
+use crate::tokens::{GLOBAL_TOKEN_METADATA_CACHE, GLOBAL_TOKEN_REGISTRY};
+use crate::wallets::WALLET_NAMES;
+use ethers::types::Address;
+use std::fmt;
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
 
+/// Buttons of the main menu and the ones shared by every menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MenuButtons {
+    Buy,
+    Sell,
+    LimitBuy,
+    LimitSell,
+    MainMenu,
+    Close,
+    /// Labels and placeholders that do nothing when clicked
+    Noop,
+}
+
+impl CallbackAction for MenuButtons {
+    const NAMESPACE: &'static str = "m";
+    const MAX_ACTION_LEN: usize = "limit_sell".len();
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Buy => "buy",
+            Self::Sell => "sell",
+            Self::LimitBuy => "limit_buy",
+            Self::LimitSell => "limit_sell",
+            Self::MainMenu => "main_menu",
+            Self::Close => "close",
+            Self::Noop => "noop",
+        }
+        .to_string()
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        if !args.is_empty() {
+            return None;
+        }
+        match action {
+            "buy" => Some(Self::Buy),
+            "sell" => Some(Self::Sell),
+            "limit_buy" => Some(Self::LimitBuy),
+            "limit_sell" => Some(Self::LimitSell),
+            "main_menu" => Some(Self::MainMenu),
+            "close" => Some(Self::Close),
+            "noop" => Some(Self::Noop),
+            _ => None,
+        }
+    }
+}
+
 /// Default layout for the keyboard
-fn create_keyboard(actions: Vec<&str>) -> InlineKeyboardMarkup {
+fn create_keyboard(actions: Vec<(&str, MenuButtons)>) -> InlineKeyboardMarkup {
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
 
     for action in actions.chunks(3) {
         let row = action
             .iter()
-            .map(|&action| InlineKeyboardButton::callback(action.to_owned(), action.to_owned()))
+            .map(|&(text, action)| callback_button(text, action))
             .collect();
 
         keyboard.push(row);
@@ -27,23 +92,94 @@ pub(crate) fn add_emoji(text: &str) -> String {
         "Wallet 1" => format!("✅ {}", text),
         "Wallet 2" => format!("✅{}", text),
         "Wallet 3" => format!("✅ {}", text),
+        "25%" => format!("✅ {}", text),
+        "50%" => format!("✅ {}", text),
+        "100%" => format!("✅ {}", text),
         _ => text.to_string(),
     };
     button
 }
 
 pub(crate) fn menu_keyboard() -> InlineKeyboardMarkup {
-    create_keyboard(vec!["Buy", "Sell", "Limit Buy", "Limit Sell"])
+    create_keyboard(vec![
+        (BUY, MenuButtons::Buy),
+        (SELL, MenuButtons::Sell),
+        (LIMIT_BUY, MenuButtons::LimitBuy),
+        (LIMIT_SELL, MenuButtons::LimitSell),
+    ])
+}
+
+/// Creates the Main Menu and Close row every sub-menu starts with
+pub(crate) fn navigation_row() -> Vec<InlineKeyboardButton> {
+    vec![
+        callback_button(add_emoji(MAIN_MENU), MenuButtons::MainMenu),
+        callback_button(add_emoji(CLOSE), MenuButtons::Close),
+    ]
 }
 
-pub(crate) fn sell_keyboard() -> InlineKeyboardMarkup {
-    create_keyboard(vec!["BTC", "ETH", "LTC", "BCH", "Main Menu", "Close"])
+/// Creates a button showing the label with a check mark when it is selected
+pub(crate) fn selectable_button(
+    label: &str,
+    selected: bool,
+    action: impl Into<CallbackData>,
+) -> InlineKeyboardButton {
+    match selected {
+        true => callback_button(add_emoji(label), action),
+        false => callback_button(label, action),
+    }
 }
 
-pub(crate) fn limit_buy_keyboard() -> InlineKeyboardMarkup {
-    create_keyboard(vec!["BTC", "ETH", "LTC", "BCH", "Main Menu", "Close"])
+/// Creates the wallet selection row of the buy and sell menus
+pub(crate) fn wallet_row<A: Into<CallbackData>>(
+    selected_slot: u8,
+    action: impl Fn(u8) -> A,
+) -> Vec<InlineKeyboardButton> {
+    WALLET_NAMES
+        .iter()
+        .enumerate()
+        .map(|(slot, name)| {
+            let slot = slot as u8;
+            selectable_button(name, slot == selected_slot, action(slot))
+        })
+        .collect()
 }
 
-pub(crate) fn limit_sell_keyboard() -> InlineKeyboardMarkup {
-    create_keyboard(vec!["BTC", "ETH", "LTC", "BCH", "Main Menu", "Close"])
+/// Creates the slippage, deadline and gas row of the buy and sell menus, tapping a button moves to the next preset
+pub(crate) fn order_settings_row(
+    slippage_bps: u32,
+    deadline_secs: u64,
+    gas_preset: GasPreset,
+    [slippage_action, deadline_action, gas_action]: [CallbackData; 3],
+) -> Vec<InlineKeyboardButton> {
+    vec![
+        callback_button(
+            labeled_value(SLIPPAGE, Some(format_slippage(slippage_bps))),
+            slippage_action,
+        ),
+        callback_button(
+            labeled_value(DEADLINE, Some(format_deadline(deadline_secs))),
+            deadline_action,
+        ),
+        callback_button(labeled_value(GAS, Some(gas_preset.label())), gas_action),
+    ]
+}
+
+/// Shows the value after the label once it is set, "Label: value"
+pub(crate) fn labeled_value(label: &str, value: Option<impl fmt::Display>) -> String {
+    match value {
+        Some(value) => format!("{}: {}", label, value),
+        None => label.to_string(),
+    }
+}
+
+/// Shows the symbol of a token of the trading chain, known from its token lists or read on chain, the address otherwise
+pub(crate) fn token_label(token: Address) -> String {
+    let chain_id = GLOBAL_CHAIN_REGISTRY.trading_chain().chain_id;
+    if let Some(info) = GLOBAL_TOKEN_REGISTRY.get(chain_id, token) {
+        return info.symbol.clone();
+    }
+    match GLOBAL_TOKEN_METADATA_CACHE.get(chain_id, token) {
+        Some(metadata) => metadata.symbol,
+        None => format!("{:?}", token),
+    }
 }
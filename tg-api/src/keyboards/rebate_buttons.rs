@@ -0,0 +1,54 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::consts::CLOSE;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, U64_LEN};
+use crate::keyboards::{add_emoji, MenuButtons};
+use crate::rebates::RebateBalance;
+use crate::requests::on_chain::format_amount;
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the /rebates menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RebateButtons {
+    /// Pays out the accrued rebate on the given chain
+    Claim(u64),
+}
+
+impl CallbackAction for RebateButtons {
+    const NAMESPACE: &'static str = "r";
+    const MAX_ACTION_LEN: usize = "claim:".len() + U64_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Claim(chain_id) => format!("claim:{}", chain_id),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("claim", [chain_id]) => Some(Self::Claim(chain_id.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Create the /rebates keyboard, one claim button per chain with an accrued rebate
+pub(crate) fn rebates_keyboard(balances: &[RebateBalance]) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for balance in balances {
+        let Some(chain) = GLOBAL_CHAIN_REGISTRY.get(balance.chain_id) else {
+            continue;
+        };
+        keyboard = keyboard.append_row(vec![callback_button(
+            format!(
+                "💸 Claim {} {} on {}",
+                format_amount(balance.balance, 18),
+                chain.native_symbol,
+                chain.name
+            ),
+            RebateButtons::Claim(balance.chain_id),
+        )]);
+    }
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
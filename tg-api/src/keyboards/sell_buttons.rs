@@ -0,0 +1,181 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::consts::{
+    CLOSE, ESTIMATED_RECEIVED_AMOUNT, PRIVATE_TX, REBATE, SELL_100_PERCENT, SELL_25_PERCENT,
+    SELL_50_PERCENT, SELL_AMOUNT, SELL_TOKEN, SEND_SELL_TX,
+};
+use crate::drafts::{SellAmount, SellOrderDraft};
+use crate::keyboards::callback_data::{callback_button, CallbackAction, ADDRESS_LEN};
+use crate::keyboards::{
+    labeled_value, navigation_row, order_settings_row, selectable_button, token_label, wallet_row,
+    MenuButtons,
+};
+use crate::requests::on_chain::{format_amount, TokenBalance};
+use ethers::types::Address;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Most held tokens offered when the sell token is picked
+pub(crate) const MAX_HELD_TOKENS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SellButtons {
+    SendSellTx,
+    PrivateTx,
+    Rebate,
+    /// Selects the wallet in the given slot
+    Wallet(u8),
+    SellToken,
+    /// Sells the given share of the wallet balance
+    Percent(u8),
+    SellAmount,
+    EstimatedReceivedAmount,
+    /// Moves the order's slippage to the next preset
+    Slippage,
+    /// Moves the order's deadline to the next preset
+    Deadline,
+    /// Moves the order's gas preset to the next one
+    Gas,
+    /// Picks the sell token out of the tokens the wallet holds
+    PickToken(Address),
+}
+
+impl CallbackAction for SellButtons {
+    const NAMESPACE: &'static str = "s";
+    const MAX_ACTION_LEN: usize = "pick:".len() + ADDRESS_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::SendSellTx => "send".to_string(),
+            Self::PrivateTx => "toggle_private".to_string(),
+            Self::Rebate => "toggle_rebate".to_string(),
+            Self::Wallet(slot) => format!("wallet:{}", slot),
+            Self::SellToken => "token".to_string(),
+            Self::Percent(percent) => format!("percent:{}", percent),
+            Self::SellAmount => "amount".to_string(),
+            Self::EstimatedReceivedAmount => "estimate".to_string(),
+            Self::Slippage => "slippage".to_string(),
+            Self::Deadline => "deadline".to_string(),
+            Self::Gas => "gas".to_string(),
+            Self::PickToken(token) => format!("pick:{:?}", token),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("send", []) => Some(Self::SendSellTx),
+            ("toggle_private", []) => Some(Self::PrivateTx),
+            ("toggle_rebate", []) => Some(Self::Rebate),
+            ("wallet", [slot]) => Some(Self::Wallet(slot.parse().ok()?)),
+            ("token", []) => Some(Self::SellToken),
+            ("percent", [percent]) => match percent.parse().ok()? {
+                percent @ 1..=100 => Some(Self::Percent(percent)),
+                _ => None,
+            },
+            ("amount", []) => Some(Self::SellAmount),
+            ("estimate", []) => Some(Self::EstimatedReceivedAmount),
+            ("slippage", []) => Some(Self::Slippage),
+            ("deadline", []) => Some(Self::Deadline),
+            ("gas", []) => Some(Self::Gas),
+            ("pick", [token]) => Some(Self::PickToken(token.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Creates a percentage button, selected when it matches the current sell amount
+fn percent_button(label: &str, percent: u8, amount: SellAmount) -> InlineKeyboardButton {
+    selectable_button(
+        label,
+        amount == SellAmount::Percent(percent),
+        SellButtons::Percent(percent),
+    )
+}
+
+/// Create the Sell keyboard layout from the user's draft, mirroring the Buy keyboard
+pub(crate) fn sell_keyboard(draft: &SellOrderDraft) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    // 1st row
+    keyboard = keyboard.append_row(navigation_row());
+
+    // 2nd row, Private Tx is only offered on chains with private relays
+    let mut toggles = vec![selectable_button(REBATE, draft.rebate, SellButtons::Rebate)];
+    if GLOBAL_CHAIN_REGISTRY.trading_chain().has_private_relays() {
+        toggles.insert(
+            0,
+            selectable_button(PRIVATE_TX, draft.private_tx, SellButtons::PrivateTx),
+        );
+    }
+    keyboard = keyboard.append_row(toggles);
+
+    // 3rd row
+    keyboard = keyboard.append_row(vec![callback_button("=Select Wallet=", MenuButtons::Noop)]);
+
+    // 4th row
+    keyboard = keyboard.append_row(wallet_row(draft.wallet_slot, SellButtons::Wallet));
+
+    // 5th row
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(SELL_TOKEN, draft.sell_token.map(token_label)),
+        SellButtons::SellToken,
+    )]);
+
+    // 6th row
+    keyboard = keyboard.append_row(vec![
+        percent_button(SELL_25_PERCENT, 25, draft.amount),
+        percent_button(SELL_50_PERCENT, 50, draft.amount),
+        percent_button(SELL_100_PERCENT, 100, draft.amount),
+    ]);
+
+    // 7th row
+    let exact_amount = match draft.amount {
+        SellAmount::Exact(value) => Some(value),
+        SellAmount::Percent(_) | SellAmount::BaseUnits(_) => None,
+    };
+    keyboard = keyboard.append_row(vec![callback_button(
+        labeled_value(SELL_AMOUNT, exact_amount),
+        SellButtons::SellAmount,
+    )]);
+
+    // 8th row
+    keyboard = keyboard.append_row(vec![callback_button(
+        ESTIMATED_RECEIVED_AMOUNT,
+        SellButtons::EstimatedReceivedAmount,
+    )]);
+
+    // 9th row
+    keyboard = keyboard.append_row(order_settings_row(
+        draft.slippage_bps,
+        draft.deadline_secs,
+        draft.gas_preset,
+        [
+            SellButtons::Slippage.into(),
+            SellButtons::Deadline.into(),
+            SellButtons::Gas.into(),
+        ],
+    ));
+
+    // 10th row
+    keyboard = keyboard.append_row(vec![callback_button(SEND_SELL_TX, SellButtons::SendSellTx)]);
+
+    keyboard
+}
+
+/// Create the keyboard to pick the sell token out of the tokens the wallet holds
+pub(crate) fn held_tokens_keyboard(tokens: &[TokenBalance]) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for token in tokens.iter().take(MAX_HELD_TOKENS) {
+        let text = format!(
+            "{} · {}",
+            token.symbol,
+            format_amount(token.balance, token.decimals)
+        );
+        keyboard = keyboard.append_row(vec![callback_button(
+            text,
+            SellButtons::PickToken(token.token),
+        )]);
+    }
+    keyboard = keyboard.append_row(vec![callback_button(CLOSE, MenuButtons::Close)]);
+
+    keyboard
+}
@@ -0,0 +1,123 @@
+use crate::consts::CLOSE;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, U64_LEN};
+use crate::keyboards::{add_emoji, selectable_button, MenuButtons};
+use crate::settings::{
+    format_deadline, format_max_fee, format_slippage, GasPreset, UserSettings,
+    DEADLINE_PRESETS_SECS, MAX_FEE_PRESETS_GWEI, SLIPPAGE_PRESETS_BPS,
+};
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the /settings menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SettingsButtons {
+    /// Sets the default slippage to the given preset, in basis points
+    Slippage(u32),
+    /// Asks for a slippage that isn't one of the presets
+    CustomSlippage,
+    /// Sets the default deadline to the given preset, in seconds
+    Deadline(u64),
+    /// Sets the default gas preset
+    Gas(GasPreset),
+    /// Sets the max fee cap to the given preset, in gwei, `None` removes it
+    MaxFee(Option<u64>),
+}
+
+impl CallbackAction for SettingsButtons {
+    const NAMESPACE: &'static str = "c";
+    const MAX_ACTION_LEN: usize = "deadline:".len() + U64_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Slippage(slippage_bps) => format!("slippage:{}", slippage_bps),
+            Self::CustomSlippage => "custom_slippage".to_string(),
+            Self::Deadline(deadline_secs) => format!("deadline:{}", deadline_secs),
+            Self::Gas(gas_preset) => format!("gas:{}", gas_preset.key()),
+            Self::MaxFee(Some(max_fee_gwei)) => format!("max_fee:{}", max_fee_gwei),
+            Self::MaxFee(None) => "max_fee:none".to_string(),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        match (action, args) {
+            ("slippage", [slippage_bps]) => Some(Self::Slippage(slippage_bps.parse().ok()?)),
+            ("custom_slippage", []) => Some(Self::CustomSlippage),
+            ("deadline", [deadline_secs]) => Some(Self::Deadline(deadline_secs.parse().ok()?)),
+            ("gas", [gas_preset]) => Some(Self::Gas(GasPreset::from_key(gas_preset)?)),
+            ("max_fee", ["none"]) => Some(Self::MaxFee(None)),
+            ("max_fee", [max_fee_gwei]) => Some(Self::MaxFee(Some(max_fee_gwei.parse().ok()?))),
+            _ => None,
+        }
+    }
+}
+
+/// Create the /settings keyboard, the presets matching the user's settings are selected
+pub(crate) fn settings_keyboard(settings: &UserSettings) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    keyboard = keyboard.append_row(vec![callback_button("=Slippage=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        SLIPPAGE_PRESETS_BPS
+            .iter()
+            .map(|&preset| {
+                selectable_button(
+                    &format_slippage(preset),
+                    preset == settings.slippage_bps,
+                    SettingsButtons::Slippage(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+    let custom = !SLIPPAGE_PRESETS_BPS.contains(&settings.slippage_bps);
+    keyboard = keyboard.append_row(vec![selectable_button(
+        &match custom {
+            true => format!("Custom: {}", format_slippage(settings.slippage_bps)),
+            false => "Custom".to_string(),
+        },
+        custom,
+        SettingsButtons::CustomSlippage,
+    )]);
+
+    keyboard = keyboard.append_row(vec![callback_button("=Deadline=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        DEADLINE_PRESETS_SECS
+            .iter()
+            .map(|&preset| {
+                selectable_button(
+                    &format_deadline(preset),
+                    preset == settings.deadline_secs,
+                    SettingsButtons::Deadline(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard = keyboard.append_row(vec![callback_button("=Gas=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        GasPreset::ALL
+            .iter()
+            .map(|&preset| {
+                selectable_button(
+                    preset.label(),
+                    preset == settings.gas_preset,
+                    SettingsButtons::Gas(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard = keyboard.append_row(vec![callback_button("=Max Fee=", MenuButtons::Noop)]);
+    keyboard = keyboard.append_row(
+        std::iter::once(None)
+            .chain(MAX_FEE_PRESETS_GWEI.iter().copied().map(Some))
+            .map(|preset| {
+                selectable_button(
+                    &format_max_fee(preset),
+                    preset == settings.max_fee_gwei,
+                    SettingsButtons::MaxFee(preset),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
@@ -0,0 +1,60 @@
+use crate::consts::CLOSE;
+use crate::keyboards::callback_data::{callback_button, CallbackAction, U8_LEN};
+use crate::keyboards::{add_emoji, MenuButtons};
+use crate::wallets::UserWallet;
+use teloxide::types::InlineKeyboardMarkup;
+
+/// Buttons of the /wallets menu, each one acting on the wallet in the given slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WalletButtons {
+    Copy(u8),
+    Rename(u8),
+    SetDefault(u8),
+}
+
+impl CallbackAction for WalletButtons {
+    const NAMESPACE: &'static str = "w";
+    const MAX_ACTION_LEN: usize = "default:".len() + U8_LEN;
+
+    fn encode_action(&self) -> String {
+        match self {
+            Self::Copy(slot) => format!("copy:{}", slot),
+            Self::Rename(slot) => format!("rename:{}", slot),
+            Self::SetDefault(slot) => format!("default:{}", slot),
+        }
+    }
+
+    fn decode_action(action: &str, args: &[&str]) -> Option<Self> {
+        let slot = match args {
+            [slot] => slot.parse().ok()?,
+            _ => return None,
+        };
+        match action {
+            "copy" => Some(Self::Copy(slot)),
+            "rename" => Some(Self::Rename(slot)),
+            "default" => Some(Self::SetDefault(slot)),
+            _ => None,
+        }
+    }
+}
+
+/// Create the /wallets keyboard, one row of actions per wallet
+pub(crate) fn wallets_keyboard(wallets: &[UserWallet]) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    for wallet in wallets {
+        keyboard = keyboard.append_row(vec![
+            callback_button(
+                format!("📋 {}", wallet.name),
+                WalletButtons::Copy(wallet.slot),
+            ),
+            callback_button("✏️ Rename", WalletButtons::Rename(wallet.slot)),
+            match wallet.is_default {
+                true => callback_button("⭐ Default", WalletButtons::SetDefault(wallet.slot)),
+                false => callback_button("Set Default", WalletButtons::SetDefault(wallet.slot)),
+            },
+        ]);
+    }
+
+    keyboard.append_row(vec![callback_button(add_emoji(CLOSE), MenuButtons::Close)])
+}
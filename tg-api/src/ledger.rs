@@ -0,0 +1,423 @@
+use crate::chains::GLOBAL_CHAIN_REGISTRY;
+use crate::database::{Database, GLOBAL_DATABASE};
+use crate::requests::on_chain::format_amount;
+use crate::requests::swap::{SentSwap, SwapToken};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, TxHash, U256};
+use lazy_static::lazy_static;
+use rusqlite::{params, Row};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::types::UserId;
+use teloxide::utils::markdown::{bold, code_inline, escape, link};
+
+lazy_static! {
+    /// Used to record and list the trades submitted by the bot
+    pub(crate) static ref GLOBAL_TRADE_LEDGER: TradeLedger = TradeLedger::new(&GLOBAL_DATABASE);
+}
+
+/// Lifecycle of a submitted trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TradeStatus {
+    Pending,
+    Confirmed,
+    Reverted,
+    Dropped,
+}
+
+impl TradeStatus {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Confirmed => "confirmed",
+            Self::Reverted => "reverted",
+            Self::Dropped => "dropped",
+        }
+    }
+
+    pub(crate) fn emoji(&self) -> &'static str {
+        match self {
+            Self::Pending => "⏳",
+            Self::Confirmed => "✅",
+            Self::Reverted => "❌",
+            Self::Dropped => "🗑",
+        }
+    }
+}
+
+impl FromStr for TradeStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(status: &str) -> anyhow::Result<Self> {
+        match status {
+            "pending" => Ok(Self::Pending),
+            "confirmed" => Ok(Self::Confirmed),
+            "reverted" => Ok(Self::Reverted),
+            "dropped" => Ok(Self::Dropped),
+            _ => Err(anyhow::anyhow!("Unknown trade status: {}", status)),
+        }
+    }
+}
+
+impl fmt::Display for TradeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A trade as recorded in the ledger, amounts are in base units of their token
+#[derive(Debug, Clone)]
+pub(crate) struct Trade {
+    pub(crate) id: i64,
+    pub(crate) wallet: Address,
+    pub(crate) chain_id: u64,
+    pub(crate) token_in: SwapToken,
+    pub(crate) token_out: SwapToken,
+    pub(crate) amount_in: U256,
+    pub(crate) amount_out: Option<U256>,
+    pub(crate) tx_hash: TxHash,
+    pub(crate) status: TradeStatus,
+    pub(crate) gas_used: Option<U256>,
+    pub(crate) timestamp: u64,
+}
+
+/// Columns selected for a [Trade], in the order [Trade::from_row] reads them
+const TRADE_COLUMNS: &str = "id, wallet, chain_id, token_in, symbol_in, decimals_in, \
+    token_out, symbol_out, decimals_out, amount_in, amount_out, tx_hash, status, gas_used, created_at";
+
+fn parse_address(address: Option<String>) -> anyhow::Result<Option<Address>> {
+    Ok(address
+        .map(|address| Address::from_str(&address))
+        .transpose()?)
+}
+
+fn parse_amount(amount: Option<String>) -> anyhow::Result<Option<U256>> {
+    Ok(amount
+        .map(|amount| U256::from_dec_str(&amount))
+        .transpose()?)
+}
+
+impl Trade {
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            wallet: Address::from_str(&row.get::<_, String>(1)?)?,
+            chain_id: row.get(2)?,
+            token_in: SwapToken {
+                address: parse_address(row.get(3)?)?,
+                symbol: row.get(4)?,
+                decimals: row.get(5)?,
+            },
+            token_out: SwapToken {
+                address: parse_address(row.get(6)?)?,
+                symbol: row.get(7)?,
+                decimals: row.get(8)?,
+            },
+            amount_in: U256::from_dec_str(&row.get::<_, String>(9)?)?,
+            amount_out: parse_amount(row.get(10)?)?,
+            tx_hash: TxHash::from_str(&row.get::<_, String>(11)?)?,
+            status: row.get::<_, String>(12)?.parse()?,
+            gas_used: parse_amount(row.get(13)?)?,
+            timestamp: row.get(14)?,
+        })
+    }
+}
+
+/// Formats a unix timestamp for the history views
+pub(crate) fn format_timestamp(timestamp: u64) -> String {
+    match DateTime::<Utc>::from_timestamp(timestamp as i64, 0) {
+        Some(time) => time.format("%Y-%m-%d %H:%M UTC").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+impl Trade {
+    /// One entry of the paginated /history list, escaped for MarkdownV2
+    pub(crate) fn summary(&self) -> String {
+        format!(
+            "{} {}\n{}",
+            bold(&format!("#{}", self.id)),
+            escape(&format!(
+                "{} {}",
+                self.status.emoji(),
+                format_timestamp(self.timestamp)
+            )),
+            escape(&format!(
+                "{} {} → {}",
+                format_amount(self.amount_in, self.token_in.decimals),
+                self.token_in.symbol,
+                self.token_out.symbol
+            )),
+        )
+    }
+
+    /// Detail view of the trade, escaped for MarkdownV2
+    pub(crate) fn details(&self) -> String {
+        let received = match self.amount_out {
+            Some(amount_out) => format!(
+                "{} {}",
+                format_amount(amount_out, self.token_out.decimals),
+                self.token_out.symbol
+            ),
+            None => format!("- {}", self.token_out.symbol),
+        };
+        let chain = GLOBAL_CHAIN_REGISTRY.get(self.chain_id);
+        let chain_name = match chain {
+            Some(chain) => chain.name.clone(),
+            None => self.chain_id.to_string(),
+        };
+        let tx = match chain {
+            Some(chain) => format!(
+                "Tx: {}",
+                link(
+                    &chain.tx_url(self.tx_hash),
+                    &escape(&format!("{:?}", self.tx_hash))
+                )
+            ),
+            None => format!("Tx: {}", code_inline(&format!("{:?}", self.tx_hash))),
+        };
+        let gas_used = match self.gas_used {
+            Some(gas_used) => gas_used.to_string(),
+            None => "-".to_string(),
+        };
+        [
+            bold(&format!("Trade #{}", self.id)),
+            escape(&format!("Status: {} {}", self.status, self.status.emoji())),
+            escape(&format!("Time: {}", format_timestamp(self.timestamp))),
+            escape(&format!("Chain: {}", chain_name)),
+            format!("Wallet: {}", code_inline(&format!("{:?}", self.wallet))),
+            escape(&format!(
+                "Sold: {} {}",
+                format_amount(self.amount_in, self.token_in.decimals),
+                self.token_in.symbol
+            )),
+            escape(&format!("Received: {}", received)),
+            escape(&format!("Gas used: {}", gas_used)),
+            tx,
+        ]
+        .join("\n")
+    }
+}
+
+/// Number of pages `total` trades fill, an empty history has none
+pub(crate) fn page_count(total: usize, page_size: usize) -> usize {
+    (total + page_size - 1) / page_size
+}
+
+/// Persistent record of every trade the bot has submitted
+#[derive(Debug)]
+pub(crate) struct TradeLedger {
+    database: &'static Database,
+}
+
+impl TradeLedger {
+    pub(crate) fn new(database: &'static Database) -> Self {
+        Self { database }
+    }
+
+    /// Records a freshly broadcast swap as pending and returns its id
+    pub(crate) fn record(&self, user_id: UserId, swap: &SentSwap) -> anyhow::Result<i64> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let connection = self.database.connection();
+        connection.execute(
+            "INSERT INTO trades (user_id, wallet, chain_id, token_in, symbol_in, decimals_in, \
+             token_out, symbol_out, decimals_out, amount_in, tx_hash, status, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                user_id.0,
+                format!("{:?}", swap.wallet),
+                swap.chain_id,
+                swap.token_in
+                    .address
+                    .map(|address| format!("{:?}", address)),
+                swap.token_in.symbol,
+                swap.token_in.decimals,
+                swap.token_out
+                    .address
+                    .map(|address| format!("{:?}", address)),
+                swap.token_out.symbol,
+                swap.token_out.decimals,
+                swap.amount_in.to_string(),
+                format!("{:?}", swap.tx_hash),
+                TradeStatus::Pending.as_str(),
+                timestamp,
+            ],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Records how a pending trade ended, with the tokens it received and the gas it used once mined
+    pub(crate) fn settle(
+        &self,
+        tx_hash: TxHash,
+        status: TradeStatus,
+        amount_out: Option<U256>,
+        gas_used: Option<U256>,
+    ) -> anyhow::Result<()> {
+        self.database.connection().execute(
+            "UPDATE trades SET status = ?2, amount_out = ?3, gas_used = ?4 \
+             WHERE tx_hash = ?1 AND status = ?5",
+            params![
+                format!("{:?}", tx_hash),
+                status.as_str(),
+                amount_out.map(|amount| amount.to_string()),
+                gas_used.map(|gas| gas.to_string()),
+                TradeStatus::Pending.as_str(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Gets one page of the user's trades, newest first, together with the total number of trades
+    pub(crate) fn page(
+        &self,
+        user_id: UserId,
+        page: usize,
+        page_size: usize,
+    ) -> anyhow::Result<(Vec<Trade>, usize)> {
+        let connection = self.database.connection();
+        let total: usize = connection.query_row(
+            "SELECT COUNT(*) FROM trades WHERE user_id = ?1",
+            params![user_id.0],
+            |row| row.get(0),
+        )?;
+
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM trades WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2 OFFSET ?3",
+            TRADE_COLUMNS
+        ))?;
+        let mut rows = statement.query(params![user_id.0, page_size, page * page_size])?;
+        let mut trades = Vec::new();
+        while let Some(row) = rows.next()? {
+            trades.push(Trade::from_row(row)?);
+        }
+        Ok((trades, total))
+    }
+
+    /// Gets the trade of a swap
+    pub(crate) fn find(&self, tx_hash: TxHash) -> anyhow::Result<Option<Trade>> {
+        let connection = self.database.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM trades WHERE tx_hash = ?1",
+            TRADE_COLUMNS
+        ))?;
+        let mut rows = statement.query(params![format!("{:?}", tx_hash)])?;
+        rows.next()?.map(Trade::from_row).transpose()
+    }
+
+    /// Gets one of the user's trades
+    pub(crate) fn get(&self, user_id: UserId, id: i64) -> anyhow::Result<Option<Trade>> {
+        let connection = self.database.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM trades WHERE user_id = ?1 AND id = ?2",
+            TRADE_COLUMNS
+        ))?;
+        let mut rows = statement.query(params![user_id.0, id])?;
+        rows.next()?.map(Trade::from_row).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn ledger() -> TradeLedger {
+        let database = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        TradeLedger::new(Box::leak(Box::new(database)))
+    }
+
+    fn token(address: Option<Address>, symbol: &str) -> SwapToken {
+        SwapToken {
+            address,
+            symbol: symbol.to_string(),
+            decimals: 18,
+        }
+    }
+
+    fn sent_swap(n: u64) -> SentSwap {
+        SentSwap {
+            chain_id: 1,
+            wallet: Address::repeat_byte(0x11),
+            tx_hash: TxHash::from_low_u64_be(n),
+            token_in: token(None, "ETH"),
+            token_out: token(Some(Address::repeat_byte(0x22)), "TKN"),
+            amount_in: U256::exp10(18) * n,
+            private_max_block: None,
+            rebate: false,
+            native_payout: None,
+        }
+    }
+
+    #[test]
+    fn records_a_trade_and_settles_it_once() {
+        let ledger = ledger();
+        let user = UserId(1);
+        let swap = sent_swap(1);
+        let id = ledger.record(user, &swap).unwrap();
+
+        let trade = ledger.get(user, id).unwrap().unwrap();
+        assert_eq!(trade.status, TradeStatus::Pending);
+        assert_eq!(trade.wallet, swap.wallet);
+        assert_eq!(trade.token_in, swap.token_in);
+        assert_eq!(trade.token_out, swap.token_out);
+        assert_eq!(trade.amount_in, swap.amount_in);
+        assert_eq!(trade.tx_hash, swap.tx_hash);
+        assert_eq!(trade.amount_out, None);
+        assert_eq!(ledger.find(swap.tx_hash).unwrap().unwrap().id, id);
+        assert!(ledger.find(sent_swap(2).tx_hash).unwrap().is_none());
+
+        ledger
+            .settle(
+                swap.tx_hash,
+                TradeStatus::Confirmed,
+                Some(500.into()),
+                Some(21_000.into()),
+            )
+            .unwrap();
+        // only a pending trade is settled, a late dropped update doesn't overwrite it
+        ledger
+            .settle(swap.tx_hash, TradeStatus::Dropped, None, None)
+            .unwrap();
+        let trade = ledger.get(user, id).unwrap().unwrap();
+        assert_eq!(trade.status, TradeStatus::Confirmed);
+        assert_eq!(trade.amount_out, Some(500.into()));
+        assert_eq!(trade.gas_used, Some(21_000.into()));
+    }
+
+    #[test]
+    fn pages_the_trades_of_each_user_newest_first() {
+        let ledger = ledger();
+        let (user, other) = (UserId(1), UserId(2));
+        let ids: Vec<i64> = (1..=7)
+            .map(|n| ledger.record(user, &sent_swap(n)).unwrap())
+            .collect();
+        let other_id = ledger.record(other, &sent_swap(8)).unwrap();
+
+        let (trades, total) = ledger.page(user, 0, 5).unwrap();
+        assert_eq!(total, 7);
+        let page: Vec<i64> = trades.iter().map(|trade| trade.id).collect();
+        assert_eq!(page, vec![ids[6], ids[5], ids[4], ids[3], ids[2]]);
+        let (trades, _) = ledger.page(user, 1, 5).unwrap();
+        let page: Vec<i64> = trades.iter().map(|trade| trade.id).collect();
+        assert_eq!(page, vec![ids[1], ids[0]]);
+        assert!(ledger.page(user, 2, 5).unwrap().0.is_empty());
+
+        let (trades, total) = ledger.page(other, 0, 5).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(trades[0].id, other_id);
+        // a user can't open another user's trade
+        assert!(ledger.get(other, ids[0]).unwrap().is_none());
+        assert!(ledger.get(user, other_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn counts_the_pages() {
+        assert_eq!(page_count(0, 5), 0);
+        assert_eq!(page_count(1, 5), 1);
+        assert_eq!(page_count(5, 5), 1);
+        assert_eq!(page_count(6, 5), 2);
+        assert_eq!(page_count(11, 5), 3);
+    }
+}
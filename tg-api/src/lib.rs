@@ -1,9 +1,23 @@
+mod approvals;
 pub mod bot;
+mod chains;
 mod consts;
+mod database;
+mod dca;
+mod drafts;
+mod exit_orders;
+mod global;
 #[allow(dead_code)]
 mod handlers;
 #[allow(dead_code)]
 mod keyboards;
+mod ledger;
+mod limit_orders;
+mod rebates;
 mod requests;
+mod settings;
 #[allow(dead_code)]
 mod storages;
+mod tokens;
+mod tracker;
+mod wallets;
@@ -0,0 +1,730 @@
+use crate::chains::{ChainConfig, GLOBAL_CHAIN_REGISTRY};
+use crate::database::{Database, GLOBAL_DATABASE};
+use crate::drafts::{LimitOrderDraft, SellAmount};
+use crate::exit_orders::{self, check_exit_orders};
+use crate::handlers::{resume_order_swap, submit_buy, submit_sell, track_sent_swap};
+use crate::keyboards::token_label;
+use crate::ledger::format_timestamp;
+use crate::requests::quote;
+use crate::requests::server::{SendBuyTxRequest, SendSellTxRequest};
+use crate::requests::swap::{ApprovalMode, SentSwap};
+use crate::settings::{GasPreset, GLOBAL_SETTINGS_STORE};
+use ethers::{
+    providers::Middleware,
+    types::{Address, TxHash, U64},
+};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::prelude::Requester;
+use teloxide::types::{ChatId, MessageId, UserId};
+use teloxide::Bot;
+use tokio::time::{sleep, Duration};
+
+lazy_static! {
+    /// Used to create, list and execute the limit orders of every user
+    pub(crate) static ref GLOBAL_LIMIT_ORDER_BOOK: LimitOrderBook =
+        LimitOrderBook::new(&GLOBAL_DATABASE);
+}
+
+/// How often the engine checks for a new block
+const ENGINE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Limit order expiries offered on the limit menus, in seconds
+pub(crate) const EXPIRY_PRESETS_SECS: [u64; 4] =
+    [60 * 60, 24 * 60 * 60, 7 * 24 * 60 * 60, 30 * 24 * 60 * 60];
+
+pub(crate) const DEFAULT_EXPIRY_SECS: u64 = 24 * 60 * 60;
+
+/// Formats an expiry in hours or days, e.g. "7d"
+pub(crate) fn format_expiry(expiry_secs: u64) -> String {
+    match expiry_secs % (24 * 60 * 60) {
+        0 => format!("{}d", expiry_secs / (24 * 60 * 60)),
+        _ => format!("{}h", expiry_secs / (60 * 60)),
+    }
+}
+
+/// Formats a price with 6 significant digits, token prices in native coin are often tiny
+pub(crate) fn format_price(price: f64) -> String {
+    if price <= 0.0 || !price.is_finite() {
+        return price.to_string();
+    }
+    let decimals = (5 - price.log10().floor() as i32).max(0) as usize;
+    format!("{:.*}", decimals, price)
+}
+
+fn now() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Whether a limit order buys the token with the native coin or sells it for the native coin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum LimitSide {
+    Buy,
+    Sell,
+}
+
+impl LimitSide {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Buy => "buy",
+            Self::Sell => "sell",
+        }
+    }
+}
+
+impl FromStr for LimitSide {
+    type Err = anyhow::Error;
+
+    fn from_str(side: &str) -> anyhow::Result<Self> {
+        match side {
+            "buy" => Ok(Self::Buy),
+            "sell" => Ok(Self::Sell),
+            _ => Err(anyhow::anyhow!("Unknown limit order side: {}", side)),
+        }
+    }
+}
+
+impl fmt::Display for LimitSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buy => write!(f, "Limit Buy"),
+            Self::Sell => write!(f, "Limit Sell"),
+        }
+    }
+}
+
+/// Lifecycle of a limit order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LimitOrderStatus {
+    Open,
+    /// Triggered, its swap is being sent or waits to be mined
+    Executing,
+    /// Its swap was mined and succeeded
+    Filled,
+    /// Its swap could not be sent, reverted or was dropped
+    Failed,
+    Cancelled,
+    Expired,
+}
+
+impl LimitOrderStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Executing => "executing",
+            Self::Filled => "filled",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+/// A limit order as stored in the order book
+#[derive(Debug, Clone)]
+pub(crate) struct LimitOrder {
+    pub(crate) id: i64,
+    pub(crate) user_id: UserId,
+    /// Chat the order was created in, where its outcome is reported
+    pub(crate) chat_id: ChatId,
+    pub(crate) chain_id: u64,
+    pub(crate) side: LimitSide,
+    pub(crate) token: Address,
+    /// Native coin spent by a buy, tokens sold by a sell
+    pub(crate) amount: f64,
+    /// Price in native coin per token, a buy triggers at or below it and a sell at or above it
+    pub(crate) trigger_price: f64,
+    pub(crate) expires_at: u64,
+    pub(crate) wallet_slot: u8,
+    pub(crate) slippage_bps: u32,
+    pub(crate) gas_preset: GasPreset,
+}
+
+/// Columns selected for a [LimitOrder], in the order [LimitOrder::from_row] reads them
+const LIMIT_ORDER_COLUMNS: &str = "id, user_id, chat_id, chain_id, side, token, amount, \
+    trigger_price, expires_at, wallet_slot, slippage_bps, gas_preset";
+
+impl LimitOrder {
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user_id: UserId(row.get(1)?),
+            chat_id: ChatId(row.get(2)?),
+            chain_id: row.get(3)?,
+            side: row.get::<_, String>(4)?.parse()?,
+            token: Address::from_str(&row.get::<_, String>(5)?)?,
+            amount: row.get(6)?,
+            trigger_price: row.get(7)?,
+            expires_at: row.get(8)?,
+            wallet_slot: row.get(9)?,
+            slippage_bps: row.get(10)?,
+            gas_preset: GasPreset::from_key(&row.get::<_, String>(11)?).unwrap_or_default(),
+        })
+    }
+
+    /// Whether the order should be executed at this price
+    pub(crate) fn is_triggered(&self, price: f64) -> bool {
+        match self.side {
+            LimitSide::Buy => price <= self.trigger_price,
+            LimitSide::Sell => price >= self.trigger_price,
+        }
+    }
+
+    /// One line of the orders menu
+    pub(crate) fn summary(&self) -> String {
+        let symbol = token_label(self.token);
+        let native_symbol = GLOBAL_CHAIN_REGISTRY
+            .get(self.chain_id)
+            .map_or("", |chain| chain.native_symbol.as_str());
+        let (amount, condition) = match self.side {
+            LimitSide::Buy => (
+                format!("{} {} of {}", self.amount, native_symbol, symbol),
+                "≤",
+            ),
+            LimitSide::Sell => (format!("{} {}", self.amount, symbol), "≥"),
+        };
+        format!(
+            "{} #{} {} when {} {} {}, until {}",
+            self.side,
+            self.id,
+            amount,
+            condition,
+            format_price(self.trigger_price),
+            native_symbol,
+            format_timestamp(self.expires_at)
+        )
+    }
+}
+
+/// Persistent book of the limit orders, executed by [run_limit_order_engine]
+#[derive(Debug)]
+pub(crate) struct LimitOrderBook {
+    database: &'static Database,
+}
+
+impl LimitOrderBook {
+    pub(crate) fn new(database: &'static Database) -> Self {
+        Self { database }
+    }
+
+    /// Opens an order from a complete draft, returns its id
+    pub(crate) fn create(
+        &self,
+        user_id: UserId,
+        chat_id: ChatId,
+        draft: &LimitOrderDraft,
+    ) -> anyhow::Result<i64> {
+        let token = draft
+            .token
+            .ok_or_else(|| anyhow::anyhow!("No token address found"))?;
+        let amount = draft
+            .amount
+            .ok_or_else(|| anyhow::anyhow!("No amount found"))?;
+        let trigger_price = draft
+            .trigger_price
+            .ok_or_else(|| anyhow::anyhow!("No trigger price found"))?;
+        let settings = GLOBAL_SETTINGS_STORE.get(user_id)?;
+        let now = now()?;
+
+        let connection = self.database.connection();
+        connection.execute(
+            "INSERT INTO limit_orders (user_id, chat_id, chain_id, side, token, amount, \
+             trigger_price, expires_at, wallet_slot, slippage_bps, gas_preset, status, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                user_id.0,
+                chat_id.0,
+                GLOBAL_CHAIN_REGISTRY.trading_chain().chain_id,
+                draft.side.as_str(),
+                format!("{:?}", token),
+                amount,
+                trigger_price,
+                now + draft.expiry_secs,
+                draft.wallet_slot,
+                settings.slippage_bps,
+                settings.gas_preset.key(),
+                LimitOrderStatus::Open.as_str(),
+                now,
+            ],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    fn query(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params,
+    ) -> anyhow::Result<Vec<LimitOrder>> {
+        let connection = self.database.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM limit_orders WHERE {} ORDER BY id",
+            LIMIT_ORDER_COLUMNS, filter
+        ))?;
+        let mut rows = statement.query(params)?;
+        let mut orders = Vec::new();
+        while let Some(row) = rows.next()? {
+            orders.push(LimitOrder::from_row(row)?);
+        }
+        Ok(orders)
+    }
+
+    /// Open orders of the user, oldest first
+    pub(crate) fn open_orders(&self, user_id: UserId) -> anyhow::Result<Vec<LimitOrder>> {
+        self.query(
+            "user_id = ?1 AND status = ?2",
+            params![user_id.0, LimitOrderStatus::Open.as_str()],
+        )
+    }
+
+    /// Open orders of every user on the chain
+    fn open_orders_on(&self, chain_id: u64) -> anyhow::Result<Vec<LimitOrder>> {
+        self.query(
+            "chain_id = ?1 AND status = ?2",
+            params![chain_id, LimitOrderStatus::Open.as_str()],
+        )
+    }
+
+    /// Open orders of every user on the other chains
+    fn open_orders_off(&self, chain_id: u64) -> anyhow::Result<Vec<LimitOrder>> {
+        self.query(
+            "chain_id != ?1 AND status = ?2",
+            params![chain_id, LimitOrderStatus::Open.as_str()],
+        )
+    }
+
+    /// Moves the order from one status to another, `false` if it wasn't in the `from` status anymore
+    fn transition(
+        &self,
+        id: i64,
+        from: LimitOrderStatus,
+        to: LimitOrderStatus,
+        tx_hash: Option<TxHash>,
+    ) -> anyhow::Result<bool> {
+        let updated = self.database.connection().execute(
+            "UPDATE limit_orders SET status = ?1, tx_hash = ?2 WHERE id = ?3 AND status = ?4",
+            params![
+                to.as_str(),
+                tx_hash.map(|tx_hash| format!("{:?}", tx_hash)),
+                id,
+                from.as_str()
+            ],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Cancels one of the user's open orders
+    pub(crate) fn cancel(&self, user_id: UserId, id: i64) -> anyhow::Result<()> {
+        let updated = self.database.connection().execute(
+            "UPDATE limit_orders SET status = ?1 WHERE id = ?2 AND user_id = ?3 AND status = ?4",
+            params![
+                LimitOrderStatus::Cancelled.as_str(),
+                id,
+                user_id.0,
+                LimitOrderStatus::Open.as_str()
+            ],
+        )?;
+        match updated {
+            1 => Ok(()),
+            _ => Err(anyhow::anyhow!("Order #{} is no longer open", id)),
+        }
+    }
+
+    /// Orders whose swap was being sent or followed when the bot stopped, with the hash of the swap
+    /// once it was sent
+    fn interrupted(&self) -> anyhow::Result<Vec<(LimitOrder, Option<TxHash>)>> {
+        let connection = self.database.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {}, tx_hash FROM limit_orders WHERE status = ?1 ORDER BY id",
+            LIMIT_ORDER_COLUMNS
+        ))?;
+        let mut rows = statement.query(params![LimitOrderStatus::Executing.as_str()])?;
+        let mut orders = Vec::new();
+        while let Some(row) = rows.next()? {
+            let tx_hash = row
+                .get::<_, Option<String>>(12)?
+                .map(|tx_hash| TxHash::from_str(&tx_hash))
+                .transpose()?;
+            orders.push((LimitOrder::from_row(row)?, tx_hash));
+        }
+        Ok(orders)
+    }
+}
+
+/// Sends the swap of a triggered order with the order's wallet
+async fn execute(order: &LimitOrder) -> anyhow::Result<SentSwap> {
+    let settings = GLOBAL_SETTINGS_STORE.get(order.user_id)?;
+    match order.side {
+        LimitSide::Buy => {
+            let request = SendBuyTxRequest {
+                wallet_slot: order.wallet_slot,
+                private_tx: false,
+                rebate: false,
+                buy: None,
+                receive: order.token,
+                buy_amount: order.amount,
+                slippage_bps: order.slippage_bps,
+                deadline_secs: settings.deadline_secs,
+                gas_preset: order.gas_preset,
+                max_fee_gwei: settings.max_fee_gwei,
+                approval: Some(ApprovalMode::Exact),
+            };
+            submit_buy(order.user_id, &request, false).await
+        }
+        LimitSide::Sell => {
+            let request = SendSellTxRequest {
+                wallet_slot: order.wallet_slot,
+                private_tx: false,
+                rebate: false,
+                sell: order.token,
+                amount: SellAmount::Exact(order.amount),
+                slippage_bps: order.slippage_bps,
+                deadline_secs: settings.deadline_secs,
+                gas_preset: order.gas_preset,
+                max_fee_gwei: settings.max_fee_gwei,
+                approval: Some(ApprovalMode::Exact),
+            };
+            submit_sell(order.user_id, &request).await
+        }
+    }
+}
+
+async fn notify(bot: &Bot, order: &LimitOrder, text: String) -> Option<MessageId> {
+    match bot.send_message(order.chat_id, text).await {
+        Ok(message) => Some(message.id),
+        Err(err) => {
+            log::warn!("limit order #{} update not sent: {}", order.id, err);
+            None
+        }
+    }
+}
+
+/// Pool prices of the tokens checked on one block, each token is priced once
+pub(crate) struct PriceCache<'a> {
+    chain: &'a ChainConfig,
+    prices: HashMap<Address, Option<f64>>,
+}
+
+impl<'a> PriceCache<'a> {
+    pub(crate) fn new(chain: &'a ChainConfig) -> Self {
+        Self {
+            chain,
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Price of the token in native coin, `None` when it has no pool
+    pub(crate) async fn get(&mut self, token: Address) -> Option<f64> {
+        if let Some(price) = self.prices.get(&token) {
+            return *price;
+        }
+        let price = quote::token_price(self.chain, token)
+            .await
+            .map_err(|err| log::warn!("price of {:?} unavailable: {}", token, err))
+            .ok();
+        self.prices.insert(token, price);
+        price
+    }
+}
+
+/// Checks the open limit orders of the chain against the pool prices
+async fn check_limit_orders(
+    bot: &Bot,
+    chain: &ChainConfig,
+    prices: &mut PriceCache<'_>,
+) -> anyhow::Result<()> {
+    let orders = GLOBAL_LIMIT_ORDER_BOOK.open_orders_on(chain.chain_id)?;
+    let now = now()?;
+    for order in orders {
+        let book = &GLOBAL_LIMIT_ORDER_BOOK;
+        if order.expires_at <= now {
+            if book.transition(
+                order.id,
+                LimitOrderStatus::Open,
+                LimitOrderStatus::Expired,
+                None,
+            )? {
+                notify(bot, &order, format!("{} #{} expired", order.side, order.id)).await;
+            }
+            continue;
+        }
+
+        let price = prices.get(order.token).await;
+        let Some(price) = price.filter(|price| order.is_triggered(*price)) else {
+            continue;
+        };
+        // the order may have been cancelled since it was read
+        if !book.transition(
+            order.id,
+            LimitOrderStatus::Open,
+            LimitOrderStatus::Executing,
+            None,
+        )? {
+            continue;
+        }
+
+        log::info!("limit order #{} triggered at {}", order.id, price);
+        // sending may wait for an approval to be mined, the other orders are checked meanwhile
+        tokio::spawn(execute_triggered(bot.clone(), chain.clone(), order, price));
+    }
+    Ok(())
+}
+
+/// Sends the swap of an order moved to executing and follows it, an order whose swap can't be sent fails
+async fn execute_triggered(bot: Bot, chain: ChainConfig, order: LimitOrder, price: f64) {
+    let book = &GLOBAL_LIMIT_ORDER_BOOK;
+    match execute(&order).await {
+        Ok(sent) => {
+            if let Err(err) = book.transition(
+                order.id,
+                LimitOrderStatus::Executing,
+                LimitOrderStatus::Executing,
+                Some(sent.tx_hash),
+            ) {
+                log::error!("tx of limit order #{} not recorded: {}", order.id, err);
+            }
+            let text = format!(
+                "{} #{} triggered at {} {}\n⏳ Tx Sent\n{}",
+                order.side,
+                order.id,
+                format_price(price),
+                chain.native_symbol,
+                chain.tx_url(sent.tx_hash)
+            );
+            let message = notify(&bot, &order, text).await;
+            settle(bot, order, sent, message).await;
+        }
+        Err(err) => {
+            log::warn!("limit order #{} failed: {}", order.id, err);
+            if let Err(err) = book.transition(
+                order.id,
+                LimitOrderStatus::Executing,
+                LimitOrderStatus::Failed,
+                None,
+            ) {
+                log::error!("limit order #{} not marked as failed: {}", order.id, err);
+            }
+            let text = format!("{} #{} triggered but failed\n{}", order.side, order.id, err);
+            notify(&bot, &order, text).await;
+        }
+    }
+}
+
+/// Fills the order once its swap is mined, a filled buy is offered a stop loss and take profit.
+/// A swap that reverts, is dropped or can't be followed fails the order, it is not retried
+async fn settle(bot: Bot, order: LimitOrder, sent: SentSwap, message: Option<MessageId>) {
+    let tx_hash = sent.tx_hash;
+    let receipt = track_sent_swap(bot.clone(), order.chat_id, order.user_id, sent, message).await;
+    let status = match receipt {
+        Some(_) => LimitOrderStatus::Filled,
+        None => LimitOrderStatus::Failed,
+    };
+    if let Err(err) = GLOBAL_LIMIT_ORDER_BOOK.transition(
+        order.id,
+        LimitOrderStatus::Executing,
+        status,
+        Some(tx_hash),
+    ) {
+        log::error!("limit order #{} not settled: {}", order.id, err);
+    }
+    if status == LimitOrderStatus::Failed {
+        let text = format!("{} #{} was not filled, it is closed", order.side, order.id);
+        notify(&bot, &order, text).await;
+    }
+}
+
+/// Settles the orders interrupted by a restart. One whose swap was sent is followed again until it is
+/// mined or dropped, like [settle] does. Whether the swap of the others went out is unknown,
+/// they fail and are not retried
+async fn resume_interrupted(bot: &Bot) -> anyhow::Result<()> {
+    let book = &GLOBAL_LIMIT_ORDER_BOOK;
+    for (order, tx_hash) in book.interrupted()? {
+        let resumed = match tx_hash {
+            Some(tx_hash) => resume_order_swap(tx_hash).await,
+            None => Err(anyhow::anyhow!("its swap was not sent")),
+        };
+        match resumed {
+            Ok(sent) => {
+                log::info!("following limit order #{} again", order.id);
+                tokio::spawn(settle(bot.clone(), order, sent, None));
+            }
+            Err(err) => {
+                log::warn!("interrupted limit order #{} failed: {}", order.id, err);
+                book.transition(
+                    order.id,
+                    LimitOrderStatus::Executing,
+                    LimitOrderStatus::Failed,
+                    tx_hash,
+                )?;
+                let text = format!(
+                    "{} #{} was interrupted by a restart, it is closed. Check your wallet before placing it again",
+                    order.side, order.id
+                );
+                notify(bot, &order, text).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cancels the open orders placed on another chain than the one the engine trades on,
+/// they would never trigger. This happens when the trading chain is changed in the config
+async fn cancel_off_chain_orders(bot: &Bot, chain: &ChainConfig) -> anyhow::Result<()> {
+    let book = &GLOBAL_LIMIT_ORDER_BOOK;
+    for order in book.open_orders_off(chain.chain_id)? {
+        if book.transition(
+            order.id,
+            LimitOrderStatus::Open,
+            LimitOrderStatus::Cancelled,
+            None,
+        )? {
+            let text = format!(
+                "{} #{} was cancelled, the bot no longer trades on its chain",
+                order.side, order.id
+            );
+            notify(bot, &order, text).await;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the open orders of the chain once per new block
+async fn check_orders(bot: &Bot, chain: &ChainConfig, last_block: &mut U64) -> anyhow::Result<()> {
+    let block = chain.provider().await?.get_block_number().await?;
+    if block <= *last_block {
+        return Ok(());
+    }
+    *last_block = block;
+
+    let mut prices = PriceCache::new(chain);
+    check_limit_orders(bot, chain, &mut prices).await?;
+    check_exit_orders(bot, chain, &mut prices).await
+}
+
+/// Background task executing the limit, stop loss and take profit orders of the trading chain
+/// when their trigger price is hit
+pub(crate) async fn run_limit_order_engine(bot: Bot) {
+    if let Err(err) = resume_interrupted(&bot).await {
+        log::error!("interrupted limit orders not settled: {}", err);
+    }
+    if let Err(err) = exit_orders::resume_interrupted(&bot).await {
+        log::error!("interrupted exit orders not settled: {}", err);
+    }
+
+    let chain = GLOBAL_CHAIN_REGISTRY.trading_chain();
+    if let Err(err) = cancel_off_chain_orders(&bot, chain).await {
+        log::error!("limit orders of other chains not cancelled: {}", err);
+    }
+    let mut last_block = U64::zero();
+    loop {
+        if let Err(err) = check_orders(&bot, chain, &mut last_block).await {
+            log::warn!("limit order check on {} failed: {}", chain.name, err);
+        }
+        sleep(ENGINE_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn book() -> LimitOrderBook {
+        let database = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        LimitOrderBook::new(Box::leak(Box::new(database)))
+    }
+
+    fn insert(book: &LimitOrderBook, user_id: UserId, side: LimitSide) -> i64 {
+        let connection = book.database.connection();
+        connection
+            .execute(
+                "INSERT INTO limit_orders (user_id, chat_id, chain_id, side, token, amount, \
+                 trigger_price, expires_at, wallet_slot, slippage_bps, gas_preset, status, created_at) \
+                 VALUES (?1, ?1, 1, ?2, ?3, 0.5, 0.001, 2000, 0, 100, ?4, ?5, 1000)",
+                params![
+                    user_id.0,
+                    side.as_str(),
+                    format!("{:?}", Address::from_low_u64_be(1)),
+                    GasPreset::Standard.key(),
+                    LimitOrderStatus::Open.as_str()
+                ],
+            )
+            .unwrap();
+        connection.last_insert_rowid()
+    }
+
+    fn order(side: LimitSide) -> LimitOrder {
+        let book = book();
+        let id = insert(&book, UserId(1), side);
+        let mut orders = book.open_orders(UserId(1)).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].id, id);
+        orders.remove(0)
+    }
+
+    #[test]
+    fn buys_at_or_below_and_sells_at_or_above_the_trigger() {
+        let buy = order(LimitSide::Buy);
+        assert!(buy.is_triggered(0.0009));
+        assert!(buy.is_triggered(0.001));
+        assert!(!buy.is_triggered(0.0011));
+
+        let sell = order(LimitSide::Sell);
+        assert!(!sell.is_triggered(0.0009));
+        assert!(sell.is_triggered(0.001));
+        assert!(sell.is_triggered(0.0011));
+    }
+
+    #[test]
+    fn formats_prices_and_expiries() {
+        assert_eq!(format_price(1234.5678), "1234.57");
+        assert_eq!(format_price(0.000123456789), "0.000123457");
+        assert_eq!(format_price(1_234_567.0), "1234567");
+        assert_eq!(format_price(0.0), "0");
+
+        assert_eq!(format_expiry(60 * 60), "1h");
+        assert_eq!(format_expiry(36 * 60 * 60), "36h");
+        assert_eq!(format_expiry(7 * 24 * 60 * 60), "7d");
+    }
+
+    #[test]
+    fn an_order_is_executed_once() {
+        let book = book();
+        let id = insert(&book, UserId(1), LimitSide::Buy);
+        let (open, executing) = (LimitOrderStatus::Open, LimitOrderStatus::Executing);
+
+        assert!(book.transition(id, open, executing, None).unwrap());
+        // a second engine pass, or a cancel, finds the order no longer open
+        assert!(!book.transition(id, open, executing, None).unwrap());
+        assert!(book.cancel(UserId(1), id).is_err());
+        assert!(book.open_orders(UserId(1)).unwrap().is_empty());
+
+        let tx_hash = TxHash::from_low_u64_be(7);
+        assert!(book
+            .transition(id, executing, executing, Some(tx_hash))
+            .unwrap());
+        let interrupted = book.interrupted().unwrap();
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0].0.id, id);
+        assert_eq!(interrupted[0].1, Some(tx_hash));
+
+        assert!(book
+            .transition(id, executing, LimitOrderStatus::Filled, Some(tx_hash))
+            .unwrap());
+        assert!(book.interrupted().unwrap().is_empty());
+    }
+
+    #[test]
+    fn users_only_cancel_their_own_orders() {
+        let book = book();
+        let id = insert(&book, UserId(1), LimitSide::Sell);
+        assert!(book.cancel(UserId(2), id).is_err());
+        assert!(book.open_orders(UserId(2)).unwrap().is_empty());
+        book.cancel(UserId(1), id).unwrap();
+        assert!(book.open_orders(UserId(1)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,539 @@
+use crate::chains::ChainConfig;
+use crate::database::{Database, GLOBAL_DATABASE};
+use crate::global::Global;
+use crate::requests::on_chain::format_amount;
+use dotenv::dotenv;
+use ethers::{
+    prelude::*,
+    types::{Address, TransactionRequest, TxHash, U256},
+};
+use rusqlite::{params, OptionalExtension, Transaction};
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::types::UserId;
+
+/// Used to accrue and claim the rebates of trades sent with the Rebate toggle on
+pub(crate) static GLOBAL_REBATE_BOOK: Global<RebateBook> = Global::new("rebate tiers");
+
+/// Share of the fee paid back once a user has made `min_trades` rebated trades
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(crate) struct RebateTier {
+    pub(crate) min_trades: u64,
+    pub(crate) share_bps: u32,
+}
+
+impl RebateTier {
+    /// Formats the share as a percentage, e.g. "10%"
+    pub(crate) fn share(&self) -> String {
+        format!("{}%", self.share_bps as f64 / 100.0)
+    }
+}
+
+/// Tiers used when no config file exists
+const DEFAULT_TIERS: [RebateTier; 3] = [
+    RebateTier {
+        min_trades: 0,
+        share_bps: 1000,
+    },
+    RebateTier {
+        min_trades: 10,
+        share_bps: 2000,
+    },
+    RebateTier {
+        min_trades: 50,
+        share_bps: 3000,
+    },
+];
+
+/// Lifecycle of a rebate claim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClaimStatus {
+    /// The balance has been taken, the payout is being sent
+    Pending,
+    /// The payout has been broadcast and is waiting for its receipt
+    Sent,
+    /// The payout has been mined
+    Paid,
+    /// The payout could not be sent, reverted or was dropped, the balance has been restored
+    Failed,
+}
+
+impl ClaimStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Sent => "sent",
+            Self::Paid => "paid",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Accrued rebate of a user on one chain, in wei of its native coin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RebateBalance {
+    pub(crate) chain_id: u64,
+    pub(crate) balance: U256,
+}
+
+/// Persistent rebate accounting: what each trade earned, the running balances and the claims
+///
+/// A trade's fee is the native coin it paid for gas, the user earns a share of it set by their tier
+#[derive(Debug)]
+pub(crate) struct RebateBook {
+    /// Sorted by `min_trades`, the first tier starts at 0 trades
+    tiers: Vec<RebateTier>,
+    /// Wallet paying the claims out, claims are unavailable without it
+    treasury: Option<LocalWallet>,
+    database: &'static Database,
+}
+
+fn now() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+fn balance_of(tx: &Transaction, user_id: UserId, chain_id: u64) -> anyhow::Result<U256> {
+    let balance: Option<String> = tx
+        .query_row(
+            "SELECT balance FROM rebate_balances WHERE user_id = ?1 AND chain_id = ?2",
+            params![user_id.0, chain_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(balance
+        .map(|balance| U256::from_dec_str(&balance))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+fn set_balance(
+    tx: &Transaction,
+    user_id: UserId,
+    chain_id: u64,
+    balance: U256,
+) -> anyhow::Result<()> {
+    tx.execute(
+        "INSERT INTO rebate_balances (user_id, chain_id, balance) VALUES (?1, ?2, ?3)
+         ON CONFLICT (user_id, chain_id) DO UPDATE SET balance = excluded.balance",
+        params![user_id.0, chain_id, balance.to_string()],
+    )?;
+    Ok(())
+}
+
+impl RebateBook {
+    /// Reads the tiers from the JSON file at `REBATE_CONFIG` (defaults to `rebates.json`),
+    /// the default tiers apply when the file doesn't exist. The treasury's private key is read
+    /// from `REBATE_TREASURY_KEY`
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        dotenv().ok();
+        let path = std::env::var("REBATE_CONFIG").unwrap_or_else(|_| "rebates.json".to_string());
+        let tiers = match std::fs::read_to_string(&path) {
+            Ok(config) => serde_json::from_str(&config)?,
+            Err(err) => {
+                log::info!("Using the default rebate tiers, {} not read: {}", path, err);
+                DEFAULT_TIERS.to_vec()
+            }
+        };
+        let treasury = std::env::var("REBATE_TREASURY_KEY")
+            .ok()
+            .map(|key| key.parse::<LocalWallet>())
+            .transpose()?;
+        if treasury.is_none() {
+            log::info!("Rebate claims are disabled, REBATE_TREASURY_KEY is not set");
+        }
+        Self::new(tiers, treasury, &GLOBAL_DATABASE)
+    }
+
+    pub(crate) fn new(
+        mut tiers: Vec<RebateTier>,
+        treasury: Option<LocalWallet>,
+        database: &'static Database,
+    ) -> anyhow::Result<Self> {
+        tiers.sort_by_key(|tier| tier.min_trades);
+        match tiers.first() {
+            Some(tier) if tier.min_trades == 0 => {}
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "The first rebate tier must start at 0 trades"
+                ))
+            }
+        }
+        if tiers.iter().any(|tier| tier.share_bps > 10_000) {
+            return Err(anyhow::anyhow!("A rebate share can't be above 100%"));
+        }
+        Ok(Self {
+            tiers,
+            treasury,
+            database,
+        })
+    }
+
+    /// Tier of a user who has made `trades` rebated trades
+    pub(crate) fn tier(&self, trades: u64) -> RebateTier {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_trades <= trades)
+            .copied()
+            .unwrap_or(self.tiers[0])
+    }
+
+    /// Tier the user moves up to after more trades, `None` at the top tier
+    pub(crate) fn next_tier(&self, trades: u64) -> Option<RebateTier> {
+        self.tiers
+            .iter()
+            .find(|tier| tier.min_trades > trades)
+            .copied()
+    }
+
+    /// Number of rebated trades of the user, on every chain
+    pub(crate) fn trade_count(&self, user_id: UserId) -> anyhow::Result<u64> {
+        Ok(self.database.connection().query_row(
+            "SELECT COUNT(*) FROM rebate_accruals WHERE user_id = ?1",
+            params![user_id.0],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Records the fee of a mined trade and credits the user's share of it, returns the share
+    ///
+    /// A trade is only credited once
+    pub(crate) fn accrue(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+        tx_hash: TxHash,
+        fee: U256,
+    ) -> anyhow::Result<U256> {
+        let mut connection = self.database.connection();
+        let tx = connection.transaction()?;
+        let trades: u64 = tx.query_row(
+            "SELECT COUNT(*) FROM rebate_accruals WHERE user_id = ?1",
+            params![user_id.0],
+            |row| row.get(0),
+        )?;
+        let tier = self.tier(trades);
+        let rebate = fee * U256::from(tier.share_bps) / U256::from(10_000u32);
+
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO rebate_accruals \
+             (user_id, chain_id, tx_hash, fee, share_bps, rebate, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                user_id.0,
+                chain_id,
+                format!("{:?}", tx_hash),
+                fee.to_string(),
+                tier.share_bps,
+                rebate.to_string(),
+                now()?,
+            ],
+        )?;
+        if inserted == 0 {
+            return Ok(U256::zero());
+        }
+        let balance = balance_of(&tx, user_id, chain_id)?;
+        set_balance(&tx, user_id, chain_id, balance + rebate)?;
+        tx.commit()?;
+        Ok(rebate)
+    }
+
+    /// Accrued balances of the user, on the chains where they have one
+    pub(crate) fn balances(&self, user_id: UserId) -> anyhow::Result<Vec<RebateBalance>> {
+        let connection = self.database.connection();
+        let mut statement = connection.prepare(
+            "SELECT chain_id, balance FROM rebate_balances WHERE user_id = ?1 ORDER BY chain_id",
+        )?;
+        let mut rows = statement.query(params![user_id.0])?;
+        let mut balances = Vec::new();
+        while let Some(row) = rows.next()? {
+            let balance = U256::from_dec_str(&row.get::<_, String>(1)?)?;
+            if !balance.is_zero() {
+                balances.push(RebateBalance {
+                    chain_id: row.get(0)?,
+                    balance,
+                });
+            }
+        }
+        Ok(balances)
+    }
+
+    /// Empties the balance into a pending claim, returns the claim id and amount
+    fn start_claim(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+        recipient: Address,
+    ) -> anyhow::Result<(i64, U256)> {
+        let mut connection = self.database.connection();
+        let tx = connection.transaction()?;
+        let amount = balance_of(&tx, user_id, chain_id)?;
+        if amount.is_zero() {
+            return Err(anyhow::anyhow!("No rebate to claim"));
+        }
+        set_balance(&tx, user_id, chain_id, U256::zero())?;
+        tx.execute(
+            "INSERT INTO rebate_claims (user_id, chain_id, recipient, amount, status, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                user_id.0,
+                chain_id,
+                format!("{:?}", recipient),
+                amount.to_string(),
+                ClaimStatus::Pending.as_str(),
+                now()?,
+            ],
+        )?;
+        let claim_id = tx.last_insert_rowid();
+        tx.commit()?;
+        Ok((claim_id, amount))
+    }
+
+    /// Moves a claim to `status`, a failed payout goes back to the user's balance
+    fn set_claim_status(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+        claim_id: i64,
+        amount: U256,
+        status: ClaimStatus,
+        tx_hash: Option<TxHash>,
+    ) -> anyhow::Result<()> {
+        let mut connection = self.database.connection();
+        let tx = connection.transaction()?;
+        if status == ClaimStatus::Failed {
+            let balance = balance_of(&tx, user_id, chain_id)?;
+            set_balance(&tx, user_id, chain_id, balance + amount)?;
+        }
+        tx.execute(
+            "UPDATE rebate_claims SET status = ?1, tx_hash = COALESCE(?2, tx_hash) WHERE id = ?3",
+            params![
+                status.as_str(),
+                tx_hash.map(|tx_hash| format!("{:?}", tx_hash)),
+                claim_id
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Pays the user's balance on the chain out to `recipient` from the treasury wallet and waits
+    /// for the payout to be mined
+    ///
+    /// A payout that is not sent, reverts or is dropped gives the balance back. When its receipt
+    /// can't be fetched the claim stays sent, the balance is only restored once it is known to have failed
+    pub(crate) async fn claim(
+        &self,
+        user_id: UserId,
+        chain: &ChainConfig,
+        recipient: Address,
+    ) -> anyhow::Result<(U256, TxHash)> {
+        let treasury = self
+            .treasury
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Rebate claims are not available yet"))?;
+        let chain_id = chain.chain_id;
+        let (claim_id, amount) = self.start_claim(user_id, chain_id, recipient)?;
+        let fail = |err: anyhow::Error, tx_hash: Option<TxHash>| {
+            self.set_claim_status(
+                user_id,
+                chain_id,
+                claim_id,
+                amount,
+                ClaimStatus::Failed,
+                tx_hash,
+            )?;
+            Err(anyhow::anyhow!(
+                "The rebate could not be paid, your balance has been restored: {}",
+                err
+            ))
+        };
+
+        let client = match chain.provider().await {
+            Ok(provider) => SignerMiddleware::new(provider, treasury.with_chain_id(chain_id)),
+            Err(err) => return fail(err, None),
+        };
+        let pending = match client
+            .send_transaction(TransactionRequest::pay(recipient, amount), None)
+            .await
+        {
+            Ok(pending) => pending,
+            Err(err) => return fail(err.into(), None),
+        };
+        let tx_hash = pending.tx_hash();
+        self.set_claim_status(
+            user_id,
+            chain_id,
+            claim_id,
+            amount,
+            ClaimStatus::Sent,
+            Some(tx_hash),
+        )?;
+
+        match pending.await {
+            Ok(Some(receipt)) if receipt.status == Some(1u64.into()) => {
+                self.set_claim_status(
+                    user_id,
+                    chain_id,
+                    claim_id,
+                    amount,
+                    ClaimStatus::Paid,
+                    None,
+                )?;
+                log::info!(
+                    "rebate of {} {} paid to {:?}: {:?}",
+                    format_amount(amount, 18),
+                    chain.native_symbol,
+                    recipient,
+                    tx_hash
+                );
+                Ok((amount, tx_hash))
+            }
+            Ok(Some(_)) => fail(anyhow::anyhow!("the payout reverted"), None),
+            Ok(None) => fail(anyhow::anyhow!("the payout was dropped"), None),
+            Err(err) => {
+                log::error!("rebate claim {} receipt unavailable: {}", claim_id, err);
+                Err(anyhow::anyhow!(
+                    "The rebate has been sent but its confirmation is unavailable\n{}",
+                    chain.tx_url(tx_hash)
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn book() -> RebateBook {
+        let database = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        RebateBook::new(DEFAULT_TIERS.to_vec(), None, Box::leak(Box::new(database))).unwrap()
+    }
+
+    fn tx_hash(n: u64) -> TxHash {
+        TxHash::from_low_u64_be(n)
+    }
+
+    fn balance(book: &RebateBook, user_id: UserId, chain_id: u64) -> U256 {
+        book.balances(user_id)
+            .unwrap()
+            .into_iter()
+            .find(|balance| balance.chain_id == chain_id)
+            .map_or(U256::zero(), |balance| balance.balance)
+    }
+
+    #[test]
+    fn picks_the_tier_of_the_trade_count() {
+        let book = book();
+        assert_eq!(book.tier(0).share_bps, 1000);
+        assert_eq!(book.tier(9).share_bps, 1000);
+        assert_eq!(book.tier(10).share_bps, 2000);
+        assert_eq!(book.tier(1000).share_bps, 3000);
+        assert_eq!(book.next_tier(9).map(|tier| tier.min_trades), Some(10));
+        assert_eq!(book.next_tier(50), None);
+        assert_eq!(book.tier(10).share(), "20%");
+    }
+
+    #[test]
+    fn rejects_invalid_tiers() {
+        let database = book().database;
+        let tier = |min_trades, share_bps| RebateTier {
+            min_trades,
+            share_bps,
+        };
+        assert!(RebateBook::new(vec![], None, database).is_err());
+        assert!(RebateBook::new(vec![tier(5, 1000)], None, database).is_err());
+        assert!(RebateBook::new(vec![tier(0, 10_001)], None, database).is_err());
+        // tiers are sorted, the config file may list them in any order
+        let book = RebateBook::new(vec![tier(10, 500), tier(0, 100)], None, database).unwrap();
+        assert_eq!(book.tier(10).share_bps, 500);
+    }
+
+    #[test]
+    fn credits_the_tier_share_of_the_fee() {
+        let book = book();
+        let user_id = UserId(1);
+        let fee = U256::from(1_000_000u64);
+        for n in 0..10 {
+            assert_eq!(
+                book.accrue(user_id, 1, tx_hash(n), fee).unwrap(),
+                U256::from(100_000u64)
+            );
+        }
+        // the 11th trade is credited at the second tier
+        assert_eq!(
+            book.accrue(user_id, 137, tx_hash(10), fee).unwrap(),
+            U256::from(200_000u64)
+        );
+        assert_eq!(book.trade_count(user_id).unwrap(), 11);
+        assert_eq!(balance(&book, user_id, 1), U256::from(1_000_000u64));
+        assert_eq!(balance(&book, user_id, 137), U256::from(200_000u64));
+        assert_eq!(balance(&book, UserId(2), 1), U256::zero());
+    }
+
+    #[test]
+    fn credits_each_trade_once() {
+        let book = book();
+        let user_id = UserId(1);
+        let fee = U256::from(1_000u64);
+        assert_eq!(
+            book.accrue(user_id, 1, tx_hash(1), fee).unwrap(),
+            U256::from(100u64)
+        );
+        assert_eq!(
+            book.accrue(user_id, 1, tx_hash(1), fee).unwrap(),
+            U256::zero()
+        );
+        assert_eq!(book.trade_count(user_id).unwrap(), 1);
+        assert_eq!(balance(&book, user_id, 1), U256::from(100u64));
+    }
+
+    #[test]
+    fn a_failed_claim_restores_the_balance() {
+        let book = book();
+        let user_id = UserId(1);
+        book.accrue(user_id, 1, tx_hash(1), U256::from(1_000u64))
+            .unwrap();
+
+        let (claim_id, amount) = book.start_claim(user_id, 1, Address::zero()).unwrap();
+        assert_eq!(amount, U256::from(100u64));
+        assert_eq!(balance(&book, user_id, 1), U256::zero());
+        assert!(book.start_claim(user_id, 1, Address::zero()).is_err());
+
+        // trades accrued while the payout was out are kept
+        book.accrue(user_id, 1, tx_hash(2), U256::from(1_000u64))
+            .unwrap();
+        book.set_claim_status(user_id, 1, claim_id, amount, ClaimStatus::Failed, None)
+            .unwrap();
+        assert_eq!(balance(&book, user_id, 1), U256::from(200u64));
+
+        let (claim_id, amount) = book.start_claim(user_id, 1, Address::zero()).unwrap();
+        book.set_claim_status(user_id, 1, claim_id, amount, ClaimStatus::Paid, None)
+            .unwrap();
+        assert_eq!(balance(&book, user_id, 1), U256::zero());
+    }
+
+    #[tokio::test]
+    async fn claims_need_a_treasury() {
+        let book = book();
+        let chain: ChainConfig = serde_json::from_value(serde_json::json!({
+            "chain_id": 1,
+            "name": "Ethereum",
+            "rpc_urls": ["http://localhost:8545"],
+            "native_symbol": "ETH",
+            "routers": ["0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"],
+            "explorer_url": "https://etherscan.io"
+        }))
+        .unwrap();
+        book.accrue(UserId(1), 1, tx_hash(1), U256::from(1_000u64))
+            .unwrap();
+        assert!(book
+            .claim(UserId(1), &chain, Address::zero())
+            .await
+            .is_err());
+        // nothing was taken from the balance
+        assert_eq!(balance(&book, UserId(1), 1), U256::from(100u64));
+    }
+}
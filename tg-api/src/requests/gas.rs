@@ -0,0 +1,213 @@
+use crate::requests::on_chain::format_gwei;
+use crate::settings::GasPreset;
+use ethers::{
+    abi::Detokenize,
+    contract::FunctionCall,
+    prelude::*,
+    types::{transaction::eip2718::TypedTransaction, BlockNumber, U256},
+    utils::parse_units,
+};
+use std::borrow::Borrow;
+
+/// Fees per gas a transaction is sent with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GasFees {
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+    /// Chains whose blocks have no base fee
+    Legacy { gas_price: U256 },
+}
+
+impl GasFees {
+    /// Sets the fees on the call, switching it to a legacy transaction when needed
+    pub(crate) fn apply<B, M, D>(&self, call: FunctionCall<B, M, D>) -> FunctionCall<B, M, D>
+    where
+        B: Borrow<M>,
+        M: Middleware,
+        D: Detokenize,
+    {
+        match *self {
+            Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let mut call = call;
+                if let TypedTransaction::Eip1559(tx) = &mut call.tx {
+                    tx.max_fee_per_gas = Some(max_fee_per_gas);
+                    tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+                }
+                call
+            }
+            Self::Legacy { gas_price } => call.legacy().gas_price(gas_price),
+        }
+    }
+}
+
+fn gwei(amount: u64) -> anyhow::Result<U256> {
+    Ok(parse_units(amount, "gwei")?.into())
+}
+
+async fn latest_block<M: Middleware>(client: &M) -> anyhow::Result<Block<TxHash>>
+where
+    M::Error: 'static,
+{
+    client
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Latest block not found"))
+}
+
+/// Gets the latest block number and the current gas price, the base fee on EIP-1559 chains
+pub(crate) async fn current_gas_price<M: Middleware>(client: &M) -> anyhow::Result<(U64, U256)>
+where
+    M::Error: 'static,
+{
+    let block = latest_block(client).await?;
+    let block_number = block.number.unwrap_or_default();
+    match block.base_fee_per_gas {
+        Some(base_fee) => Ok((block_number, base_fee)),
+        None => Ok((block_number, client.get_gas_price().await?)),
+    }
+}
+
+/// Prices the preset from the latest block, chains without a base fee fall back to legacy gas prices
+///
+/// Fails when gas is above `max_fee_gwei`, see [eip1559_fees] for how the cap lowers the max fee
+pub(crate) async fn gas_fees<M: Middleware>(
+    client: &M,
+    preset: GasPreset,
+    max_fee_gwei: Option<u64>,
+) -> anyhow::Result<GasFees>
+where
+    M::Error: 'static,
+{
+    let block = latest_block(client).await?;
+    match block.base_fee_per_gas {
+        Some(base_fee) => eip1559_fees(base_fee, preset, max_fee_gwei),
+        None => legacy_fees(client.get_gas_price().await?, preset, max_fee_gwei),
+    }
+}
+
+fn above_cap(price: U256, max_fee_gwei: Option<u64>) -> anyhow::Result<()> {
+    match max_fee_gwei {
+        Some(max_fee_gwei) if price > gwei(max_fee_gwei)? => Err(anyhow::anyhow!(
+            "Gas is {} gwei, above your max fee of {} gwei",
+            format_gwei(price),
+            max_fee_gwei
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Leaves room for the base fee to rise by the preset's share on top of the tip
+///
+/// A cap between the base fee plus the tip and that max fee lowers the max fee to the cap, the
+/// transaction then waits in the mempool if the base fee rises past the cap before it is mined
+fn eip1559_fees(
+    base_fee: U256,
+    preset: GasPreset,
+    max_fee_gwei: Option<u64>,
+) -> anyhow::Result<GasFees> {
+    let max_priority_fee_per_gas = gwei(preset.priority_fee_gwei())?;
+    above_cap(base_fee + max_priority_fee_per_gas, max_fee_gwei)?;
+    let mut max_fee_per_gas = base_fee * preset.base_fee_percent() / 100 + max_priority_fee_per_gas;
+    if let Some(max_fee_gwei) = max_fee_gwei {
+        max_fee_per_gas = max_fee_per_gas.min(gwei(max_fee_gwei)?);
+    }
+    Ok(GasFees::Eip1559 {
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+    })
+}
+
+fn legacy_fees(
+    gas_price: U256,
+    preset: GasPreset,
+    max_fee_gwei: Option<u64>,
+) -> anyhow::Result<GasFees> {
+    let gas_price = gas_price * preset.legacy_percent() / 100;
+    above_cap(gas_price, max_fee_gwei)?;
+    Ok(GasFees::Legacy { gas_price })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eip1559(max_fee_gwei: u64, max_priority_fee_gwei: u64) -> GasFees {
+        GasFees::Eip1559 {
+            max_fee_per_gas: gwei(max_fee_gwei).unwrap(),
+            max_priority_fee_per_gas: gwei(max_priority_fee_gwei).unwrap(),
+        }
+    }
+
+    #[test]
+    fn prices_each_preset_from_the_base_fee() {
+        let base_fee = gwei(20).unwrap();
+        assert_eq!(
+            eip1559_fees(base_fee, GasPreset::Standard, None).unwrap(),
+            eip1559(26, 1)
+        );
+        assert_eq!(
+            eip1559_fees(base_fee, GasPreset::Fast, None).unwrap(),
+            eip1559(32, 2)
+        );
+        assert_eq!(
+            eip1559_fees(base_fee, GasPreset::Turbo, None).unwrap(),
+            eip1559(45, 5)
+        );
+    }
+
+    #[test]
+    fn prices_each_preset_from_the_legacy_gas_price() {
+        let gas_price = gwei(40).unwrap();
+        for (preset, expected) in [
+            (GasPreset::Standard, 40),
+            (GasPreset::Fast, 50),
+            (GasPreset::Turbo, 60),
+        ] {
+            assert_eq!(
+                legacy_fees(gas_price, preset, None).unwrap(),
+                GasFees::Legacy {
+                    gas_price: gwei(expected).unwrap()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn a_cap_above_the_max_fee_changes_nothing() {
+        assert_eq!(
+            eip1559_fees(gwei(20).unwrap(), GasPreset::Fast, Some(50)).unwrap(),
+            eip1559(32, 2)
+        );
+        assert_eq!(
+            legacy_fees(gwei(40).unwrap(), GasPreset::Fast, Some(50)).unwrap(),
+            GasFees::Legacy {
+                gas_price: gwei(50).unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn a_cap_below_the_max_fee_lowers_it() {
+        // 20 gwei base fee and 2 gwei tip fit under the 25 gwei cap, the 32 gwei max fee does not
+        assert_eq!(
+            eip1559_fees(gwei(20).unwrap(), GasPreset::Fast, Some(25)).unwrap(),
+            eip1559(25, 2)
+        );
+        assert_eq!(
+            eip1559_fees(gwei(20).unwrap(), GasPreset::Fast, Some(22)).unwrap(),
+            eip1559(22, 2)
+        );
+    }
+
+    #[test]
+    fn fails_when_gas_is_above_the_cap() {
+        assert!(eip1559_fees(gwei(20).unwrap(), GasPreset::Fast, Some(21)).is_err());
+        assert!(eip1559_fees(gwei(30).unwrap(), GasPreset::Standard, Some(20)).is_err());
+        assert!(legacy_fees(gwei(40).unwrap(), GasPreset::Fast, Some(49)).is_err());
+    }
+}
@@ -1,2 +1,9 @@
+pub(crate) mod gas;
 pub(crate) mod on_chain;
+pub(crate) mod permit;
+pub(crate) mod quote;
+pub(crate) mod relay;
 pub(crate) mod server;
+pub(crate) mod simulation;
+pub(crate) mod swap;
+pub(crate) mod token_safety;
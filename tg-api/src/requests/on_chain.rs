@@ -1,83 +1,384 @@
-use dotenv::dotenv;
+use crate::chains::{ChainConfig, GLOBAL_CHAIN_REGISTRY};
+use crate::requests::gas;
+use crate::requests::swap::Erc20;
+use crate::tokens::{TokenMetadata, GLOBAL_TOKEN_METADATA_CACHE};
+use crate::wallets::{UserWallet, GLOBAL_WALLET_STORE};
 use ethers::{
-    prelude::*,
+    abi::{self, ParamType, Tokenizable},
+    contract::{
+        multicall_contract::{Call3, Result as CallResult},
+        Multicall,
+    },
     providers::{Http, Middleware, Provider},
-    types::{U256, U64},
+    types::{Address, U256, U64},
+    utils::format_units,
 };
-use std::convert::TryFrom;
+use futures::future::{join_all, try_join_all};
+use std::sync::Arc;
+use teloxide::types::UserId;
+use teloxide::utils::markdown::{bold, code_inline, escape};
+
+/// Balance of an ERC-20 token held by a wallet
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBalance {
+    pub(crate) token: Address,
+    pub(crate) symbol: String,
+    pub(crate) decimals: u8,
+    pub(crate) balance: U256,
+}
+
+/// Formats an amount in base units with the token's decimals, without trailing zeros
+pub(crate) fn format_amount(amount: U256, decimals: u8) -> String {
+    let formatted = format_units(amount, decimals as u32).unwrap_or_else(|_| amount.to_string());
+    match formatted.contains('.') {
+        true => formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+        false => formatted,
+    }
+}
+
+/// Decodes the result of one call of a multicall, `None` if it reverted or returned something else
+fn decode_call<T: Tokenizable>(result: Option<&CallResult>, kind: ParamType) -> Option<T> {
+    let result = result.filter(|result| result.success)?;
+    let token = abi::decode(&[kind], &result.return_data).ok()?.pop()?;
+    T::from_token(token).ok()
+}
+
+/// Decodes the result of a `name()` or `symbol()` call, older tokens like MKR and SAI
+/// return a bytes32 right-padded with zeros instead of a string
+fn decode_text(result: Option<&CallResult>) -> Option<String> {
+    if let Some(text) = decode_call::<String>(result, ParamType::String) {
+        return Some(text);
+    }
+    let data = result.filter(|result| result.success)?.return_data.as_ref();
+    if data.len() != 32 {
+        return None;
+    }
+    let text = data.split(|&byte| byte == 0).next()?;
+    match text.is_empty() {
+        true => None,
+        false => String::from_utf8(text.to_vec()).ok(),
+    }
+}
 
 /// Type to query on chain info
 #[derive(Debug, Clone)]
 pub(crate) struct OnChainInfoQuery {
+    chain: ChainConfig,
     provider: Provider<Http>,
 }
 
 impl OnChainInfoQuery {
-    pub(crate) fn new(_chain_id: u64) -> anyhow::Result<Self> {
-        dotenv().ok();
-        //let provider = match chain_id {
-        //    137 => {
-        //        let rpc_url = std::env::var("POLYGON_RPC_URL")?;
-        //        let provider = Provider::<Http>::try_from(rpc_url)?;
-        //        log::info!("Connected to Polygon mainnet");
-        //        provider
-        //    }
-        //    1 => {
-        //        let rpc_url = std::env::var("ETH_RPC_URL")?;
-        //        let provider = Provider::<Http>::try_from(rpc_url)?;
-        //        log::info!("Connected to Ethereum mainnet");
-        //        provider
-        //    }
-        //    _ => return Err(anyhow!("Unsupported chain id: {}", chain_id)),
-        let rpc_url = std::env::var("ETH_RPC_URL")?;
-        let provider = Provider::<Http>::try_from(rpc_url)?;
-
-        Ok(Self { provider })
-    }
-
-    /// Gets the block number and gas fee
+    pub(crate) async fn new(chain: &ChainConfig) -> anyhow::Result<Self> {
+        let provider = chain.provider().await?;
+
+        Ok(Self {
+            chain: chain.clone(),
+            provider,
+        })
+    }
+
+    /// Gets the block number and gas fee, the base fee on EIP-1559 chains
     pub(crate) async fn query_info(&self) -> anyhow::Result<(U64, U256)> {
-        let block_number = self.provider.get_block_number().await?;
+        gas::current_gas_price(&self.provider).await
+    }
+
+    /// Gets the native coin balance of the address
+    pub(crate) async fn native_balance(&self, owner: Address) -> anyhow::Result<U256> {
+        Ok(self.provider.get_balance(owner, None).await?)
+    }
+
+    /// Gets the ERC-20 balance of the address together with the token's symbol and decimals
+    pub(crate) async fn token_balance(
+        &self,
+        token: Address,
+        owner: Address,
+    ) -> anyhow::Result<TokenBalance> {
+        let erc20 = Erc20::new(token, Arc::new(self.provider.clone()));
+        let balance = erc20.balance_of(owner);
+        let (metadata, balance) = futures::try_join!(self.token_metadata(token), async {
+            Ok(balance.call().await?)
+        })?;
+        Ok(TokenBalance {
+            token,
+            symbol: metadata.symbol,
+            decimals: metadata.decimals,
+            balance,
+        })
+    }
+
+    /// Gets the non-zero balances of the address among the ERC-20 tokens the user has traded on the chain
+    pub(crate) async fn held_tokens(
+        &self,
+        user_id: UserId,
+        owner: Address,
+    ) -> anyhow::Result<Vec<TokenBalance>> {
+        let tokens = GLOBAL_WALLET_STORE.traded_tokens(user_id, self.chain.chain_id)?;
+        let balances =
+            try_join_all(tokens.iter().map(|&token| self.token_balance(token, owner))).await?;
+        Ok(balances
+            .into_iter()
+            .filter(|token| !token.balance.is_zero())
+            .collect())
+    }
+
+    /// Gets the name, symbol, decimals and total supply of an ERC-20 token in one multicall.
+    /// Fails with a message for the user when the address is not a contract or not an ERC-20 token
+    pub(crate) async fn token_metadata(&self, token: Address) -> anyhow::Result<TokenMetadata> {
+        if let Some(metadata) = GLOBAL_TOKEN_METADATA_CACHE.get(self.chain.chain_id, token) {
+            return Ok(metadata);
+        }
+
+        let code = self.provider.get_code(token, None).await?;
+        if code.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{:?} is not a contract on {}",
+                token,
+                self.chain.name
+            ));
+        }
+
+        let client = Arc::new(self.provider.clone());
+        let erc20 = Erc20::new(token, client.clone());
+        let multicall = Multicall::<Provider<Http>>::new_with_chain_id(
+            client,
+            self.chain.multicall,
+            Some(self.chain.chain_id),
+        )?;
+        // the calls are aggregated by hand, as the multicall would fail to decode a bytes32 name or symbol
+        let calls = [
+            erc20.name().calldata(),
+            erc20.symbol().calldata(),
+            erc20.decimals().calldata(),
+            erc20.total_supply().calldata(),
+        ]
+        .into_iter()
+        .map(|call_data| Call3 {
+            target: token,
+            allow_failure: true,
+            call_data: call_data.unwrap_or_default(),
+        })
+        .collect();
+        let results = multicall.contract.aggregate_3(calls).call().await?;
 
-        let gas_price = self.provider.get_gas_price().await?;
+        let metadata = match (
+            decode_text(results.first()),
+            decode_text(results.get(1)),
+            decode_call(results.get(2), ParamType::Uint(8)),
+            decode_call(results.get(3), ParamType::Uint(256)),
+        ) {
+            (Some(name), Some(symbol), Some(decimals), Some(total_supply)) => TokenMetadata {
+                address: token,
+                name,
+                symbol,
+                decimals,
+                total_supply,
+            },
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{:?} is not an ERC-20 token on {}",
+                    token,
+                    self.chain.name
+                ))
+            }
+        };
+        GLOBAL_TOKEN_METADATA_CACHE.insert(self.chain.chain_id, metadata.clone());
+        Ok(metadata)
+    }
+}
 
-        Ok((block_number, gas_price))
+/// Gets the metadata of an ERC-20 token, connecting to the chain only when it isn't cached
+pub(crate) async fn token_metadata(
+    chain: &ChainConfig,
+    token: Address,
+) -> anyhow::Result<TokenMetadata> {
+    match GLOBAL_TOKEN_METADATA_CACHE.get(chain.chain_id, token) {
+        Some(metadata) => Ok(metadata),
+        None => {
+            OnChainInfoQuery::new(chain)
+                .await?
+                .token_metadata(token)
+                .await
+        }
     }
 }
 
-/// Helper function to query the block number and gas fee from supported networks
+/// Formats a gas price in Gwei with at most two decimals
+pub(crate) fn format_gwei(gas_price: U256) -> String {
+    let gwei = format_units(gas_price, "gwei").unwrap_or_else(|_| gas_price.to_string());
+    match gwei.split_once('.') {
+        Some((whole, fraction)) => {
+            let fraction = fraction[..fraction.len().min(2)].trim_end_matches('0');
+            match fraction.is_empty() {
+                true => whole.to_string(),
+                false => format!("{}.{}", whole, fraction),
+            }
+        }
+        None => gwei,
+    }
+}
+
+/// Renders the gas price and block number of one chain
+async fn chain_info(chain: &ChainConfig) -> String {
+    let info = match OnChainInfoQuery::new(chain).await {
+        Ok(query) => query.query_info().await,
+        Err(err) => Err(err),
+    };
+    match info {
+        Ok((block_number, gas_price)) => format!(
+            "{}\n{} {} Gwei  ═  {} {}",
+            bold(&escape(&chain.name)),
+            bold("Gas:"),
+            escape(&format_gwei(gas_price)),
+            bold("Block:"),
+            block_number,
+        ),
+        Err(err) => {
+            log::warn!("on chain info query on {} failed: {}", chain.name, err);
+            format!("{}\n{}", bold(&escape(&chain.name)), escape("unavailable"))
+        }
+    }
+}
+
+/// Helper function to query the block number and gas fee from every enabled chain
 pub(crate) async fn get_on_chain_info() -> anyhow::Result<String> {
-    let eth_provider = OnChainInfoQuery::new(1).unwrap();
-    //let poly_provider = OnChainInfoQuery::new(137).unwrap();
-    let (eth_block_number, eth_gas_price) = eth_provider.query_info().await.unwrap();
-    //let (poly_block_number, poly_gas_price) = poly_provider.query_info().await.unwrap();
-    let eth_gas_price = eth_gas_price / 1_000_000_000u64;
-    //let poly_gas_price = poly_gas_price / 1_000_000_000u64;
-    let message = format!(
-        "*Ethereum*\n*Gas:* {} Gwei  ═  *Block:* {}\n\n*Polygon*\n*Gas:* {} Gwei  ═  *Block:* {}",
-        eth_gas_price, eth_block_number, 64, 48849599,
-    );
-    Ok(message)
+    let chains = join_all(GLOBAL_CHAIN_REGISTRY.enabled().map(chain_info)).await;
+    Ok(chains.join("\n\n"))
+}
+
+/// Helper function to show the on chain info together with the user's wallets, creating them on the first /start
+pub(crate) async fn get_on_chain_info_start(user_id: UserId) -> anyhow::Result<String> {
+    let wallets = GLOBAL_WALLET_STORE.wallets(user_id)?;
+    let wallets = wallets
+        .iter()
+        .map(|wallet| {
+            format!(
+                "{} {}",
+                bold(&escape(&wallet.name)),
+                code_inline(&format!("{:?}", wallet.address))
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let message = get_on_chain_info().await?;
+    Ok(format!("{}\n\n{}", message, wallets))
+}
+
+/// Renders the balances of one wallet on one chain
+async fn wallet_chain_balances(
+    user_id: UserId,
+    wallet: &UserWallet,
+    chain: &ChainConfig,
+) -> anyhow::Result<Vec<String>> {
+    let query = OnChainInfoQuery::new(chain).await?;
+    let (native, token_balances) = futures::try_join!(
+        query.native_balance(wallet.address),
+        query.held_tokens(user_id, wallet.address)
+    )?;
+
+    let mut lines = vec![escape(&format!(
+        "{}: {} {}",
+        chain.name,
+        format_amount(native, 18),
+        chain.native_symbol
+    ))];
+    lines.extend(token_balances.iter().map(|token| {
+        escape(&format!(
+            "  {}: {}",
+            token.symbol,
+            format_amount(token.balance, token.decimals)
+        ))
+    }));
+    Ok(lines)
 }
 
-pub(crate) async fn get_on_chain_info_start() -> anyhow::Result<String> {
-    let wallet1 = LocalWallet::new(&mut rand::thread_rng());
-    let address1 = wallet1.address();
-
-    let wallet2 = LocalWallet::new(&mut rand::thread_rng());
-    let address2 = wallet2.address();
-
-    let wallet3 = LocalWallet::new(&mut rand::thread_rng());
-    let address3 = wallet3.address();
-    let eth_provider = OnChainInfoQuery::new(1).unwrap();
-    //let poly_provider = OnChainInfoQuery::new(137).unwrap();
-    let (eth_block_number, eth_gas_price) = eth_provider.query_info().await.unwrap();
-    //let (poly_block_number, poly_gas_price) = poly_provider.query_info().await.unwrap();
-    let eth_gas_price = eth_gas_price / 1_000_000_000u64;
-    //let poly_gas_price = poly_gas_price / 1_000_000_000u64;
-    let message = format!(
-        "*Ethereum*\n*Gas:* {} Gwei  ═  *Block:* {}\n\n*Polygon*\n*Gas:* {} Gwei  ═  *Block:* {}\n *Wallet 1* {}\n *Wallet2* {}\n*Wallet 3* {}",
-        eth_gas_price, eth_block_number, 64, 48849599, address1, address2, address3
-    );
-    Ok(message)
+/// Helper function to list the user's wallets with their balances on every supported chain
+pub(crate) async fn get_wallets_info(user_id: UserId) -> anyhow::Result<String> {
+    let wallets = GLOBAL_WALLET_STORE.wallets(user_id)?;
+
+    let sections = join_all(wallets.iter().map(|wallet| async move {
+        let mut lines = vec![
+            match wallet.is_default {
+                true => format!("{} ⭐", bold(&escape(&wallet.name))),
+                false => bold(&escape(&wallet.name)),
+            },
+            code_inline(&format!("{:?}", wallet.address)),
+        ];
+        let balances = join_all(
+            GLOBAL_CHAIN_REGISTRY
+                .enabled()
+                .map(|chain| wallet_chain_balances(user_id, wallet, chain)),
+        )
+        .await;
+        for (chain, balances) in GLOBAL_CHAIN_REGISTRY.enabled().zip(balances) {
+            match balances {
+                Ok(balances) => lines.extend(balances),
+                Err(err) => {
+                    log::warn!("balance query on {} failed: {}", chain.name, err);
+                    lines.push(escape(&format!("{}: unavailable", chain.name)));
+                }
+            }
+        }
+        lines.join("\n")
+    }))
+    .await;
+
+    Ok(sections.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::Token;
+
+    fn returned(data: Vec<u8>) -> CallResult {
+        CallResult {
+            success: true,
+            return_data: data.into(),
+        }
+    }
+
+    #[test]
+    fn decodes_string_and_bytes32_texts() {
+        let string = returned(abi::encode(&[Token::String("Dai Stablecoin".to_string())]));
+        assert_eq!(
+            decode_text(Some(&string)).as_deref(),
+            Some("Dai Stablecoin")
+        );
+
+        let mut bytes32 = b"MKR".to_vec();
+        bytes32.resize(32, 0);
+        assert_eq!(
+            decode_text(Some(&returned(bytes32))).as_deref(),
+            Some("MKR")
+        );
+
+        assert_eq!(decode_text(Some(&returned(vec![]))), None);
+        let reverted = CallResult {
+            success: false,
+            ..string
+        };
+        assert_eq!(decode_text(Some(&reverted)), None);
+        assert_eq!(decode_text(None), None);
+    }
+
+    #[test]
+    fn decodes_numbers() {
+        let decimals = returned(abi::encode(&[Token::Uint(18.into())]));
+        assert_eq!(
+            decode_call::<u8>(Some(&decimals), ParamType::Uint(8)),
+            Some(18)
+        );
+        assert_eq!(
+            decode_call::<U256>(Some(&decimals), ParamType::Uint(256)),
+            Some(U256::from(18))
+        );
+        assert_eq!(
+            decode_call::<u8>(Some(&returned(vec![1])), ParamType::Uint(8)),
+            None
+        );
+    }
 }
@@ -0,0 +1,558 @@
+use crate::chains::ChainConfig;
+use crate::database::{Database, GLOBAL_DATABASE};
+use crate::global::Global;
+use dotenv::dotenv;
+use ethers::{
+    abi::{self, Token},
+    prelude::*,
+    types::{Address, Bytes, Signature, H256, U256},
+    utils::keccak256,
+};
+use lazy_static::lazy_static;
+use rusqlite::params;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Wallet sending the EIP-2612 permits of the users' wallets, see [sponsored_permit]
+pub(crate) static GLOBAL_PERMIT_SPONSOR: Global<Option<LocalWallet>> =
+    Global::new("permit sponsor key");
+
+lazy_static! {
+    /// Used to bound what the sponsor wallet pays for, see [SponsoredPermitBook]
+    pub(crate) static ref GLOBAL_SPONSORED_PERMITS: SponsoredPermitBook =
+        SponsoredPermitBook::new(&GLOBAL_DATABASE);
+}
+
+abigen!(
+    Erc20Permit,
+    r#"[
+        function name() external view returns (string)
+        function version() external view returns (string)
+        function DOMAIN_SEPARATOR() external view returns (bytes32)
+        function nonces(address owner) external view returns (uint256)
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s) external
+    ]"#
+);
+
+abigen!(
+    Permit2,
+    r#"[
+        function allowance(address user, address token, address spender) external view returns (uint160 amount, uint48 expiration, uint48 nonce)
+    ]"#
+);
+
+/// Seconds an EIP-2612 permit stays valid, it is sent right after being signed
+const PERMIT_VALIDITY_SECS: u64 = 10 * 60;
+
+/// Permits the sponsor sends for the wallets of one user in [SPONSORED_PERMITS_WINDOW_SECS]
+const SPONSORED_PERMITS_PER_USER: u64 = 3;
+
+const SPONSORED_PERMITS_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// EIP-712 type of the EIP-2612 permit
+const EIP2612_PERMIT_TYPE: &str =
+    "Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)";
+
+/// EIP-712 type of the Permit2 allowance details
+const PERMIT_DETAILS_TYPE: &str =
+    "PermitDetails(address token,uint160 amount,uint48 expiration,uint48 nonce)";
+
+/// EIP-712 type of the Permit2 single allowance, without the [PERMIT_DETAILS_TYPE] it references
+const PERMIT_SINGLE_TYPE: &str =
+    "PermitSingle(PermitDetails details,address spender,uint256 sigDeadline)";
+
+/// Hashes an EIP-712 struct into the digest the wallet signs
+fn eip712_digest(domain_separator: [u8; 32], struct_hash: [u8; 32]) -> H256 {
+    H256(keccak256(
+        [&[0x19, 0x01][..], &domain_separator, &struct_hash].concat(),
+    ))
+}
+
+fn signature_bytes(signature: &Signature) -> ([u8; 32], [u8; 32]) {
+    let (mut r, mut s) = ([0u8; 32], [0u8; 32]);
+    signature.r.to_big_endian(&mut r);
+    signature.s.to_big_endian(&mut s);
+    (r, s)
+}
+
+/// Reads the sponsor's private key from `PERMIT_SPONSOR_KEY`, permits are off when it isn't set
+pub(crate) fn permit_sponsor_from_env() -> anyhow::Result<Option<LocalWallet>> {
+    dotenv().ok();
+    let sponsor = std::env::var("PERMIT_SPONSOR_KEY")
+        .ok()
+        .map(|key| key.parse::<LocalWallet>())
+        .transpose()?;
+    if sponsor.is_none() {
+        log::info!("Permits are disabled, PERMIT_SPONSOR_KEY is not set");
+    }
+    Ok(sponsor)
+}
+
+/// The usual EIP-2612 domain separator, built from the token's name and version
+fn eip2612_domain_separator(name: &str, version: &str, chain_id: u64, token: Address) -> [u8; 32] {
+    keccak256(abi::encode(&[
+        Token::FixedBytes(
+            keccak256(
+                "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)",
+            )
+            .to_vec(),
+        ),
+        Token::FixedBytes(keccak256(name).to_vec()),
+        Token::FixedBytes(keccak256(version).to_vec()),
+        Token::Uint(chain_id.into()),
+        Token::Address(token),
+    ]))
+}
+
+/// Hash of the EIP-2612 `Permit` struct
+fn eip2612_struct_hash(
+    owner: Address,
+    spender: Address,
+    value: U256,
+    nonce: U256,
+    deadline: u64,
+) -> [u8; 32] {
+    keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(EIP2612_PERMIT_TYPE).to_vec()),
+        Token::Address(owner),
+        Token::Address(spender),
+        Token::Uint(value),
+        Token::Uint(nonce),
+        Token::Uint(deadline.into()),
+    ]))
+}
+
+/// Domain separator of the token's EIP-2612 permits, `None` when it has no permit or its domain
+/// isn't the usual one built from its name and version
+async fn eip2612_domain<M: Middleware>(token: &Erc20Permit<M>, chain_id: u64) -> Option<[u8; 32]> {
+    let domain_separator = token.domain_separator().call().await.ok()?;
+    let name = token.name().call().await.ok()?;
+    let version = token
+        .version()
+        .call()
+        .await
+        .unwrap_or_else(|_| "1".to_string());
+    let expected = eip2612_domain_separator(&name, &version, chain_id, token.address());
+    (expected == domain_separator).then_some(domain_separator)
+}
+
+/// Remembers the permits the sponsor wallet sent. Each token and spender pair of a wallet is
+/// permitted once, for the max amount, and the wallets of a user get at most
+/// [SPONSORED_PERMITS_PER_USER] permits a day
+pub(crate) struct SponsoredPermitBook {
+    database: &'static Database,
+}
+
+impl SponsoredPermitBook {
+    pub(crate) fn new(database: &'static Database) -> Self {
+        Self { database }
+    }
+
+    /// Records the permit of the pair for `owner` unless the pair was sponsored already or the
+    /// user of `owner` ran out of sponsored permits, returns whether it was recorded
+    pub(crate) fn claim(
+        &self,
+        chain_id: u64,
+        owner: Address,
+        token: Address,
+        spender: Address,
+        now: u64,
+    ) -> anyhow::Result<bool> {
+        let owner = format!("{:?}", owner);
+        let mut connection = self.database.connection();
+        let tx = connection.transaction()?;
+        let sponsored: u64 = tx.query_row(
+            "SELECT COUNT(*) FROM sponsored_permits \
+             WHERE created_at > ?2 AND (owner = ?1 OR owner IN ( \
+                 SELECT address FROM wallets WHERE user_id IN ( \
+                     SELECT user_id FROM wallets WHERE address = ?1)))",
+            params![owner, now.saturating_sub(SPONSORED_PERMITS_WINDOW_SECS)],
+            |row| row.get(0),
+        )?;
+        if sponsored >= SPONSORED_PERMITS_PER_USER {
+            return Ok(false);
+        }
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO sponsored_permits (chain_id, owner, token, spender, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                chain_id,
+                owner,
+                format!("{:?}", token),
+                format!("{:?}", spender),
+                now
+            ],
+        )?;
+        tx.commit()?;
+        Ok(inserted == 1)
+    }
+}
+
+/// Gives `spender` an unlimited allowance with an EIP-2612 permit signed by the wallet, sent and paid
+/// for by the sponsor wallet of `PERMIT_SPONSOR_KEY`. It covers the one on-chain approval a swap
+/// still needs: of Permit2 on chains with a Universal Router, which takes no EIP-2612 signature,
+/// and of the V2 router elsewhere, whose swaps pull with `transferFrom` and take no signature at all.
+/// The wallet saves the approval tx and its gas, the sponsor pays them instead.
+/// Returns false when there is no sponsor, the token doesn't support permits or the
+/// [SponsoredPermitBook] refuses the permit
+pub(crate) async fn sponsored_permit(
+    chain: &ChainConfig,
+    wallet: &LocalWallet,
+    token: Address,
+    spender: Address,
+) -> anyhow::Result<bool> {
+    let Some(sponsor) = (*GLOBAL_PERMIT_SPONSOR).clone() else {
+        return Ok(false);
+    };
+    let provider = chain.provider().await?;
+    let client = Arc::new(SignerMiddleware::new(
+        provider,
+        sponsor.with_chain_id(chain.chain_id),
+    ));
+    let erc20 = Erc20Permit::new(token, client);
+    let Some(domain_separator) = eip2612_domain(&erc20, chain.chain_id).await else {
+        return Ok(false);
+    };
+    let owner = wallet.address();
+    let nonce = erc20.nonces(owner).call().await?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if !GLOBAL_SPONSORED_PERMITS.claim(chain.chain_id, owner, token, spender, now)? {
+        return Ok(false);
+    }
+    let deadline = now + PERMIT_VALIDITY_SECS;
+    let value = U256::MAX;
+
+    let struct_hash = eip2612_struct_hash(owner, spender, value, nonce, deadline);
+    let signature = wallet.sign_hash(eip712_digest(domain_separator, struct_hash))?;
+    let (r, s) = signature_bytes(&signature);
+
+    log::info!("permitting {} of {:?} for {:?}", value, token, spender);
+    let call = erc20.permit(
+        owner,
+        spender,
+        value,
+        deadline.into(),
+        signature.v as u8,
+        r,
+        s,
+    );
+    let pending = call.send().await?;
+    match pending.await? {
+        Some(receipt) if receipt.status == Some(1u64.into()) => Ok(true),
+        _ => Err(anyhow::anyhow!("Permit of {:?} failed", token)),
+    }
+}
+
+/// Permit2 allowance for `spender` to pull `amount` of `token` until `expiration`, signed into the
+/// swap that uses it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PermitSingle {
+    pub(crate) token: Address,
+    pub(crate) amount: U256,
+    pub(crate) expiration: u64,
+    pub(crate) nonce: u64,
+    pub(crate) spender: Address,
+    pub(crate) sig_deadline: U256,
+}
+
+impl PermitSingle {
+    /// Reads the wallet's Permit2 nonce for the pair, `None` when its current allowance covers `amount`
+    pub(crate) async fn for_swap<M: Middleware + 'static>(
+        client: Arc<M>,
+        permit2: Address,
+        owner: Address,
+        token: Address,
+        spender: Address,
+        amount: U256,
+        deadline: u64,
+    ) -> anyhow::Result<Option<Self>> {
+        let (allowance, expiration, nonce) = Permit2::new(permit2, client)
+            .allowance(owner, token, spender)
+            .call()
+            .await?;
+        if allowance >= amount && expiration >= deadline {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            token,
+            amount,
+            expiration: deadline,
+            nonce,
+            spender,
+            sig_deadline: deadline.into(),
+        }))
+    }
+
+    fn details(&self) -> Vec<Token> {
+        vec![
+            Token::Address(self.token),
+            Token::Uint(self.amount),
+            Token::Uint(self.expiration.into()),
+            Token::Uint(self.nonce.into()),
+        ]
+    }
+
+    /// Domain separator of the Permit2 contract of the chain
+    fn domain_separator(chain_id: u64, permit2: Address) -> [u8; 32] {
+        keccak256(abi::encode(&[
+            Token::FixedBytes(
+                keccak256("EIP712Domain(string name,uint256 chainId,address verifyingContract)")
+                    .to_vec(),
+            ),
+            Token::FixedBytes(keccak256("Permit2").to_vec()),
+            Token::Uint(chain_id.into()),
+            Token::Address(permit2),
+        ]))
+    }
+
+    fn struct_hash(&self) -> [u8; 32] {
+        let mut details = vec![Token::FixedBytes(keccak256(PERMIT_DETAILS_TYPE).to_vec())];
+        details.extend(self.details());
+        keccak256(abi::encode(&[
+            Token::FixedBytes(
+                keccak256(format!("{}{}", PERMIT_SINGLE_TYPE, PERMIT_DETAILS_TYPE)).to_vec(),
+            ),
+            Token::FixedBytes(keccak256(abi::encode(&details)).to_vec()),
+            Token::Address(self.spender),
+            Token::Uint(self.sig_deadline),
+        ]))
+    }
+
+    /// Signs the permit for the Permit2 contract of the chain
+    pub(crate) fn sign(
+        &self,
+        wallet: &LocalWallet,
+        chain_id: u64,
+        permit2: Address,
+    ) -> anyhow::Result<Signature> {
+        let digest = eip712_digest(
+            Self::domain_separator(chain_id, permit2),
+            self.struct_hash(),
+        );
+        Ok(wallet.sign_hash(digest)?)
+    }
+
+    /// Encodes the permit and its signature as the input of the Universal Router's `PERMIT2_PERMIT`
+    pub(crate) fn encode(&self, signature: &Signature) -> Bytes {
+        abi::encode(&[
+            Token::Tuple(vec![
+                Token::Tuple(self.details()),
+                Token::Address(self.spender),
+                Token::Uint(self.sig_deadline),
+            ]),
+            Token::Bytes(signature.to_vec()),
+        ])
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::core::rand::thread_rng;
+    use ethers::types::transaction::eip712::{Eip712, TypedData};
+    use serde_json::json;
+
+    const OWNER: &str = "0x1111111111111111111111111111111111111111";
+    const SPENDER: &str = "0x2222222222222222222222222222222222222222";
+    const TOKEN: &str = "0x3333333333333333333333333333333333333333";
+    /// Permit2, at the same address on every chain
+    const PERMIT2: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+
+    fn hash(hex: &str) -> [u8; 32] {
+        hex.parse::<H256>().unwrap().0
+    }
+
+    #[test]
+    fn digests_the_eip712_example() {
+        // the Mail example of the EIP-712 specification
+        let digest = eip712_digest(
+            hash("0xf2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"),
+            hash("0xc52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"),
+        );
+        assert_eq!(
+            digest,
+            "0xbe609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn type_hashes_match_the_contracts() {
+        // PERMIT_TYPEHASH of EIP-2612, _PERMIT_DETAILS_TYPEHASH and _PERMIT_SINGLE_TYPEHASH of Permit2
+        assert_eq!(
+            keccak256(EIP2612_PERMIT_TYPE),
+            hash("0x6e71edae12b1b97f4d1f60370fef10105fa2faae0126114a169c64845d6126c9")
+        );
+        assert_eq!(
+            keccak256(PERMIT_DETAILS_TYPE),
+            hash("0x65626cad6cb96493bf6f5ebea28756c966f023ab9e8a83a7101849d5573b3678")
+        );
+        assert_eq!(
+            keccak256(format!("{}{}", PERMIT_SINGLE_TYPE, PERMIT_DETAILS_TYPE)),
+            hash("0xf3841cd1ff0085026a6327b620b67997ce40f282c88a8e905a7a5626e310f3d0")
+        );
+    }
+
+    #[test]
+    fn eip2612_permit_hashes_like_eth_sign_typed_data() {
+        let typed_data: TypedData = serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Permit": [
+                    {"name": "owner", "type": "address"},
+                    {"name": "spender", "type": "address"},
+                    {"name": "value", "type": "uint256"},
+                    {"name": "nonce", "type": "uint256"},
+                    {"name": "deadline", "type": "uint256"}
+                ]
+            },
+            "primaryType": "Permit",
+            "domain": {"name": "USD Coin", "version": "2", "chainId": 1, "verifyingContract": TOKEN},
+            "message": {
+                "owner": OWNER,
+                "spender": SPENDER,
+                "value": "1000000",
+                "nonce": "7",
+                "deadline": "1700000000"
+            }
+        }))
+        .unwrap();
+
+        let domain_separator = eip2612_domain_separator("USD Coin", "2", 1, TOKEN.parse().unwrap());
+        let struct_hash = eip2612_struct_hash(
+            OWNER.parse().unwrap(),
+            SPENDER.parse().unwrap(),
+            1_000_000.into(),
+            7.into(),
+            1_700_000_000,
+        );
+        assert_eq!(domain_separator, typed_data.domain_separator().unwrap());
+        assert_eq!(struct_hash, typed_data.struct_hash().unwrap());
+        assert_eq!(
+            eip712_digest(domain_separator, struct_hash).0,
+            typed_data.encode_eip712().unwrap()
+        );
+    }
+
+    #[test]
+    fn permit_single_hashes_like_eth_sign_typed_data() {
+        let typed_data: TypedData = serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "PermitSingle": [
+                    {"name": "details", "type": "PermitDetails"},
+                    {"name": "spender", "type": "address"},
+                    {"name": "sigDeadline", "type": "uint256"}
+                ],
+                "PermitDetails": [
+                    {"name": "token", "type": "address"},
+                    {"name": "amount", "type": "uint160"},
+                    {"name": "expiration", "type": "uint48"},
+                    {"name": "nonce", "type": "uint48"}
+                ]
+            },
+            "primaryType": "PermitSingle",
+            "domain": {"name": "Permit2", "chainId": 8453, "verifyingContract": PERMIT2},
+            "message": {
+                "details": {
+                    "token": TOKEN,
+                    "amount": "5000000000000000000",
+                    "expiration": "1700000600",
+                    "nonce": "3"
+                },
+                "spender": SPENDER,
+                "sigDeadline": "1700000600"
+            }
+        }))
+        .unwrap();
+
+        let permit = PermitSingle {
+            token: TOKEN.parse().unwrap(),
+            amount: U256::exp10(18) * 5,
+            expiration: 1_700_000_600,
+            nonce: 3,
+            spender: SPENDER.parse().unwrap(),
+            sig_deadline: 1_700_000_600u64.into(),
+        };
+        let domain_separator = PermitSingle::domain_separator(8453, PERMIT2.parse().unwrap());
+        assert_eq!(domain_separator, typed_data.domain_separator().unwrap());
+        assert_eq!(permit.struct_hash(), typed_data.struct_hash().unwrap());
+
+        // the signature recovers to the wallet over the digest eth_signTypedData would sign
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let signature = permit
+            .sign(&wallet, 8453, PERMIT2.parse().unwrap())
+            .unwrap();
+        let digest = H256(typed_data.encode_eip712().unwrap());
+        assert_eq!(signature.recover(digest).unwrap(), wallet.address());
+    }
+
+    fn sponsored_permits(wallets: &[(u64, u8, Address)]) -> SponsoredPermitBook {
+        let database = Database::new(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        for (user_id, slot, address) in wallets {
+            database
+                .connection()
+                .execute(
+                    "INSERT INTO wallets (user_id, slot, address, encrypted_key) VALUES (?1, ?2, ?3, x'00')",
+                    params![user_id, slot, format!("{:?}", address)],
+                )
+                .unwrap();
+        }
+        SponsoredPermitBook::new(Box::leak(Box::new(database)))
+    }
+
+    #[test]
+    fn sponsors_each_pair_once() {
+        let owner = Address::from_low_u64_be(1);
+        let book = sponsored_permits(&[(1, 0, owner)]);
+        let (token, spender) = (TOKEN.parse().unwrap(), SPENDER.parse().unwrap());
+
+        assert!(book.claim(1, owner, token, spender, 1000).unwrap());
+        assert!(!book.claim(1, owner, token, spender, 1000).unwrap());
+        assert!(!book
+            .claim(
+                1,
+                owner,
+                token,
+                spender,
+                1000 + SPONSORED_PERMITS_WINDOW_SECS
+            )
+            .unwrap());
+        assert!(book.claim(10, owner, token, spender, 1000).unwrap());
+    }
+
+    #[test]
+    fn caps_the_sponsored_permits_of_each_user_a_day() {
+        let (first, second, other) = (
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            Address::from_low_u64_be(3),
+        );
+        let book = sponsored_permits(&[(1, 0, first), (1, 1, second), (2, 0, other)]);
+        let spender: Address = SPENDER.parse().unwrap();
+
+        for token in 0..SPONSORED_PERMITS_PER_USER {
+            let owner = if token % 2 == 0 { first } else { second };
+            let token = Address::from_low_u64_be(100 + token);
+            assert!(book.claim(1, owner, token, spender, 1000).unwrap());
+        }
+        let token = Address::from_low_u64_be(200);
+        assert!(!book.claim(1, first, token, spender, 1000).unwrap());
+        assert!(!book.claim(1, second, token, spender, 1000).unwrap());
+        assert!(book.claim(1, other, token, spender, 1000).unwrap());
+
+        let next_day = 1000 + SPONSORED_PERMITS_WINDOW_SECS;
+        assert!(book.claim(1, first, token, spender, next_day).unwrap());
+    }
+}
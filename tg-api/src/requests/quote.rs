@@ -0,0 +1,219 @@
+use crate::chains::ChainConfig;
+use crate::requests::on_chain::format_amount;
+use crate::requests::swap::{erc20_token, native_token, to_base_units, UniswapV2Router};
+use ethers::{
+    prelude::*,
+    types::{Address, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+abigen!(
+    UniswapV2Factory,
+    r#"[
+        function getPair(address tokenA, address tokenB) external view returns (address pair)
+    ]"#
+);
+
+abigen!(
+    UniswapV2Pair,
+    r#"[
+        function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
+    ]"#
+);
+
+/// Expected outcome of a buy, quoted with the router's `getAmountsOut`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BuyQuote {
+    /// Token paid with, `None` for the native coin
+    pub(crate) buy_token: Option<Address>,
+    pub(crate) receive_token: Address,
+    /// Amount of the buy token the quote is for
+    pub(crate) buy_amount: f64,
+    pub(crate) amount_out: U256,
+    pub(crate) symbol_out: String,
+    pub(crate) decimals_out: u8,
+    /// Percentage the output falls short of the pools' mid price, pool fees included
+    pub(crate) price_impact: f64,
+    /// Symbols of the tokens the swap goes through
+    pub(crate) route: Vec<String>,
+}
+
+impl BuyQuote {
+    /// Whether the quote was made for these tokens and amount
+    pub(crate) fn is_for(
+        &self,
+        buy_token: Option<Address>,
+        receive_token: Option<Address>,
+        buy_amount: Option<f64>,
+    ) -> bool {
+        self.buy_token == buy_token
+            && Some(self.receive_token) == receive_token
+            && Some(self.buy_amount) == buy_amount
+    }
+
+    /// Summarizes the quote on one line, the minimum received is computed with the given slippage
+    pub(crate) fn summary(&self, slippage_bps: u32) -> String {
+        format!(
+            "≈ {} {} · min {} · impact {:.2}% · {}",
+            format_amount(self.amount_out, self.decimals_out),
+            self.symbol_out,
+            format_amount(
+                minimum_out(self.amount_out, slippage_bps),
+                self.decimals_out
+            ),
+            self.price_impact,
+            self.route.join(" → ")
+        )
+    }
+}
+
+pub(crate) fn to_f64(amount: U256) -> f64 {
+    amount.to_string().parse().unwrap_or(f64::MAX)
+}
+
+/// Applies the slippage tolerance to the expected output
+pub(crate) fn minimum_out(amount_out: U256, slippage_bps: u32) -> U256 {
+    amount_out * U256::from(10_000u32.saturating_sub(slippage_bps)) / U256::from(10_000u32)
+}
+
+/// Quotes buying `receive` with `buy_amount` of `buy`, or of the native coin when `buy` is `None`,
+/// along the path [send_buy_tx](crate::requests::swap::send_buy_tx) swaps along
+pub(crate) async fn quote_buy(
+    chain: &ChainConfig,
+    buy: Option<Address>,
+    receive: Address,
+    buy_amount: f64,
+) -> anyhow::Result<BuyQuote> {
+    let client = Arc::new(chain.provider().await?);
+    let router = UniswapV2Router::new(chain.router()?, client.clone());
+    let (token_in, token_out) = futures::try_join!(
+        async {
+            match buy {
+                Some(buy) => erc20_token(chain, buy).await,
+                None => Ok(native_token(chain)),
+            }
+        },
+        erc20_token(chain, receive)
+    )?;
+    let path = match buy {
+        Some(buy) => vec![buy, receive],
+        None => vec![router.weth().call().await?, receive],
+    };
+
+    let amount_in = to_base_units(buy_amount, token_in.decimals)?;
+    let amounts = router
+        .get_amounts_out(amount_in, path.clone())
+        .call()
+        .await
+        .map_err(|err| anyhow::anyhow!("No {} pool found for this pair: {}", chain.name, err))?;
+    let amount_out = amounts.last().copied().unwrap_or_default();
+
+    // output at the pools' mid price, without the price moving or fees
+    let factory = UniswapV2Factory::new(router.factory().call().await?, client.clone());
+    let mut mid_out = to_f64(amount_in);
+    for hop in path.windows(2) {
+        let (token_a, token_b) = (hop[0], hop[1]);
+        let pair = UniswapV2Pair::new(
+            factory.get_pair(token_a, token_b).call().await?,
+            client.clone(),
+        );
+        let (reserve0, reserve1, _) = pair.get_reserves().call().await?;
+        // the pair sorts its tokens by address
+        let (reserve_in, reserve_out) = match token_a < token_b {
+            true => (reserve0, reserve1),
+            false => (reserve1, reserve0),
+        };
+        mid_out = mid_out * reserve_out as f64 / reserve_in as f64;
+    }
+    let price_impact = match mid_out > 0.0 {
+        true => ((1.0 - to_f64(amount_out) / mid_out) * 100.0).max(0.0),
+        false => 0.0,
+    };
+
+    Ok(BuyQuote {
+        buy_token: buy,
+        receive_token: receive,
+        buy_amount,
+        amount_out,
+        symbol_out: token_out.symbol.clone(),
+        decimals_out: token_out.decimals,
+        price_impact,
+        route: vec![token_in.symbol, token_out.symbol],
+    })
+}
+
+/// Price of the token in native coin, at the mid price of its pool with the wrapped native coin
+pub(crate) async fn token_price(chain: &ChainConfig, token: Address) -> anyhow::Result<f64> {
+    let client = Arc::new(chain.provider().await?);
+    let router = UniswapV2Router::new(chain.router()?, client.clone());
+    let weth = router.weth().call().await?;
+    let factory = UniswapV2Factory::new(router.factory().call().await?, client.clone());
+    let pair = factory.get_pair(token, weth).call().await?;
+    if pair.is_zero() {
+        return Err(anyhow::anyhow!(
+            "No {} pool found for this token",
+            chain.name
+        ));
+    }
+    let token_decimals = erc20_token(chain, token).await?.decimals;
+    let (reserve0, reserve1, _) = UniswapV2Pair::new(pair, client)
+        .get_reserves()
+        .call()
+        .await?;
+    // the pair sorts its tokens by address
+    let (reserve_token, reserve_weth) = match token < weth {
+        true => (reserve0, reserve1),
+        false => (reserve1, reserve0),
+    };
+    if reserve_token == 0 {
+        return Err(anyhow::anyhow!(
+            "The {} pool of this token is empty",
+            chain.name
+        ));
+    }
+    Ok(reserve_weth as f64 / reserve_token as f64 * 10f64.powi(token_decimals as i32 - 18))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote() -> BuyQuote {
+        BuyQuote {
+            buy_token: None,
+            receive_token: Address::from_low_u64_be(1),
+            buy_amount: 0.5,
+            amount_out: U256::exp10(18) * 1200,
+            symbol_out: "USDC".to_string(),
+            decimals_out: 18,
+            price_impact: 0.456,
+            route: vec!["WETH".to_string(), "USDC".to_string()],
+        }
+    }
+
+    #[test]
+    fn quote_is_only_for_its_tokens_and_amount() {
+        let quote = quote();
+        let receive = Some(Address::from_low_u64_be(1));
+        assert!(quote.is_for(None, receive, Some(0.5)));
+
+        assert!(!quote.is_for(Some(Address::from_low_u64_be(2)), receive, Some(0.5)));
+        assert!(!quote.is_for(None, Some(Address::from_low_u64_be(2)), Some(0.5)));
+        assert!(!quote.is_for(None, None, Some(0.5)));
+        assert!(!quote.is_for(None, receive, Some(0.25)));
+        assert!(!quote.is_for(None, receive, None));
+    }
+
+    #[test]
+    fn summary_shows_the_minimum_with_the_slippage() {
+        assert_eq!(
+            quote().summary(100),
+            "≈ 1200 USDC · min 1188 · impact 0.46% · WETH → USDC"
+        );
+        assert_eq!(
+            quote().summary(0),
+            "≈ 1200 USDC · min 1200 · impact 0.46% · WETH → USDC"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Bytes, TransactionReceipt, TxHash, U64},
+    utils::keccak256,
+};
+use futures::future::join_all;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use tokio::time::{sleep, Duration};
+
+/// How often inclusion of a private transaction is checked
+const INCLUSION_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// JSON-RPC method a relay is sent private transactions with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RelayMethod {
+    /// `eth_sendPrivateTransaction`, the relay keeps the transaction until `maxBlockNumber`
+    #[default]
+    PrivateTransaction,
+    /// `eth_sendBundle`, a one-transaction bundle is sent for each block
+    Bundle,
+}
+
+/// A private relay or builder endpoint of a chain
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RelayConfig {
+    pub(crate) url: String,
+    #[serde(default)]
+    pub(crate) method: RelayMethod,
+}
+
+/// Sends the signed transaction to one relay for the blocks after `current_block` up to `max_block`
+async fn send_to_relay(
+    relay: &RelayConfig,
+    raw_tx: &Bytes,
+    current_block: U64,
+    max_block: U64,
+) -> anyhow::Result<()> {
+    let provider = Provider::<Http>::try_from(relay.url.as_str())?;
+    match relay.method {
+        RelayMethod::PrivateTransaction => {
+            let params = json!({ "tx": raw_tx, "maxBlockNumber": max_block });
+            provider
+                .request::<_, Value>("eth_sendPrivateTransaction", [params])
+                .await?;
+        }
+        RelayMethod::Bundle => {
+            let bundles = (current_block.as_u64() + 1..=max_block.as_u64()).map(|block| {
+                let params = json!({ "txs": [raw_tx], "blockNumber": U64::from(block) });
+                let provider = &provider;
+                async move {
+                    provider
+                        .request::<_, Value>("eth_sendBundle", [params])
+                        .await
+                }
+            });
+            // the transaction only has to land in one of the blocks
+            let results = join_all(bundles).await;
+            if !results.iter().any(|result| result.is_ok()) {
+                if let Some(Err(err)) = results.into_iter().next() {
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends the signed transaction to every relay instead of the public mempool,
+/// it stays valid for the `blocks` blocks following `current_block`
+///
+/// Succeeds as soon as one relay accepts it, returns the transaction hash and the last block it may land in
+pub(crate) async fn send_private_transaction(
+    relays: &[RelayConfig],
+    raw_tx: Bytes,
+    current_block: U64,
+    blocks: u64,
+) -> anyhow::Result<(TxHash, U64)> {
+    if relays.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No private relay configured, turn off Private Tx to send publicly"
+        ));
+    }
+    let max_block = current_block + blocks;
+    let results = join_all(
+        relays
+            .iter()
+            .map(|relay| send_to_relay(relay, &raw_tx, current_block, max_block)),
+    )
+    .await;
+
+    let mut errors = Vec::new();
+    for (relay, result) in relays.iter().zip(results) {
+        match result {
+            Ok(()) => log::info!("private tx sent to {}", relay.url),
+            Err(err) => {
+                log::warn!("private relay {} failed: {}", relay.url, err);
+                errors.push(err.to_string());
+            }
+        }
+    }
+    if errors.len() == relays.len() {
+        return Err(anyhow::anyhow!(
+            "No private relay accepted the transaction: {}",
+            errors.join(", ")
+        ));
+    }
+    Ok((TxHash::from(keccak256(&raw_tx)), max_block))
+}
+
+/// Waits for the transaction to be mined, `None` once `max_block` has passed without it
+pub(crate) async fn wait_for_inclusion<M: Middleware>(
+    client: &M,
+    tx_hash: TxHash,
+    max_block: U64,
+) -> anyhow::Result<Option<TransactionReceipt>>
+where
+    M::Error: 'static,
+{
+    loop {
+        if let Some(receipt) = client.get_transaction_receipt(tx_hash).await? {
+            return Ok(Some(receipt));
+        }
+        if client.get_block_number().await? > max_block {
+            return Ok(None);
+        }
+        sleep(INCLUSION_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    type Respond = Arc<dyn Fn(&Value) -> Result<Value, Value> + Send + Sync>;
+
+    /// Local JSON-RPC server standing in for a relay, records the requests it gets
+    struct MockRelay {
+        url: String,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl MockRelay {
+        fn start(respond: impl Fn(&Value) -> Result<Value, Value> + Send + Sync + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let respond: Respond = Arc::new(respond);
+            let recorded = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let (respond, recorded) = (respond.clone(), recorded.clone());
+                    std::thread::spawn(move || serve(stream, respond, recorded));
+                }
+            });
+            Self { url, requests }
+        }
+
+        fn config(&self, method: RelayMethod) -> RelayConfig {
+            RelayConfig {
+                url: self.url.clone(),
+                method,
+            }
+        }
+
+        fn requests(&self) -> Vec<Value> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    /// Answers the HTTP requests of one connection until the client closes it
+    fn serve(stream: TcpStream, respond: Respond, recorded: Arc<Mutex<Vec<Value>>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let header = line.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+
+            let response = match respond(&request) {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+                Err(error) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": error }),
+            };
+            recorded.lock().unwrap().push(request);
+            let response = response.to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    }
+
+    fn accept(request: &Value) -> Result<Value, Value> {
+        Ok(json!(format!(
+            "{:?}",
+            TxHash::from(keccak256(raw_tx_bytes(request)))
+        )))
+    }
+
+    fn reject(_: &Value) -> Result<Value, Value> {
+        Err(json!({ "code": -32000, "message": "bundle rejected" }))
+    }
+
+    /// The raw transaction the relay was sent
+    fn raw_tx_bytes(request: &Value) -> Vec<u8> {
+        let params = &request["params"][0];
+        let raw_tx = params["tx"].as_str().or(params["txs"][0].as_str()).unwrap();
+        ethers::utils::hex::decode(raw_tx).unwrap()
+    }
+
+    fn raw_tx() -> Bytes {
+        Bytes::from(vec![0x02, 0xf8, 0x6b, 0x01])
+    }
+
+    #[tokio::test]
+    async fn private_transaction_is_sent_to_every_relay() {
+        let relays = [MockRelay::start(accept), MockRelay::start(accept)];
+        let configs: Vec<RelayConfig> = relays
+            .iter()
+            .map(|relay| relay.config(RelayMethod::PrivateTransaction))
+            .collect();
+
+        let (tx_hash, max_block) = send_private_transaction(&configs, raw_tx(), U64::from(100), 25)
+            .await
+            .unwrap();
+
+        assert_eq!(tx_hash, TxHash::from(keccak256(raw_tx())));
+        assert_eq!(max_block, U64::from(125));
+        for relay in &relays {
+            let requests = relay.requests();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0]["method"], "eth_sendPrivateTransaction");
+            assert_eq!(requests[0]["params"][0]["tx"], "0x02f86b01");
+            assert_eq!(requests[0]["params"][0]["maxBlockNumber"], "0x7d");
+        }
+    }
+
+    #[tokio::test]
+    async fn bundle_is_sent_for_each_tracked_block() {
+        let relay = MockRelay::start(accept);
+
+        send_private_transaction(
+            &[relay.config(RelayMethod::Bundle)],
+            raw_tx(),
+            U64::from(100),
+            3,
+        )
+        .await
+        .unwrap();
+
+        let mut blocks: Vec<String> = relay
+            .requests()
+            .iter()
+            .inspect(|request| {
+                assert_eq!(request["method"], "eth_sendBundle");
+                assert_eq!(request["params"][0]["txs"], json!(["0x02f86b01"]));
+            })
+            .map(|request| {
+                request["params"][0]["blockNumber"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        blocks.sort();
+        assert_eq!(blocks, ["0x65", "0x66", "0x67"]);
+    }
+
+    #[tokio::test]
+    async fn one_accepting_relay_is_enough() {
+        let (rejecting, accepting) = (MockRelay::start(reject), MockRelay::start(accept));
+        let configs = [
+            rejecting.config(RelayMethod::PrivateTransaction),
+            accepting.config(RelayMethod::Bundle),
+        ];
+
+        let sent = send_private_transaction(&configs, raw_tx(), U64::from(100), 2).await;
+
+        assert!(sent.is_ok());
+        assert_eq!(rejecting.requests().len(), 1);
+        assert_eq!(accepting.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_relay_accepts() {
+        let relay = MockRelay::start(reject);
+
+        let err = send_private_transaction(
+            &[relay.config(RelayMethod::Bundle)],
+            raw_tx(),
+            U64::from(100),
+            2,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err.to_string().contains("bundle rejected"));
+    }
+
+    #[tokio::test]
+    async fn fails_without_relays() {
+        let sent = send_private_transaction(&[], raw_tx(), U64::from(100), 25).await;
+
+        assert!(sent.is_err());
+    }
+
+    #[tokio::test]
+    async fn inclusion_is_found_in_the_tracked_blocks() {
+        let tx_hash = TxHash::from(keccak256(raw_tx()));
+        let node = MockRelay::start(move |request| match request["method"].as_str() {
+            Some("eth_getTransactionReceipt") => Ok(json!({
+                "transactionHash": tx_hash,
+                "transactionIndex": "0x0",
+                "blockHash": TxHash::zero(),
+                "blockNumber": "0x66",
+                "from": ethers::types::Address::zero(),
+                "to": ethers::types::Address::zero(),
+                "cumulativeGasUsed": "0x5208",
+                "gasUsed": "0x5208",
+                "contractAddress": null,
+                "logs": [],
+                "logsBloom": format!("0x{}", "00".repeat(256)),
+                "status": "0x1",
+            })),
+            _ => Ok(json!("0x66")),
+        });
+        let provider = Provider::<Http>::try_from(node.url.as_str()).unwrap();
+
+        let receipt = wait_for_inclusion(&provider, tx_hash, U64::from(125))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(receipt.block_number, Some(U64::from(0x66)));
+    }
+
+    #[tokio::test]
+    async fn tracking_stops_after_the_last_block() {
+        let node = MockRelay::start(|request| match request["method"].as_str() {
+            Some("eth_getTransactionReceipt") => Ok(Value::Null),
+            _ => Ok(json!("0x7e")),
+        });
+        let provider = Provider::<Http>::try_from(node.url.as_str()).unwrap();
+
+        let receipt = wait_for_inclusion(&provider, TxHash::zero(), U64::from(125))
+            .await
+            .unwrap();
+
+        assert!(receipt.is_none());
+    }
+}
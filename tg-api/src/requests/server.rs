@@ -1,92 +1,88 @@
-use teloxide::types::InlineKeyboardMarkup;
+use crate::drafts::{BuyOrderDraft, SellAmount, SellOrderDraft};
+use crate::requests::swap::ApprovalMode;
+use crate::settings::GasPreset;
+use ethers::types::Address;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub(crate) struct SendBuyTxRequest {
-    pub(crate) wallet: String,
+    pub(crate) wallet_slot: u8,
     pub(crate) private_tx: bool,
     pub(crate) rebate: bool,
-    pub(crate) buy: String,
-    pub(crate) receive: String,
+    /// Token paid with, `None` pays with the native coin
+    pub(crate) buy: Option<Address>,
+    pub(crate) receive: Address,
     pub(crate) buy_amount: f64,
+    pub(crate) slippage_bps: u32,
+    pub(crate) deadline_secs: u64,
+    pub(crate) gas_preset: GasPreset,
+    /// The user's max fee cap, in gwei
+    pub(crate) max_fee_gwei: Option<u64>,
+    /// How to approve the router when its allowance is too low, `None` asks the user first
+    pub(crate) approval: Option<ApprovalMode>,
 }
 
 #[allow(dead_code)]
-impl SendBuyTxRequest {
-    ///  function called in handle_send_tx() to extract the [InlineKeyboardButton](cteloxide::types::InlineKeyboardButton) texts
-    /// Note: any change to the buy button layout from the [keyboard.rs](crate::keyboards) will affect this function
-    pub(crate) fn new(keyboard: &InlineKeyboardMarkup) -> anyhow::Result<Self> {
-        // find the wallet that has emoji
-        let selected_wallet = match keyboard.inline_keyboard.get(3).and_then(|row| {
-            row.iter()
-                .find(|&button| {
-                    !(button.text == "Wallet 1"
-                        || button.text == "Wallet 2"
-                        || button.text == "Wallet 3")
-                })
-                .map(|button| button.text.clone())
-        }) {
-            Some(wallet) => wallet,
-            None => return Err(anyhow::anyhow!("No wallet found")),
-        };
-
-        // find it user want private transaction or not
-        // if has emoji, then user wants private tx, otherwise no
-        let private_tx = match keyboard.inline_keyboard[1][0].text.as_str() {
-            "Private Tx" => false,
-            _ => true,
-        };
-
-        // find if user wants rebate or not
-        // if has emoji, then user wants rebate, otherwise no
-        let rebate = match keyboard.inline_keyboard[1][1].text.as_str() {
-            "Rebate" => false,
-            _ => true,
-        };
+#[derive(Debug, Clone)]
+pub(crate) struct SendSellTxRequest {
+    pub(crate) wallet_slot: u8,
+    pub(crate) private_tx: bool,
+    pub(crate) rebate: bool,
+    pub(crate) sell: Address,
+    pub(crate) amount: SellAmount,
+    pub(crate) slippage_bps: u32,
+    pub(crate) deadline_secs: u64,
+    pub(crate) gas_preset: GasPreset,
+    /// The user's max fee cap, in gwei
+    pub(crate) max_fee_gwei: Option<u64>,
+    /// How to approve the router when its allowance is too low, `None` asks the user first
+    pub(crate) approval: Option<ApprovalMode>,
+}
 
-        let buy_token_address = match keyboard.inline_keyboard[4][0]
-            .text
-            .as_str()
-            .split(": ")
-            .collect::<Vec<&str>>()
-            .get(1)
-            .cloned()
-        {
-            Some(token_address) => token_address,
-            None => return Err(anyhow::anyhow!("No token address found")),
-        };
+impl SendBuyTxRequest {
+    /// Checks that the buy menu draft is complete enough to be sent
+    pub(crate) fn new(draft: &BuyOrderDraft, max_fee_gwei: Option<u64>) -> anyhow::Result<Self> {
+        let receive = draft
+            .receive_token
+            .ok_or_else(|| anyhow::anyhow!("No receive token address found"))?;
+        let buy_amount = draft
+            .buy_amount
+            .ok_or_else(|| anyhow::anyhow!("No amount found"))?;
 
-        let receive_token_addesss = match keyboard.inline_keyboard[4][1]
-            .text
-            .as_str()
-            .split(": ")
-            .collect::<Vec<&str>>()
-            .get(1)
-            .cloned()
-        {
-            Some(token_address) => token_address,
-            None => return Err(anyhow::anyhow!("No token address found")),
-        };
+        Ok(Self {
+            wallet_slot: draft.wallet_slot,
+            private_tx: draft.private_tx,
+            rebate: draft.rebate,
+            buy: draft.buy_token,
+            receive,
+            buy_amount,
+            slippage_bps: draft.slippage_bps,
+            deadline_secs: draft.deadline_secs,
+            gas_preset: draft.gas_preset,
+            max_fee_gwei,
+            approval: None,
+        })
+    }
+}
 
-        let buy_amount: f64 = match keyboard.inline_keyboard[5][0]
-            .text
-            .as_str()
-            .split(": ")
-            .collect::<Vec<&str>>()
-            .get(1)
-            .cloned()
-        {
-            Some(amount) => amount.parse().expect("Unable to parse amount"),
-            None => return Err(anyhow::anyhow!("No amount found")),
-        };
+impl SendSellTxRequest {
+    /// Checks that the sell menu draft is complete enough to be sent
+    pub(crate) fn new(draft: &SellOrderDraft, max_fee_gwei: Option<u64>) -> anyhow::Result<Self> {
+        let sell = draft
+            .sell_token
+            .ok_or_else(|| anyhow::anyhow!("No sell token address found"))?;
 
         Ok(Self {
-            wallet: selected_wallet,
-            private_tx,
-            rebate,
-            buy: buy_token_address.to_string(),
-            receive: receive_token_addesss.to_string(),
-            buy_amount,
+            wallet_slot: draft.wallet_slot,
+            private_tx: draft.private_tx,
+            rebate: draft.rebate,
+            sell,
+            amount: draft.amount,
+            slippage_bps: draft.slippage_bps,
+            deadline_secs: draft.deadline_secs,
+            gas_preset: draft.gas_preset,
+            max_fee_gwei,
+            approval: None,
         })
     }
 }
@@ -0,0 +1,446 @@
+use crate::chains::ChainConfig;
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::{Middleware, MiddlewareError},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, CallConfig,
+        CallFrame, GethDebugBuiltInTracerConfig, GethDebugBuiltInTracerType, GethDebugTracerConfig,
+        GethDebugTracerType, GethDebugTracingCallOptions, GethDebugTracingOptions, GethTrace,
+        GethTraceFrame, NameOrAddress, TransactionReceipt, TransactionRequest, U256,
+    },
+    utils::{hex, id},
+};
+use std::fmt;
+
+/// Readable reason a transaction reverts, decoded from its revert data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertReason {
+    InsufficientAllowance,
+    InsufficientBalance,
+    /// The router's slippage check, the price moved beyond the tolerance
+    InsufficientOutputAmount,
+    InsufficientLiquidity,
+    TransferFailed,
+    Expired,
+    /// `Error(string)` that isn't one of the above
+    Message(String),
+    /// `Panic(uint256)` raised by the compiler, e.g. on an overflow
+    Panic(U256),
+    /// Custom error the bot doesn't know, shown by its selector
+    Custom([u8; 4]),
+    /// Reverted without data
+    Unknown,
+}
+
+impl RevertReason {
+    /// Decodes `Error(string)`, `Panic(uint256)` and the ERC-20 custom errors of OpenZeppelin 5
+    pub(crate) fn decode(data: &[u8]) -> Self {
+        if data.len() < 4 {
+            return Self::Unknown;
+        }
+        let (selector, args) = data.split_at(4);
+        let selector: [u8; 4] = selector.try_into().unwrap_or_default();
+        if selector == id("Error(string)") {
+            return match abi::decode(&[ParamType::String], args).as_deref() {
+                Ok([Token::String(message)]) => Self::from_message(message),
+                _ => Self::Unknown,
+            };
+        }
+        if selector == id("Panic(uint256)") {
+            return match abi::decode(&[ParamType::Uint(256)], args).as_deref() {
+                Ok([Token::Uint(code)]) => Self::Panic(*code),
+                _ => Self::Unknown,
+            };
+        }
+        if selector == id("ERC20InsufficientAllowance(address,uint256,uint256)") {
+            return Self::InsufficientAllowance;
+        }
+        if selector == id("ERC20InsufficientBalance(address,uint256,uint256)") {
+            return Self::InsufficientBalance;
+        }
+        Self::Custom(selector)
+    }
+
+    /// Recognizes the revert strings of Uniswap V2 routers, their pairs and common ERC-20s
+    pub(crate) fn from_message(message: &str) -> Self {
+        let upper = message.to_uppercase();
+        if upper.contains("INSUFFICIENT_OUTPUT_AMOUNT") {
+            Self::InsufficientOutputAmount
+        } else if upper.contains("INSUFFICIENT_LIQUIDITY") {
+            Self::InsufficientLiquidity
+        } else if upper.contains("ALLOWANCE") {
+            Self::InsufficientAllowance
+        } else if upper.contains("EXCEEDS BALANCE") || upper.contains("INSUFFICIENT BALANCE") {
+            Self::InsufficientBalance
+        } else if upper.contains("TRANSFER_FAILED")
+            || upper.contains("TRANSFER_FROM_FAILED")
+            || upper.contains("TRANSFER FAILED")
+        {
+            Self::TransferFailed
+        } else if upper.contains("EXPIRED") {
+            Self::Expired
+        } else {
+            Self::Message(message.to_string())
+        }
+    }
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientAllowance => write!(f, "insufficient allowance for the router"),
+            Self::InsufficientBalance => write!(f, "insufficient token balance"),
+            Self::InsufficientOutputAmount => write!(
+                f,
+                "INSUFFICIENT_OUTPUT_AMOUNT, the price moved beyond the slippage tolerance"
+            ),
+            Self::InsufficientLiquidity => write!(f, "not enough liquidity in the pool"),
+            Self::TransferFailed => write!(
+                f,
+                "token transfer failed, the token may block or tax transfers"
+            ),
+            Self::Expired => write!(f, "the deadline has passed"),
+            Self::Message(message) => write!(f, "{}", message),
+            Self::Panic(code) => write!(f, "panic code {:#x}", code),
+            Self::Custom(selector) => write!(f, "custom error 0x{}", hex::encode(selector)),
+            Self::Unknown => write!(f, "reverted without a reason"),
+        }
+    }
+}
+
+/// A transaction that would revert, it is reported instead of being broadcast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationError {
+    pub(crate) reason: RevertReason,
+    /// Contract that reverted, known when the simulation was traced
+    pub(crate) contract: Option<Address>,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Simulation failed: {}", self.reason)?;
+        if let Some(contract) = self.contract {
+            write!(f, " (in {:?})", contract)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// Deepest call of the trace that reverted, the one whose revert the outer calls bubbled up
+fn reverted_frame(frame: &CallFrame) -> Option<&CallFrame> {
+    frame.error.as_ref()?;
+    let inner = frame
+        .calls
+        .iter()
+        .flatten()
+        .filter_map(reverted_frame)
+        .last();
+    Some(inner.unwrap_or(frame))
+}
+
+/// Traces the transaction with `debug_traceCall` to find the call that reverted and its reason
+async fn trace_revert<M: Middleware>(
+    client: &M,
+    tx: &TypedTransaction,
+    block: BlockId,
+) -> anyhow::Result<Option<SimulationError>> {
+    let options = GethDebugTracingCallOptions {
+        tracing_options: GethDebugTracingOptions {
+            tracer: Some(GethDebugTracerType::BuiltInTracer(
+                GethDebugBuiltInTracerType::CallTracer,
+            )),
+            tracer_config: Some(GethDebugTracerConfig::BuiltInTracer(
+                GethDebugBuiltInTracerConfig::CallTracer(CallConfig::default()),
+            )),
+            ..Default::default()
+        },
+        state_overrides: None,
+    };
+    let trace = client
+        .debug_trace_call(tx.clone(), Some(block), options)
+        .await
+        .map_err(|err| anyhow::anyhow!("debug_traceCall failed: {}", err))?;
+    let GethTrace::Known(GethTraceFrame::CallTracer(root)) = trace else {
+        return Ok(None);
+    };
+    Ok(reverted_frame(&root).map(|frame| SimulationError {
+        reason: frame
+            .output
+            .as_ref()
+            .map_or(RevertReason::Unknown, |output| RevertReason::decode(output)),
+        contract: match frame.to {
+            Some(NameOrAddress::Address(address)) => Some(address),
+            _ => None,
+        },
+    }))
+}
+
+/// Finds why a mined transaction reverted. Chains with `trace_simulation` on trace it, the others
+/// replay it with `eth_call` on the state before its block, which misses a revert caused by a transaction
+/// earlier in the same block
+pub(crate) async fn mined_revert_reason<M: Middleware>(
+    chain: &ChainConfig,
+    client: &M,
+    receipt: &TransactionReceipt,
+) -> anyhow::Result<RevertReason> {
+    if chain.trace_simulation {
+        let options = GethDebugTracingOptions {
+            tracer: Some(GethDebugTracerType::BuiltInTracer(
+                GethDebugBuiltInTracerType::CallTracer,
+            )),
+            ..Default::default()
+        };
+        let trace = client
+            .debug_trace_transaction(receipt.transaction_hash, options)
+            .await
+            .map_err(|err| anyhow::anyhow!("debug_traceTransaction failed: {}", err))?;
+        if let GethTrace::Known(GethTraceFrame::CallTracer(root)) = trace {
+            if let Some(output) = reverted_frame(&root).and_then(|frame| frame.output.as_ref()) {
+                return Ok(RevertReason::decode(output));
+            }
+        }
+        return Ok(RevertReason::Unknown);
+    }
+
+    let tx = client
+        .get_transaction(receipt.transaction_hash)
+        .await
+        .map_err(|err| anyhow::anyhow!("{}", err))?
+        .ok_or_else(|| anyhow::anyhow!("Transaction {:?} not found", receipt.transaction_hash))?;
+    let mut replay = TransactionRequest::new()
+        .from(tx.from)
+        .gas(tx.gas)
+        .value(tx.value)
+        .data(tx.input);
+    if let Some(to) = tx.to {
+        replay = replay.to(to);
+    }
+    let block = receipt
+        .block_number
+        .unwrap_or_default()
+        .saturating_sub(1u64.into());
+    let replay: TypedTransaction = replay.into();
+    match client
+        .call(&replay, Some(BlockId::Number(BlockNumber::Number(block))))
+        .await
+    {
+        Ok(_) => Ok(RevertReason::Unknown),
+        Err(err) => Ok(err
+            .as_error_response()
+            .and_then(|err| err.as_revert_data())
+            .map_or(RevertReason::Unknown, |data| RevertReason::decode(&data))),
+    }
+}
+
+/// Runs the signed-to-be transaction with `eth_call` against the latest block, a revert is returned
+/// as a [SimulationError] with its decoded reason. Chains with `trace_simulation` on also trace the
+/// failed call to point at the contract that reverted
+pub(crate) async fn simulate<M: Middleware>(
+    chain: &ChainConfig,
+    client: &M,
+    tx: &TypedTransaction,
+) -> anyhow::Result<()> {
+    let block = BlockId::Number(BlockNumber::Latest);
+    let revert_data = match client.call(tx, Some(block)).await {
+        Ok(_) => return Ok(()),
+        Err(err) => match err.as_error_response().and_then(|err| err.as_revert_data()) {
+            Some(revert_data) => revert_data,
+            None => return Err(anyhow::anyhow!("Simulation unavailable: {}", err)),
+        },
+    };
+
+    let simulated = SimulationError {
+        reason: RevertReason::decode(&revert_data),
+        contract: None,
+    };
+    if !chain.trace_simulation {
+        return Err(simulated.into());
+    }
+    let traced = trace_revert(client, tx, block).await.unwrap_or_else(|err| {
+        log::warn!("trace of the reverted simulation unavailable: {}", err);
+        None
+    });
+    Err(match traced {
+        // the router often replaces the token's own reason with a generic one
+        Some(traced) if traced.reason != RevertReason::Unknown => traced,
+        Some(traced) => SimulationError {
+            contract: traced.contract,
+            ..simulated
+        },
+        None => simulated,
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn revert_data(signature: &str, args: &[Token]) -> Vec<u8> {
+        [id(signature).as_slice(), &abi::encode(args)].concat()
+    }
+
+    fn error_string(message: &str) -> Vec<u8> {
+        revert_data("Error(string)", &[Token::String(message.to_string())])
+    }
+
+    #[test]
+    fn decodes_the_uniswap_v2_revert_strings() {
+        for (message, reason) in [
+            (
+                "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT",
+                RevertReason::InsufficientOutputAmount,
+            ),
+            (
+                "UniswapV2Library: INSUFFICIENT_LIQUIDITY",
+                RevertReason::InsufficientLiquidity,
+            ),
+            (
+                "UniswapV2: INSUFFICIENT_LIQUIDITY",
+                RevertReason::InsufficientLiquidity,
+            ),
+            ("UniswapV2Router: EXPIRED", RevertReason::Expired),
+            (
+                "TransferHelper: TRANSFER_FROM_FAILED",
+                RevertReason::TransferFailed,
+            ),
+            ("UniswapV2: TRANSFER_FAILED", RevertReason::TransferFailed),
+            (
+                "ERC20: transfer amount exceeds allowance",
+                RevertReason::InsufficientAllowance,
+            ),
+            (
+                "ERC20: transfer amount exceeds balance",
+                RevertReason::InsufficientBalance,
+            ),
+        ] {
+            assert_eq!(
+                RevertReason::decode(&error_string(message)),
+                reason,
+                "{}",
+                message
+            );
+        }
+        assert_eq!(
+            RevertReason::decode(&error_string("Ownable: caller is not the owner")),
+            RevertReason::Message("Ownable: caller is not the owner".to_string())
+        );
+    }
+
+    #[test]
+    fn decodes_panics() {
+        let data = revert_data("Panic(uint256)", &[Token::Uint(U256::from(0x11))]);
+        assert_eq!(
+            RevertReason::decode(&data),
+            RevertReason::Panic(U256::from(0x11))
+        );
+        assert_eq!(
+            RevertReason::Panic(U256::from(0x11)).to_string(),
+            "panic code 0x11"
+        );
+    }
+
+    #[test]
+    fn decodes_the_openzeppelin_5_erc20_errors() {
+        let args = [
+            Token::Address(Address::repeat_byte(1)),
+            Token::Uint(U256::from(1)),
+            Token::Uint(U256::from(2)),
+        ];
+        let allowance = revert_data("ERC20InsufficientAllowance(address,uint256,uint256)", &args);
+        assert_eq!(&allowance[..4], &[0xfb, 0x8f, 0x41, 0xb2]);
+        assert_eq!(
+            RevertReason::decode(&allowance),
+            RevertReason::InsufficientAllowance
+        );
+        let balance = revert_data("ERC20InsufficientBalance(address,uint256,uint256)", &args);
+        assert_eq!(&balance[..4], &[0xe4, 0x50, 0xd3, 0x8c]);
+        assert_eq!(
+            RevertReason::decode(&balance),
+            RevertReason::InsufficientBalance
+        );
+    }
+
+    #[test]
+    fn short_or_malformed_data_is_unknown() {
+        assert_eq!(RevertReason::decode(&[]), RevertReason::Unknown);
+        assert_eq!(
+            RevertReason::decode(&[0x08, 0xc3, 0x79]),
+            RevertReason::Unknown
+        );
+        // the selector of Error(string) without its argument
+        assert_eq!(
+            RevertReason::decode(&id("Error(string)")),
+            RevertReason::Unknown
+        );
+        assert_eq!(
+            RevertReason::decode(&id("Panic(uint256)")),
+            RevertReason::Unknown
+        );
+    }
+
+    #[test]
+    fn unknown_selectors_are_custom_errors() {
+        let data = revert_data("TransferTaxTooHigh(uint256)", &[Token::Uint(U256::one())]);
+        let selector = id("TransferTaxTooHigh(uint256)");
+        assert_eq!(RevertReason::decode(&data), RevertReason::Custom(selector));
+        assert_eq!(
+            RevertReason::Custom(selector).to_string(),
+            format!("custom error 0x{}", hex::encode(selector))
+        );
+    }
+
+    fn frame(to: Address, error: bool, calls: Vec<serde_json::Value>) -> serde_json::Value {
+        let mut frame = json!({
+            "type": "CALL",
+            "from": Address::zero(),
+            "to": to,
+            "gas": "0x0",
+            "gasUsed": "0x0",
+            "input": "0x",
+            "calls": calls,
+        });
+        if error {
+            frame["error"] = json!("execution reverted");
+        }
+        frame
+    }
+
+    #[test]
+    fn finds_the_deepest_reverted_call() {
+        let (router, pair, token, other) = (
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            Address::repeat_byte(3),
+            Address::repeat_byte(4),
+        );
+        // the router calls a pair that succeeds, then a pair whose token transfer reverts
+        let trace: CallFrame = serde_json::from_value(frame(
+            router,
+            true,
+            vec![
+                frame(other, false, vec![]),
+                frame(pair, true, vec![frame(token, true, vec![])]),
+            ],
+        ))
+        .unwrap();
+        let reverted = reverted_frame(&trace).unwrap();
+        assert_eq!(reverted.to, Some(NameOrAddress::Address(token)));
+
+        // a call that reverts without reverting inner calls is the one reported
+        let trace: CallFrame =
+            serde_json::from_value(frame(router, true, vec![frame(other, false, vec![])])).unwrap();
+        assert_eq!(
+            reverted_frame(&trace).unwrap().to,
+            Some(NameOrAddress::Address(router))
+        );
+
+        let trace: CallFrame =
+            serde_json::from_value(frame(router, false, vec![frame(other, false, vec![])]))
+                .unwrap();
+        assert!(reverted_frame(&trace).is_none());
+    }
+}
@@ -0,0 +1,1050 @@
+use crate::chains::ChainConfig;
+use crate::drafts::SellAmount;
+use crate::requests::gas::{self, GasFees};
+use crate::requests::on_chain;
+use crate::requests::permit::{self, PermitSingle};
+use crate::requests::quote::minimum_out;
+use crate::requests::relay;
+use crate::requests::server::{SendBuyTxRequest, SendSellTxRequest};
+use crate::requests::simulation;
+use crate::requests::token_safety;
+use ethers::{
+    abi::{self, Detokenize, Token},
+    prelude::*,
+    providers::{Http, Provider},
+    types::{Address, TxHash, U256},
+    utils::{keccak256, parse_units},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+abigen!(
+    UniswapV2Router,
+    r#"[
+        function WETH() external pure returns (address)
+        function factory() external pure returns (address)
+        function getAmountsOut(uint256 amountIn, address[] calldata path) external view returns (uint256[] memory amounts)
+        function swapExactETHForTokens(uint256 amountOutMin, address[] calldata path, address to, uint256 deadline) external payable returns (uint256[] memory amounts)
+        function swapExactTokensForTokensSupportingFeeOnTransferTokens(uint256 amountIn, uint256 amountOutMin, address[] calldata path, address to, uint256 deadline) external
+        function swapExactTokensForETHSupportingFeeOnTransferTokens(uint256 amountIn, uint256 amountOutMin, address[] calldata path, address to, uint256 deadline) external
+    ]"#
+);
+
+abigen!(
+    Erc20,
+    r#"[
+        function name() external view returns (string)
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function totalSupply() external view returns (uint256)
+        function balanceOf(address owner) external view returns (uint256)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function approve(address spender, uint256 amount) external returns (bool)
+    ]"#
+);
+
+abigen!(
+    UniversalRouter,
+    r#"[
+        function execute(bytes commands, bytes[] inputs, uint256 deadline) external payable
+    ]"#
+);
+
+/// Universal Router commands, one byte each in `execute`'s commands
+const V2_SWAP_EXACT_IN: u8 = 0x08;
+const PERMIT2_PERMIT: u8 = 0x0a;
+const UNWRAP_WETH: u8 = 0x0c;
+
+/// Recipient the Universal Router reads as itself, the swap output stays there to be unwrapped
+const ADDRESS_THIS: Address = H160([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+pub(crate) type SwapClient = SignerMiddleware<NonceManagerMiddleware<Provider<Http>>, LocalWallet>;
+
+/// Token on one side of a swap, `address` is `None` for the native coin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SwapToken {
+    pub(crate) address: Option<Address>,
+    pub(crate) symbol: String,
+    pub(crate) decimals: u8,
+}
+
+/// A swap that has been broadcast
+#[derive(Debug, Clone)]
+pub(crate) struct SentSwap {
+    pub(crate) chain_id: u64,
+    pub(crate) wallet: Address,
+    pub(crate) tx_hash: TxHash,
+    pub(crate) token_in: SwapToken,
+    pub(crate) token_out: SwapToken,
+    pub(crate) amount_in: U256,
+    /// Last block a Private Tx swap may be included in, `None` when it went to the public mempool
+    pub(crate) private_max_block: Option<U64>,
+    /// Sent with the Rebate toggle on, its fee earns a rebate once mined
+    pub(crate) rebate: bool,
+    /// How a swap to the native coin pays it out, `None` for swaps to an ERC-20
+    pub(crate) native_payout: Option<NativePayout>,
+}
+
+/// The router unwrapping WETH to pay a swap's output out as the native coin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NativePayout {
+    pub(crate) weth: Address,
+    pub(crate) router: Address,
+}
+
+impl SentSwap {
+    /// ERC-20 tokens the swap spends or receives
+    pub(crate) fn erc20_tokens(&self) -> Vec<Address> {
+        [&self.token_in, &self.token_out]
+            .iter()
+            .filter_map(|token| token.address)
+            .collect()
+    }
+}
+
+/// How the router is approved when its allowance is too low for a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ApprovalMode {
+    /// Approves the amount of the swap only
+    Exact,
+    /// Approves the largest amount possible, later swaps of the token need no approval
+    Unlimited,
+}
+
+impl ApprovalMode {
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Unlimited => "max",
+        }
+    }
+
+    pub(crate) fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "exact" => Some(Self::Exact),
+            "max" => Some(Self::Unlimited),
+            _ => None,
+        }
+    }
+}
+
+/// The allowance of the router, or of Permit2, is too low for the swap and the request didn't say how to approve it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AllowanceError {
+    pub(crate) token: SwapToken,
+    pub(crate) spender: Address,
+    pub(crate) allowance: U256,
+    pub(crate) amount: U256,
+}
+
+impl fmt::Display for AllowanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Approval needed: this wallet allows swapping {} {}, the swap needs {}",
+            on_chain::format_amount(self.allowance, self.token.decimals),
+            self.token.symbol,
+            on_chain::format_amount(self.amount, self.token.decimals)
+        )
+    }
+}
+
+impl std::error::Error for AllowanceError {}
+
+/// Connects the wallet to the chain, signing for its chain id
+pub(crate) async fn swap_client(
+    chain: &ChainConfig,
+    wallet: LocalWallet,
+) -> anyhow::Result<Arc<SwapClient>> {
+    let provider = chain.provider().await?;
+    // the nonces start after the wallet's pending txs, the approval and swap of one trade follow each other
+    let nonce_manager = NonceManagerMiddleware::new(provider, wallet.address());
+    nonce_manager
+        .initialize_nonce(Some(BlockNumber::Pending.into()))
+        .await?;
+    Ok(Arc::new(SignerMiddleware::new(
+        nonce_manager,
+        wallet.with_chain_id(chain.chain_id),
+    )))
+}
+
+/// Unix timestamp after which a swap built now reverts
+pub(crate) fn swap_deadline(deadline_secs: u64) -> anyhow::Result<U256> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(U256::from(now + deadline_secs))
+}
+
+/// Waits for the swap to be mined, `None` when it was dropped or a private swap expired
+pub(crate) async fn wait_for_receipt(
+    chain: &ChainConfig,
+    sent: &SentSwap,
+) -> anyhow::Result<Option<TransactionReceipt>> {
+    let provider = chain.provider().await?;
+    match sent.private_max_block {
+        Some(max_block) => relay::wait_for_inclusion(&provider, sent.tx_hash, max_block).await,
+        None => Ok(PendingTransaction::new(sent.tx_hash, &provider).await?),
+    }
+}
+
+/// How the chain's router pays out the swaps to the native coin sent with [send_sell_tx]
+pub(crate) async fn native_payout(chain: &ChainConfig) -> anyhow::Result<NativePayout> {
+    let router = chain.router()?;
+    let client = Arc::new(chain.provider().await?);
+    let weth = UniswapV2Router::new(router, client).weth().call().await?;
+    Ok(NativePayout {
+        weth,
+        router: chain.universal_router.unwrap_or(router),
+    })
+}
+
+/// Amount of the output token the swap's wallet received according to the receipt's logs.
+/// The native coin is read from the last withdrawal of the chain's WETH by the router, earlier ones
+/// are the swaps a fee-on-transfer token makes of its tax while it is sold
+pub(crate) fn amount_received(sent: &SentSwap, receipt: &TransactionReceipt) -> U256 {
+    let transfer = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let withdrawal = H256::from(keccak256("Withdrawal(address,uint256)"));
+    let amount = |log: &Log| U256::from_big_endian(&log.data);
+    match (sent.token_out.address, sent.native_payout) {
+        (Some(token), _) => {
+            let recipient = H256::from(sent.wallet);
+            receipt
+                .logs
+                .iter()
+                .filter(|log| {
+                    log.address == token
+                        && log.topics.first() == Some(&transfer)
+                        && log.topics.get(2) == Some(&recipient)
+                })
+                .fold(U256::zero(), |received, log| received + amount(log))
+        }
+        (None, Some(payout)) => {
+            let router = H256::from(payout.router);
+            receipt
+                .logs
+                .iter()
+                .filter(|log| {
+                    log.address == payout.weth
+                        && log.topics.first() == Some(&withdrawal)
+                        && log.topics.get(1) == Some(&router)
+                })
+                .last()
+                .map_or(U256::zero(), amount)
+        }
+        (None, None) => U256::zero(),
+    }
+}
+
+/// Simulates the swap, then sends it to the public mempool, or signs it and hands it to the chain's
+/// private relays when `private_tx` is on. A swap that would revert is never sent.
+/// Returns the transaction hash and the last block a private swap may land in
+async fn broadcast<D: Detokenize>(
+    chain: &ChainConfig,
+    client: &SwapClient,
+    mut call: ContractCall<SwapClient, D>,
+    private_tx: bool,
+) -> anyhow::Result<(TxHash, Option<U64>)> {
+    call.tx.set_from(client.address());
+    simulation::simulate(chain, client, &call.tx).await?;
+    if !private_tx {
+        return Ok((call.send().await?.tx_hash(), None));
+    }
+    let mut tx = call.tx;
+    client.fill_transaction(&mut tx, None).await?;
+    let signature = client.signer().sign_transaction(&tx).await?;
+    let current_block = client.get_block_number().await?;
+    let (tx_hash, max_block) = relay::send_private_transaction(
+        &chain.private_relays,
+        tx.rlp_signed(&signature),
+        current_block,
+        chain.private_tx_blocks,
+    )
+    .await?;
+    Ok((tx_hash, Some(max_block)))
+}
+
+/// Least output accepted when swapping `amount_in` along `path`, the router's quote minus the
+/// transfer taxes in `tax_bps` and the slippage tolerance
+async fn amount_out_min(
+    router: &UniswapV2Router<SwapClient>,
+    amount_in: U256,
+    path: Vec<Address>,
+    tax_bps: u32,
+    slippage_bps: u32,
+) -> anyhow::Result<U256> {
+    let amounts = router.get_amounts_out(amount_in, path).call().await?;
+    let amount_out = amounts.last().copied().unwrap_or_default();
+    Ok(minimum_out(minimum_out(amount_out, tax_bps), slippage_bps))
+}
+
+/// Share of a swap's quote the transfer taxes keep, in basis points: the sell tax of `sold` on its
+/// way into the pair and the buy tax of `bought` on its way out. The router quotes as if there were
+/// none, a token the check can't measure is taken to have none too
+async fn transfer_tax_bps(chain: &ChainConfig, sold: Address, bought: Option<Address>) -> u32 {
+    let tax = |token: Address| async move {
+        token_safety::transfer_tax_bps(chain, token)
+            .await
+            .map_err(|err| log::warn!("transfer tax of {:?} not measured: {}", token, err))
+            .unwrap_or_default()
+    };
+    let (_, sell_tax) = tax(sold).await;
+    let buy_tax = match bought {
+        Some(bought) => tax(bought).await.0,
+        None => 0,
+    };
+    combined_tax_bps(sell_tax, buy_tax)
+}
+
+/// Both taxes taken one after the other, in basis points
+fn combined_tax_bps(first: u32, second: u32) -> u32 {
+    let (first, second) = (first.min(10_000), second.min(10_000));
+    first + second - first * second / 10_000
+}
+
+/// Converts a human readable amount into the token's base units
+pub(crate) fn to_base_units(amount: f64, decimals: u8) -> anyhow::Result<U256> {
+    Ok(parse_units(amount.to_string(), decimals as u32)?.into())
+}
+
+/// Gets the symbol and decimals of an ERC-20 token, read on chain only the first time
+pub(crate) async fn erc20_token(chain: &ChainConfig, token: Address) -> anyhow::Result<SwapToken> {
+    let metadata = on_chain::token_metadata(chain, token).await?;
+    Ok(SwapToken {
+        address: Some(token),
+        symbol: metadata.symbol,
+        decimals: metadata.decimals,
+    })
+}
+
+/// The native coin of the chain
+pub(crate) fn native_token(chain: &ChainConfig) -> SwapToken {
+    SwapToken {
+        address: None,
+        symbol: chain.native_symbol.clone(),
+        decimals: 18,
+    }
+}
+
+/// Approves `spender` to spend `amount` of `token` if the current allowance is too low
+/// and waits for the approval to be mined, approvals always go to the public mempool.
+/// A token supporting EIP-2612 is permitted instead when a sponsor sends the permit, otherwise
+/// without an `approval` mode a too low allowance is returned as an [AllowanceError]
+pub(crate) async fn ensure_allowance(
+    chain: &ChainConfig,
+    client: Arc<SwapClient>,
+    token: &SwapToken,
+    spender: Address,
+    amount: U256,
+    fees: &GasFees,
+    approval: Option<ApprovalMode>,
+) -> anyhow::Result<()> {
+    let address = token
+        .address
+        .ok_or_else(|| anyhow::anyhow!("The native coin needs no approval"))?;
+    let erc20 = Erc20::new(address, client.clone());
+    let allowance = erc20.allowance(client.address(), spender).call().await?;
+    if allowance >= amount {
+        return Ok(());
+    }
+    match permit::sponsored_permit(chain, client.signer(), address, spender).await {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        Err(err) => log::warn!("permit of {} failed: {}", token.symbol, err),
+    }
+    let approved = match approval {
+        Some(ApprovalMode::Exact) => amount,
+        Some(ApprovalMode::Unlimited) => U256::MAX,
+        None => {
+            return Err(AllowanceError {
+                token: token.clone(),
+                spender,
+                allowance,
+                amount,
+            }
+            .into())
+        }
+    };
+
+    log::info!("approving {} of {:?} for {:?}", approved, address, spender);
+    let call = fees.apply(erc20.approve(spender, approved));
+    let pending = call.send().await?;
+    match pending.await? {
+        Some(receipt) if receipt.status == Some(1u64.into()) => Ok(()),
+        _ => Err(anyhow::anyhow!("Approval of {} failed", token.symbol)),
+    }
+}
+
+/// Builds the Universal Router swap of `amount_in` of the ERC-20 along `path`, the router pulls the
+/// tokens through Permit2. Its Permit2 allowance is signed into the swap when the current one is too low,
+/// so only Permit2 itself ever needs an approval of the token. `unwrap` pays the output out as the native coin
+#[allow(clippy::too_many_arguments)]
+async fn universal_router_swap(
+    chain: &ChainConfig,
+    client: Arc<SwapClient>,
+    universal_router: Address,
+    token_in: &SwapToken,
+    amount_in: U256,
+    min_out: U256,
+    path: Vec<Address>,
+    unwrap: bool,
+    deadline: U256,
+    fees: &GasFees,
+    approval: Option<ApprovalMode>,
+) -> anyhow::Result<ContractCall<SwapClient, ()>> {
+    let token = token_in
+        .address
+        .ok_or_else(|| anyhow::anyhow!("The native coin can't be sent through Permit2"))?;
+    let permit2 = chain.permit2();
+    ensure_allowance(
+        chain,
+        client.clone(),
+        token_in,
+        permit2,
+        amount_in,
+        fees,
+        approval,
+    )
+    .await?;
+
+    let to = client.address();
+    let mut commands = Vec::new();
+    let mut inputs = Vec::new();
+    let permit = PermitSingle::for_swap(
+        client.clone(),
+        permit2,
+        to,
+        token,
+        universal_router,
+        amount_in,
+        deadline.as_u64(),
+    )
+    .await?;
+    if let Some(permit) = permit {
+        let signature = permit.sign(client.signer(), chain.chain_id, permit2)?;
+        commands.push(PERMIT2_PERMIT);
+        inputs.push(permit.encode(&signature));
+    }
+    commands.push(V2_SWAP_EXACT_IN);
+    inputs.push(
+        abi::encode(&[
+            Token::Address(if unwrap { ADDRESS_THIS } else { to }),
+            Token::Uint(amount_in),
+            Token::Uint(min_out),
+            Token::Array(path.into_iter().map(Token::Address).collect()),
+            // the router pulls the input from the wallet
+            Token::Bool(true),
+        ])
+        .into(),
+    );
+    if unwrap {
+        commands.push(UNWRAP_WETH);
+        inputs.push(abi::encode(&[Token::Address(to), Token::Uint(min_out)]).into());
+    }
+
+    let router = UniversalRouter::new(universal_router, client);
+    Ok(fees.apply(router.execute(commands.into(), inputs, deadline)))
+}
+
+/// Builds, signs and broadcasts the swap described by the buy menu.
+/// Pays with the native coin when no buy token was entered, otherwise swaps token to token.
+pub(crate) async fn send_buy_tx(
+    chain: &ChainConfig,
+    request: &SendBuyTxRequest,
+    wallet: LocalWallet,
+) -> anyhow::Result<SentSwap> {
+    let client = swap_client(chain, wallet).await?;
+    let router_address = chain.router()?;
+    let router = UniswapV2Router::new(router_address, client.clone());
+    let receive = request.receive;
+    let token_out = erc20_token(chain, receive).await?;
+    let deadline = swap_deadline(request.deadline_secs)?;
+    let fees = gas::gas_fees(client.as_ref(), request.gas_preset, request.max_fee_gwei).await?;
+    let to = client.address();
+
+    let (token_in, amount_in, (tx_hash, private_max_block)) = match request.buy {
+        None => {
+            let weth = router.weth().call().await?;
+            let amount_in = to_base_units(request.buy_amount, 18)?;
+            let path = vec![weth, receive];
+            // the router checks the output it computed, a buy tax can't make the swap revert
+            let min_out =
+                amount_out_min(&router, amount_in, path.clone(), 0, request.slippage_bps).await?;
+            let call = fees.apply(
+                router
+                    .swap_exact_eth_for_tokens(min_out, path, to, deadline)
+                    .value(amount_in),
+            );
+            let sent = broadcast(chain, &client, call, request.private_tx).await?;
+            (native_token(chain), amount_in, sent)
+        }
+        Some(buy) => {
+            let token_in = erc20_token(chain, buy).await?;
+            let amount_in = to_base_units(request.buy_amount, token_in.decimals)?;
+            let path = vec![buy, receive];
+            let tax_bps = transfer_tax_bps(chain, buy, Some(receive)).await;
+            let min_out = amount_out_min(
+                &router,
+                amount_in,
+                path.clone(),
+                tax_bps,
+                request.slippage_bps,
+            )
+            .await?;
+            let sent = match chain.universal_router {
+                Some(universal_router) => {
+                    let call = universal_router_swap(
+                        chain,
+                        client.clone(),
+                        universal_router,
+                        &token_in,
+                        amount_in,
+                        min_out,
+                        path,
+                        false,
+                        deadline,
+                        &fees,
+                        request.approval,
+                    )
+                    .await?;
+                    broadcast(chain, &client, call, request.private_tx).await?
+                }
+                None => {
+                    ensure_allowance(
+                        chain,
+                        client.clone(),
+                        &token_in,
+                        router_address,
+                        amount_in,
+                        &fees,
+                        request.approval,
+                    )
+                    .await?;
+                    let call = fees.apply(
+                        router.swap_exact_tokens_for_tokens_supporting_fee_on_transfer_tokens(
+                            amount_in, min_out, path, to, deadline,
+                        ),
+                    );
+                    broadcast(chain, &client, call, request.private_tx).await?
+                }
+            };
+            (token_in, amount_in, sent)
+        }
+    };
+
+    log::info!("buy tx sent: {:?}", tx_hash);
+    Ok(SentSwap {
+        chain_id: chain.chain_id,
+        wallet: to,
+        tx_hash,
+        token_in,
+        token_out,
+        amount_in,
+        private_max_block,
+        rebate: request.rebate,
+        native_payout: None,
+    })
+}
+
+/// Builds, signs and broadcasts the swap described by the sell menu, selling the token for the native coin
+pub(crate) async fn send_sell_tx(
+    chain: &ChainConfig,
+    request: &SendSellTxRequest,
+    wallet: LocalWallet,
+) -> anyhow::Result<SentSwap> {
+    let client = swap_client(chain, wallet).await?;
+    let router_address = chain.router()?;
+    let router = UniswapV2Router::new(router_address, client.clone());
+    let sell = request.sell;
+    let token_in = erc20_token(chain, sell).await?;
+    let erc20 = Erc20::new(sell, client.clone());
+    let to = client.address();
+
+    let balance = erc20.balance_of(to).call().await?;
+    let amount_in = match request.amount {
+        SellAmount::Percent(percent) => balance * U256::from(percent) / U256::from(100u64),
+        SellAmount::Exact(amount) => to_base_units(amount, token_in.decimals)?,
+        // part of it may have been sold from the wallet since the amount was recorded
+        SellAmount::BaseUnits(amount) => amount.min(balance),
+    };
+    if amount_in.is_zero() {
+        return Err(anyhow::anyhow!("Nothing to sell"));
+    }
+    if amount_in > balance {
+        return Err(anyhow::anyhow!("Insufficient token balance"));
+    }
+
+    let fees = gas::gas_fees(client.as_ref(), request.gas_preset, request.max_fee_gwei).await?;
+    let weth = router.weth().call().await?;
+    let deadline = swap_deadline(request.deadline_secs)?;
+    let path = vec![sell, weth];
+    let tax_bps = transfer_tax_bps(chain, sell, None).await;
+    let min_out = amount_out_min(
+        &router,
+        amount_in,
+        path.clone(),
+        tax_bps,
+        request.slippage_bps,
+    )
+    .await?;
+    let (tx_hash, private_max_block) = match chain.universal_router {
+        Some(universal_router) => {
+            let call = universal_router_swap(
+                chain,
+                client.clone(),
+                universal_router,
+                &token_in,
+                amount_in,
+                min_out,
+                path,
+                true,
+                deadline,
+                &fees,
+                request.approval,
+            )
+            .await?;
+            broadcast(chain, &client, call, request.private_tx).await?
+        }
+        None => {
+            ensure_allowance(
+                chain,
+                client.clone(),
+                &token_in,
+                router_address,
+                amount_in,
+                &fees,
+                request.approval,
+            )
+            .await?;
+            let call = fees.apply(
+                router.swap_exact_tokens_for_eth_supporting_fee_on_transfer_tokens(
+                    amount_in, min_out, path, to, deadline,
+                ),
+            );
+            broadcast(chain, &client, call, request.private_tx).await?
+        }
+    };
+
+    log::info!("sell tx sent: {:?}", tx_hash);
+    Ok(SentSwap {
+        chain_id: chain.chain_id,
+        wallet: to,
+        tx_hash,
+        token_in,
+        token_out: native_token(chain),
+        amount_in,
+        private_max_block,
+        rebate: request.rebate,
+        native_payout: Some(NativePayout {
+            weth,
+            router: chain.universal_router.unwrap_or(router_address),
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::GasPreset;
+    use ethers::solc::{CompilerOutput, Solc};
+    use ethers::utils::{parse_ether, Anvil, AnvilInstance};
+    use serde_json::json;
+    use std::process::Command;
+
+    abigen!(
+        LiquidityRouter,
+        r#"[
+            function addLiquidity(address tokenA, address tokenB, uint256 amountADesired, uint256 amountBDesired, uint256 amountAMin, uint256 amountBMin, address to, uint256 deadline) external returns (uint256 amountA, uint256 amountB, uint256 liquidity)
+            function addLiquidityETH(address token, uint256 amountTokenDesired, uint256 amountTokenMin, uint256 amountETHMin, address to, uint256 deadline) external payable returns (uint256 amountToken, uint256 amountETH, uint256 liquidity)
+        ]"#
+    );
+
+    /// Compact Uniswap V2 the local swap tests deploy
+    const UNISWAP_V2_SOURCE: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/contracts/test/UniswapV2.sol");
+
+    /// USDC on Ethereum mainnet
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    /// Mainnet chain entry pointing at the fork, swaps go straight to the Uniswap V2 router
+    fn fork_chain(endpoint: String) -> ChainConfig {
+        serde_json::from_value(json!({
+            "chain_id": 1,
+            "name": "Ethereum fork",
+            "rpc_urls": [endpoint],
+            "native_symbol": "ETH",
+            "routers": ["0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"],
+            "explorer_url": "https://etherscan.io"
+        }))
+        .unwrap()
+    }
+
+    /// Uniswap V2 deployed on a fresh anvil node, with WETH pools of a plain token and of a token
+    /// keeping 5% of every transfer, and a pool of the two. The wallet holds the rest of both supplies
+    struct LocalUniswap {
+        _anvil: AnvilInstance,
+        chain: ChainConfig,
+        wallet: LocalWallet,
+        token: Address,
+        taxed: Address,
+    }
+
+    async fn deploy<T: abi::Tokenize>(
+        output: &CompilerOutput,
+        client: Arc<SwapClient>,
+        name: &str,
+        args: T,
+    ) -> Address {
+        let (abi, bytecode, _) = output.find(name).unwrap().into_parts_or_default();
+        ContractFactory::new(abi, bytecode, client)
+            .deploy(args)
+            .unwrap()
+            .send()
+            .await
+            .unwrap()
+            .address()
+    }
+
+    /// `None` when anvil or solc isn't installed, the test is skipped then
+    async fn local_uniswap() -> Option<LocalUniswap> {
+        let solc = Solc::default();
+        if Command::new("anvil").arg("--version").output().is_err() || solc.version().is_err() {
+            eprintln!("skipped, the local swap tests need anvil and solc installed");
+            return None;
+        }
+        let output = solc.compile_source(UNISWAP_V2_SOURCE).unwrap();
+        assert!(!output.has_error(), "{:?}", output.errors);
+
+        // the wallets approve on-chain, no sponsor sends permits for them
+        permit::GLOBAL_PERMIT_SPONSOR.load(|| Ok(None)).unwrap();
+        let anvil = Anvil::new().spawn();
+        let wallet = LocalWallet::from(anvil.keys()[0].clone());
+        let chain: ChainConfig = serde_json::from_value(json!({
+            "chain_id": anvil.chain_id(),
+            "name": "Anvil",
+            "rpc_urls": [anvil.endpoint()],
+            "native_symbol": "ETH",
+            "routers": [],
+            "explorer_url": "http://localhost"
+        }))
+        .unwrap();
+        let client = swap_client(&chain, wallet.clone()).await.unwrap();
+
+        let weth = deploy(&output, client.clone(), "WETH9", ()).await;
+        let factory = deploy(&output, client.clone(), "UniswapV2Factory", ()).await;
+        let router = deploy(
+            &output,
+            client.clone(),
+            "UniswapV2Router02",
+            (factory, weth),
+        )
+        .await;
+        let supply = parse_ether(1_000_000).unwrap();
+        let token = deploy(
+            &output,
+            client.clone(),
+            "TestToken",
+            (
+                "Test Token".to_string(),
+                "TST".to_string(),
+                supply,
+                U256::zero(),
+            ),
+        )
+        .await;
+        let taxed = deploy(
+            &output,
+            client.clone(),
+            "TestToken",
+            (
+                "Taxed Token".to_string(),
+                "TAX".to_string(),
+                supply,
+                U256::from(500),
+            ),
+        )
+        .await;
+
+        let to = client.address();
+        let deadline = swap_deadline(600).unwrap();
+        let liquidity = parse_ether(100_000).unwrap();
+        let pools = LiquidityRouter::new(router, client.clone());
+        for token in [token, taxed] {
+            Erc20::new(token, client.clone())
+                .approve(router, U256::MAX)
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+            pools
+                .add_liquidity_eth(token, liquidity, 0.into(), 0.into(), to, deadline)
+                .value(parse_ether(100).unwrap())
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+        }
+        pools
+            .add_liquidity(
+                token,
+                taxed,
+                liquidity,
+                liquidity,
+                0.into(),
+                0.into(),
+                to,
+                deadline,
+            )
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+
+        let mut chain = chain;
+        chain.routers = vec![router];
+        Some(LocalUniswap {
+            _anvil: anvil,
+            chain,
+            wallet,
+            token,
+            taxed,
+        })
+    }
+
+    fn buy_request(buy: Option<Address>, receive: Address, buy_amount: f64) -> SendBuyTxRequest {
+        SendBuyTxRequest {
+            wallet_slot: 0,
+            private_tx: false,
+            rebate: false,
+            buy,
+            receive,
+            buy_amount,
+            slippage_bps: 100,
+            deadline_secs: 600,
+            gas_preset: GasPreset::Standard,
+            max_fee_gwei: None,
+            approval: Some(ApprovalMode::Exact),
+        }
+    }
+
+    /// Waits for the swap to be mined, it must succeed
+    async fn mined(chain: &ChainConfig, sent: &SentSwap) -> TransactionReceipt {
+        let receipt = wait_for_receipt(chain, sent)
+            .await
+            .unwrap()
+            .expect("the swap is mined");
+        assert_eq!(receipt.status, Some(1u64.into()));
+        receipt
+    }
+
+    #[tokio::test]
+    async fn buys_with_the_native_coin_on_a_local_node() {
+        let Some(local) = local_uniswap().await else {
+            return;
+        };
+        let request = buy_request(None, local.token, 1.0);
+        let client = swap_client(&local.chain, local.wallet.clone())
+            .await
+            .unwrap();
+        let balance = Erc20::new(local.token, client.clone());
+        let before = balance.balance_of(client.address()).call().await.unwrap();
+
+        let sent = send_buy_tx(&local.chain, &request, local.wallet.clone())
+            .await
+            .unwrap();
+        let receipt = mined(&local.chain, &sent).await;
+        let received = amount_received(&sent, &receipt);
+        let after = balance.balance_of(client.address()).call().await.unwrap();
+        assert!(!received.is_zero());
+        assert_eq!(after - before, received);
+    }
+
+    #[tokio::test]
+    async fn buys_a_taxed_token_with_another_token_on_a_local_node() {
+        let Some(local) = local_uniswap().await else {
+            return;
+        };
+        // the wallet gets 95% of the output, the router checks the minimum against what arrived
+        let request = buy_request(Some(local.token), local.taxed, 100.0);
+        let sent = send_buy_tx(&local.chain, &request, local.wallet.clone())
+            .await
+            .unwrap();
+        let receipt = mined(&local.chain, &sent).await;
+        assert!(!amount_received(&sent, &receipt).is_zero());
+
+        // the pool gets 95% of the input, the plain swap would fail on it with UniswapV2: K
+        let request = buy_request(Some(local.taxed), local.token, 100.0);
+        let sent = send_buy_tx(&local.chain, &request, local.wallet.clone())
+            .await
+            .unwrap();
+        let receipt = mined(&local.chain, &sent).await;
+        assert!(!amount_received(&sent, &receipt).is_zero());
+    }
+
+    #[test]
+    fn takes_one_transfer_tax_after_the_other() {
+        assert_eq!(combined_tax_bps(0, 0), 0);
+        assert_eq!(combined_tax_bps(500, 0), 500);
+        assert_eq!(combined_tax_bps(0, 300), 300);
+        // 95% of 95% arrives
+        assert_eq!(combined_tax_bps(500, 500), 975);
+        assert_eq!(combined_tax_bps(10_000, 500), 10_000);
+        assert_eq!(combined_tax_bps(20_000, 20_000), 10_000);
+    }
+
+    #[tokio::test]
+    async fn sells_a_taxed_token_on_a_local_node() {
+        let Some(local) = local_uniswap().await else {
+            return;
+        };
+        let client = swap_client(&local.chain, local.wallet.clone())
+            .await
+            .unwrap();
+        let taxed = Erc20::new(local.taxed, client.clone());
+        let held = taxed.balance_of(client.address()).call().await.unwrap();
+        let request = SendSellTxRequest {
+            wallet_slot: 0,
+            private_tx: false,
+            rebate: false,
+            sell: local.taxed,
+            amount: SellAmount::Exact(1_000.0),
+            slippage_bps: 100,
+            deadline_secs: 600,
+            gas_preset: GasPreset::Standard,
+            max_fee_gwei: None,
+            approval: Some(ApprovalMode::Exact),
+        };
+
+        // the pool gets 95% of the tokens sold, the plain swap would fail on it with UniswapV2: K
+        let sent = send_sell_tx(&local.chain, &request, local.wallet.clone())
+            .await
+            .unwrap();
+        let receipt = mined(&local.chain, &sent).await;
+        assert!(!amount_received(&sent, &receipt).is_zero());
+        let sold = held - taxed.balance_of(client.address()).call().await.unwrap();
+        assert_eq!(sold, parse_ether(1_000).unwrap());
+    }
+
+    fn sent_swap(token_out: SwapToken, native_payout: Option<NativePayout>) -> SentSwap {
+        SentSwap {
+            chain_id: 1,
+            wallet: Address::repeat_byte(0x11),
+            tx_hash: TxHash::zero(),
+            token_in: native_token(&fork_chain("http://localhost:8545".to_string())),
+            token_out,
+            amount_in: U256::zero(),
+            private_max_block: None,
+            rebate: false,
+            native_payout,
+        }
+    }
+
+    fn log(address: Address, event: &str, topics: &[Address], amount: u64) -> Log {
+        let mut data = [0u8; 32];
+        U256::from(amount).to_big_endian(&mut data);
+        Log {
+            address,
+            topics: std::iter::once(H256::from(keccak256(event)))
+                .chain(topics.iter().map(|topic| H256::from(*topic)))
+                .collect(),
+            data: data.to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_the_native_coin_from_the_router_withdrawal() {
+        let weth = Address::repeat_byte(0xee);
+        let router = Address::repeat_byte(0x77);
+        let withdrawal = "Withdrawal(address,uint256)";
+        let receipt = TransactionReceipt {
+            logs: vec![
+                // the tax swap of a fee-on-transfer token, made while it is sold
+                log(weth, withdrawal, &[router], 5),
+                // another contract unwrapping WETH in the same tx
+                log(weth, withdrawal, &[Address::repeat_byte(0x99)], 7),
+                // an event with the same signature from a contract that isn't WETH
+                log(Address::repeat_byte(0x55), withdrawal, &[router], 11),
+                // the payout of the swap
+                log(weth, withdrawal, &[router], 1_000),
+            ],
+            ..Default::default()
+        };
+        let sent = sent_swap(
+            native_token(&fork_chain("http://localhost:8545".to_string())),
+            Some(NativePayout { weth, router }),
+        );
+        assert_eq!(amount_received(&sent, &receipt), 1_000.into());
+
+        let receipt = TransactionReceipt {
+            logs: vec![log(weth, withdrawal, &[Address::repeat_byte(0x99)], 7)],
+            ..Default::default()
+        };
+        assert!(amount_received(&sent, &receipt).is_zero());
+    }
+
+    #[test]
+    fn sums_the_transfers_to_the_wallet() {
+        let token = Address::repeat_byte(0x33);
+        let wallet = Address::repeat_byte(0x11);
+        let pair = Address::repeat_byte(0x44);
+        let transfer = "Transfer(address,address,uint256)";
+        let receipt = TransactionReceipt {
+            logs: vec![
+                log(token, transfer, &[pair, wallet], 900),
+                log(token, transfer, &[pair, Address::repeat_byte(0x99)], 100),
+                log(Address::repeat_byte(0x55), transfer, &[pair, wallet], 50),
+                log(token, transfer, &[pair, wallet], 25),
+            ],
+            ..Default::default()
+        };
+        let token_out = SwapToken {
+            address: Some(token),
+            symbol: "TKN".to_string(),
+            decimals: 18,
+        };
+        let sent = sent_swap(token_out, None);
+        assert_eq!(amount_received(&sent, &receipt), 925.into());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs anvil and a mainnet RPC in ANVIL_FORK_URL"]
+    async fn buys_with_the_native_coin_on_a_mainnet_fork() {
+        let fork_url = std::env::var("ANVIL_FORK_URL").expect("ANVIL_FORK_URL is not set");
+        let anvil = Anvil::new().fork(fork_url).spawn();
+        let chain = fork_chain(anvil.endpoint());
+        let wallet = LocalWallet::from(anvil.keys()[0].clone());
+        let request = SendBuyTxRequest {
+            wallet_slot: 0,
+            private_tx: false,
+            rebate: false,
+            buy: None,
+            receive: USDC.parse().unwrap(),
+            buy_amount: 1.0,
+            slippage_bps: 100,
+            deadline_secs: 600,
+            gas_preset: GasPreset::Standard,
+            max_fee_gwei: None,
+            approval: None,
+        };
+
+        let client = swap_client(&chain, wallet.clone()).await.unwrap();
+        let router = UniswapV2Router::new(chain.router().unwrap(), client.clone());
+        let path = vec![router.weth().call().await.unwrap(), request.receive];
+        let min_out = amount_out_min(&router, parse_units(1, 18).unwrap().into(), path, 0, 100)
+            .await
+            .unwrap();
+        assert!(!min_out.is_zero());
+
+        let sent = send_buy_tx(&chain, &request, wallet).await.unwrap();
+        let receipt = wait_for_receipt(&chain, &sent)
+            .await
+            .unwrap()
+            .expect("the swap is mined");
+        assert_eq!(receipt.status, Some(1u64.into()));
+        assert!(amount_received(&sent, &receipt) >= min_out);
+    }
+}
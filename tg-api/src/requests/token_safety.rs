@@ -0,0 +1,493 @@
+use crate::chains::ChainConfig;
+use crate::requests::quote::to_f64;
+use crate::requests::swap::{Erc20, UniswapV2Router};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::{Middleware, RawCall},
+    types::{
+        spoof, transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, Bytes,
+        TransactionRequest, U256,
+    },
+    utils::{hex, id},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Runtime code of the contract the check runs in, placed at [CHECKER_ADDRESS] with a state
+/// override. Called with the words `router, weth, token, deadline` and the native coin to buy with:
+///
+/// 1. buys the token with `swapExactETHForTokens(0, [weth, token], this, deadline)`
+/// 2. reads its `balanceOf(this)`
+/// 3. `approve(router, balance)`
+/// 4. quotes selling the balance with `getAmountsOut(balance, [token, weth])`
+/// 5. sells it with `swapExactTokensForETHSupportingFeeOnTransferTokens(balance, 0, [token, weth], this, deadline)`
+///
+/// and returns the words `buy ok, tokens received, approve ok, quote ok, quoted coin out, sell ok,
+/// coin received`. A failing step doesn't stop the ones after it, its flag is just 0. Calls without
+/// calldata stop right away, they are the router paying the sell out.
+///
+/// Assembled from `contracts/token_checker.etk` with `eas tg-api/contracts/token_checker.etk`
+/// ([etk](https://github.com/quilt/etk)), a test keeps the two in sync
+const CHECKER_CODE: &str = "36600557005b637ff36ab560e01b610100526080610124523061014452606035610164\
+    526002610184526020356101a4526040356101c4526000600060e4610100346000355af16000526370a0823160e0\
+    1b61020052306102045260206020602461020060006040355af15063095ea7b360e01b6103005260003561030452\
+    6020516103245260006000604461030060006040355af160405263d06ca61f60e01b610400526020516104045260\
+    40610424526002610444526040356104645260203561048452608061070060a461040060006000355af160605261\
+    07605160805263791ac94760e01b610500526020516105045260a06105445230610564526060356105845260026105\
+    a4526040356105c4526020356105e4526000600061010461050060006000355af160a0524760c05260e06000f3";
+
+/// Address the checker is simulated at, no token treats it differently from any other buyer
+const CHECKER_ADDRESS: Address = Address::repeat_byte(0x4b);
+
+/// Address the simulated buy is paid from, funded with a state override
+const CHECK_SENDER: Address = Address::repeat_byte(0x5c);
+
+/// Native coin spent on the simulated buy, in wei (0.01 coin)
+const CHECK_BUY_AMOUNT: u64 = 10_000_000_000_000_000;
+
+/// Taxes from this percentage up make a token medium risk
+const MEDIUM_TAX_PERCENT: f64 = 3.0;
+
+/// Taxes from this percentage up make a token high risk
+const HIGH_TAX_PERCENT: f64 = 10.0;
+
+/// Max tx or max wallet limits below this share of the supply make a token medium risk
+const LOW_LIMIT_PERCENT: f64 = 1.0;
+
+/// Getters tokens commonly expose their max tx limit with
+const MAX_TX_GETTERS: [&str; 4] = [
+    "_maxTxAmount()",
+    "maxTxAmount()",
+    "maxTransactionAmount()",
+    "maxBuyAmount()",
+];
+
+/// Getters tokens commonly expose their max wallet limit with
+const MAX_WALLET_GETTERS: [&str; 4] = [
+    "_maxWalletSize()",
+    "maxWallet()",
+    "maxWalletSize()",
+    "_maxWalletToken()",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl RiskLevel {
+    pub(crate) fn badge(&self) -> &'static str {
+        match self {
+            Self::Low => "🟢 Low Risk",
+            Self::Medium => "🟡 Medium Risk",
+            Self::High => "🔴 High Risk",
+        }
+    }
+}
+
+/// Outcome of the buy and sell simulated on a token before buying it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TokenRisk {
+    pub(crate) token: Address,
+    pub(crate) buy_blocked: bool,
+    pub(crate) sell_blocked: bool,
+    /// Share of the bought tokens kept by the token, in percent
+    pub(crate) buy_tax: f64,
+    /// Share of the sold tokens kept by the token, in percent, `None` when it couldn't be measured
+    pub(crate) sell_tax: Option<f64>,
+    /// Max tx limit in percent of the supply, when the token exposes one
+    pub(crate) max_tx_percent: Option<f64>,
+    /// Max wallet limit in percent of the supply, when the token exposes one
+    pub(crate) max_wallet_percent: Option<f64>,
+}
+
+impl TokenRisk {
+    pub(crate) fn level(&self) -> RiskLevel {
+        let max_tax = self.buy_tax.max(self.sell_tax.unwrap_or_default());
+        let min_limit = [self.max_tx_percent, self.max_wallet_percent]
+            .into_iter()
+            .flatten()
+            .fold(f64::INFINITY, f64::min);
+        if self.buy_blocked || self.sell_blocked || max_tax >= HIGH_TAX_PERCENT {
+            RiskLevel::High
+        } else if max_tax >= MEDIUM_TAX_PERCENT || min_limit < LOW_LIMIT_PERCENT {
+            RiskLevel::Medium
+        } else {
+            RiskLevel::Low
+        }
+    }
+
+    /// Risk badge with the main finding, shown on the buy menu
+    pub(crate) fn badge(&self) -> String {
+        let finding = if self.buy_blocked {
+            "buy blocked".to_string()
+        } else if self.sell_blocked {
+            "sell blocked".to_string()
+        } else {
+            format!(
+                "tax {:.1}% / {:.1}%",
+                self.buy_tax,
+                self.sell_tax.unwrap_or_default()
+            )
+        };
+        format!("{} · {}", self.level().badge(), finding)
+    }
+
+    /// Every finding of the check, one per line
+    pub(crate) fn details(&self) -> String {
+        let mut lines = vec![self.level().badge().to_string()];
+        lines.push(match self.buy_blocked {
+            true => "Buying is blocked".to_string(),
+            false => format!("Buy tax: {:.1}%", self.buy_tax),
+        });
+        if self.sell_blocked {
+            lines.push("Selling is blocked, this looks like a honeypot".to_string());
+        } else if let Some(sell_tax) = self.sell_tax {
+            lines.push(format!("Sell tax: {:.1}%", sell_tax));
+        }
+        if let Some(percent) = self.max_tx_percent {
+            lines.push(format!("Max tx: {:.2}% of supply", percent));
+        }
+        if let Some(percent) = self.max_wallet_percent {
+            lines.push(format!("Max wallet: {:.2}% of supply", percent));
+        }
+        lines.join("\n")
+    }
+}
+
+fn word(words: &[Token], index: usize) -> U256 {
+    match words.get(index) {
+        Some(Token::Uint(value)) => *value,
+        _ => U256::zero(),
+    }
+}
+
+/// Share of `expected` that didn't arrive, in percent
+fn tax_percent(received: U256, expected: U256) -> f64 {
+    if expected.is_zero() {
+        return 0.0;
+    }
+    ((1.0 - to_f64(received) / to_f64(expected)) * 100.0).clamp(0.0, 100.0)
+}
+
+/// First of the getters the token answers, in percent of its supply
+async fn limit_percent<M: Middleware>(
+    client: &M,
+    token: Address,
+    getters: &[&str],
+    total_supply: U256,
+) -> Option<f64> {
+    if total_supply.is_zero() {
+        return None;
+    }
+    for getter in getters {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(token)
+            .data(Bytes::from(id(getter).to_vec()))
+            .into();
+        let Ok(output) = client.call(&tx, None).await else {
+            continue;
+        };
+        if output.len() != 32 {
+            continue;
+        }
+        let percent = to_f64(U256::from_big_endian(&output)) / to_f64(total_supply) * 100.0;
+        // zero or at least the whole supply means the limit is off
+        if percent > 0.0 && percent < 100.0 {
+            return Some(percent);
+        }
+    }
+    None
+}
+
+/// Outcome of the simulated buy and sell of a token
+struct RoundTrip {
+    buy_blocked: bool,
+    sell_blocked: bool,
+    buy_tax: f64,
+    sell_tax: Option<f64>,
+}
+
+/// Simulates buying a little of the token with the native coin and selling it right back in one
+/// `eth_call`, the checker contract and the funds it needs are injected with a state override
+async fn round_trip(chain: &ChainConfig, token: Address) -> anyhow::Result<RoundTrip> {
+    let provider = chain.provider().await?;
+    let client = Arc::new(provider.clone());
+    let router_address = chain.router()?;
+    let router = UniswapV2Router::new(router_address, client);
+    let weth = router.weth().call().await?;
+    let buy_amount = U256::from(CHECK_BUY_AMOUNT);
+    let expected_tokens = router
+        .get_amounts_out(buy_amount, vec![weth, token])
+        .call()
+        .await
+        .map_err(|_| anyhow::anyhow!("No {} pool found for this token", chain.name))?
+        .last()
+        .copied()
+        .unwrap_or_default();
+
+    let deadline = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + 60;
+    let input = abi::encode(&[
+        Token::Address(router_address),
+        Token::Address(weth),
+        Token::Address(token),
+        Token::Uint(deadline.into()),
+    ]);
+    let tx: TypedTransaction = TransactionRequest::new()
+        .from(CHECK_SENDER)
+        .to(CHECKER_ADDRESS)
+        .value(buy_amount)
+        .data(input)
+        .into();
+    let mut state = spoof::state();
+    state.account(CHECK_SENDER).balance(buy_amount);
+    state
+        .account(CHECKER_ADDRESS)
+        .code(Bytes::from(hex::decode(CHECKER_CODE)?));
+    let output = provider
+        .call_raw(&tx)
+        .block(BlockId::Number(BlockNumber::Latest))
+        .state(&state)
+        .await
+        .map_err(|err| anyhow::anyhow!("Token check unavailable: {}", err))?;
+    let words = abi::decode(&vec![ParamType::Uint(256); 7], &output)?;
+
+    let buy_ok = !word(&words, 0).is_zero();
+    let tokens_received = word(&words, 1);
+    let approve_ok = !word(&words, 2).is_zero();
+    let quote_ok = !word(&words, 3).is_zero();
+    let quoted_out = word(&words, 4);
+    let sell_ok = !word(&words, 5).is_zero();
+    let coin_received = word(&words, 6);
+
+    let buy_blocked = !buy_ok || tokens_received.is_zero();
+    let sold = approve_ok && sell_ok && !coin_received.is_zero();
+    let sell_blocked = !buy_blocked && !sold;
+    Ok(RoundTrip {
+        buy_blocked,
+        sell_blocked,
+        buy_tax: match buy_blocked {
+            true => 0.0,
+            false => tax_percent(tokens_received, expected_tokens),
+        },
+        sell_tax: match sell_blocked || buy_blocked || !quote_ok {
+            true => None,
+            false => Some(tax_percent(coin_received, quoted_out)),
+        },
+    })
+}
+
+/// Checks the token for a honeypot, taxes and limits with a simulated buy and sell
+pub(crate) async fn check_token(chain: &ChainConfig, token: Address) -> anyhow::Result<TokenRisk> {
+    let round_trip = round_trip(chain, token).await?;
+    let client = chain.provider().await?;
+    let total_supply = Erc20::new(token, Arc::new(client.clone()))
+        .total_supply()
+        .call()
+        .await
+        .unwrap_or_default();
+
+    Ok(TokenRisk {
+        token,
+        buy_blocked: round_trip.buy_blocked,
+        sell_blocked: round_trip.sell_blocked,
+        buy_tax: round_trip.buy_tax,
+        sell_tax: round_trip.sell_tax,
+        max_tx_percent: limit_percent(&client, token, &MAX_TX_GETTERS, total_supply).await,
+        max_wallet_percent: limit_percent(&client, token, &MAX_WALLET_GETTERS, total_supply).await,
+    })
+}
+
+/// Buy and sell taxes the token takes on transfer, in basis points, measured like [check_token].
+/// A sell tax that couldn't be measured is 0, the swap's own simulation still catches a blocked sell
+pub(crate) async fn transfer_tax_bps(
+    chain: &ChainConfig,
+    token: Address,
+) -> anyhow::Result<(u32, u32)> {
+    let round_trip = round_trip(chain, token).await?;
+    let bps = |percent: f64| (percent * 100.0).round() as u32;
+    Ok((
+        bps(round_trip.buy_tax),
+        bps(round_trip.sell_tax.unwrap_or_default()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{Http, Provider};
+    use ethers::utils::{parse_ether, Anvil};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Source of [CHECKER_CODE]
+    const CHECKER_SOURCE: &str = include_str!("../../contracts/token_checker.etk");
+
+    /// Tether USD on Ethereum mainnet, its owner can blacklist holders and turn a transfer fee on
+    const USDT: &str = "0xdAC17F958D2ee523a2206206994597C13D831ec7";
+
+    /// Assembles the subset of etk the checker is written in: one instruction per line, `#`
+    /// comments and `label:` lines marking the jump targets
+    fn assemble(source: &str) -> Vec<u8> {
+        let opcodes = HashMap::from([
+            ("stop", 0x00),
+            ("shl", 0x1b),
+            ("address", 0x30),
+            ("callvalue", 0x34),
+            ("calldataload", 0x35),
+            ("calldatasize", 0x36),
+            ("selfbalance", 0x47),
+            ("pop", 0x50),
+            ("mload", 0x51),
+            ("mstore", 0x52),
+            ("jumpi", 0x57),
+            ("gas", 0x5a),
+            ("jumpdest", 0x5b),
+            ("call", 0xf1),
+            ("return", 0xf3),
+        ]);
+        let lines: Vec<&str> = source
+            .lines()
+            .map(|line| line.split('#').next().unwrap().trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let mut labels = HashMap::new();
+        let mut offset = 0;
+        for line in &lines {
+            match line.strip_suffix(':') {
+                Some(label) => {
+                    labels.insert(label, offset);
+                }
+                None => {
+                    offset += 1 + line.strip_prefix("push").map_or(0, |size| {
+                        size.split_whitespace().next().unwrap().parse().unwrap()
+                    })
+                }
+            }
+        }
+
+        let mut code = Vec::new();
+        for line in lines.iter().filter(|line| !line.ends_with(':')) {
+            let mut parts = line.split_whitespace();
+            let mnemonic = parts.next().unwrap();
+            let Some(size) = mnemonic.strip_prefix("push") else {
+                code.push(opcodes[mnemonic]);
+                continue;
+            };
+            let size: usize = size.parse().unwrap();
+            let argument = parts.next().unwrap();
+            let value = match argument.strip_prefix("0x") {
+                Some(digits) => u64::from_str_radix(digits, 16).unwrap(),
+                None => labels[argument],
+            };
+            code.push(0x5f + size as u8);
+            code.extend_from_slice(&value.to_be_bytes()[8 - size..]);
+        }
+        code
+    }
+
+    #[test]
+    fn token_checker_matches_its_source() {
+        assert_eq!(
+            hex::encode(assemble(CHECKER_SOURCE)),
+            CHECKER_CODE,
+            "rebuild CHECKER_CODE from contracts/token_checker.etk"
+        );
+    }
+
+    /// Mainnet chain entry pointing at the fork, checked against the Uniswap V2 router
+    fn fork_chain(endpoint: String) -> ChainConfig {
+        serde_json::from_value(json!({
+            "chain_id": 1,
+            "name": "Ethereum fork",
+            "rpc_urls": [endpoint],
+            "native_symbol": "ETH",
+            "routers": ["0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"],
+            "explorer_url": "https://etherscan.io"
+        }))
+        .unwrap()
+    }
+
+    /// Sends `data` to USDT from its owner, impersonated on the fork
+    async fn call_as_usdt_owner(provider: &Provider<Http>, data: Vec<u8>) {
+        let usdt: Address = USDT.parse().unwrap();
+        let owner_call: TypedTransaction = TransactionRequest::new()
+            .to(usdt)
+            .data(Bytes::from(id("owner()").to_vec()))
+            .into();
+        let owner = Address::from_slice(&provider.call(&owner_call, None).await.unwrap()[12..]);
+        provider
+            .request::<_, ()>("anvil_impersonateAccount", [owner])
+            .await
+            .unwrap();
+        provider
+            .request::<_, ()>("anvil_setBalance", (owner, parse_ether(1).unwrap()))
+            .await
+            .unwrap();
+        let receipt = provider
+            .send_transaction(
+                TransactionRequest::new().from(owner).to(usdt).data(data),
+                None,
+            )
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .expect("the owner call is mined");
+        assert_eq!(receipt.status, Some(1u64.into()));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs anvil and a mainnet RPC in ANVIL_FORK_URL"]
+    async fn flags_a_token_that_blocks_selling() {
+        let fork_url = std::env::var("ANVIL_FORK_URL").expect("ANVIL_FORK_URL is not set");
+        let anvil = Anvil::new().fork(fork_url).spawn();
+        let chain = fork_chain(anvil.endpoint());
+        let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+        let usdt: Address = USDT.parse().unwrap();
+
+        // a blacklisted holder can receive USDT but not move it, the sell's transferFrom fails
+        let mut data = id("addBlackList(address)").to_vec();
+        data.extend(abi::encode(&[Token::Address(CHECKER_ADDRESS)]));
+        call_as_usdt_owner(&provider, data).await;
+
+        let risk = check_token(&chain, usdt).await.unwrap();
+        assert!(!risk.buy_blocked);
+        assert!(risk.sell_blocked);
+        assert_eq!(risk.sell_tax, None);
+        assert_eq!(risk.level(), RiskLevel::High);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs anvil and a mainnet RPC in ANVIL_FORK_URL"]
+    async fn measures_the_fee_of_a_fee_on_transfer_token() {
+        let fork_url = std::env::var("ANVIL_FORK_URL").expect("ANVIL_FORK_URL is not set");
+        let anvil = Anvil::new().fork(fork_url).spawn();
+        let chain = fork_chain(anvil.endpoint());
+        let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+        let usdt: Address = USDT.parse().unwrap();
+
+        let clean = check_token(&chain, usdt).await.unwrap();
+        assert!(!clean.buy_blocked && !clean.sell_blocked);
+        assert!(clean.buy_tax < 0.1);
+
+        // 0.19% of every transfer, the most USDT allows, capped at 49 USDT
+        let mut data = id("setParams(uint256,uint256)").to_vec();
+        data.extend(abi::encode(&[
+            Token::Uint(19.into()),
+            Token::Uint(49.into()),
+        ]));
+        call_as_usdt_owner(&provider, data).await;
+
+        let risk = check_token(&chain, usdt).await.unwrap();
+        assert!(!risk.buy_blocked && !risk.sell_blocked);
+        assert!(risk.buy_tax > 0.1 && risk.buy_tax < 1.0, "{}", risk.buy_tax);
+        let sell_tax = risk.sell_tax.expect("the sell is quoted");
+        assert!(sell_tax > 0.1 && sell_tax < 1.0, "{}", sell_tax);
+        assert_eq!(risk.level(), RiskLevel::Low);
+    }
+}
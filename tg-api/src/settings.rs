@@ -0,0 +1,310 @@
+use crate::database::GLOBAL_DATABASE;
+use lazy_static::lazy_static;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use teloxide::types::UserId;
+
+lazy_static! {
+    /// Used to read and change the defaults of each user's orders
+    pub(crate) static ref GLOBAL_SETTINGS_STORE: SettingsStore = SettingsStore;
+}
+
+/// Slippage tolerances offered on /settings and cycled through on the buy and sell menus, in basis points
+pub(crate) const SLIPPAGE_PRESETS_BPS: [u32; 5] = [50, 100, 300, 500, 1000];
+
+pub(crate) const DEFAULT_SLIPPAGE_BPS: u32 = 100;
+
+/// Highest slippage tolerance a user can enter, in basis points
+pub(crate) const MAX_SLIPPAGE_BPS: u32 = 5000;
+
+/// Transaction deadlines offered on /settings and cycled through on the buy and sell menus, in seconds
+pub(crate) const DEADLINE_PRESETS_SECS: [u64; 4] = [5 * 60, 10 * 60, 20 * 60, 60 * 60];
+
+pub(crate) const DEFAULT_DEADLINE_SECS: u64 = 20 * 60;
+
+/// Max fee caps offered on /settings, in gwei
+pub(crate) const MAX_FEE_PRESETS_GWEI: [u64; 4] = [20, 50, 100, 300];
+
+/// How fast a trade should be mined, sets the fees its transactions pay
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum GasPreset {
+    #[default]
+    Standard,
+    Fast,
+    Turbo,
+}
+
+impl GasPreset {
+    pub(crate) const ALL: [GasPreset; 3] = [Self::Standard, Self::Fast, Self::Turbo];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Standard => "Standard",
+            Self::Fast => "Fast",
+            Self::Turbo => "Turbo",
+        }
+    }
+
+    /// Name stored in the database and in callback data
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Fast => "fast",
+            Self::Turbo => "turbo",
+        }
+    }
+
+    pub(crate) fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.key() == key)
+    }
+
+    /// Multiple of the base fee the max fee leaves room for, in percent
+    pub(crate) fn base_fee_percent(self) -> u64 {
+        match self {
+            Self::Standard => 125,
+            Self::Fast => 150,
+            Self::Turbo => 200,
+        }
+    }
+
+    /// Priority tip paid to the block builder, in gwei
+    pub(crate) fn priority_fee_gwei(self) -> u64 {
+        match self {
+            Self::Standard => 1,
+            Self::Fast => 2,
+            Self::Turbo => 5,
+        }
+    }
+
+    /// Multiple of the node's gas price paid on chains without EIP-1559, in percent
+    pub(crate) fn legacy_percent(self) -> u64 {
+        match self {
+            Self::Standard => 100,
+            Self::Fast => 125,
+            Self::Turbo => 150,
+        }
+    }
+
+    /// The following preset, wrapping around to the first one
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Self::Standard => Self::Fast,
+            Self::Fast => Self::Turbo,
+            Self::Turbo => Self::Standard,
+        }
+    }
+}
+
+/// Defaults every new buy and sell order of a user starts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UserSettings {
+    /// Least share of the quoted output accepted, in basis points below it
+    pub(crate) slippage_bps: u32,
+    /// Seconds a swap stays valid after it has been built
+    pub(crate) deadline_secs: u64,
+    pub(crate) gas_preset: GasPreset,
+    /// Highest fee per gas a trade may pay, in gwei, trades are blocked while gas is above it
+    pub(crate) max_fee_gwei: Option<u64>,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            slippage_bps: DEFAULT_SLIPPAGE_BPS,
+            deadline_secs: DEFAULT_DEADLINE_SECS,
+            gas_preset: GasPreset::default(),
+            max_fee_gwei: None,
+        }
+    }
+}
+
+/// Formats a slippage tolerance as a percentage, e.g. "0.5%"
+pub(crate) fn format_slippage(slippage_bps: u32) -> String {
+    format!("{}%", slippage_bps as f64 / 100.0)
+}
+
+/// Formats a deadline in minutes, e.g. "20m"
+pub(crate) fn format_deadline(deadline_secs: u64) -> String {
+    format!("{}m", deadline_secs / 60)
+}
+
+/// Formats a max fee cap, e.g. "50 gwei"
+pub(crate) fn format_max_fee(max_fee_gwei: Option<u64>) -> String {
+    match max_fee_gwei {
+        Some(max_fee_gwei) => format!("{} gwei", max_fee_gwei),
+        None => "No cap".to_string(),
+    }
+}
+
+/// Parses a slippage tolerance entered in percent, e.g. "0.5" or "0.5%", into basis points
+pub(crate) fn parse_slippage(text: &str) -> anyhow::Result<u32> {
+    let percent = match text.trim().trim_end_matches('%').trim().parse::<f64>() {
+        Ok(percent) if percent.is_finite() => percent,
+        _ => return Err(anyhow::anyhow!("Enter the slippage as a number, e.g. 0.5")),
+    };
+    let slippage_bps = (percent * 100.0).round().clamp(0.0, u32::MAX as f64) as u32;
+    check_slippage(slippage_bps)?;
+    Ok(slippage_bps)
+}
+
+fn check_slippage(slippage_bps: u32) -> anyhow::Result<()> {
+    if slippage_bps == 0 || slippage_bps > MAX_SLIPPAGE_BPS {
+        return Err(anyhow::anyhow!(
+            "The slippage must be above 0% and at most {}",
+            format_slippage(MAX_SLIPPAGE_BPS)
+        ));
+    }
+    Ok(())
+}
+
+fn check_deadline(deadline_secs: u64) -> anyhow::Result<()> {
+    if !DEADLINE_PRESETS_SECS.contains(&deadline_secs) {
+        return Err(anyhow::anyhow!("Unsupported deadline"));
+    }
+    Ok(())
+}
+
+/// Gets the preset following the current value, wrapping around to the first one
+pub(crate) fn next_preset<T: Copy + PartialOrd>(presets: &[T], current: T) -> T {
+    presets
+        .iter()
+        .copied()
+        .find(|preset| *preset > current)
+        .unwrap_or(presets[0])
+}
+
+/// Keeps the per-user settings in the `user_settings` table, unset values fall back to the defaults
+pub(crate) struct SettingsStore;
+
+impl SettingsStore {
+    pub(crate) fn get(&self, user_id: UserId) -> anyhow::Result<UserSettings> {
+        type Row = (Option<u32>, Option<u64>, Option<String>, Option<u64>);
+        let settings: Option<Row> = GLOBAL_DATABASE
+            .connection()
+            .query_row(
+                "SELECT slippage_bps, deadline_secs, gas_preset, max_fee_gwei
+                 FROM user_settings WHERE user_id = ?1",
+                params![user_id.0],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        let (slippage_bps, deadline_secs, gas_preset, max_fee_gwei) = settings.unwrap_or_default();
+        Ok(UserSettings {
+            slippage_bps: slippage_bps.unwrap_or(DEFAULT_SLIPPAGE_BPS),
+            deadline_secs: deadline_secs.unwrap_or(DEFAULT_DEADLINE_SECS),
+            gas_preset: gas_preset
+                .as_deref()
+                .and_then(GasPreset::from_key)
+                .unwrap_or_default(),
+            max_fee_gwei,
+        })
+    }
+
+    pub(crate) fn set_slippage(&self, user_id: UserId, slippage_bps: u32) -> anyhow::Result<()> {
+        check_slippage(slippage_bps)?;
+        GLOBAL_DATABASE.connection().execute(
+            "INSERT INTO user_settings (user_id, slippage_bps) VALUES (?1, ?2)
+             ON CONFLICT (user_id) DO UPDATE SET slippage_bps = excluded.slippage_bps",
+            params![user_id.0, slippage_bps],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn set_deadline(&self, user_id: UserId, deadline_secs: u64) -> anyhow::Result<()> {
+        check_deadline(deadline_secs)?;
+        GLOBAL_DATABASE.connection().execute(
+            "INSERT INTO user_settings (user_id, deadline_secs) VALUES (?1, ?2)
+             ON CONFLICT (user_id) DO UPDATE SET deadline_secs = excluded.deadline_secs",
+            params![user_id.0, deadline_secs],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn set_gas_preset(
+        &self,
+        user_id: UserId,
+        gas_preset: GasPreset,
+    ) -> anyhow::Result<()> {
+        GLOBAL_DATABASE.connection().execute(
+            "INSERT INTO user_settings (user_id, gas_preset) VALUES (?1, ?2)
+             ON CONFLICT (user_id) DO UPDATE SET gas_preset = excluded.gas_preset",
+            params![user_id.0, gas_preset.key()],
+        )?;
+        Ok(())
+    }
+
+    /// `None` removes the cap
+    pub(crate) fn set_max_fee(
+        &self,
+        user_id: UserId,
+        max_fee_gwei: Option<u64>,
+    ) -> anyhow::Result<()> {
+        if max_fee_gwei == Some(0) {
+            return Err(anyhow::anyhow!("The max fee must be above 0 gwei"));
+        }
+        GLOBAL_DATABASE.connection().execute(
+            "INSERT INTO user_settings (user_id, max_fee_gwei) VALUES (?1, ?2)
+             ON CONFLICT (user_id) DO UPDATE SET max_fee_gwei = excluded.max_fee_gwei",
+            params![user_id.0, max_fee_gwei],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests::quote::minimum_out;
+    use ethers::types::U256;
+
+    #[test]
+    fn parses_slippage_in_percent() {
+        assert_eq!(parse_slippage("0.5").unwrap(), 50);
+        assert_eq!(parse_slippage(" 1.25 % ").unwrap(), 125);
+        assert_eq!(parse_slippage("0.01").unwrap(), 1);
+        assert_eq!(parse_slippage("50").unwrap(), MAX_SLIPPAGE_BPS);
+    }
+
+    #[test]
+    fn rejects_slippage_out_of_bounds_or_not_a_number() {
+        for text in [
+            "0", "0.001", "50.01", "51", "-1", "-0.5", "abc", "", "NaN", "inf", "1e30",
+        ] {
+            assert!(parse_slippage(text).is_err(), "{:?} was accepted", text);
+        }
+        assert!(check_slippage(MAX_SLIPPAGE_BPS + 1).is_err());
+    }
+
+    #[test]
+    fn accepts_only_the_deadline_presets() {
+        for deadline_secs in DEADLINE_PRESETS_SECS {
+            assert!(check_deadline(deadline_secs).is_ok());
+        }
+        assert!(check_deadline(DEFAULT_DEADLINE_SECS).is_ok());
+        for deadline_secs in [0, 1, 7 * 60, 24 * 60 * 60] {
+            assert!(check_deadline(deadline_secs).is_err());
+        }
+    }
+
+    #[test]
+    fn minimum_out_takes_the_slippage_off_the_quote() {
+        let amount_out = U256::from(1_000_000u64);
+        assert_eq!(minimum_out(amount_out, 50), U256::from(995_000u64));
+        assert_eq!(
+            minimum_out(amount_out, MAX_SLIPPAGE_BPS),
+            U256::from(500_000u64)
+        );
+        assert_eq!(minimum_out(amount_out, 0), amount_out);
+        assert_eq!(minimum_out(amount_out, 20_000), U256::zero());
+        // rounds down, never asks for more than the quote minus the slippage
+        assert_eq!(minimum_out(U256::from(999u64), 100), U256::from(989u64));
+        assert!(minimum_out(U256::MAX / 10_000, 100) > U256::zero());
+    }
+
+    #[test]
+    fn cycles_through_the_presets() {
+        assert_eq!(next_preset(&SLIPPAGE_PRESETS_BPS, 50), 100);
+        assert_eq!(next_preset(&SLIPPAGE_PRESETS_BPS, 1000), 50);
+        assert_eq!(next_preset(&SLIPPAGE_PRESETS_BPS, 250), 300);
+    }
+}
@@ -0,0 +1,209 @@
+pub(crate) mod sqlite;
+
+use crate::database::GLOBAL_DATABASE;
+use crate::drafts::{BuyOrderDraft, DcaDraft, LimitOrderDraft, SellOrderDraft};
+use crate::global::Global;
+use dotenv::dotenv;
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlite::{SqliteDialogueStorage, SqliteSessionStorage};
+use std::sync::Arc;
+use teloxide::dispatching::dialogue::{ErasedStorage, InMemStorage, Storage};
+use teloxide::types::{ChatId, MessageId, UserId};
+
+/// Backend every storage below is kept in, see [StorageBackend::from_env]
+pub(crate) static GLOBAL_STORAGE_BACKEND: Global<StorageBackend> = Global::new("storage backend");
+
+lazy_static! {
+    /// Used to locate the main menu location
+    pub(crate) static ref GLOBAL_MAIN_MENU_STORAGE: MenuStorage = session_storage("main_menu");
+}
+
+lazy_static! {
+    /// Used to locate the buy menu location
+    pub(crate) static ref GLOBAL_BUY_MENU_STORAGE: MenuStorage = session_storage("buy_menu");
+}
+
+lazy_static! {
+    /// Used to locate the sell menu location
+    pub(crate) static ref GLOBAL_SELL_MENU_STORAGE: MenuStorage = session_storage("sell_menu");
+}
+
+lazy_static! {
+    /// Used to locate the limit order menu location
+    pub(crate) static ref GLOBAL_LIMIT_MENU_STORAGE: MenuStorage = session_storage("limit_menu");
+}
+
+lazy_static! {
+    /// Holds the buy order each user is editing
+    pub(crate) static ref GLOBAL_BUY_ORDER_DRAFTS: DynSessionStorage<BuyOrderDraft> =
+        session_storage("buy_order_draft");
+}
+
+lazy_static! {
+    /// Holds the sell order each user is editing
+    pub(crate) static ref GLOBAL_SELL_ORDER_DRAFTS: DynSessionStorage<SellOrderDraft> =
+        session_storage("sell_order_draft");
+}
+
+lazy_static! {
+    /// Holds the limit order each user is editing
+    pub(crate) static ref GLOBAL_LIMIT_ORDER_DRAFTS: DynSessionStorage<LimitOrderDraft> =
+        session_storage("limit_order_draft");
+}
+
+lazy_static! {
+    /// Used to locate the DCA menu location
+    pub(crate) static ref GLOBAL_DCA_MENU_STORAGE: MenuStorage = session_storage("dca_menu");
+}
+
+lazy_static! {
+    /// Holds the DCA schedule each user is editing
+    pub(crate) static ref GLOBAL_DCA_DRAFTS: DynSessionStorage<DcaDraft> =
+        session_storage("dca_draft");
+}
+
+/// Where dialogues, menu locations and order drafts are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StorageBackend {
+    /// Kept in memory, lost on restart
+    Memory,
+    /// Kept in the bot's database, survives a restart
+    Sqlite,
+}
+
+impl StorageBackend {
+    /// Reads `STORAGE_BACKEND`, either `memory` or `sqlite`, defaults to `sqlite`
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        dotenv().ok();
+        match std::env::var("STORAGE_BACKEND").as_deref() {
+            Ok("sqlite") | Err(_) => Ok(Self::Sqlite),
+            Ok("memory") => Ok(Self::Memory),
+            Ok(backend) => Err(anyhow::anyhow!("Unknown storage backend: {}", backend)),
+        }
+    }
+}
+
+/// Creates the dialogue storage handed to the dispatcher, on the configured backend
+pub(crate) fn dialogue_storage<D>() -> Arc<ErasedStorage<D>>
+where
+    D: Clone + Serialize + DeserializeOwned + Send + 'static,
+{
+    match *GLOBAL_STORAGE_BACKEND {
+        StorageBackend::Memory => InMemStorage::<D>::new().erase(),
+        StorageBackend::Sqlite => Arc::new(SqliteDialogueStorage::new(&GLOBAL_DATABASE)).erase(),
+    }
+}
+
+/// Creates a session storage on the configured backend, `name` tells the storages apart in the database
+fn session_storage<T>(name: &'static str) -> DynSessionStorage<T>
+where
+    T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    match *GLOBAL_STORAGE_BACKEND {
+        StorageBackend::Memory => Box::new(SessionStorage::new()),
+        StorageBackend::Sqlite => Box::new(SqliteSessionStorage::new(&GLOBAL_DATABASE, name)),
+    }
+}
+
+/// Identifies the session of one user in one chat, a user talking to the bot
+/// from several chats gets a separate session in each of them
+pub(crate) type SessionKey = (ChatId, UserId);
+
+/// Keeps one value per session
+pub(crate) trait TgMessageStorage<T> {
+    fn insert(&self, key: SessionKey, value: T);
+    fn get(&self, key: SessionKey) -> Option<T>;
+    fn remove(&self, key: SessionKey) -> Option<T>;
+    fn delete_all(&self);
+    /// Applies the change to the session's value and returns the updated value, `None` if there is no value
+    fn update(&self, key: SessionKey, change: Box<dyn FnOnce(&mut T) + '_>) -> Option<T>;
+}
+
+/// Session storage on the backend picked at startup
+pub(crate) type DynSessionStorage<T> = Box<dyn TgMessageStorage<T> + Send + Sync>;
+
+/// Location of a menu message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TgMessage {
+    pub(crate) chat_id: ChatId,
+    pub(crate) message_id: MessageId,
+}
+
+/// Locates the menu message of each session
+pub(crate) type MenuStorage = DynSessionStorage<TgMessage>;
+
+/// Keeps one value per session in memory
+#[derive(Debug, Default)]
+pub(crate) struct SessionStorage<T> {
+    storage: Arc<RwLock<HashMap<SessionKey, T>>>,
+}
+
+impl<T> SessionStorage<T> {
+    pub(crate) fn new() -> Self {
+        SessionStorage {
+            storage: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+impl<T: Clone> TgMessageStorage<T> for SessionStorage<T> {
+    fn insert(&self, key: SessionKey, value: T) {
+        let mut storage = self.storage.write();
+        storage.insert(key, value);
+    }
+
+    fn get(&self, key: SessionKey) -> Option<T> {
+        let storage = self.storage.read();
+        storage.get(&key).cloned()
+    }
+
+    fn remove(&self, key: SessionKey) -> Option<T> {
+        let mut storage = self.storage.write();
+        storage.remove(&key)
+    }
+
+    fn delete_all(&self) {
+        let mut storage = self.storage.write();
+        storage.clear();
+    }
+
+    fn update(&self, key: SessionKey, change: Box<dyn FnOnce(&mut T) + '_>) -> Option<T> {
+        let mut storage = self.storage.write();
+        let value = storage.get_mut(&key)?;
+        change(value);
+        Some(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_in_one_chat_keep_separate_sessions() {
+        let storage = SessionStorage::<u32>::new();
+        let group = ChatId(-100);
+        let alice = (group, UserId(1));
+        let bob = (group, UserId(2));
+
+        storage.insert(alice, 10);
+        storage.insert(bob, 20);
+        assert_eq!(storage.get(alice), Some(10));
+        assert_eq!(storage.get(bob), Some(20));
+        assert_eq!(storage.get((ChatId(1), UserId(1))), None);
+
+        assert_eq!(
+            storage.update(bob, Box::new(|amount| *amount += 1)),
+            Some(21)
+        );
+        assert_eq!(storage.get(alice), Some(10));
+
+        assert_eq!(storage.remove(alice), Some(10));
+        assert_eq!(storage.get(alice), None);
+        assert_eq!(storage.get(bob), Some(21));
+    }
+}
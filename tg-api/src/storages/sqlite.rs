@@ -0,0 +1,314 @@
+use crate::database::Database;
+use crate::storages::{SessionKey, TgMessageStorage};
+use futures::future::BoxFuture;
+use rusqlite::{params, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+
+/// Errors of the SQLite storages
+#[derive(Debug)]
+pub(crate) enum SqliteStorageError {
+    Database(rusqlite::Error),
+    Serde(serde_json::Error),
+    /// Removing a dialogue that does not exist, mirrors teloxide's in-memory storage
+    DialogueNotFound,
+}
+
+impl fmt::Display for SqliteStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(err) => write!(f, "Database error: {}", err),
+            Self::Serde(err) => write!(f, "Serialization error: {}", err),
+            Self::DialogueNotFound => write!(f, "Dialogue not found"),
+        }
+    }
+}
+
+impl std::error::Error for SqliteStorageError {}
+
+impl From<rusqlite::Error> for SqliteStorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Database(err)
+    }
+}
+
+impl From<serde_json::Error> for SqliteStorageError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Serde(err)
+    }
+}
+
+/// Dialogue storage in the `dialogues` table, states are stored as JSON
+pub(crate) struct SqliteDialogueStorage<D> {
+    database: &'static Database,
+    _state: PhantomData<fn() -> D>,
+}
+
+impl<D> SqliteDialogueStorage<D> {
+    pub(crate) fn new(database: &'static Database) -> Self {
+        Self {
+            database,
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<D> Storage<D> for SqliteDialogueStorage<D>
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = SqliteStorageError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let removed = self.database.connection().execute(
+                "DELETE FROM dialogues WHERE chat_id = ?1",
+                params![chat_id.0],
+            )?;
+            match removed {
+                0 => Err(SqliteStorageError::DialogueNotFound),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let state = serde_json::to_string(&dialogue)?;
+            self.database.connection().execute(
+                "INSERT INTO dialogues (chat_id, state) VALUES (?1, ?2)
+                 ON CONFLICT (chat_id) DO UPDATE SET state = excluded.state",
+                params![chat_id.0, state],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let state: Option<String> = self
+                .database
+                .connection()
+                .query_row(
+                    "SELECT state FROM dialogues WHERE chat_id = ?1",
+                    params![chat_id.0],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(state
+                .map(|state| serde_json::from_str(&state))
+                .transpose()?)
+        })
+    }
+}
+
+/// Session storage in the `sessions` table, values are stored as JSON
+///
+/// The trait can't report errors, failures are logged and read as a missing value
+pub(crate) struct SqliteSessionStorage<T> {
+    database: &'static Database,
+    /// Tells the storages sharing the table apart
+    name: &'static str,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> SqliteSessionStorage<T> {
+    pub(crate) fn new(database: &'static Database, name: &'static str) -> Self {
+        Self {
+            database,
+            name,
+            _value: PhantomData,
+        }
+    }
+
+    fn try_get(
+        &self,
+        connection: &rusqlite::Connection,
+        (chat_id, user_id): SessionKey,
+    ) -> Result<Option<T>, SqliteStorageError> {
+        let value: Option<String> = connection
+            .query_row(
+                "SELECT value FROM sessions WHERE storage = ?1 AND chat_id = ?2 AND user_id = ?3",
+                params![self.name, chat_id.0, user_id.0],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()?)
+    }
+
+    fn try_insert(
+        &self,
+        connection: &rusqlite::Connection,
+        (chat_id, user_id): SessionKey,
+        value: &T,
+    ) -> Result<(), SqliteStorageError> {
+        connection.execute(
+            "INSERT INTO sessions (storage, chat_id, user_id, value) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (storage, chat_id, user_id) DO UPDATE SET value = excluded.value",
+            params![
+                self.name,
+                chat_id.0,
+                user_id.0,
+                serde_json::to_string(value)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn log_error<V>(&self, result: Result<Option<V>, SqliteStorageError>) -> Option<V> {
+        result.unwrap_or_else(|err| {
+            log::error!("Session storage {}: {}", self.name, err);
+            None
+        })
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TgMessageStorage<T> for SqliteSessionStorage<T> {
+    fn insert(&self, key: SessionKey, value: T) {
+        let connection = self.database.connection();
+        self.log_error(self.try_insert(&connection, key, &value).map(Some));
+    }
+
+    fn get(&self, key: SessionKey) -> Option<T> {
+        let connection = self.database.connection();
+        self.log_error(self.try_get(&connection, key))
+    }
+
+    fn remove(&self, key: SessionKey) -> Option<T> {
+        let connection = self.database.connection();
+        let (chat_id, user_id) = key;
+        self.log_error(self.try_get(&connection, key).and_then(|value| {
+            connection.execute(
+                "DELETE FROM sessions WHERE storage = ?1 AND chat_id = ?2 AND user_id = ?3",
+                params![self.name, chat_id.0, user_id.0],
+            )?;
+            Ok(value)
+        }))
+    }
+
+    fn delete_all(&self) {
+        let result = self.database.connection().execute(
+            "DELETE FROM sessions WHERE storage = ?1",
+            params![self.name],
+        );
+        if let Err(err) = result {
+            log::error!("Session storage {}: {}", self.name, err);
+        }
+    }
+
+    fn update(&self, key: SessionKey, change: Box<dyn FnOnce(&mut T) + '_>) -> Option<T> {
+        // the connection stays locked so that concurrent updates don't overwrite each other
+        let connection = self.database.connection();
+        self.log_error(self.try_get(&connection, key).and_then(|value| {
+            let Some(mut value) = value else {
+                return Ok(None);
+            };
+            change(&mut value);
+            self.try_insert(&connection, key, &value)?;
+            Ok(Some(value))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use serde::Deserialize;
+    use teloxide::types::UserId;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum State {
+        Start,
+        AmountReceived { amount: f64 },
+    }
+
+    fn database() -> &'static Database {
+        let database = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        Box::leak(Box::new(database))
+    }
+
+    #[tokio::test]
+    async fn dialogues_round_trip() {
+        let storage = Arc::new(SqliteDialogueStorage::<State>::new(database()));
+        let chat_id = ChatId(-100);
+        assert_eq!(storage.clone().get_dialogue(chat_id).await.unwrap(), None);
+
+        storage
+            .clone()
+            .update_dialogue(chat_id, State::Start)
+            .await
+            .unwrap();
+        let received = State::AmountReceived { amount: 0.5 };
+        storage
+            .clone()
+            .update_dialogue(chat_id, received.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.clone().get_dialogue(chat_id).await.unwrap(),
+            Some(received)
+        );
+        assert_eq!(storage.clone().get_dialogue(ChatId(7)).await.unwrap(), None);
+
+        storage.clone().remove_dialogue(chat_id).await.unwrap();
+        assert_eq!(storage.get_dialogue(chat_id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn removing_a_missing_dialogue_fails() {
+        let storage = Arc::new(SqliteDialogueStorage::<State>::new(database()));
+        assert!(matches!(
+            storage.remove_dialogue(ChatId(1)).await,
+            Err(SqliteStorageError::DialogueNotFound)
+        ));
+    }
+
+    #[test]
+    fn sessions_are_kept_per_storage_and_user() {
+        let database = database();
+        let amounts = SqliteSessionStorage::<u32>::new(database, "amounts");
+        let others = SqliteSessionStorage::<u32>::new(database, "others");
+        let key = (ChatId(1), UserId(2));
+
+        assert_eq!(amounts.update(key, Box::new(|amount| *amount += 1)), None);
+        amounts.insert(key, 10);
+        others.insert(key, 20);
+        assert_eq!(amounts.get((ChatId(1), UserId(3))), None);
+        assert_eq!(
+            amounts.update(key, Box::new(|amount| *amount += 1)),
+            Some(11)
+        );
+        assert_eq!(amounts.get(key), Some(11));
+
+        assert_eq!(amounts.remove(key), Some(11));
+        assert_eq!(amounts.get(key), None);
+        assert_eq!(others.get(key), Some(20));
+
+        others.delete_all();
+        assert_eq!(others.get(key), None);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::chains::{ChainRegistry, GLOBAL_CHAIN_REGISTRY};
+use ethers::types::{Address, U256};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+lazy_static! {
+    /// Tokens users can pick by symbol or name, see [TokenRegistry::from_chains]
+    pub(crate) static ref GLOBAL_TOKEN_REGISTRY: TokenRegistry =
+        TokenRegistry::from_chains(&GLOBAL_CHAIN_REGISTRY);
+}
+
+lazy_static! {
+    /// ERC-20 metadata read on chain, so quotes and amount conversions don't query it again
+    pub(crate) static ref GLOBAL_TOKEN_METADATA_CACHE: TokenMetadataCache =
+        TokenMetadataCache::new(TOKEN_METADATA_CACHE_SIZE);
+}
+
+/// Most tokens kept in [GLOBAL_TOKEN_METADATA_CACHE]
+const TOKEN_METADATA_CACHE_SIZE: usize = 1024;
+
+/// One token of a Uniswap-format token list, see https://tokenlists.org
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TokenInfo {
+    pub(crate) chain_id: u64,
+    pub(crate) address: Address,
+    pub(crate) name: String,
+    pub(crate) symbol: String,
+    pub(crate) decimals: u8,
+}
+
+/// A Uniswap-format token list, only the tokens are read
+#[derive(Debug, Deserialize)]
+struct TokenList {
+    tokens: Vec<TokenInfo>,
+}
+
+fn read_token_list(path: &str) -> anyhow::Result<TokenList> {
+    let list = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&list)?)
+}
+
+/// Tokens imported from the token lists of each chain
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TokenRegistry {
+    tokens: Vec<TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// Imports the `token_lists` files of every enabled chain
+    ///
+    /// A list may cover several chains, a chain only imports the tokens listed for it.
+    /// Lists that can't be read are skipped, their tokens can still be entered by address
+    pub(crate) fn from_chains(chains: &ChainRegistry) -> Self {
+        let mut tokens = Vec::new();
+        for chain in chains.enabled() {
+            for path in &chain.token_lists {
+                let list = match read_token_list(path) {
+                    Ok(list) => list,
+                    Err(err) => {
+                        log::warn!("Skipping token list {}: {}", path, err);
+                        continue;
+                    }
+                };
+                let before = tokens.len();
+                tokens.extend(
+                    list.tokens
+                        .into_iter()
+                        .filter(|token| token.chain_id == chain.chain_id),
+                );
+                log::info!(
+                    "Imported {} {} tokens from {}",
+                    tokens.len() - before,
+                    chain.name,
+                    path
+                );
+            }
+        }
+        Self::new(tokens)
+    }
+
+    /// Keeps the first entry of a token listed more than once
+    pub(crate) fn new(tokens: Vec<TokenInfo>) -> Self {
+        let mut registry = Self::default();
+        for token in tokens {
+            if registry.get(token.chain_id, token.address).is_none() {
+                registry.tokens.push(token);
+            }
+        }
+        registry
+    }
+
+    pub(crate) fn get(&self, chain_id: u64, address: Address) -> Option<&TokenInfo> {
+        self.tokens
+            .iter()
+            .find(|token| token.chain_id == chain_id && token.address == address)
+    }
+
+    /// Finds the tokens whose symbol, or failing that whose name, is the query, ignoring case
+    pub(crate) fn search(&self, chain_id: u64, query: &str) -> Vec<&TokenInfo> {
+        let query = query.trim();
+        let on_chain = || {
+            self.tokens
+                .iter()
+                .filter(|token| token.chain_id == chain_id)
+        };
+        let by_symbol: Vec<&TokenInfo> = on_chain()
+            .filter(|token| token.symbol.eq_ignore_ascii_case(query))
+            .collect();
+        if !by_symbol.is_empty() {
+            return by_symbol;
+        }
+        on_chain()
+            .filter(|token| token.name.eq_ignore_ascii_case(query))
+            .collect()
+    }
+}
+
+/// ERC-20 metadata of a token contract, as read on chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TokenMetadata {
+    pub(crate) address: Address,
+    pub(crate) name: String,
+    pub(crate) symbol: String,
+    pub(crate) decimals: u8,
+    pub(crate) total_supply: U256,
+}
+
+/// Bounded cache of token metadata, the oldest entry is evicted once it is full
+#[derive(Debug)]
+pub(crate) struct TokenMetadataCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    tokens: HashMap<(u64, Address), TokenMetadata>,
+    /// Keys in insertion order
+    order: VecDeque<(u64, Address)>,
+}
+
+impl TokenMetadataCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    pub(crate) fn get(&self, chain_id: u64, token: Address) -> Option<TokenMetadata> {
+        self.entries.lock().tokens.get(&(chain_id, token)).cloned()
+    }
+
+    pub(crate) fn insert(&self, chain_id: u64, metadata: TokenMetadata) {
+        let key = (chain_id, metadata.address);
+        let mut entries = self.entries.lock();
+        if entries.tokens.insert(key, metadata).is_some() {
+            return;
+        }
+        entries.order.push_back(key);
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.tokens.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::utils::to_checksum;
+
+    #[test]
+    fn bundled_token_list_is_valid() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../tokenlists/default.json");
+        let list = read_token_list(path).unwrap();
+        let raw: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        for (token, raw) in list.tokens.iter().zip(raw["tokens"].as_array().unwrap()) {
+            assert_eq!(
+                raw["address"].as_str().unwrap(),
+                to_checksum(&token.address, None),
+                "{} on chain {}",
+                token.symbol,
+                token.chain_id
+            );
+        }
+
+        let registry = TokenRegistry::new(list.tokens);
+        assert_eq!(registry.search(1, "usdc").len(), 1);
+        assert_eq!(registry.search(137, "Wrapped Matic")[0].symbol, "WMATIC");
+    }
+
+    fn token(chain_id: u64, address: u64, name: &str, symbol: &str) -> TokenInfo {
+        TokenInfo {
+            chain_id,
+            address: Address::from_low_u64_be(address),
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            decimals: 18,
+        }
+    }
+
+    fn metadata(address: u64) -> TokenMetadata {
+        TokenMetadata {
+            address: Address::from_low_u64_be(address),
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            decimals: 18,
+            total_supply: U256::zero(),
+        }
+    }
+
+    #[test]
+    fn search_prefers_symbols_over_names() {
+        let registry = TokenRegistry::new(vec![
+            token(1, 1, "Maker", "MKR"),
+            token(1, 2, "mkr", "MKRX"),
+            token(1, 3, "Wrapped Ether", "WETH"),
+        ]);
+
+        let found = registry.search(1, " mkr ");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].address, Address::from_low_u64_be(1));
+        assert_eq!(registry.search(1, "wrapped ether")[0].symbol, "WETH");
+        assert!(registry.search(1, "Ether").is_empty());
+    }
+
+    #[test]
+    fn search_returns_every_match_on_the_chain() {
+        let registry = TokenRegistry::new(vec![
+            token(1, 1, "Tether USD", "USDT"),
+            token(1, 2, "Bridged Tether", "USDT"),
+            token(1, 1, "Duplicate", "USDT"),
+            token(137, 3, "Tether USD", "USDT"),
+        ]);
+
+        let found = registry.search(1, "usdt");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].name, "Tether USD");
+        assert_eq!(found[1].name, "Bridged Tether");
+        assert_eq!(registry.search(137, "USDT").len(), 1);
+        assert!(registry.search(10, "USDT").is_empty());
+    }
+
+    #[test]
+    fn cache_evicts_the_oldest_token_once_full() {
+        let cache = TokenMetadataCache::new(TOKEN_METADATA_CACHE_SIZE);
+        for address in 0..TOKEN_METADATA_CACHE_SIZE as u64 {
+            cache.insert(1, metadata(address));
+        }
+        // inserting a cached token again doesn't make room or change the order
+        cache.insert(1, metadata(0));
+        assert!(cache.get(1, Address::from_low_u64_be(0)).is_some());
+
+        cache.insert(1, metadata(TOKEN_METADATA_CACHE_SIZE as u64));
+        assert_eq!(cache.get(1, Address::from_low_u64_be(0)), None);
+        assert!(cache.get(1, Address::from_low_u64_be(1)).is_some());
+        assert!(cache
+            .get(
+                1,
+                Address::from_low_u64_be(TOKEN_METADATA_CACHE_SIZE as u64)
+            )
+            .is_some());
+        assert_eq!(cache.entries.lock().tokens.len(), TOKEN_METADATA_CACHE_SIZE);
+
+        // the same address on another chain is another entry
+        assert_eq!(cache.get(137, Address::from_low_u64_be(1)), None);
+    }
+}
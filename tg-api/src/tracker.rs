@@ -0,0 +1,133 @@
+use crate::chains::{ChainConfig, GLOBAL_CHAIN_REGISTRY};
+use crate::ledger::{TradeStatus, GLOBAL_TRADE_LEDGER};
+use crate::requests::on_chain::format_amount;
+use crate::requests::simulation;
+use crate::requests::swap::{self, SentSwap};
+use ethers::types::{TransactionReceipt, U256};
+use teloxide::payloads::SendMessageSetters;
+use teloxide::prelude::Requester;
+use teloxide::types::{ChatId, MessageId};
+use teloxide::Bot;
+use tokio::time::{timeout, Duration};
+
+/// How long a swap may go without a receipt before the user is told it may be stuck
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Final status of a tracked swap with the tokens it received, and the text replacing its "Tx Sent" message
+async fn final_status(
+    chain: &ChainConfig,
+    sent: &SentSwap,
+    receipt: Option<&TransactionReceipt>,
+) -> (TradeStatus, Option<U256>, String) {
+    let Some(receipt) = receipt else {
+        let text = match sent.private_max_block {
+            Some(max_block) => format!(
+                "Private Tx was not included by block {}, it is no longer valid",
+                max_block
+            ),
+            None => "Tx dropped, it was never mined".to_string(),
+        };
+        return (TradeStatus::Dropped, None, text);
+    };
+
+    let block = receipt.block_number.unwrap_or_default();
+    let gas_used = receipt.gas_used.unwrap_or_default();
+    if receipt.status == Some(1u64.into()) {
+        let received = swap::amount_received(sent, receipt);
+        return (
+            TradeStatus::Confirmed,
+            Some(received),
+            format!(
+                "Tx confirmed in block {}\nSold: {} {}\nReceived: {} {}\nGas used: {}",
+                block,
+                format_amount(sent.amount_in, sent.token_in.decimals),
+                sent.token_in.symbol,
+                format_amount(received, sent.token_out.decimals),
+                sent.token_out.symbol,
+                gas_used
+            ),
+        );
+    }
+
+    let reason = async {
+        let provider = chain.provider().await?;
+        simulation::mined_revert_reason(chain, &provider, receipt).await
+    };
+    let reason = reason.await.unwrap_or_else(|err| {
+        log::warn!("revert reason of {:?} unavailable: {}", sent.tx_hash, err);
+        simulation::RevertReason::Unknown
+    });
+    (
+        TradeStatus::Reverted,
+        None,
+        format!(
+            "Tx reverted in block {}\nReason: {}\nGas used: {}",
+            block, reason, gas_used
+        ),
+    )
+}
+
+/// Watches a submitted swap until it is mined or dropped, records the outcome in the ledger and
+/// replaces `message`, its "Tx Sent" message, with the final status. Without a message it is sent
+/// as a new one. Returns the receipt of a mined swap, `None` when it was dropped, and fails when
+/// the swap could not be followed and its outcome is unknown
+pub(crate) async fn track_trade(
+    bot: &Bot,
+    chat_id: ChatId,
+    sent: &SentSwap,
+    message: Option<MessageId>,
+) -> anyhow::Result<Option<TransactionReceipt>> {
+    let chain = GLOBAL_CHAIN_REGISTRY
+        .get(sent.chain_id)
+        .ok_or_else(|| anyhow::anyhow!("Chain {} is not supported", sent.chain_id))?;
+    let receipt = match timeout(RECEIPT_TIMEOUT, swap::wait_for_receipt(chain, sent)).await {
+        Ok(receipt) => receipt,
+        Err(_) => {
+            let text = format!(
+                "No receipt for {:?} after {} minutes, it may be stuck behind a low gas price. Still watching it\n{}",
+                sent.tx_hash,
+                RECEIPT_TIMEOUT.as_secs() / 60,
+                chain.tx_url(sent.tx_hash)
+            );
+            let notified = match message {
+                Some(message) => bot.send_message(chat_id, text).reply_to_message_id(message),
+                None => bot.send_message(chat_id, text),
+            };
+            if let Err(err) = notified.await {
+                log::warn!("stuck tx notification not sent: {}", err);
+            }
+            swap::wait_for_receipt(chain, sent).await
+        }
+    };
+    let receipt = match receipt {
+        Ok(receipt) => receipt,
+        Err(err) => {
+            log::warn!("tracking tx {:?} failed: {}", sent.tx_hash, err);
+            return Err(err);
+        }
+    };
+
+    let (status, amount_out, text) = final_status(chain, sent, receipt.as_ref()).await;
+    let gas_used = receipt.as_ref().and_then(|receipt| receipt.gas_used);
+    if let Err(err) = GLOBAL_TRADE_LEDGER.settle(sent.tx_hash, status, amount_out, gas_used) {
+        log::error!("trade {:?} not settled: {}", sent.tx_hash, err);
+    }
+
+    let text = format!(
+        "{} {}\n{}",
+        status.emoji(),
+        text,
+        chain.tx_url(sent.tx_hash)
+    );
+    let updated = match message {
+        Some(message) => bot
+            .edit_message_text(chat_id, message, text)
+            .await
+            .map(|_| ()),
+        None => bot.send_message(chat_id, text).await.map(|_| ()),
+    };
+    if let Err(err) = updated {
+        log::warn!("tx status of {:?} not shown: {}", sent.tx_hash, err);
+    }
+    Ok(receipt)
+}
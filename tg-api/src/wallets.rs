@@ -0,0 +1,282 @@
+use crate::consts::{WALLET_1, WALLET_2, WALLET_3};
+use crate::database::GLOBAL_DATABASE;
+use crate::global::Global;
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use dotenv::dotenv;
+use ethers::{
+    core::rand::thread_rng,
+    signers::{LocalWallet, Signer},
+    types::Address,
+    utils::hex,
+};
+use rusqlite::{params, OptionalExtension};
+use std::str::FromStr;
+use teloxide::types::UserId;
+
+/// Used to create and unlock the per-user wallets
+pub(crate) static GLOBAL_WALLET_STORE: Global<WalletStore> = Global::new("wallet master key");
+
+/// Names of the wallets every user gets, in slot order
+pub(crate) const WALLET_NAMES: [&str; 3] = [WALLET_1, WALLET_2, WALLET_3];
+
+/// Size of the AES-GCM nonce stored in front of every encrypted key
+const NONCE_LEN: usize = 12;
+
+/// Longest name a wallet can be renamed to
+pub(crate) const MAX_WALLET_NAME_LEN: usize = 24;
+
+/// Public part of a user's wallet
+#[derive(Debug, Clone)]
+pub(crate) struct UserWallet {
+    pub(crate) slot: u8,
+    pub(crate) name: String,
+    pub(crate) address: Address,
+    pub(crate) is_default: bool,
+}
+
+/// Creates the wallets of each Telegram user once and keeps their keys encrypted at rest
+/// with the AES-256-GCM master key from `WALLET_MASTER_KEY` (32 bytes, hex encoded)
+pub(crate) struct WalletStore {
+    cipher: Aes256Gcm,
+}
+
+impl WalletStore {
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        dotenv().ok();
+        let master_key = hex::decode(std::env::var("WALLET_MASTER_KEY")?.trim_start_matches("0x"))?;
+        Self::new(&master_key)
+    }
+
+    pub(crate) fn new(master_key: &[u8]) -> anyhow::Result<Self> {
+        if master_key.len() != 32 {
+            return Err(anyhow::anyhow!("The wallet master key must be 32 bytes"));
+        }
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(master_key));
+        Ok(Self { cipher })
+    }
+
+    /// Gets the user's wallets, creating the missing ones on first use
+    pub(crate) fn wallets(&self, user_id: UserId) -> anyhow::Result<Vec<UserWallet>> {
+        let default_slot = self.default_slot(user_id)?;
+        let mut wallets = Vec::with_capacity(WALLET_NAMES.len());
+        for (slot, default_name) in WALLET_NAMES.iter().enumerate() {
+            let slot = slot as u8;
+            let (address, name) = match self.address_and_name(user_id, slot)? {
+                Some(wallet) => wallet,
+                None => (self.create(user_id, slot)?, None),
+            };
+            wallets.push(UserWallet {
+                slot,
+                name: name.unwrap_or_else(|| default_name.to_string()),
+                address,
+                is_default: slot == default_slot,
+            });
+        }
+        Ok(wallets)
+    }
+
+    /// Gives the wallet a name shown in /wallets and /start
+    pub(crate) fn rename(&self, user_id: UserId, slot: u8, name: &str) -> anyhow::Result<()> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_WALLET_NAME_LEN {
+            return Err(anyhow::anyhow!(
+                "The name must be between 1 and {} characters",
+                MAX_WALLET_NAME_LEN
+            ));
+        }
+        let updated = GLOBAL_DATABASE.connection().execute(
+            "UPDATE wallets SET name = ?3 WHERE user_id = ?1 AND slot = ?2",
+            params![user_id.0, slot, name],
+        )?;
+        match updated {
+            0 => Err(anyhow::anyhow!("Wallet not found")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Gets the slot of the wallet preselected on the buy and sell menus
+    pub(crate) fn default_slot(&self, user_id: UserId) -> anyhow::Result<u8> {
+        let slot: Option<u8> = GLOBAL_DATABASE
+            .connection()
+            .query_row(
+                "SELECT default_wallet FROM user_settings WHERE user_id = ?1",
+                params![user_id.0],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(slot.unwrap_or(0))
+    }
+
+    pub(crate) fn set_default(&self, user_id: UserId, slot: u8) -> anyhow::Result<()> {
+        if slot as usize >= WALLET_NAMES.len() {
+            return Err(anyhow::anyhow!("Wallet not found"));
+        }
+        GLOBAL_DATABASE.connection().execute(
+            "INSERT INTO user_settings (user_id, default_wallet) VALUES (?1, ?2)
+             ON CONFLICT (user_id) DO UPDATE SET default_wallet = excluded.default_wallet",
+            params![user_id.0, slot],
+        )?;
+        Ok(())
+    }
+
+    /// Remembers the ERC-20 tokens a user has traded so /wallets can show their balances
+    pub(crate) fn record_traded_tokens(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+        tokens: &[Address],
+    ) -> anyhow::Result<()> {
+        let connection = GLOBAL_DATABASE.connection();
+        for token in tokens {
+            connection.execute(
+                "INSERT OR IGNORE INTO traded_tokens (user_id, chain_id, token) VALUES (?1, ?2, ?3)",
+                params![user_id.0, chain_id, format!("{:?}", token)],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn traded_tokens(
+        &self,
+        user_id: UserId,
+        chain_id: u64,
+    ) -> anyhow::Result<Vec<Address>> {
+        let connection = GLOBAL_DATABASE.connection();
+        let mut statement = connection
+            .prepare("SELECT token FROM traded_tokens WHERE user_id = ?1 AND chain_id = ?2")?;
+        let tokens = statement
+            .query_map(params![user_id.0, chain_id], |row| row.get::<_, String>(0))?
+            .map(|token| Ok(Address::from_str(&token?)?))
+            .collect::<anyhow::Result<Vec<Address>>>()?;
+        Ok(tokens)
+    }
+
+    /// Decrypts the signer of the user's wallet in the given slot
+    pub(crate) fn signer(&self, user_id: UserId, slot: u8) -> anyhow::Result<LocalWallet> {
+        let encrypted_key: Vec<u8> = GLOBAL_DATABASE
+            .connection()
+            .query_row(
+                "SELECT encrypted_key FROM wallets WHERE user_id = ?1 AND slot = ?2",
+                params![user_id.0, slot],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| {
+                anyhow::anyhow!("Wallet not found, use /start to create your wallets")
+            })?;
+
+        let private_key = self.decrypt(user_id, slot, &encrypted_key)?;
+        Ok(LocalWallet::from_bytes(&private_key)?)
+    }
+
+    fn address_and_name(
+        &self,
+        user_id: UserId,
+        slot: u8,
+    ) -> anyhow::Result<Option<(Address, Option<String>)>> {
+        let wallet: Option<(String, Option<String>)> = GLOBAL_DATABASE
+            .connection()
+            .query_row(
+                "SELECT address, name FROM wallets WHERE user_id = ?1 AND slot = ?2",
+                params![user_id.0, slot],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        match wallet {
+            Some((address, name)) => Ok(Some((Address::from_str(&address)?, name))),
+            None => Ok(None),
+        }
+    }
+
+    fn create(&self, user_id: UserId, slot: u8) -> anyhow::Result<Address> {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let encrypted_key = self.encrypt(user_id, slot, &wallet.signer().to_bytes())?;
+
+        // another handler may have created the wallet in the meantime, keep the first one
+        GLOBAL_DATABASE.connection().execute(
+            "INSERT OR IGNORE INTO wallets (user_id, slot, address, encrypted_key) VALUES (?1, ?2, ?3, ?4)",
+            params![user_id.0, slot, format!("{:?}", wallet.address()), encrypted_key],
+        )?;
+        log::info!("Created wallet {} for user {}", slot + 1, user_id);
+
+        self.address_and_name(user_id, slot)?
+            .map(|(address, _)| address)
+            .ok_or_else(|| anyhow::anyhow!("Unable to create wallet"))
+    }
+
+    /// Encrypts the key, binding it to its owner and slot, and prepends the nonce
+    fn encrypt(&self, user_id: UserId, slot: u8, private_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = format!("{}:{}", user_id.0, slot);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: private_key,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Unable to encrypt wallet"))?;
+
+        Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    fn decrypt(&self, user_id: UserId, slot: u8, encrypted_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if encrypted_key.len() <= NONCE_LEN {
+            return Err(anyhow::anyhow!("Corrupted wallet"));
+        }
+        let (nonce, ciphertext) = encrypted_key.split_at(NONCE_LEN);
+        let aad = format!("{}:{}", user_id.0, slot);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Unable to decrypt wallet, wrong master key?"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> WalletStore {
+        WalletStore::new(&[7u8; 32]).unwrap()
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypted() {
+        let store = store();
+        let private_key = LocalWallet::new(&mut thread_rng()).signer().to_bytes();
+        let encrypted_key = store.encrypt(UserId(42), 1, &private_key).unwrap();
+        assert_ne!(&encrypted_key[NONCE_LEN..], private_key.as_slice());
+        assert_eq!(
+            store.decrypt(UserId(42), 1, &encrypted_key).unwrap(),
+            private_key.as_slice()
+        );
+    }
+
+    #[test]
+    fn rejects_a_key_stored_under_another_user_or_slot() {
+        let store = store();
+        let encrypted_key = store.encrypt(UserId(42), 1, &[1u8; 32]).unwrap();
+        assert!(store.decrypt(UserId(43), 1, &encrypted_key).is_err());
+        assert!(store.decrypt(UserId(42), 2, &encrypted_key).is_err());
+    }
+
+    #[test]
+    fn rejects_another_master_key_and_corrupted_keys() {
+        let encrypted_key = store().encrypt(UserId(42), 0, &[1u8; 32]).unwrap();
+        let other = WalletStore::new(&[8u8; 32]).unwrap();
+        assert!(other.decrypt(UserId(42), 0, &encrypted_key).is_err());
+        assert!(store().decrypt(UserId(42), 0, &[0u8; NONCE_LEN]).is_err());
+        assert!(WalletStore::new(&[7u8; 16]).is_err());
+    }
+}